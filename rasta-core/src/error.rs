@@ -0,0 +1,30 @@
+//! Errors shared by the message and telegram codecs.
+
+use core::ops::RangeInclusive;
+
+/// A message, telegram or one of their fields has a length outside
+/// the range its layout allows.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, thiserror::Error)]
+pub enum LengthError {
+    #[error("{length} bytes are shorter than the minimum of {min} bytes")]
+    TooShort { length: usize, min: usize },
+    #[error("{length} bytes exceed the maximum of {max} bytes")]
+    TooLong { length: usize, max: usize },
+}
+
+/// Checks that `length` lies within `allowed`.
+pub fn check_length(length: usize, allowed: RangeInclusive<usize>) -> Result<(), LengthError> {
+    if length < *allowed.start() {
+        Err(LengthError::TooShort {
+            length,
+            min: *allowed.start(),
+        })
+    } else if length > *allowed.end() {
+        Err(LengthError::TooLong {
+            length,
+            max: *allowed.end(),
+        })
+    } else {
+        Ok(())
+    }
+}
@@ -0,0 +1,28 @@
+//! The layout of RaSTA messages and of the SCI telegrams they carry.
+
+/// The size of every RaSTA message on the wire.
+pub const MESSAGE_BUFFER_LENGTH: usize = 1024;
+/// The offset of the data in a RaSTA message.
+pub const DATA_OFFSET: usize = 34;
+/// The length of the security code at the end of a RaSTA message.
+pub const SECURITY_CODE_LENGTH: usize = 8;
+/// The maximum amount of data a single RaSTA message can carry.
+pub const MAX_DATA_LENGTH: usize = MESSAGE_BUFFER_LENGTH - DATA_OFFSET - SECURITY_CODE_LENGTH;
+/// The value of the length field of a RaSTA message without data.
+pub const MIN_MESSAGE_LENGTH: u16 = 36;
+
+/// The length of the sender and receiver names in an SCI telegram.
+pub const SCI_NAME_LENGTH: usize = 20;
+/// The offset of the protocol type byte in an SCI telegram.
+pub const SCI_PROTOCOL_TYPE_OFFSET: usize = 0;
+/// The offset of the (little endian) message type in an SCI telegram.
+pub const SCI_MESSAGE_TYPE_OFFSET: usize = 1;
+/// The offset of the sender name in an SCI telegram.
+pub const SCI_SENDER_OFFSET: usize = 3;
+/// The offset of the receiver name in an SCI telegram.
+pub const SCI_RECEIVER_OFFSET: usize = SCI_SENDER_OFFSET + SCI_NAME_LENGTH;
+/// The length of the SCI header, i.e. the offset of the payload.
+pub const SCI_HEADER_LENGTH: usize = SCI_RECEIVER_OFFSET + SCI_NAME_LENGTH;
+/// The maximum length of the payload of an SCI telegram, i.e. the data
+/// of a RaSTA message without the SCI header.
+pub const SCI_MAX_PAYLOAD_LENGTH: usize = MAX_DATA_LENGTH - SCI_HEADER_LENGTH;
@@ -0,0 +1,20 @@
+//! # rasta-core
+//!
+//! Primitives shared by `rasta-rs` and `sci-rs`: RaSTA IDs, SCI names,
+//! the layout of RaSTA messages and SCI telegrams and the errors of
+//! checking it. Both crates re-export these items where they used to
+//! define them, so this crate is the single source of truth for them.
+//!
+//! The crate only needs `core`.
+
+#![no_std]
+
+pub mod error;
+pub mod layout;
+pub mod name;
+
+pub use error::{check_length, LengthError};
+pub use name::SciName;
+
+/// Identifies a RaSTA endpoint.
+pub type RastaId = u32;
@@ -0,0 +1,70 @@
+//! The sender and receiver names of SCI telegrams.
+
+use core::ops::Deref;
+
+use crate::{check_length, layout::SCI_NAME_LENGTH, LengthError};
+
+/// The name of an SCI endpoint as it is sent in telegrams: exactly
+/// [`SCI_NAME_LENGTH`] bytes, with shorter names padded by `_`.
+/// Names compare equal if they are sent the same, so a received name
+/// keeps its padding but still matches the configured one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SciName([u8; SCI_NAME_LENGTH]);
+
+impl SciName {
+    /// Pads `name` to [`SCI_NAME_LENGTH`] bytes, rejecting longer names.
+    pub fn new(name: &str) -> Result<Self, LengthError> {
+        check_length(name.len(), 0..=SCI_NAME_LENGTH)?;
+        Ok(Self::truncated(name))
+    }
+
+    /// Pads `name` to [`SCI_NAME_LENGTH`] bytes, cutting off longer names.
+    pub fn truncated(name: &str) -> Self {
+        let mut bytes = [b'_'; SCI_NAME_LENGTH];
+        let length = name.len().min(SCI_NAME_LENGTH);
+        bytes[..length].copy_from_slice(&name.as_bytes()[..length]);
+        Self(bytes)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; SCI_NAME_LENGTH] {
+        &self.0
+    }
+}
+
+impl From<[u8; SCI_NAME_LENGTH]> for SciName {
+    fn from(bytes: [u8; SCI_NAME_LENGTH]) -> Self {
+        Self(bytes)
+    }
+}
+
+impl Deref for SciName {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SciName;
+    use crate::LengthError;
+
+    #[test]
+    fn test_sci_name() {
+        let name = SciName::new("C").unwrap();
+        assert_eq!(&name[..3], b"C__");
+        assert_eq!(name, SciName::truncated("C_________"));
+        assert_eq!(name, SciName::from(*name.as_bytes()));
+
+        let long = "an element with a long name";
+        assert_eq!(
+            SciName::new(long),
+            Err(LengthError::TooLong {
+                length: long.len(),
+                max: 20
+            })
+        );
+        assert_eq!(&SciName::truncated(long)[..], &long.as_bytes()[..20]);
+    }
+}
@@ -1,5 +1,3 @@
-use std::net::SocketAddrV4;
-
 use rasta_rs::{message::Message, RastaListener};
 
 fn on_receive(msg: Message) -> Option<Vec<u8>> {
@@ -8,7 +6,10 @@ fn on_receive(msg: Message) -> Option<Vec<u8>> {
 }
 
 fn main() {
-    let addr: SocketAddrV4 = "127.0.0.1:8888".parse().unwrap();
-    let mut conn = RastaListener::try_new(addr, 1337).unwrap();
+    // Host names and IPv6 addresses such as "[::1]:8888" work as well
+    let addr = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "localhost:8888".to_string());
+    let mut conn = RastaListener::try_new(addr.as_str(), 1337).unwrap();
     conn.listen(on_receive).unwrap();
 }
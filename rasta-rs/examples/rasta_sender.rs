@@ -1,10 +1,11 @@
-use std::net::SocketAddrV4;
-
 use rasta_rs::{RastaCommand, RastaConnection};
 
 fn main() {
-    let addr: SocketAddrV4 = "127.0.0.1:8888".parse().unwrap();
-    let mut conn = RastaConnection::try_new(addr, 1234).unwrap();
+    // Host names and IPv6 addresses such as "[::1]:8888" work as well
+    let addr = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "localhost:8888".to_string());
+    let mut conn = RastaConnection::try_new(addr.as_str(), 1234).unwrap();
     let mut sent = false;
     conn.run(5678, |data| {
         if !sent {
@@ -0,0 +1,99 @@
+//! Resolution of the addresses of RaSTA endpoints. Host names may
+//! resolve to several IPv4 and IPv6 addresses, which are tried in
+//! order until one of them can be used.
+
+use std::{
+    fmt::Display,
+    io::{self, ErrorKind},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+use crate::RastaError;
+
+/// The addresses that were tried and the reason each of them failed.
+#[derive(Debug)]
+pub struct AddressErrors(pub Vec<(SocketAddr, io::Error)>);
+
+impl Display for AddressErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (idx, (addr, e)) in self.0.iter().enumerate() {
+            if idx > 0 {
+                write!(f, "; ")?;
+            }
+            write!(f, "{addr}: {e}")?;
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn resolve<S: ToSocketAddrs>(addr: S) -> Result<Vec<SocketAddr>, RastaError> {
+    let addrs: Vec<SocketAddr> = addr
+        .to_socket_addrs()
+        .map_err(RastaError::Resolve)?
+        .collect();
+    if addrs.is_empty() {
+        return Err(RastaError::Resolve(io::Error::new(
+            ErrorKind::NotFound,
+            "no addresses found",
+        )));
+    }
+    Ok(addrs)
+}
+
+/// Connects to the first reachable address, waiting at most `timeout` for each.
+pub(crate) fn connect<S: ToSocketAddrs>(
+    addr: S,
+    timeout: Duration,
+) -> Result<TcpStream, RastaError> {
+    let mut errors = Vec::new();
+    for addr in resolve(addr)? {
+        match TcpStream::connect_timeout(&addr, timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => errors.push((addr, e)),
+        }
+    }
+    Err(RastaError::Connect(AddressErrors(errors)))
+}
+
+/// Binds to the first address that is available.
+pub(crate) fn bind<S: ToSocketAddrs>(addr: S) -> Result<TcpListener, RastaError> {
+    let mut errors = Vec::new();
+    for addr in resolve(addr)? {
+        match TcpListener::bind(addr) {
+            Ok(listener) => return Ok(listener),
+            Err(e) => errors.push((addr, e)),
+        }
+    }
+    Err(RastaError::Bind(AddressErrors(errors)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::TcpListener, time::Duration};
+
+    use crate::RastaError;
+
+    #[test]
+    fn test_connect_tries_all_addresses() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let open = listener.local_addr().unwrap();
+        let closed = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let timeout = Duration::from_secs(1);
+        assert!(super::connect(&[closed, open][..], timeout).is_ok());
+        match super::connect(closed, timeout) {
+            Err(RastaError::Connect(errors)) => {
+                assert_eq!(errors.0.len(), 1);
+                assert!(errors.to_string().starts_with(&closed.to_string()));
+            }
+            _ => panic!("connecting to a closed port succeeded"),
+        }
+        assert!(matches!(
+            super::resolve("invalid host name:1"),
+            Err(RastaError::Resolve(_))
+        ));
+    }
+}
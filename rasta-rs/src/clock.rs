@@ -0,0 +1,97 @@
+//! The source of the timestamps written to outgoing messages and of the
+//! time heartbeats and timeouts are scheduled on. Endpoints use the
+//! [`SystemClock`] unless another [`Clock`] is set, e.g. to re-create a
+//! captured session with its original timestamps or to test timeouts
+//! with a [`MockClock`] instead of waiting for them.
+
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// Provides the timestamps of outgoing messages and the current time.
+pub trait Clock: Send + Sync {
+    /// The current timestamp in the unit used on the wire.
+    fn timestamp(&self) -> u32;
+
+    /// The current point in time that heartbeats and timeouts are measured from.
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    /// Waits for `duration`, e.g. between heartbeats.
+    fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration)
+    }
+}
+
+/// Seconds since the Unix epoch.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn timestamp(&self) -> u32 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as u32
+    }
+}
+
+impl<F> Clock for F
+where
+    F: Fn() -> u32 + Send + Sync,
+{
+    fn timestamp(&self) -> u32 {
+        self()
+    }
+}
+
+/// A clock that only advances when told to. Clones share their time,
+/// so a test can keep one and pass another to an endpoint.
+/// Sleeping advances the clock instead of waiting.
+/// Timestamps are the seconds that have passed on the clock.
+#[derive(Clone, Debug)]
+pub struct MockClock {
+    start: Instant,
+    elapsed: Arc<Mutex<Duration>>,
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        Self {
+            start: Instant::now(),
+            elapsed: Arc::default(),
+        }
+    }
+}
+
+impl MockClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Lets `duration` pass on this clock and all of its clones.
+    pub fn advance(&self, duration: Duration) {
+        *self.elapsed.lock().unwrap() += duration;
+    }
+
+    /// The time that has passed on the clock since it was created.
+    pub fn elapsed(&self) -> Duration {
+        *self.elapsed.lock().unwrap()
+    }
+}
+
+impl Clock for MockClock {
+    fn timestamp(&self) -> u32 {
+        self.elapsed().as_secs() as u32
+    }
+
+    fn now(&self) -> Instant {
+        self.start + self.elapsed()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration)
+    }
+}
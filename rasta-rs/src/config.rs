@@ -0,0 +1,345 @@
+//! Configuration of [`RastaConnection`](crate::RastaConnection)s
+//! and [`RastaListener`](crate::RastaListener)s.
+
+use std::{sync::Arc, time::Duration};
+
+use crate::message::{MAX_DATA_LENGTH, MESSAGE_BUFFER_LENGTH};
+use crate::ring::OverflowPolicy;
+use crate::safety::{NoSafetyCode, SafetyCode};
+use crate::socket::SocketOptions;
+#[cfg(feature = "tls")]
+use crate::tls::TlsConfig;
+use crate::{RastaError, N_SENDMAX, RASTA_TIMEOUT_DURATION};
+
+/// What happens when data exceeds the maximum data length of the peer.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum OversizedSend {
+    /// Fail with [`RastaError::PeerLimitExceeded`](crate::RastaError::PeerLimitExceeded)
+    /// without sending anything.
+    #[default]
+    Reject,
+    /// Send the data in consecutive messages of at most the maximum data length.
+    /// The peer has to reassemble them.
+    Split,
+}
+
+/// What endpoints do with messages of a type they do not know,
+/// e.g. one added by a later version of the standard.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum UnknownMessagePolicy {
+    /// Discard them.
+    #[default]
+    Drop,
+    /// Pass them to the application like data messages, e.g. for bridges
+    /// that forward them. See [`Message::message_type_raw`](crate::message::Message::message_type_raw).
+    Forward,
+}
+
+/// How writes that fail with a transient error (`WouldBlock` or
+/// `Interrupted`) are repeated before the error is returned. The wait
+/// before each retry doubles, starting at `initial_backoff`, up to
+/// `max_backoff`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct WriteRetryPolicy {
+    /// The number of retries after the first attempt. Zero disables retries.
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for WriteRetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 5,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(50),
+        }
+    }
+}
+
+impl WriteRetryPolicy {
+    /// The wait before retry number `retry`, counted from zero.
+    pub fn backoff(&self, retry: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(1 << retry.min(16))
+            .min(self.max_backoff)
+    }
+}
+
+/// The number of entries each buffer of an endpoint may hold, including the
+/// buffers of SCI endpoints built on top of it. Every buffer is bounded;
+/// what happens when one is full is documented with its limit.
+/// The receive buffer of [`RastaListener::listen_buffered`](crate::RastaListener::listen_buffered)
+/// is limited by [`RastaConfig::receive_buffer_capacity`] and handled
+/// according to [`RastaConfig::overflow_policy`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct MemoryBudget {
+    /// Messages received while the application was not waiting for them,
+    /// e.g. while waiting for the response to a heartbeat. Receiving
+    /// another one fails with [`RastaError::BufferFull`].
+    pub pending_messages: usize,
+    /// Sent data messages kept until the peer confirms them, so they can
+    /// be retransmitted. Has to be at least [`RastaConfig::n_sendmax`].
+    /// Nothing is discarded: while the buffer is full, sending data fails
+    /// with [`RastaError::BufferFull`], which fails the connection.
+    pub unconfirmed_messages: usize,
+    /// Telegrams queued for an SCI connection. Queueing another one fails
+    /// until the queue has been drained. At least one telegram is queued.
+    pub send_queue: usize,
+    /// Entries of a journal attached to an SCI endpoint.
+    /// The oldest entry is discarded to make room.
+    pub journal_entries: usize,
+    /// Telegrams kept per peer in the history of an SCI endpoint.
+    /// The oldest telegram is discarded to make room.
+    pub history_length: usize,
+    /// Peers kept in the history of an SCI endpoint. The peer whose
+    /// latest telegram is the oldest is forgotten to make room.
+    pub history_peers: usize,
+    /// The amount of memory all buffers may occupy when full,
+    /// see [`MemoryBudget::required_bytes`]. Unlimited if `None`.
+    pub max_bytes: Option<usize>,
+}
+
+impl Default for MemoryBudget {
+    fn default() -> Self {
+        Self {
+            pending_messages: 1024,
+            unconfirmed_messages: usize::from(N_SENDMAX),
+            send_queue: 1024,
+            journal_entries: 100_000,
+            history_length: 50,
+            history_peers: 256,
+            max_bytes: None,
+        }
+    }
+}
+
+impl MemoryBudget {
+    /// The memory the buffers occupy when full, given the capacity of the
+    /// receive buffer. Every entry is accounted for with the size of a
+    /// whole message, which also bounds the size of an SCI telegram.
+    pub fn required_bytes(&self, receive_buffer_capacity: usize) -> usize {
+        let messages = receive_buffer_capacity
+            .saturating_add(self.pending_messages)
+            .saturating_add(self.send_queue)
+            .saturating_add(self.journal_entries)
+            .saturating_add(self.history_length.saturating_mul(self.history_peers));
+        messages
+            .saturating_mul(MESSAGE_BUFFER_LENGTH)
+            .saturating_add(self.unconfirmed_messages.saturating_mul(MAX_DATA_LENGTH))
+    }
+}
+
+/// Settings shared by RaSTA connections and listeners.
+/// Use [`RastaConfig::default`] and override the fields you need.
+/// With the `serde` feature, configurations can be read from files;
+/// missing fields keep their default, and the safety code and TLS
+/// settings have to be set in code.
+#[derive(Clone, Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct RastaConfig {
+    /// Sets `TCP_NODELAY` on the underlying sockets. Since writes are
+    /// coalesced and flushed at protocol boundaries, disabling Nagle's
+    /// algorithm only adds latency when it is left enabled.
+    pub nodelay: bool,
+    /// Further options of the TCP sockets, such as DSCP marking and buffer sizes.
+    pub socket: SocketOptions,
+    /// The number of received data messages that
+    /// [`RastaListener::listen_buffered`](crate::RastaListener::listen_buffered)
+    /// keeps while the worker is busy.
+    pub receive_buffer_capacity: usize,
+    /// What to do with data messages that arrive while the receive buffer is full.
+    pub overflow_policy: OverflowPolicy,
+    /// The maximum time between two messages (T_max) before the
+    /// connection is considered dead.
+    pub t_max: Duration,
+    /// The lower bound of the adaptive heartbeat interval.
+    pub min_heartbeat_interval: Duration,
+    /// The upper bound of the adaptive heartbeat interval.
+    pub max_heartbeat_interval: Duration,
+    /// How long a connection waits for each address of its peer
+    /// before trying the next one.
+    pub connect_timeout: Duration,
+    /// How long a listener that is shut down waits for its peer
+    /// to receive the final disconnection request.
+    pub shutdown_grace_period: Duration,
+    /// The maximum amount of data accepted in one message,
+    /// announced to the peer when connecting.
+    pub max_data_length: usize,
+    /// The number of messages (N_SENDMAX) the endpoint accepts ahead of
+    /// the next expected sequence number, announced to the peer when
+    /// connecting. Messages further ahead close the connection.
+    pub n_sendmax: u16,
+    /// What a connection does with data that exceeds the maximum
+    /// data length announced by its peer.
+    pub oversized_send: OversizedSend,
+    /// What endpoints do with messages of unknown types.
+    pub unknown_messages: UnknownMessagePolicy,
+    /// The limits of all buffers of the endpoint, checked when it is created.
+    pub memory: MemoryBudget,
+    /// How writes failing with transient errors are retried.
+    pub write_retry: WriteRetryPolicy,
+    /// The safety code written to and checked on every message.
+    /// Both peers have to use the same algorithm and key.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub safety_code: Arc<dyn SafetyCode>,
+    /// Wraps all connections in TLS if set (requires the `tls` feature).
+    #[cfg(feature = "tls")]
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub tls: Option<TlsConfig>,
+}
+
+impl Default for RastaConfig {
+    fn default() -> Self {
+        Self {
+            nodelay: true,
+            socket: SocketOptions::default(),
+            receive_buffer_capacity: 64,
+            overflow_policy: OverflowPolicy::default(),
+            t_max: RASTA_TIMEOUT_DURATION,
+            min_heartbeat_interval: Duration::from_millis(10),
+            max_heartbeat_interval: RASTA_TIMEOUT_DURATION,
+            connect_timeout: Duration::from_secs(2),
+            shutdown_grace_period: Duration::from_millis(100),
+            max_data_length: MAX_DATA_LENGTH,
+            n_sendmax: N_SENDMAX,
+            oversized_send: OversizedSend::default(),
+            unknown_messages: UnknownMessagePolicy::default(),
+            memory: MemoryBudget::default(),
+            write_retry: WriteRetryPolicy::default(),
+            safety_code: Arc::new(NoSafetyCode),
+            #[cfg(feature = "tls")]
+            tls: None,
+        }
+    }
+}
+
+impl RastaConfig {
+    /// Fails if the buffers configured by `self` could exceed
+    /// [`MemoryBudget::max_bytes`], or if fewer than
+    /// [`n_sendmax`](Self::n_sendmax) unconfirmed messages can be kept
+    /// for retransmission.
+    pub fn check_memory_budget(&self) -> Result<(), RastaError> {
+        if self.memory.unconfirmed_messages < usize::from(self.n_sendmax) {
+            return Err(RastaError::RetransmissionBufferTooSmall {
+                capacity: self.memory.unconfirmed_messages,
+                n_sendmax: self.n_sendmax,
+            });
+        }
+        let required = self.memory.required_bytes(self.receive_buffer_capacity);
+        match self.memory.max_bytes {
+            Some(budget) if required > budget => {
+                Err(RastaError::MemoryBudgetExceeded { required, budget })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// The interval between heartbeats given the measured round-trip time.
+    /// A heartbeat has to reach the peer before `t_max` has passed since
+    /// the previous message, so the time left after the round trip is
+    /// halved to absorb processing jitter. The result is bounded by
+    /// `min_heartbeat_interval` and `max_heartbeat_interval`.
+    pub fn heartbeat_interval(&self, round_trip_time: Duration) -> Duration {
+        (self.t_max.saturating_sub(round_trip_time) / 2)
+            .min(self.max_heartbeat_interval)
+            .max(self.min_heartbeat_interval)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{MemoryBudget, RastaConfig, WriteRetryPolicy};
+    use crate::{
+        message::{MAX_DATA_LENGTH, MESSAGE_BUFFER_LENGTH},
+        RastaError,
+    };
+
+    #[test]
+    fn test_heartbeat_interval() {
+        let config = RastaConfig {
+            t_max: Duration::from_millis(500),
+            min_heartbeat_interval: Duration::from_millis(20),
+            max_heartbeat_interval: Duration::from_millis(200),
+            ..Default::default()
+        };
+        assert_eq!(
+            config.heartbeat_interval(Duration::ZERO),
+            Duration::from_millis(200)
+        );
+        assert_eq!(
+            config.heartbeat_interval(Duration::from_millis(200)),
+            Duration::from_millis(150)
+        );
+        assert_eq!(
+            config.heartbeat_interval(Duration::from_secs(1)),
+            Duration::from_millis(20)
+        );
+    }
+
+    #[test]
+    fn test_write_retry_backoff() {
+        let policy = WriteRetryPolicy::default();
+        assert_eq!(policy.backoff(0), Duration::from_millis(1));
+        assert_eq!(policy.backoff(3), Duration::from_millis(8));
+        assert_eq!(policy.backoff(10), Duration::from_millis(50));
+        assert_eq!(policy.backoff(u32::MAX), Duration::from_millis(50));
+    }
+
+    #[test]
+    fn test_memory_budget() {
+        let memory = MemoryBudget {
+            pending_messages: 1,
+            unconfirmed_messages: 1,
+            send_queue: 1,
+            journal_entries: 0,
+            history_length: 2,
+            history_peers: 2,
+            max_bytes: Some(8 * MESSAGE_BUFFER_LENGTH + MAX_DATA_LENGTH),
+        };
+        let mut config = RastaConfig {
+            receive_buffer_capacity: 2,
+            n_sendmax: 1,
+            memory,
+            ..Default::default()
+        };
+        assert_eq!(
+            memory.required_bytes(2),
+            8 * MESSAGE_BUFFER_LENGTH + MAX_DATA_LENGTH
+        );
+        assert!(config.check_memory_budget().is_ok());
+        config.receive_buffer_capacity = 3;
+        assert!(matches!(
+            config.check_memory_budget(),
+            Err(RastaError::MemoryBudgetExceeded { .. })
+        ));
+        // Every message the peer may request again has to be kept
+        config.n_sendmax = 2;
+        assert!(matches!(
+            config.check_memory_budget(),
+            Err(RastaError::RetransmissionBufferTooSmall {
+                capacity: 1,
+                n_sendmax: 2
+            })
+        ));
+        assert!(RastaConfig::default().check_memory_budget().is_ok());
+    }
+}
@@ -0,0 +1,41 @@
+//! Snapshots of endpoints for inventories and monitoring.
+//!
+//! [`RastaConnection::describe`](crate::RastaConnection::describe) and
+//! [`RastaListener::describe`](crate::RastaListener::describe) return an
+//! [`EndpointDescriptor`] that can be stored, logged and, with the `serde`
+//! feature, serialized without keeping a reference to the endpoint.
+
+use std::{net::SocketAddr, time::Duration};
+
+use crate::{RastaConnectionState, RastaId};
+
+/// Which side of the connection setup an endpoint takes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum EndpointRole {
+    Connection,
+    Listener,
+}
+
+/// The addresses, IDs and timing parameters of an endpoint
+/// at the time it was described.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EndpointDescriptor {
+    pub role: EndpointRole,
+    pub id: RastaId,
+    /// The connected peers: at most one for a connection,
+    /// all peers that connected so far for a listener.
+    pub peers: Vec<RastaId>,
+    pub local_addr: Option<SocketAddr>,
+    /// The address of the peer of a connection.
+    pub peer_addr: Option<SocketAddr>,
+    /// The state of a connection. Listeners have no state of their own.
+    pub state: Option<RastaConnectionState>,
+    pub t_max: Duration,
+    /// The current heartbeat interval of a connection,
+    /// the upper bound of the interval for a listener.
+    pub heartbeat_interval: Duration,
+    /// The smoothed round-trip time of a connection, once measured.
+    pub round_trip_time: Option<Duration>,
+}
@@ -0,0 +1,118 @@
+//! # State Diagrams
+//!
+//! State machines declare their transitions with
+//! [`state_transitions!`](crate::state_transitions), which generates a
+//! table of [`Transition`]s and renders it as a Mermaid diagram into the
+//! documentation of that table. State machines check every change of their
+//! state against the table in debug builds, so the diagrams cannot drift
+//! from the code. [`mermaid`] and [`dot`] render a table at runtime,
+//! e.g. for documents under review.
+
+use std::fmt::{Debug, Write};
+
+/// A change from one state to another.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Transition<S: 'static> {
+    pub from: S,
+    pub to: S,
+    /// What causes the transition. Only used for documentation.
+    pub event: &'static str,
+}
+
+/// Declares the transitions of a state machine as a constant table of
+/// [`Transition`]s whose documentation contains a Mermaid diagram.
+///
+/// ```
+/// #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// enum Door {
+///     Open,
+///     Closed,
+/// }
+///
+/// rasta_rs::state_transitions! {
+///     /// The transitions of a door.
+///     const DOOR: [Door] = [
+///         Open => Closed on "push",
+///         Closed => Open on "pull",
+///     ];
+/// }
+///
+/// assert!(rasta_rs::diagram::allows(DOOR, Door::Open, Door::Closed));
+/// ```
+#[macro_export]
+macro_rules! state_transitions {
+    (
+        $(#[$meta:meta])*
+        $vis:vis const $name:ident: [$state:ident] = [
+            $($from:ident => $to:ident on $event:literal),* $(,)?
+        ];
+    ) => {
+        $(#[$meta])*
+        ///
+        /// ```mermaid
+        /// stateDiagram-v2
+        $(#[doc = concat!("    ", stringify!($from), " --> ", stringify!($to), " : ", $event)])*
+        /// ```
+        $vis const $name: &[$crate::diagram::Transition<$state>] = &[
+            $($crate::diagram::Transition {
+                from: $state::$from,
+                to: $state::$to,
+                event: $event,
+            }),*
+        ];
+    };
+}
+
+/// Whether `transitions` contain a change from `from` to `to`.
+pub fn allows<S: PartialEq>(transitions: &[Transition<S>], from: S, to: S) -> bool {
+    transitions.iter().any(|t| t.from == from && t.to == to)
+}
+
+/// Renders `transitions` as a Mermaid state diagram,
+/// like the one in the documentation of the table.
+pub fn mermaid<S: Debug>(transitions: &[Transition<S>]) -> String {
+    let mut diagram = String::from("stateDiagram-v2\n");
+    for t in transitions {
+        let _ = writeln!(diagram, "    {:?} --> {:?} : {}", t.from, t.to, t.event);
+    }
+    diagram
+}
+
+/// Renders `transitions` as a Graphviz digraph called `name`.
+pub fn dot<S: Debug>(name: &str, transitions: &[Transition<S>]) -> String {
+    let mut diagram = format!("digraph {name} {{\n");
+    for t in transitions {
+        let _ = writeln!(
+            diagram,
+            "    {:?} -> {:?} [label={:?}];",
+            t.from, t.to, t.event
+        );
+    }
+    diagram.push_str("}\n");
+    diagram
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{RastaConnectionState, SR_TRANSITIONS};
+
+    #[test]
+    fn test_render_transitions() {
+        assert!(super::allows(
+            SR_TRANSITIONS,
+            RastaConnectionState::Down,
+            RastaConnectionState::Up
+        ));
+        assert!(!super::allows(
+            SR_TRANSITIONS,
+            RastaConnectionState::Closed,
+            RastaConnectionState::Down
+        ));
+        let mermaid = super::mermaid(SR_TRANSITIONS);
+        assert!(mermaid.starts_with("stateDiagram-v2\n"));
+        assert!(mermaid.contains("    Down --> Up : ConnResp\n"));
+        let dot = super::dot("sr", SR_TRANSITIONS);
+        assert!(dot.contains("    Down -> Closed [label=\"DiscReq\"];\n"));
+        assert!(dot.ends_with("}\n"));
+    }
+}
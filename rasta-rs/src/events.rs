@@ -0,0 +1,142 @@
+//! The events of a [`RastaListener`](crate::RastaListener) as values, see
+//! [`RastaListener::events`](crate::RastaListener::events). Handling
+//! logic written against [`ListenerEvent`]s can be driven by a running
+//! listener as well as by events constructed in tests.
+
+use std::{
+    sync::mpsc::{self, RecvTimeoutError},
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use crate::{
+    message::{Message, RastaId},
+    shutdown::ShutdownHandle,
+    RastaError,
+};
+
+/// Why a listener lost its connection to a peer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DisconnectReason {
+    /// The peer sent a disconnection request.
+    PeerRequest,
+    /// The peer closed the TCP connection without a disconnection request.
+    Closed,
+    /// Reading from the peer failed.
+    Lost,
+    /// The peer did not send anything for T_max.
+    Timeout,
+    /// The peer sent a sequence number too far ahead or confirmed
+    /// one that was not sent.
+    InvalidSequenceNumber,
+    /// The peer requested a connection with an incompatible protocol version.
+    VersionMismatch,
+    /// The listener was shut down.
+    Shutdown,
+}
+
+/// Something that happened on a connection of a listener.
+pub enum ListenerEvent {
+    /// A peer connected with its connection request.
+    ConnectionOpened(RastaId),
+    /// A peer sent data. It was answered with a heartbeat. With
+    /// [`UnknownMessagePolicy::Forward`](crate::UnknownMessagePolicy::Forward),
+    /// messages of unknown types are passed on this way as well.
+    Data(Message),
+    /// A peer sent a heartbeat.
+    Heartbeat(RastaId),
+    /// The connection to a peer that had connected ended.
+    Disconnected(RastaId, DisconnectReason),
+}
+
+/// Observes the events of a running listener,
+/// see [`RastaListener::set_event_hook`](crate::RastaListener::set_event_hook).
+pub type ListenerEventHook = Box<dyn FnMut(&ListenerEvent) + Send>;
+
+/// Data messages are shown by their metadata.
+impl std::fmt::Debug for ListenerEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ConnectionOpened(peer) => f.debug_tuple("ConnectionOpened").field(peer).finish(),
+            Self::Data(msg) if msg.try_message_type().is_ok() => {
+                f.debug_tuple("Data").field(&msg.metadata()).finish()
+            }
+            Self::Data(msg) => f
+                .debug_tuple("Data")
+                .field(&format_args!("Unknown({})", msg.message_type_raw()))
+                .finish(),
+            Self::Heartbeat(peer) => f.debug_tuple("Heartbeat").field(peer).finish(),
+            Self::Disconnected(peer, reason) => f
+                .debug_tuple("Disconnected")
+                .field(peer)
+                .field(reason)
+                .finish(),
+        }
+    }
+}
+
+/// The events of a listener running on a separate thread. Iterating
+/// blocks until the next event and ends once the listener stopped.
+pub struct ListenerEvents {
+    receiver: mpsc::Receiver<ListenerEvent>,
+    shutdown: ShutdownHandle,
+    thread: Option<JoinHandle<Result<(), RastaError>>>,
+}
+
+impl ListenerEvents {
+    pub(crate) fn new(
+        receiver: mpsc::Receiver<ListenerEvent>,
+        shutdown: ShutdownHandle,
+        thread: JoinHandle<Result<(), RastaError>>,
+    ) -> Self {
+        Self {
+            receiver,
+            shutdown,
+            thread: Some(thread),
+        }
+    }
+
+    /// The channel the events arrive on, e.g. to wait for them
+    /// together with other channels.
+    pub fn receiver(&self) -> &mpsc::Receiver<ListenerEvent> {
+        &self.receiver
+    }
+
+    /// Waits up to `timeout` for the next event. Fails with
+    /// [`RecvTimeoutError::Disconnected`] once the listener stopped.
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<ListenerEvent, RecvTimeoutError> {
+        self.receiver.recv_timeout(timeout)
+    }
+
+    /// The next event if one has already arrived.
+    pub fn try_next(&self) -> Option<ListenerEvent> {
+        self.receiver.try_recv().ok()
+    }
+
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    /// Shuts the listener down and returns the result it stopped with.
+    pub fn shutdown(mut self) -> Result<(), RastaError> {
+        self.shutdown.shutdown();
+        self.join()
+    }
+
+    fn join(&mut self) -> Result<(), RastaError> {
+        match self.thread.take().map(JoinHandle::join) {
+            Some(Ok(result)) => result,
+            Some(Err(_)) => Err(RastaError::Other("Listener thread panicked".to_string())),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Iterator for ListenerEvents {
+    type Item = ListenerEvent;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.receiver.recv().ok()
+    }
+}
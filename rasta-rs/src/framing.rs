@@ -0,0 +1,64 @@
+//! Reassembly of RaSTA frames from a byte stream.
+//!
+//! Every [`Message`](crate::message::Message) is sent as a frame of
+//! [`MESSAGE_BUFFER_LENGTH`] bytes, while its length field only covers
+//! the part in use. TCP does not preserve these boundaries, so a single
+//! read may return part of a frame or several coalesced frames.
+
+use std::collections::VecDeque;
+
+use crate::message::MESSAGE_BUFFER_LENGTH;
+
+/// Collects received bytes and hands them out frame by frame.
+#[derive(Debug, Default)]
+pub(crate) struct FrameBuffer {
+    pending: VecDeque<u8>,
+}
+
+impl FrameBuffer {
+    pub(crate) fn extend(&mut self, bytes: &[u8]) {
+        self.pending.extend(bytes);
+    }
+
+    /// Takes the next complete frame, if one has been received.
+    pub(crate) fn next_frame(&mut self) -> Option<Vec<u8>> {
+        if self.pending.len() < MESSAGE_BUFFER_LENGTH {
+            return None;
+        }
+        Some(self.pending.drain(..MESSAGE_BUFFER_LENGTH).collect())
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.pending.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FrameBuffer;
+    use crate::message::{Message, MessageType};
+
+    #[test]
+    fn test_coalesced_frames() {
+        let mut stream = Vec::new();
+        stream.extend_from_slice(&Message::heartbeat(1, 2, 3, 4, 5, 6));
+        stream.extend_from_slice(&Message::data_message(1, 2, 4, 4, 5, 6, &[7, 8]).unwrap());
+        stream.extend_from_slice(&Message::heartbeat(1, 2, 5, 4, 5, 6));
+
+        let mut frames = FrameBuffer::default();
+        // The third frame arrives in two parts
+        let split = stream.len() - 100;
+        frames.extend(&stream[..split]);
+        let first = Message::from(frames.next_frame().unwrap().as_slice());
+        assert_eq!(first.try_message_type().unwrap(), MessageType::HB);
+        let second = Message::from(frames.next_frame().unwrap().as_slice());
+        assert_eq!(second.try_message_type().unwrap(), MessageType::Data);
+        assert_eq!(second.data(), &[7, 8]);
+        assert!(frames.next_frame().is_none());
+
+        frames.extend(&stream[split..]);
+        let third = Message::from(frames.next_frame().unwrap().as_slice());
+        assert_eq!(third.sequence_number(), 5);
+        assert!(frames.next_frame().is_none());
+    }
+}
@@ -0,0 +1,230 @@
+//! Structured reports of protocol violations that made an endpoint
+//! terminate a connection. Reports are collected in an [`IncidentLog`]
+//! and can be exported as JSON, e.g. to attach them to maintenance tickets.
+//! Protocol layers on top of RaSTA record their own incidents in the
+//! same log, e.g. when an SCI peer closes its PDI.
+
+use std::{
+    collections::VecDeque,
+    fmt::Write,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{logging::event, message::RastaId, RastaConnectionState};
+
+/// The number of reports kept by an [`IncidentLog`].
+/// Older reports are dropped first.
+pub const MAX_INCIDENTS: usize = 64;
+
+/// The protocol violation that caused an incident.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IncidentKind {
+    /// A sequence number or confirmed sequence number was outside
+    /// its acceptance window, see [`crate::sequence`].
+    InvalidSequenceNumber,
+    /// The peer did not send a message within `t_max`.
+    Timeout,
+    /// The peer uses an incompatible RaSTA version.
+    VersionMismatch,
+    /// The peer ended the protocol layer on top of RaSTA, e.g. by
+    /// closing an SCI PDI, for [`IncidentReport::reason`].
+    PeerClosed,
+}
+
+/// A snapshot of a connection at the time of a protocol violation.
+#[derive(Clone, Debug)]
+pub struct IncidentReport {
+    pub kind: IncidentKind,
+    pub occurred_at: SystemTime,
+    /// The offending frame, up to its length field.
+    pub frame: Vec<u8>,
+    /// The reason given by the peer, if any.
+    pub reason: Option<String>,
+    pub local_id: RastaId,
+    pub peer: Option<RastaId>,
+    pub state: RastaConnectionState,
+    /// The confirmed sequence number the endpoint expected next.
+    pub expected_sequence_number: Option<u32>,
+    /// The time since the previous message of the peer.
+    pub since_last_message: Option<Duration>,
+    pub round_trip_time: Option<Duration>,
+    /// The 99th percentile of the acknowledgement latency,
+    /// see [`AckLatency`](crate::latency::AckLatency).
+    pub ack_latency_p99: Option<Duration>,
+    pub t_max: Duration,
+}
+
+impl IncidentReport {
+    /// Creates a report for `frame`. The remaining fields
+    /// are filled in by the reporting endpoint.
+    pub fn new(
+        kind: IncidentKind,
+        frame: &[u8],
+        local_id: RastaId,
+        state: RastaConnectionState,
+        t_max: Duration,
+    ) -> Self {
+        let length = match frame {
+            [high, low, ..] => usize::from(u16::from_be_bytes([*high, *low])).min(frame.len()),
+            _ => frame.len(),
+        };
+        Self {
+            kind,
+            occurred_at: SystemTime::now(),
+            frame: frame[..length].to_vec(),
+            reason: None,
+            local_id,
+            peer: None,
+            state,
+            expected_sequence_number: None,
+            since_last_message: None,
+            round_trip_time: None,
+            ack_latency_p99: None,
+            t_max,
+        }
+    }
+
+    /// Serializes the report as a JSON object. Durations are given in
+    /// milliseconds, the time of the incident in milliseconds since the
+    /// Unix epoch and the frame as a hex string.
+    pub fn to_json(&self) -> String {
+        let millis = |d: Duration| d.as_secs_f64() * 1000.0;
+        let optional = |value: Option<String>| value.unwrap_or_else(|| "null".to_string());
+        let mut frame = String::with_capacity(2 * self.frame.len());
+        for byte in &self.frame {
+            let _ = write!(frame, "{byte:02x}");
+        }
+        format!(
+            concat!(
+                "{{\"kind\":\"{:?}\",\"occurred_at\":{},\"frame\":\"{}\",\"reason\":{},",
+                "\"state\":{{\"local_id\":{},\"peer\":{},\"connection_state\":\"{:?}\",",
+                "\"expected_sequence_number\":{}}},",
+                "\"timing\":{{\"since_last_message_ms\":{},\"round_trip_time_ms\":{},\"ack_latency_p99_ms\":{},\"t_max_ms\":{}}}}}"
+            ),
+            self.kind,
+            self.occurred_at
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis(),
+            frame,
+            optional(self.reason.as_deref().map(json_string)),
+            self.local_id,
+            optional(self.peer.map(|peer| peer.to_string())),
+            self.state,
+            optional(self.expected_sequence_number.map(|seq| seq.to_string())),
+            optional(self.since_last_message.map(|d| millis(d).to_string())),
+            optional(self.round_trip_time.map(|d| millis(d).to_string())),
+            optional(self.ack_latency_p99.map(|d| millis(d).to_string())),
+            millis(self.t_max),
+        )
+    }
+}
+
+/// Quotes `value` as a JSON string.
+fn json_string(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if c.is_control() => {
+                let _ = write!(quoted, "\\u{:04x}", u32::from(c));
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// A shared handle to the incidents of an endpoint. Clones refer to
+/// the same log, so a clone can be polled while the endpoint is running.
+#[derive(Clone, Debug, Default)]
+pub struct IncidentLog {
+    reports: Arc<Mutex<VecDeque<IncidentReport>>>,
+}
+
+impl IncidentLog {
+    /// Adds `report`, dropping the oldest one if [`MAX_INCIDENTS`] are kept.
+    pub fn record(&self, report: IncidentReport) {
+        event!(
+            warn,
+            kind = ?report.kind,
+            peer = ?report.peer,
+            "Protocol violation"
+        );
+        let mut reports = self.reports.lock().unwrap();
+        if reports.len() == MAX_INCIDENTS {
+            reports.pop_front();
+        }
+        reports.push_back(report);
+    }
+
+    /// Returns a snapshot of the recorded reports, oldest first.
+    pub fn reports(&self) -> Vec<IncidentReport> {
+        self.reports.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Removes and returns all recorded reports.
+    pub fn take(&self) -> Vec<IncidentReport> {
+        self.reports.lock().unwrap().drain(..).collect()
+    }
+
+    pub fn latest(&self) -> Option<IncidentReport> {
+        self.reports.lock().unwrap().back().cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.reports.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::RastaConnectionState;
+
+    use super::{IncidentKind, IncidentLog, IncidentReport};
+
+    #[test]
+    fn test_incident_json() {
+        let mut report = IncidentReport::new(
+            IncidentKind::InvalidSequenceNumber,
+            &[0, 4, 0xAB, 0xCD, 0, 0],
+            2,
+            RastaConnectionState::Up,
+            Duration::from_millis(500),
+        );
+        report.peer.replace(1);
+        let log = IncidentLog::default();
+        log.record(report);
+        let json = log.latest().unwrap().to_json();
+        assert!(json.starts_with("{\"kind\":\"InvalidSequenceNumber\""));
+        assert!(json.contains("\"frame\":\"0004abcd\""));
+        assert!(json.contains("\"reason\":null,"));
+        assert!(json.contains("\"peer\":1,"));
+        assert!(json.contains("\"expected_sequence_number\":null"));
+        assert!(json.ends_with("\"t_max_ms\":500}}"));
+        assert_eq!(log.take().len(), 1);
+        assert!(log.is_empty());
+
+        let mut report = IncidentReport::new(
+            IncidentKind::PeerClosed,
+            &[],
+            2,
+            RastaConnectionState::Up,
+            Duration::from_millis(500),
+        );
+        report.reason.replace("said \"bye\"\n".to_string());
+        assert!(report
+            .to_json()
+            .contains(r#""reason":"said \"bye\"\u000a","#));
+    }
+}
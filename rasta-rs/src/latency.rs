@@ -0,0 +1,164 @@
+//! Measurement of acknowledgement latencies: the time between sending a
+//! message and receiving the first message of the peer that confirms its
+//! sequence number. The most recent samples are kept per connection, so
+//! that percentiles can be reported to demonstrate that confirmations
+//! arrive well within `t_max`.
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// The number of samples kept for computing percentiles.
+/// Older samples are dropped first.
+pub const MAX_LATENCY_SAMPLES: usize = 1024;
+
+/// The latencies of a connection at one point in time.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AckLatencySnapshot {
+    /// The number of confirmed messages since the connection was created.
+    pub confirmed: u64,
+    /// The number of sent messages that are not confirmed yet.
+    pub outstanding: usize,
+    pub p50: Option<Duration>,
+    pub p90: Option<Duration>,
+    pub p99: Option<Duration>,
+    pub max: Option<Duration>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    outstanding: VecDeque<(u32, Instant)>,
+    samples: VecDeque<Duration>,
+    confirmed: u64,
+}
+
+/// A shared handle to the acknowledgement latencies of a connection.
+/// Clones refer to the same measurements, so a handle can be polled
+/// while the connection is running on another thread.
+#[derive(Clone, Debug)]
+pub struct AckLatency {
+    inner: Arc<Mutex<State>>,
+    n_sendmax: usize,
+}
+
+impl AckLatency {
+    /// Measures a connection on which at most `n_sendmax` messages are
+    /// unconfirmed, see [`RastaConfig::n_sendmax`](crate::RastaConfig::n_sendmax).
+    pub fn new(n_sendmax: u16) -> Self {
+        Self {
+            inner: Arc::default(),
+            n_sendmax: usize::from(n_sendmax),
+        }
+    }
+
+    /// Records that the message with `sequence_number` was sent at `now`.
+    pub(crate) fn sent(&self, sequence_number: u32, now: Instant) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.outstanding.len() == self.n_sendmax {
+            inner.outstanding.pop_front();
+        }
+        inner.outstanding.push_back((sequence_number, now));
+    }
+
+    /// Records that the peer confirmed all messages up to and
+    /// including `confirmed_sequence_number`, received at `now`.
+    pub(crate) fn confirmed(&self, confirmed_sequence_number: u32, now: Instant) {
+        let mut inner = self.inner.lock().unwrap();
+        // Sequence numbers wrap around, so a message is confirmed if it
+        // lies in the half of the number space before the confirmation
+        while let Some(&(sequence_number, sent)) = inner.outstanding.front() {
+            if confirmed_sequence_number.wrapping_sub(sequence_number) > u32::MAX / 2 {
+                break;
+            }
+            inner.outstanding.pop_front();
+            if inner.samples.len() == MAX_LATENCY_SAMPLES {
+                inner.samples.pop_front();
+            }
+            inner.samples.push_back(now.saturating_duration_since(sent));
+            inner.confirmed += 1;
+        }
+    }
+
+    /// Forgets the outstanding messages, e.g. after the connection was
+    /// re-established. The samples are kept.
+    pub(crate) fn clear_outstanding(&self) {
+        self.inner.lock().unwrap().outstanding.clear();
+    }
+
+    /// The latency below which `percentile` percent of the recent
+    /// samples lie, or `None` if no message was confirmed yet.
+    pub fn percentile(&self, percentile: f64) -> Option<Duration> {
+        let mut samples: Vec<Duration> =
+            self.inner.lock().unwrap().samples.iter().copied().collect();
+        samples.sort_unstable();
+        nearest_rank(&samples, percentile)
+    }
+
+    pub fn snapshot(&self) -> AckLatencySnapshot {
+        let inner = self.inner.lock().unwrap();
+        let mut samples: Vec<Duration> = inner.samples.iter().copied().collect();
+        samples.sort_unstable();
+        AckLatencySnapshot {
+            confirmed: inner.confirmed,
+            outstanding: inner.outstanding.len(),
+            p50: nearest_rank(&samples, 50.0),
+            p90: nearest_rank(&samples, 90.0),
+            p99: nearest_rank(&samples, 99.0),
+            max: samples.last().copied(),
+        }
+    }
+
+    /// Discards all samples and outstanding messages.
+    pub fn reset(&self) {
+        *self.inner.lock().unwrap() = State::default();
+    }
+}
+
+fn nearest_rank(sorted: &[Duration], percentile: f64) -> Option<Duration> {
+    let rank = (percentile.clamp(0.0, 100.0) / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted.get(rank.max(1) - 1).copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::AckLatency;
+
+    #[test]
+    fn test_ack_latency_percentiles() {
+        let latency = AckLatency::new(4);
+        assert_eq!(latency.percentile(50.0), None);
+        let now = Instant::now();
+        for sequence_number in [u32::MAX - 1, u32::MAX, 0, 1] {
+            latency.sent(sequence_number, now);
+        }
+        latency.confirmed(u32::MAX, now + Duration::from_millis(10));
+        assert_eq!(latency.snapshot().outstanding, 2);
+        latency.confirmed(1, now + Duration::from_millis(30));
+
+        let snapshot = latency.snapshot();
+        assert_eq!(snapshot.confirmed, 4);
+        assert_eq!(snapshot.outstanding, 0);
+        assert_eq!(snapshot.p50, Some(Duration::from_millis(10)));
+        assert_eq!(snapshot.p99, Some(Duration::from_millis(30)));
+        assert_eq!(snapshot.p99, snapshot.max);
+        latency.reset();
+        assert_eq!(latency.snapshot().confirmed, 0);
+    }
+
+    #[test]
+    fn test_ack_latency_n_sendmax() {
+        let latency = AckLatency::new(2);
+        let now = Instant::now();
+        for sequence_number in 0..3 {
+            latency.sent(sequence_number, now);
+        }
+        assert_eq!(latency.snapshot().outstanding, 2);
+        // The oldest message is no longer tracked
+        latency.confirmed(2, now);
+        assert_eq!(latency.snapshot().confirmed, 2);
+    }
+}
@@ -1,12 +1,21 @@
 //! # rasta-rs
 //!
 //! A simplified implementation of the Rail Safe Transport Application Protocol (RaSTA) in Rust.
-//! This implementation only provides very basic functionality, no redundancy and no
-//! explicit retransmission (since it is TCP-based).
+//! This implementation only provides very basic functionality. Sent data
+//! messages are kept until the peer confirms them and are retransmitted
+//! when the peer requests it (RetrReq/RetrResp). At most as many messages
+//! as the peer's N_SENDMAX and [`config::MemoryBudget::unconfirmed_messages`]
+//! allow may be unconfirmed; sending more fails with
+//! [`RastaError::BufferFull`] until the peer confirms some, so every
+//! requested message can be retransmitted. The redundancy layer is
+//! available separately for UDP, see [`redundancy`].
 //!
 //! ## Example - Sending:
 //!
-//! ```rust
+//! ```rust,no_run
+//! # use std::net::SocketAddrV4;
+//! # use rasta_rs::{RastaCommand, RastaConnection};
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
 //! let addr: SocketAddrV4 = "127.0.0.1:8888".parse()?;
 //! // Connect to receiver on localhost
 //! // using RaSTA ID 1234 for sender
@@ -26,11 +35,16 @@
 //!         RastaCommand::Wait
 //!     }
 //! })?;
+//! # Ok(())
+//! # }
 //! ```
 //!
 //! ## Example - Receiving:
 //!
-//! ```rust
+//! ```rust,no_run
+//! # use std::net::SocketAddrV4;
+//! # use rasta_rs::RastaListener;
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
 //! let addr: SocketAddrV4 = "127.0.0.1:8888".parse()?;
 //! // Listen on localhost with RaSTA ID 5678
 //! let mut conn = RastaListener::try_new(addr, 5678)?;
@@ -39,36 +53,262 @@
 //!     // Return Some() to respond with data to message
 //!     Some(vec![5, 6, 7, 8])
 //! })?;
+//! # Ok(())
+//! # }
 //! ```
 
-use message::{Message, MessageType, RastaId, RASTA_VERSION};
+#![cfg_attr(not(any(test, feature = "std")), no_std)]
 
+extern crate alloc;
+
+#[cfg(feature = "std")]
+use address::AddressErrors;
+#[cfg(feature = "std")]
+use clock::{Clock, SystemClock};
+#[cfg(feature = "std")]
+use descriptor::EndpointRole;
+#[cfg(feature = "std")]
+use events::{DisconnectReason, ListenerEvent, ListenerEventHook, ListenerEvents};
+#[cfg(feature = "std")]
+use incident::{IncidentKind, IncidentLog, IncidentReport};
+#[cfg(feature = "std")]
+use logging::event;
+use message::RastaId;
+#[cfg(feature = "std")]
+use message::{
+    DiscReason, Message, MessageType, PeerLimits, MAX_DATA_LENGTH, MESSAGE_BUFFER_LENGTH,
+    RASTA_VERSION,
+};
+
+// The endpoints and everything around them need `std`. Without it,
+// only the message codec and the safety codes are available.
+#[cfg(feature = "std")]
+pub mod address;
+#[cfg(feature = "std")]
+pub mod clock;
+#[cfg(feature = "std")]
+pub mod config;
+#[cfg(feature = "std")]
+pub mod descriptor;
+#[cfg(feature = "std")]
+pub mod diagram;
+#[cfg(feature = "std")]
+pub mod events;
+#[cfg(feature = "std")]
+mod framing;
+#[cfg(feature = "std")]
+pub mod incident;
+#[cfg(feature = "std")]
+pub mod latency;
+#[cfg(feature = "std")]
+mod logging;
 pub mod message;
+pub mod prelude;
+#[cfg(feature = "std")]
+pub mod redundancy;
+#[cfg(feature = "std")]
+pub mod ring;
+#[cfg(feature = "std")]
+pub mod runtime;
+pub mod safety;
+pub mod sequence;
+#[cfg(feature = "std")]
+pub mod shutdown;
+#[cfg(feature = "std")]
+pub mod socket;
+#[cfg(feature = "std")]
+mod sr;
+#[cfg(feature = "std")]
+pub mod step;
+#[cfg(feature = "std")]
+pub mod supervisor;
+#[cfg(feature = "tls")]
+pub mod tls;
+#[cfg(feature = "std")]
+pub mod trace;
+#[cfg(feature = "std")]
+mod transport;
+
+#[cfg(feature = "std")]
+pub use config::{OversizedSend, RastaConfig, UnknownMessagePolicy, WriteRetryPolicy};
+#[cfg(feature = "std")]
+pub use descriptor::EndpointDescriptor;
 
+#[cfg(feature = "std")]
+use framing::FrameBuffer;
+#[cfg(feature = "std")]
+use latency::AckLatency;
+#[cfg(feature = "std")]
+use rasta_core::check_length;
+use rasta_core::LengthError;
+#[cfg(feature = "std")]
+use ring::{RingBuffer, RingBufferMetrics};
+#[cfg(feature = "std")]
+use safety::SafetyCode;
+#[cfg(feature = "std")]
+use shutdown::ShutdownHandle;
+#[cfg(feature = "std")]
+use sr::{Received, SrState};
+#[cfg(feature = "std")]
+use trace::TraceControl;
+#[cfg(feature = "std")]
+use transport::Transport;
+
+use alloc::{boxed::Box, string::String};
+#[cfg(feature = "std")]
 use std::{
-    io::{ErrorKind, Read, Write},
-    net::{TcpListener, TcpStream, ToSocketAddrs},
+    collections::VecDeque,
+    io::{self, BufWriter, ErrorKind, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc, Mutex,
+    },
     time::{Duration, Instant},
 };
 
 #[cfg(feature = "wasi_sockets")]
 use std::os::wasi::io::FromRawFd;
 
-/// The maximum number of messages in a [`RastaConnection`] or [`RastaListener`] buffer.
+/// The default maximum number of messages in a [`RastaConnection`] or [`RastaListener`] buffer,
+/// see [`RastaConfig::n_sendmax`].
+#[cfg(feature = "std")]
 pub const N_SENDMAX: u16 = u16::MAX;
-/// The timeout duration for messages between a [`RastaConnection`] and [`RastaListener`].
+/// The default timeout duration for messages between a [`RastaConnection`] and [`RastaListener`],
+/// see [`RastaConfig::t_max`].
+#[cfg(feature = "std")]
 pub const RASTA_TIMEOUT_DURATION: Duration = Duration::from_millis(500);
 
-#[derive(Debug)]
+#[derive(Debug, thiserror::Error)]
 pub enum RastaError {
+    #[error("Invalid sequence number")]
     InvalidSeqNr,
+    #[error("Invalid confirmed sequence number")]
+    InvalidConfirmedSeqNr,
+    #[error("Operation not allowed in the current connection state")]
     StateError,
+    #[error("Timeout")]
     Timeout,
+    #[error("RaSTA version mismatch")]
     VersionMismatch,
-    IOError(std::io::Error),
+    #[error("Unknown message type {0}")]
+    UnknownMessageType(u16),
+    #[error("Message of {length} bytes exceeds the maximum of {max} bytes")]
+    MessageTooLong { length: usize, max: usize },
+    #[error("Data of {length} bytes exceeds the maximum of {max} bytes accepted by peer {peer}")]
+    PeerLimitExceeded {
+        length: usize,
+        max: usize,
+        peer: RastaId,
+    },
+    #[error("The {buffer} is full ({capacity} entries)")]
+    BufferFull {
+        buffer: &'static str,
+        capacity: usize,
+    },
+    #[error("Buffers need {required} bytes, exceeding the memory budget of {budget} bytes")]
+    MemoryBudgetExceeded { required: usize, budget: usize },
+    #[error("Keeping {capacity} unconfirmed messages is less than N_SENDMAX ({n_sendmax})")]
+    RetransmissionBufferTooSmall { capacity: usize, n_sendmax: u16 },
+    #[error("Message of {length} bytes is shorter than the minimum of {min} bytes")]
+    MessageTooShort { length: usize, min: usize },
+    #[error("Safety code mismatch")]
+    SafetyCodeMismatch,
+    #[cfg(feature = "std")]
+    #[error("I/O error: {0}")]
+    IOError(#[source] std::io::Error),
+    #[cfg(feature = "std")]
+    #[error("Could not resolve address: {0}")]
+    Resolve(#[source] std::io::Error),
+    #[cfg(feature = "std")]
+    #[error("Could not connect to any address: {0}")]
+    Connect(AddressErrors),
+    #[cfg(feature = "std")]
+    #[error("Could not bind to any address: {0}")]
+    Bind(AddressErrors),
+    #[cfg(feature = "tls")]
+    #[error("TLS error: {0}")]
+    Tls(#[from] rustls::Error),
+    /// An error of a protocol layer on top of RaSTA, such as SCI.
+    /// The error of that layer is kept as the source, so that it can
+    /// be inspected with [`core::error::Error::source`] and downcast.
+    #[error("{message}")]
+    Application {
+        message: String,
+        recovery: RecoveryAction,
+        #[source]
+        source: Option<Box<dyn core::error::Error + Send + Sync>>,
+    },
+    #[error("{0}")]
     Other(String),
 }
 
+impl RastaError {
+    /// The suggested reaction of the application to this error.
+    pub fn recovery_action(&self) -> RecoveryAction {
+        match self {
+            Self::MessageTooShort { .. } | Self::UnknownMessageType(_) => RecoveryAction::Retry,
+            #[cfg(feature = "std")]
+            Self::IOError(e)
+                if matches!(e.kind(), ErrorKind::Interrupted | ErrorKind::WouldBlock) =>
+            {
+                RecoveryAction::Retry
+            }
+            // A wrong safety code means the peers disagree on the key
+            // or the stream is corrupted, neither of which passes by itself
+            Self::SafetyCodeMismatch
+            | Self::InvalidSeqNr
+            | Self::InvalidConfirmedSeqNr
+            | Self::StateError
+            | Self::BufferFull { .. }
+            | Self::Timeout => RecoveryAction::Reconnect,
+            #[cfg(feature = "std")]
+            Self::IOError(_) | Self::Resolve(_) | Self::Connect(_) => RecoveryAction::Reconnect,
+            Self::Application { recovery, .. } => *recovery,
+            // Oversized data is rejected however often it is sent again
+            Self::MessageTooLong { .. }
+            | Self::PeerLimitExceeded { .. }
+            | Self::VersionMismatch
+            | Self::MemoryBudgetExceeded { .. }
+            | Self::RetransmissionBufferTooSmall { .. }
+            | Self::Other(_) => RecoveryAction::FailSafe,
+            #[cfg(feature = "std")]
+            Self::Bind(_) => RecoveryAction::FailSafe,
+            #[cfg(feature = "tls")]
+            Self::Tls(_) => RecoveryAction::FailSafe,
+        }
+    }
+}
+
+/// What the application should do after an operation failed,
+/// see [`RastaError::recovery_action`]. Actions are ordered by
+/// severity, so the most drastic of several suggestions is their maximum.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RecoveryAction {
+    /// Only the failed operation was affected. It may be repeated
+    /// on the same connection.
+    Retry,
+    /// The protocol layer on top of the connection has to start over,
+    /// e.g. by re-initialising the PDI of an SCI connection.
+    ReinitialisePdi,
+    /// The connection is unusable and has to be re-established.
+    Reconnect,
+    /// Reconnecting is not expected to help. The application
+    /// should enter its fail-safe state.
+    FailSafe,
+}
+
+impl From<LengthError> for RastaError {
+    fn from(value: LengthError) -> Self {
+        match value {
+            LengthError::TooShort { length, min } => Self::MessageTooShort { length, min },
+            LengthError::TooLong { length, max } => Self::MessageTooLong { length, max },
+        }
+    }
+}
+
+#[cfg(feature = "std")]
 impl From<std::io::Error> for RastaError {
     fn from(value: std::io::Error) -> Self {
         match value.kind() {
@@ -79,7 +319,9 @@ impl From<std::io::Error> for RastaError {
 }
 
 /// The State of a RaSTA connection as defined in the specification.
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum RastaConnectionState {
     Closed,
     Down,
@@ -87,9 +329,21 @@ pub enum RastaConnectionState {
     Up,
 }
 
+#[cfg(feature = "std")]
+state_transitions! {
+    /// The state changes of a [`RastaConnection`].
+    pub const SR_TRANSITIONS: [RastaConnectionState] = [
+        Down => Up on "ConnResp",
+        Closed => Up on "ConnResp",
+        Down => Closed on "DiscReq",
+        Up => Closed on "DiscReq / close_connection / protocol error",
+    ];
+}
+
 /// The control flow in a RaSTA connection.
 /// Determines which messages a [`RastaConnection`]
 /// should send.
+#[cfg(feature = "std")]
 pub enum RastaCommand<D: AsRef<[u8]>> {
     /// Send a data messages constructed from the passed buffer.
     Data(D),
@@ -105,176 +359,800 @@ pub enum RastaCommand<D: AsRef<[u8]>> {
 /// can manage the connection yourself. If you want to do this,
 /// look at the implementation of [`RastaListener::listen`] for
 /// inspiration.
+#[cfg(feature = "std")]
 pub struct RastaListener {
     listener: TcpListener,
     connections: Vec<RastaId>,
     id: RastaId,
-    seq_nr: Option<u32>,
     last_message_timestamp: Option<Instant>,
+    config: RastaConfig,
+    receive_metrics: RingBufferMetrics,
+    trace: TraceControl,
+    shutdown: Arc<AtomicBool>,
+    clock: Arc<dyn Clock>,
+    incidents: IncidentLog,
+    ack_latency: AckLatency,
+    /// Receives the events of [`RastaListener::events`].
+    events: Option<mpsc::Sender<ListenerEvent>>,
+    event_hook: Option<ListenerEventHook>,
 }
 
+#[cfg(feature = "std")]
 impl RastaListener {
     pub fn try_new<S: ToSocketAddrs>(addr: S, id: RastaId) -> Result<Self, RastaError> {
+        Self::try_new_with_config(addr, id, RastaConfig::default())
+    }
+
+    pub fn try_new_with_config<S: ToSocketAddrs>(
+        addr: S,
+        id: RastaId,
+        config: RastaConfig,
+    ) -> Result<Self, RastaError> {
+        config.check_memory_budget()?;
         #[cfg(feature = "wasi_sockets")]
         let listener = unsafe { TcpListener::from_raw_fd(3) };
         #[cfg(not(feature = "wasi_sockets"))]
-        let listener = TcpListener::bind(addr).map_err(RastaError::from)?;
+        let listener = address::bind(addr)?;
+        let ack_latency = AckLatency::new(config.n_sendmax);
         Ok(Self {
             listener,
             connections: Vec::new(),
             id,
-            seq_nr: None,
             last_message_timestamp: None,
+            config,
+            receive_metrics: RingBufferMetrics::default(),
+            trace: TraceControl::default(),
+            shutdown: Arc::new(AtomicBool::new(false)),
+            clock: Arc::new(SystemClock),
+            incidents: IncidentLog::default(),
+            ack_latency,
+            events: None,
+            event_hook: None,
         })
     }
 
+    /// Replaces the source of the timestamps of outgoing messages
+    /// and of the time T_max and ticks are measured with.
+    pub fn set_clock<C: Clock + 'static>(&mut self, clock: C) {
+        self.clock = Arc::new(clock);
+    }
+
+    pub fn config(&self) -> &RastaConfig {
+        &self.config
+    }
+
+    /// The RaSTA ID of this endpoint.
+    pub fn id(&self) -> RastaId {
+        self.id
+    }
+
     fn timestamp(&self) -> u32 {
-        std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_secs() as u32
+        self.clock.timestamp()
+    }
+
+    /// The address the listener is bound to, e.g. to learn
+    /// the port chosen by the system when binding to port 0.
+    pub fn local_addr(&self) -> Result<SocketAddr, RastaError> {
+        self.listener.local_addr().map_err(RastaError::from)
+    }
+
+    /// A snapshot of the addresses, IDs and timing parameters of the listener.
+    pub fn describe(&self) -> EndpointDescriptor {
+        EndpointDescriptor {
+            role: EndpointRole::Listener,
+            id: self.id,
+            peers: self.connections.clone(),
+            local_addr: self.listener.local_addr().ok(),
+            peer_addr: None,
+            state: None,
+            t_max: self.config.t_max,
+            heartbeat_interval: self.config.max_heartbeat_interval,
+            round_trip_time: None,
+        }
+    }
+
+    /// Fill level statistics of the receive buffer used by
+    /// [`RastaListener::listen_buffered`]. The returned handle stays
+    /// valid while the listener is running and can be polled from
+    /// another thread.
+    pub fn receive_buffer_metrics(&self) -> RingBufferMetrics {
+        self.receive_metrics.clone()
+    }
+
+    /// Controls the tracing of messages exchanged with the peers.
+    /// The returned handle can be used while the listener is running.
+    pub fn trace(&self) -> TraceControl {
+        self.trace.clone()
+    }
+
+    /// A handle to stop the listener from another thread. The listening
+    /// methods return `Ok(())` once the shutdown is complete.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        ShutdownHandle::new(self.shutdown.clone(), self.listener.local_addr().ok())
+    }
+
+    fn is_shutting_down(&self) -> bool {
+        self.shutdown.load(Ordering::Relaxed)
+    }
+
+    /// Calls `hook` with the connection events of the peers
+    /// ([`ListenerEvent::ConnectionOpened`], [`ListenerEvent::Heartbeat`]
+    /// and [`ListenerEvent::Disconnected`]) while the listener is running,
+    /// e.g. to reset state that is kept per connection.
+    pub fn set_event_hook<H: FnMut(&ListenerEvent) + Send + 'static>(&mut self, hook: H) {
+        self.event_hook.replace(Box::new(hook));
+    }
+
+    fn notify(&mut self, event: ListenerEvent) {
+        if let Some(hook) = &mut self.event_hook {
+            hook(&event);
+        }
+        if let Some(events) = &self.events {
+            // No one may be listening anymore
+            let _ = events.send(event);
+        }
+    }
+
+    /// Forgets the connection to `peer` and notifies that it ended,
+    /// if it had been opened.
+    fn disconnected(&mut self, peer: &mut Option<RastaId>, reason: DisconnectReason) {
+        if let Some(peer) = peer.take() {
+            self.connections.retain(|c| *c != peer);
+            self.notify(ListenerEvent::Disconnected(peer, reason));
+        }
+    }
+
+    /// The reports of protocol violations that made the listener
+    /// drop a connection. The returned handle can be polled while
+    /// the listener is running.
+    pub fn incidents(&self) -> IncidentLog {
+        self.incidents.clone()
+    }
+
+    /// The time the current peer takes to confirm the sequence numbers
+    /// of sent messages. The measurements start over for every accepted
+    /// connection. The returned handle can be polled while the listener
+    /// is running.
+    pub fn ack_latency(&self) -> AckLatency {
+        self.ack_latency.clone()
     }
 
-    pub fn listen<F, D>(&mut self, mut on_receive: F) -> Result<(), RastaError>
+    fn report_incident(&self, kind: IncidentKind, msg: &Message, sr: &SrState) {
+        let state = if self.connections.contains(&msg.sender()) {
+            RastaConnectionState::Up
+        } else {
+            RastaConnectionState::Down
+        };
+        let mut report = IncidentReport::new(kind, msg, self.id, state, self.config.t_max);
+        report.peer = Some(msg.sender());
+        report.expected_sequence_number = sr.window().map(|window| window.sent);
+        report.since_last_message = self
+            .last_message_timestamp
+            .map(|t| self.clock.now().saturating_duration_since(t));
+        report.ack_latency_p99 = self.ack_latency.percentile(99.0);
+        self.incidents.record(report);
+    }
+
+    /// Runs the listener on a separate thread and returns its events
+    /// instead of calling a closure. Data messages are answered with
+    /// heartbeats. The listener runs until it is shut down through
+    /// [`ListenerEvents::shutdown`] or a [`ShutdownHandle`] or fails.
+    pub fn events(mut self) -> ListenerEvents {
+        let (sender, receiver) = mpsc::channel();
+        self.events.replace(sender.clone());
+        let shutdown = self.shutdown_handle();
+        let thread = std::thread::spawn(move || {
+            self.listen(|msg| {
+                let _ = sender.send(ListenerEvent::Data(msg));
+                None::<Vec<u8>>
+            })
+        });
+        ListenerEvents::new(receiver, shutdown, thread)
+    }
+
+    /// Accepts connections and handles their messages. `on_receive` is called
+    /// for every data message and may return any number of responses
+    /// (e.g. an [`Option`] or a [`Vec`]). If it returns none, a heartbeat
+    /// is sent instead.
+    pub fn listen<F, I, D>(&mut self, mut on_receive: F) -> Result<(), RastaError>
+    where
+        F: FnMut(Message) -> I,
+        I: IntoIterator<Item = D>,
+        D: AsRef<[u8]>,
+    {
+        let trace = self.trace.clone();
+        let clock = self.clock.clone();
+        let safety_code = self.config.safety_code.clone();
+        self.serve(
+            |msg, writer| {
+                let responses = on_receive(msg);
+                let mut writer = writer.lock().unwrap();
+                write_responses(&mut *writer, &trace, &*clock, &*safety_code, responses)
+            },
+            None,
+            |_| Ok(()),
+        )
+    }
+
+    /// Like [`RastaListener::listen`], but additionally calls `on_tick`
+    /// at most once per `interval` while a peer is connected. Since the
+    /// listener only sends while processing messages of its peer, ticks
+    /// are interleaved with the handling of heartbeats and data. Data
+    /// returned by `on_tick` is sent to the peer as data messages,
+    /// allowing cyclic application behaviour on the listener side.
+    pub fn listen_with_tick<F, I, D, T, J, E>(
+        &mut self,
+        interval: Duration,
+        mut on_receive: F,
+        mut on_tick: T,
+    ) -> Result<(), RastaError>
+    where
+        F: FnMut(Message) -> I,
+        I: IntoIterator<Item = D>,
+        D: AsRef<[u8]>,
+        T: FnMut() -> J,
+        J: IntoIterator<Item = E>,
+        E: AsRef<[u8]>,
+    {
+        let trace = self.trace.clone();
+        let clock = self.clock.clone();
+        let safety_code = self.config.safety_code.clone();
+        self.serve(
+            |msg, writer| {
+                let responses = on_receive(msg);
+                let mut writer = writer.lock().unwrap();
+                write_responses(&mut *writer, &trace, &*clock, &*safety_code, responses)
+            },
+            Some(interval),
+            |writer| {
+                let mut writer = writer.lock().unwrap();
+                write_data(&mut *writer, &trace, &*clock, &*safety_code, on_tick()).map(|_| ())
+            },
+        )
+    }
+
+    /// Like [`RastaListener::listen`], but `on_receive` runs on a separate
+    /// worker thread. The socket thread keeps answering heartbeats and
+    /// deposits data messages in a bounded buffer which the worker drains,
+    /// so bursts of data do not delay the protocol handling. The size of
+    /// the buffer and the behaviour when it is full are set with
+    /// [`RastaConfig::receive_buffer_capacity`] and
+    /// [`RastaConfig::overflow_policy`]. Messages that are still buffered
+    /// when the listener shuts down are passed to `on_receive` before
+    /// this method returns.
+    pub fn listen_buffered<F, I, D>(&mut self, mut on_receive: F) -> Result<(), RastaError>
     where
-        F: FnMut(Message) -> Option<D>,
+        F: FnMut(Message) -> I + Send,
+        I: IntoIterator<Item = D>,
         D: AsRef<[u8]>,
     {
-        for conn in self.listener.incoming() {
+        let trace = self.trace.clone();
+        let clock = self.clock.clone();
+        let safety_code = self.config.safety_code.clone();
+        let ring: RingBuffer<(Message, SharedWriter)> = RingBuffer::new(
+            self.config.receive_buffer_capacity,
+            self.config.overflow_policy,
+            self.receive_metrics.clone(),
+        );
+        std::thread::scope(|s| {
+            let worker = s.spawn(|| {
+                let result = (|| {
+                    while let Some((msg, writer)) = ring.pop() {
+                        let responses = on_receive(msg);
+                        let mut writer = writer.lock().unwrap();
+                        write_responses(&mut *writer, &trace, &*clock, &*safety_code, responses)?;
+                        writer.flush().map_err(RastaError::from)?;
+                    }
+                    Ok(())
+                })();
+                // Make the socket thread notice that no one is draining the buffer
+                ring.close();
+                let unprocessed = ring.drain();
+                if !unprocessed.is_empty() {
+                    event!(
+                        warn,
+                        count = unprocessed.len(),
+                        "Receive worker stopped with buffered messages"
+                    );
+                }
+                result
+            });
+            let result = self.serve(
+                |msg, writer| {
+                    ring.push((msg, writer.clone()))
+                        .map_err(|_| RastaError::Other("Receive worker stopped".to_string()))
+                },
+                None,
+                |_| Ok(()),
+            );
+            ring.close();
+            let worker_result = worker.join().unwrap();
+            worker_result.and(result)
+        })
+    }
+
+    /// The connection handling shared by [`RastaListener::listen`] and
+    /// [`RastaListener::listen_buffered`]. Protocol messages are answered
+    /// directly, data messages are passed to `on_data` together with the
+    /// writer of their connection. If `tick_interval` is set, `on_tick` is
+    /// called after handling a message of a connected peer whenever the
+    /// interval has passed.
+    fn serve<H, T>(
+        &mut self,
+        mut on_data: H,
+        tick_interval: Option<Duration>,
+        mut on_tick: T,
+    ) -> Result<(), RastaError>
+    where
+        H: FnMut(Message, &SharedWriter) -> Result<(), RastaError>,
+        T: FnMut(&SharedWriter) -> Result<(), RastaError>,
+    {
+        let safety_code = self.config.safety_code.clone();
+        loop {
+            let conn = self.listener.accept().map(|(conn, _)| conn);
             if let Err(e) = &conn {
                 if e.kind() == ErrorKind::WouldBlock {
                     continue;
                 }
             }
-            let mut conn = conn.map_err(RastaError::from)?;
+            let conn = conn.map_err(RastaError::from)?;
+            if self.is_shutting_down() {
+                break;
+            }
+            #[cfg(not(feature = "wasi_sockets"))]
+            conn.set_read_timeout(Some(self.config.t_max))
+                .map_err(RastaError::from)?;
             #[cfg(not(feature = "wasi_sockets"))]
-            conn.set_read_timeout(Some(RASTA_TIMEOUT_DURATION))
+            conn.set_nodelay(self.config.nodelay)
                 .map_err(RastaError::from)?;
             #[cfg(not(feature = "wasi_sockets"))]
-            println!(
-                "New connection: {}",
-                conn.peer_addr().map_err(RastaError::from)?
+            self.config.socket.apply(&conn).map_err(RastaError::from)?;
+            #[cfg(feature = "tracing")]
+            let _span = tracing::info_span!(
+                "rasta_listener",
+                id = self.id,
+                peer_addr = ?conn.peer_addr().ok()
+            )
+            .entered();
+            event!(info, "Accepted connection");
+            let conn = Transport::server(conn, &self.config)?;
+            // Plain TCP is read through a separate handle,
+            // so writing responses never waits for a read
+            let mut reader = conn.try_clone_reader();
+            let probe = conn.tcp().try_clone().map_err(RastaError::from)?;
+            // All responses to a message are written at once
+            let sr = SrState::new(
+                self.id,
+                self.config.n_sendmax,
+                self.config.memory.unconfirmed_messages,
             );
-            #[cfg(feature = "wasi_sockets")]
-            println!("New connection!");
+            self.ack_latency.reset();
+            let writer: SharedWriter = Arc::new(Mutex::new(ListenerWriter::new(
+                conn,
+                sr,
+                self.ack_latency.clone(),
+            )));
+            let mut last_tick = self.clock.now();
+            self.last_message_timestamp = None;
+            let mut frames = FrameBuffer::default();
+            // The peer that opened a connection, for the events
+            let mut peer = None;
             loop {
-                let mut buf = vec![0; 1024];
-                let conn_result = conn.read(&mut buf);
-                if conn_result.is_err() {
-                    let c = self.connections.pop();
-                    println!("Client {} unexpectedly disconnected", c.unwrap());
-                    self.seq_nr = None;
-                    break;
-                } else if conn_result.as_ref().unwrap() == &0 {
-                    println!("Invalid message received - aborting connection");
-                    self.seq_nr = None;
+                if self.is_shutting_down() {
+                    // Tell a connected peer instead of letting it time out.
+                    // The peer may already be gone, so this is best-effort
+                    let mut writer = writer.lock().unwrap();
+                    if peer.is_some() {
+                        let response = writer
+                            .sr
+                            .disconnection_request(self.timestamp(), DiscReason::UserRequest);
+                        match writer.send(&self.trace, &*self.clock, &*safety_code, response) {
+                            Ok(_) => {}
+                            #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+                            Err(e) => {
+                                event!(warn, error = %e, "Could not send disconnection request");
+                            }
+                        }
+                    }
+                    writer.sr.reset();
+                    self.disconnected(&mut peer, DisconnectReason::Shutdown);
+                    match writer.flush() {
+                        Ok(_) => {}
+                        #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+                        Err(e) => {
+                            event!(warn, error = %e, "Could not flush disconnection request");
+                        }
+                    }
+                    match writer.get_mut().close(self.config.shutdown_grace_period) {
+                        Ok(_) => {}
+                        #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+                        Err(e) => {
+                            event!(warn, error = %e, "Could not close connection");
+                        }
+                    }
                     break;
                 }
-                let msg = Message::from(&buf[..conn_result.unwrap()]);
-                dbg!(msg.message_type());
-                dbg!(msg.sender());
-                dbg!(msg.receiver());
-                dbg!(msg.sequence_number());
-                dbg!(msg.confirmed_sequence_number());
-                dbg!(self.seq_nr);
-                if self.seq_nr.is_some() && msg.confirmed_sequence_number() != self.seq_nr.unwrap()
+                writer.lock().unwrap().flush().map_err(RastaError::from)?;
+                // A read may return several frames, so all of them
+                // are processed before reading again
+                let Some(frame) = frames.next_frame() else {
+                    let mut buf = vec![0; MESSAGE_BUFFER_LENGTH];
+                    let read = match reader.as_mut() {
+                        Some(reader) => reader.read(&mut buf),
+                        None => read_shared(&writer, &probe, &mut buf),
+                    };
+                    match read {
+                        Ok(0) => {
+                            event!(info, "Connection closed by the peer");
+                            writer.lock().unwrap().sr.reset();
+                            self.disconnected(&mut peer, DisconnectReason::Closed);
+                            break;
+                        }
+                        Ok(n) => frames.extend(&buf[..n]),
+                        Err(_) if self.is_shutting_down() => {}
+                        Err(_) => {
+                            event!(warn, ?peer, "Peer unexpectedly disconnected");
+                            writer.lock().unwrap().sr.reset();
+                            self.disconnected(&mut peer, DisconnectReason::Lost);
+                            break;
+                        }
+                    }
+                    continue;
+                };
+                let mut msg = match Message::try_parse_passthrough(&frame)
+                    .and_then(|msg| msg.verify(&*safety_code).map(|_| msg))
                 {
-                    dbg!(msg.confirmed_sequence_number(), self.seq_nr.unwrap());
-                    return Err(RastaError::InvalidSeqNr);
-                }
-                if self.last_message_timestamp.is_some()
-                    && Instant::now().duration_since(self.last_message_timestamp.unwrap())
-                        > RASTA_TIMEOUT_DURATION
+                    Ok(msg) => msg,
+                    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+                    Err(e) => {
+                        event!(warn, error = %e, "Dropping malformed message");
+                        continue;
+                    }
+                };
+                self.trace.incoming(&msg);
+                msg.retransmitted_to_data();
+                let now = self.clock.now();
+                if self
+                    .last_message_timestamp
+                    .is_some_and(|last| now.saturating_duration_since(last) > self.config.t_max)
                 {
-                    let response = Message::disconnection_request(
-                        msg.sender(),
-                        msg.receiver(),
-                        msg.sequence_number() + 1,
-                        msg.sequence_number(),
-                        self.timestamp(),
-                        msg.timestamp(),
-                    );
-                    conn.write(&response).map_err(RastaError::from)?;
+                    event!(warn, peer = msg.sender(), "Peer exceeded T_max");
+                    let mut writer = writer.lock().unwrap();
+                    self.report_incident(IncidentKind::Timeout, &msg, &writer.sr);
+                    self.drop_peer(
+                        &mut writer,
+                        &mut peer,
+                        DiscReason::Timeout,
+                        DisconnectReason::Timeout,
+                    )?;
                     break;
                 }
-                self.seq_nr.replace(msg.sequence_number());
-                match msg.message_type() {
-                    MessageType::ConnReq => {
-                        let resp = Message::connection_response(
-                            msg.sender(),
-                            msg.receiver(),
-                            msg.sequence_number(),
-                            self.timestamp(),
-                            msg.timestamp(),
-                            N_SENDMAX,
+                // A connection request starts a new connection with its
+                // own sequence numbers, whatever came before it
+                let is_request = matches!(msg.try_message_type(), Ok(MessageType::ConnReq));
+                let mut guard = writer.lock().unwrap();
+                if !is_request && guard.sr.is_connected() {
+                    match guard.sr.receive(&msg) {
+                        Ok(Received::Accepted) => {}
+                        Ok(Received::Missing) => {
+                            event!(
+                                debug,
+                                peer = msg.sender(),
+                                received = msg.sequence_number(),
+                                "Requesting retransmission"
+                            );
+                            let request = guard.sr.retransmission_request(self.timestamp());
+                            guard.send(&self.trace, &*self.clock, &*safety_code, request)?;
+                            self.last_message_timestamp.replace(now);
+                            continue;
+                        }
+                        Ok(Received::Discarded) => {
+                            event!(
+                                warn,
+                                peer = msg.sender(),
+                                received = msg.sequence_number(),
+                                "Discarding message out of sequence"
+                            );
+                            continue;
+                        }
+                        #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+                        Err(e) => {
+                            event!(
+                                warn,
+                                peer = msg.sender(),
+                                received = msg.sequence_number(),
+                                confirmed = msg.confirmed_sequence_number(),
+                                error = %e,
+                                "Invalid sequence number"
+                            );
+                            self.report_incident(
+                                IncidentKind::InvalidSequenceNumber,
+                                &msg,
+                                &guard.sr,
+                            );
+                            // Only this peer is dropped, the listener keeps serving
+                            self.drop_peer(
+                                &mut guard,
+                                &mut peer,
+                                DiscReason::SequenceNumberError,
+                                DisconnectReason::InvalidSequenceNumber,
+                            )?;
+                            break;
+                        }
+                    }
+                    guard
+                        .latency
+                        .confirmed(msg.confirmed_sequence_number(), now);
+                }
+                drop(guard);
+                self.last_message_timestamp.replace(now);
+                let sender = msg.sender();
+                match msg.try_message_type() {
+                    Ok(MessageType::ConnReq) if msg.data().get(..4) != Some(&RASTA_VERSION[..]) => {
+                        event!(
+                            warn,
+                            peer = msg.sender(),
+                            "Rejecting connection with incompatible version"
                         );
-                        conn.write(&resp).map_err(RastaError::from)?;
-                        self.seq_nr.replace(msg.sequence_number() + 1);
-                        self.connections.push(msg.sender());
+                        let mut writer = writer.lock().unwrap();
+                        self.report_incident(IncidentKind::VersionMismatch, &msg, &writer.sr);
+                        // The connection response is never sent
+                        writer.sr.accept(&msg, self.timestamp());
+                        self.drop_peer(
+                            &mut writer,
+                            &mut peer,
+                            DiscReason::ProtocolVersionError,
+                            DisconnectReason::VersionMismatch,
+                        )?;
+                        break;
                     }
-                    MessageType::ConnResp => {
+                    Ok(MessageType::ConnReq) => {
+                        let mut writer = writer.lock().unwrap();
+                        let mut resp = writer.sr.accept(&msg, self.timestamp());
+                        resp.advertise_max_data_length(self.config.max_data_length);
+                        writer.send(&self.trace, &*self.clock, &*safety_code, resp)?;
+                        if !self.connections.contains(&sender) {
+                            self.connections.push(sender);
+                        }
+                        event!(info, peer = sender, "Connection established");
+                        peer.replace(sender);
+                        self.notify(ListenerEvent::ConnectionOpened(sender));
+                    }
+                    Ok(MessageType::ConnResp) => {
                         //Ignore
                     }
-                    MessageType::RetrReq => unimplemented!("Handled by TCP"),
-                    MessageType::RetrResp => unimplemented!("Handled by TCP"),
-                    MessageType::DiscReq => {
-                        if let Some(idx) = self.connections.iter().position(|c| *c == msg.sender())
-                        {
-                            self.connections.remove(idx);
+                    Ok(MessageType::RetrReq) => {
+                        if self.connections.contains(&sender) {
+                            event!(debug, peer = sender, "Retransmission request");
+                            let mut writer = writer.lock().unwrap();
+                            for response in writer.sr.retransmission(self.timestamp())? {
+                                writer.send(&self.trace, &*self.clock, &*safety_code, response)?;
+                            }
+                        }
+                    }
+                    Ok(MessageType::RetrResp) => {
+                        // The retransmitted messages follow and are checked as usual
+                    }
+                    Ok(MessageType::DiscReq) => {
+                        if self.connections.contains(&sender) {
+                            event!(
+                                info,
+                                peer = sender,
+                                reason = ?msg.disconnect_reason(),
+                                "Disconnection requested by the peer"
+                            );
+                            writer.lock().unwrap().sr.reset();
+                            self.disconnected(&mut peer, DisconnectReason::PeerRequest);
                             break;
                         }
                     }
-                    MessageType::HB => {
-                        if self.connections.contains(&msg.sender()) {
-                            println!("Heartbeat from {}", msg.sender());
-                            self.seq_nr.replace(msg.sequence_number() + 1);
-                            let response = Message::heartbeat(
-                                msg.sender(),
-                                msg.receiver(),
-                                self.seq_nr.unwrap(),
-                                msg.sequence_number(),
-                                self.timestamp(),
-                                msg.timestamp(),
+                    Ok(MessageType::HB) => {
+                        if self.connections.contains(&sender) {
+                            event!(
+                                trace,
+                                peer = sender,
+                                seq = msg.sequence_number(),
+                                "Heartbeat"
+                            );
+                            let mut writer = writer.lock().unwrap();
+                            let response = writer.sr.heartbeat(self.timestamp());
+                            writer.send(&self.trace, &*self.clock, &*safety_code, response)?;
+                            self.notify(ListenerEvent::Heartbeat(sender));
+                        }
+                    }
+                    Ok(MessageType::Data) => {
+                        if self.connections.contains(&sender) {
+                            event!(
+                                trace,
+                                peer = sender,
+                                seq = msg.sequence_number(),
+                                len = msg.data().len(),
+                                "Received data"
                             );
-                            conn.write(&response).map_err(RastaError::from)?;
+                            on_data(msg, &writer)?;
                         }
                     }
-                    MessageType::Data => {
-                        if self.connections.contains(&msg.sender()) {
-                            println!("Received data from {}", msg.sender());
-                            let seq_nr = msg.sequence_number();
-                            let receiver = msg.sender();
-                            let timestamp = msg.timestamp();
-                            let response = if let Some(data) = (on_receive)(msg) {
-                                Message::data_message(
-                                    receiver,
-                                    self.id,
-                                    self.seq_nr.unwrap(),
-                                    seq_nr,
-                                    self.timestamp(),
-                                    timestamp,
-                                    data.as_ref(),
-                                )
-                            } else {
-                                Message::heartbeat(
-                                    receiver,
-                                    self.id,
-                                    self.seq_nr.unwrap(),
-                                    seq_nr,
-                                    self.timestamp(),
-                                    timestamp,
-                                )
-                            };
-
-                            conn.write(&response).map_err(RastaError::from)?;
+                    // Converted to data messages above
+                    Ok(MessageType::RetrData) => {}
+                    Err(_) if self.config.unknown_messages == UnknownMessagePolicy::Forward => {
+                        if self.connections.contains(&sender) {
+                            on_data(msg, &writer)?;
                         }
                     }
-                    MessageType::RetrData => unimplemented!("Handled by TCP"),
+                    Err(_) => {
+                        event!(
+                            debug,
+                            peer = sender,
+                            message_type = msg.message_type_raw(),
+                            "Dropping message of unknown type"
+                        );
+                    }
+                }
+                if let Some(interval) = tick_interval {
+                    let now = self.clock.now();
+                    if peer.is_some() && now.saturating_duration_since(last_tick) >= interval {
+                        last_tick = now;
+                        on_tick(&writer)?;
+                    }
                 }
             }
+            if self.is_shutting_down() {
+                break;
+            }
         }
         Ok(())
     }
+
+    /// Closes the connection of `peer` with a disconnection request
+    /// giving `reason`, so that the listener can accept the next one.
+    fn drop_peer(
+        &mut self,
+        writer: &mut ListenerWriter,
+        peer: &mut Option<RastaId>,
+        reason: DiscReason,
+        event_reason: DisconnectReason,
+    ) -> Result<(), RastaError> {
+        let response = writer.sr.disconnection_request(self.timestamp(), reason);
+        writer.send(
+            &self.trace,
+            &*self.clock,
+            &*self.config.safety_code,
+            response,
+        )?;
+        writer.flush().map_err(RastaError::from)?;
+        writer.sr.reset();
+        self.disconnected(peer, event_reason);
+        Ok(())
+    }
+}
+
+/// The writing half of a connection accepted by a [`RastaListener`].
+/// It is shared between the socket thread and the worker of
+/// [`RastaListener::listen_buffered`].
+#[cfg(feature = "std")]
+type SharedWriter = Arc<Mutex<ListenerWriter>>;
+
+/// Buffers the responses of a listener together with the state of the
+/// connection they belong to. Every message to the peer is numbered by
+/// the same [`SrState`], no matter which path sends it.
+#[cfg(feature = "std")]
+struct ListenerWriter<T: Write = Transport> {
+    writer: BufWriter<T>,
+    sr: SrState,
+    latency: AckLatency,
+}
+
+#[cfg(feature = "std")]
+impl<T: Write> ListenerWriter<T> {
+    fn new(transport: T, sr: SrState, latency: AckLatency) -> Self {
+        Self {
+            writer: BufWriter::new(transport),
+            sr,
+            latency,
+        }
+    }
+
+    /// Writes `msg` like [`write_sealed`] and records the time
+    /// it was sent on `clock` for the acknowledgement latency.
+    fn send(
+        &mut self,
+        trace: &TraceControl,
+        clock: &dyn Clock,
+        safety_code: &dyn SafetyCode,
+        msg: Message,
+    ) -> Result<(), RastaError> {
+        self.latency.sent(msg.sequence_number(), clock.now());
+        write_sealed(&mut self.writer, trace, safety_code, msg)
+    }
+
+    fn get_mut(&mut self) -> &mut T {
+        self.writer.get_mut()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: Write> Write for ListenerWriter<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Reads from a transport that cannot be read independently of writing.
+/// Waits for data on `probe` without holding the lock, so that the worker
+/// of [`RastaListener::listen_buffered`] can respond in the meantime.
+#[cfg(feature = "std")]
+fn read_shared(writer: &SharedWriter, probe: &TcpStream, buf: &mut [u8]) -> io::Result<usize> {
+    if !writer.lock().unwrap().get_mut().has_buffered_data() && probe.peek(&mut [0])? == 0 {
+        return Ok(0);
+    }
+    writer.lock().unwrap().get_mut().read(buf)
+}
+
+/// Answers a data message of the peer with `responses`,
+/// or with a heartbeat if there are none.
+#[cfg(feature = "std")]
+fn write_responses<W, I, D>(
+    writer: &mut ListenerWriter<W>,
+    trace: &TraceControl,
+    clock: &dyn Clock,
+    safety_code: &dyn SafetyCode,
+    responses: I,
+) -> Result<(), RastaError>
+where
+    W: Write,
+    I: IntoIterator<Item = D>,
+    D: AsRef<[u8]>,
+{
+    if write_data(writer, trace, clock, safety_code, responses)? == 0 {
+        let response = writer.sr.heartbeat(clock.timestamp());
+        writer.send(trace, clock, safety_code, response)?;
+    }
+    Ok(())
+}
+
+/// Sends `data` to the peer as data messages
+/// and returns the number of messages written.
+#[cfg(feature = "std")]
+fn write_data<W, I, D>(
+    writer: &mut ListenerWriter<W>,
+    trace: &TraceControl,
+    clock: &dyn Clock,
+    safety_code: &dyn SafetyCode,
+    data: I,
+) -> Result<u32, RastaError>
+where
+    W: Write,
+    I: IntoIterator<Item = D>,
+    D: AsRef<[u8]>,
+{
+    let mut count = 0;
+    for data in data {
+        let response = writer.sr.data(clock.timestamp(), data.as_ref())?;
+        writer.send(trace, clock, safety_code, response)?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Writes `msg` after computing its safety code. Peers split the stream
+/// into frames of [`MESSAGE_BUFFER_LENGTH`] bytes, so a message of any
+/// other size is rejected before anything is written.
+#[cfg(feature = "std")]
+fn write_sealed<W: Write>(
+    writer: &mut W,
+    trace: &TraceControl,
+    safety_code: &dyn SafetyCode,
+    mut msg: Message,
+) -> Result<(), RastaError> {
+    check_length(msg.len(), MESSAGE_BUFFER_LENGTH..=MESSAGE_BUFFER_LENGTH)?;
+    msg.seal(safety_code);
+    trace.outgoing(&msg);
+    writer.write_all(&msg).map_err(RastaError::from)?;
+    Ok(())
 }
 
 /// This type roughly corresponds to [`std::net::TcpStream`].
@@ -283,65 +1161,246 @@ impl RastaListener {
 /// can manage the connection yourself. If you want to do this,
 /// look at the implementation of [`RastaConnection::run`] for
 /// inspiration.
+///
+/// Outgoing messages are buffered and written together whenever
+/// a response is awaited, the connection is closed or
+/// [`RastaConnection::flush`] is called.
+#[cfg(feature = "std")]
 pub struct RastaConnection {
     state: RastaConnectionState,
     id: RastaId,
-    peer: RastaId,
-    seq_nr: Option<u32>,
-    confirmed_timestamp: u32,
-    server: TcpStream,
+    /// The sequence numbers and unconfirmed data of the connection.
+    sr: SrState,
+    server: BufWriter<Transport>,
+    pending: VecDeque<Message>,
+    trace: TraceControl,
+    config: RastaConfig,
+    round_trip_time: Option<Duration>,
+    frames: FrameBuffer,
+    clock: Arc<dyn Clock>,
+    incidents: IncidentLog,
+    ack_latency: AckLatency,
+    /// The receive capabilities of the peer, known once connected.
+    peer_limits: Option<PeerLimits>,
+    /// When the last message was written to the peer.
+    last_sent: Instant,
+    /// The sequence number of the heartbeat the peer has not
+    /// answered yet and when it was sent.
+    heartbeat_sent: Option<(u32, Instant)>,
 }
 
+#[cfg(feature = "std")]
 impl RastaConnection {
     pub fn try_new<S: ToSocketAddrs>(server: S, id: RastaId) -> Result<Self, RastaError> {
-        let connection = TcpStream::connect(server).map_err(RastaError::from)?;
+        Self::try_new_with_config(server, id, RastaConfig::default())
+    }
+
+    pub fn try_new_with_config<S: ToSocketAddrs>(
+        server: S,
+        id: RastaId,
+        config: RastaConfig,
+    ) -> Result<Self, RastaError> {
+        config.check_memory_budget()?;
+        let connection = address::connect(server, config.connect_timeout)?;
+        connection
+            .set_read_timeout(Some(config.t_max))
+            .map_err(RastaError::from)?;
         connection
-            .set_read_timeout(Some(RASTA_TIMEOUT_DURATION))
+            .set_nodelay(config.nodelay)
             .map_err(RastaError::from)?;
+        config.socket.apply(&connection).map_err(RastaError::from)?;
+        let connection = Transport::client(connection, &config)?;
+        let ack_latency = AckLatency::new(config.n_sendmax);
         Ok(Self {
             state: RastaConnectionState::Down,
             id,
-            peer: 0,
-            seq_nr: None,
-            confirmed_timestamp: 0,
-            server: connection,
+            sr: SrState::new(id, config.n_sendmax, config.memory.unconfirmed_messages),
+            server: BufWriter::new(connection),
+            pending: VecDeque::new(),
+            trace: TraceControl::default(),
+            config,
+            round_trip_time: None,
+            frames: FrameBuffer::default(),
+            clock: Arc::new(SystemClock),
+            incidents: IncidentLog::default(),
+            ack_latency,
+            peer_limits: None,
+            last_sent: Instant::now(),
+            heartbeat_sent: None,
         })
     }
 
-    fn next_seq_nr(&mut self) -> (u32, u32) {
-        if let Some(seq_nr) = self.seq_nr {
-            self.seq_nr.replace(seq_nr + 1);
-            (seq_nr, seq_nr + 1)
-        } else {
-            self.seq_nr.replace(0);
-            (0, 1)
+    /// The maximum amount of data that can be sent in one message.
+    /// Limited by the peer once it has announced its capabilities.
+    pub fn max_data_length(&self) -> usize {
+        self.peer_limits
+            .map_or(MAX_DATA_LENGTH, |limits| limits.max_data_length)
+    }
+
+    pub fn config(&self) -> &RastaConfig {
+        &self.config
+    }
+
+    /// The RaSTA ID of this endpoint.
+    pub fn id(&self) -> RastaId {
+        self.id
+    }
+
+    /// The receive capabilities the peer announced in its connection response.
+    pub fn peer_limits(&self) -> Option<PeerLimits> {
+        self.peer_limits
+    }
+
+    /// Checks that `length` bytes of data fit into one message to the peer.
+    pub fn check_data_length(&self, length: usize) -> Result<(), RastaError> {
+        let max = self.max_data_length();
+        match self.peer_limits {
+            Some(_) if length > max => Err(RastaError::PeerLimitExceeded {
+                length,
+                max,
+                peer: self.sr.peer(),
+            }),
+            None if length > max => Err(RastaError::MessageTooLong { length, max }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Writes all buffered messages to the peer.
+    pub fn flush(&mut self) -> Result<(), RastaError> {
+        self.server.flush().map_err(RastaError::from)
+    }
+
+    /// Controls the tracing of messages exchanged with the peer.
+    /// The returned handle can be used while the connection is running.
+    pub fn trace(&self) -> TraceControl {
+        self.trace.clone()
+    }
+
+    /// Replaces the source of the timestamps of outgoing messages
+    /// and of the time heartbeats and round trips are measured with.
+    pub fn set_clock<C: Clock + 'static>(&mut self, clock: C) {
+        self.clock = Arc::new(clock);
+        self.last_sent = self.clock.now();
+    }
+
+    /// The reports of protocol violations that made the connection fail.
+    pub fn incidents(&self) -> IncidentLog {
+        self.incidents.clone()
+    }
+
+    /// The time the peer takes to confirm the sequence numbers of sent
+    /// messages. The returned handle can be polled while the connection
+    /// is running.
+    pub fn ack_latency(&self) -> AckLatency {
+        self.ack_latency.clone()
+    }
+
+    /// The number of sent data messages the peer has not confirmed yet.
+    /// They are retransmitted when the peer requests it.
+    pub fn unconfirmed_messages(&self) -> usize {
+        self.sr.unconfirmed_messages()
+    }
+
+    /// The smoothed round-trip time of connection requests and heartbeats,
+    /// once one has been measured.
+    pub fn round_trip_time(&self) -> Option<Duration> {
+        self.round_trip_time
+    }
+
+    /// The interval between heartbeats used by [`RastaConnection::run`],
+    /// see [`RastaConfig::heartbeat_interval`].
+    pub fn heartbeat_interval(&self) -> Duration {
+        self.config
+            .heartbeat_interval(self.round_trip_time.unwrap_or_default())
+    }
+
+    /// A snapshot of the addresses, IDs and timing parameters of the connection.
+    pub fn describe(&self) -> EndpointDescriptor {
+        let tcp = self.server.get_ref().tcp();
+        EndpointDescriptor {
+            role: EndpointRole::Connection,
+            id: self.id,
+            peers: match self.state {
+                RastaConnectionState::Up | RastaConnectionState::Start => vec![self.sr.peer()],
+                _ => Vec::new(),
+            },
+            local_addr: tcp.local_addr().ok(),
+            peer_addr: tcp.peer_addr().ok(),
+            state: Some(self.state),
+            t_max: self.config.t_max,
+            heartbeat_interval: self.heartbeat_interval(),
+            round_trip_time: self.round_trip_time,
         }
     }
 
+    fn record_round_trip(&mut self, sample: Duration) {
+        // Exponential smoothing as for the TCP retransmission timer
+        let rtt = match self.round_trip_time {
+            Some(rtt) => (rtt * 7 + sample) / 8,
+            None => sample,
+        };
+        self.round_trip_time.replace(rtt);
+    }
+
+    fn write_message(&mut self, msg: Message) -> Result<(), RastaError> {
+        self.ack_latency
+            .sent(msg.sequence_number(), self.clock.now());
+        self.last_sent = self.clock.now();
+        write_sealed(
+            &mut self.server,
+            &self.trace,
+            &*self.config.safety_code,
+            msg,
+        )
+    }
+
     fn timestamp(&self) -> u32 {
-        std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_secs() as u32
+        self.clock.timestamp()
+    }
+
+    /// The time that has passed on the clock since `earlier`.
+    fn elapsed_since(&self, earlier: Instant) -> Duration {
+        self.clock.now().saturating_duration_since(earlier)
+    }
+
+    /// Changes the state along one of the [`SR_TRANSITIONS`].
+    fn enter(&mut self, state: RastaConnectionState) {
+        debug_assert!(
+            self.state == state || diagram::allows(SR_TRANSITIONS, self.state, state),
+            "Undocumented transition from {:?} to {state:?}",
+            self.state
+        );
+        self.state = state;
     }
 
     pub fn open_connection(&mut self, receiver: u32) -> Result<(), RastaError> {
-        println!("Sending connection request to {receiver}");
-        let msg = Message::connection_request(receiver, self.id, self.timestamp(), N_SENDMAX);
-        self.server.write(&msg).map_err(RastaError::from)?;
+        event!(info, peer = receiver, "Sending connection request");
+        let mut msg =
+            Message::connection_request(receiver, self.id, self.timestamp(), self.config.n_sendmax);
+        msg.advertise_max_data_length(self.config.max_data_length);
+        self.ack_latency.clear_outstanding();
+        self.sr.requested(&msg);
+        self.heartbeat_sent = None;
+        self.write_message(msg)?;
+        let sent = self.clock.now();
         let response = self.receive_message()?;
-        let remote_version = &response.data()[0..4];
-        if remote_version != RASTA_VERSION {
-            return Err(RastaError::VersionMismatch);
-        }
-        if response.message_type() == MessageType::ConnResp {
-            self.state = RastaConnectionState::Up;
-            self.seq_nr.replace(response.sequence_number());
-            self.confirmed_timestamp = response.timestamp();
-            self.peer = response.sender();
-            println!(
-                "Connected to {}",
-                self.server.peer_addr().map_err(RastaError::from)?
+        self.record_round_trip(self.elapsed_since(sent));
+        if matches!(response.try_message_type(), Ok(MessageType::ConnResp)) {
+            if response.data().get(..4) != Some(&RASTA_VERSION[..]) {
+                self.report_incident(IncidentKind::VersionMismatch, &response);
+                return Err(RastaError::VersionMismatch);
+            }
+            if let Err(e) = self.sr.connected(&response) {
+                self.report_incident(IncidentKind::InvalidSequenceNumber, &response);
+                return Err(e);
+            }
+            self.enter(RastaConnectionState::Up);
+            self.peer_limits = response.peer_limits();
+            event!(
+                info,
+                peer = self.sr.peer(),
+                peer_addr = ?self.server.get_ref().tcp().peer_addr().ok(),
+                "Connected"
             );
         }
         Ok(())
@@ -351,51 +1410,139 @@ impl RastaConnection {
         if self.connection_state_request() != RastaConnectionState::Up {
             Ok(())
         } else {
-            let (confirmed_seq_nr, seq_nr) = self.next_seq_nr();
-            let msg = Message::disconnection_request(
-                self.peer,
-                self.id,
-                seq_nr,
-                confirmed_seq_nr,
-                self.timestamp(),
-                self.confirmed_timestamp,
-            );
-            self.server.write(&msg).map_err(RastaError::from)?;
-            self.state = RastaConnectionState::Closed;
-            Ok(())
+            event!(info, peer = self.sr.peer(), "Closing connection");
+            // The peer is disconnected either way
+            let _ = self.await_heartbeat();
+            if self.state != RastaConnectionState::Up {
+                // The answer already closed the connection
+                return Ok(());
+            }
+            self.disconnect(DiscReason::UserRequest)
         }
     }
 
+    /// Marks a broken connection as closed without notifying the peer,
+    /// so that dropping it does not write to the socket.
+    pub(crate) fn abandon(&mut self) {
+        self.enter(RastaConnectionState::Closed);
+    }
+
+    /// Queues a data message. It is written to the peer together with
+    /// other buffered messages at the next flush point.
+    /// Data exceeding the maximum data length of the peer is handled
+    /// according to [`RastaConfig::oversized_send`]. Fails with
+    /// [`RastaError::BufferFull`] while as many data messages as the peer
+    /// accepts are unconfirmed.
     pub fn send_data(&mut self, data: &[u8]) -> Result<(), RastaError> {
-        let (confirmed_seq_nr, seq_nr) = self.next_seq_nr();
-        let msg = Message::data_message(
-            self.peer,
-            self.id,
-            seq_nr,
-            confirmed_seq_nr,
-            self.timestamp(),
-            self.confirmed_timestamp,
-            data,
-        );
-        self.server.write(&msg).map_err(RastaError::from)?;
-        Ok(())
+        match self.check_data_length(data.len()) {
+            Err(RastaError::PeerLimitExceeded { .. })
+                if self.config.oversized_send == OversizedSend::Split =>
+            {
+                let max = self.max_data_length();
+                data.chunks(max)
+                    .try_for_each(|chunk| self.send_message_data(chunk))
+            }
+            Err(e) => Err(e),
+            Ok(()) => self.send_message_data(data),
+        }
+    }
+
+    fn send_message_data(&mut self, data: &[u8]) -> Result<(), RastaError> {
+        self.await_heartbeat()?;
+        let msg = self.sr.data(self.timestamp(), data)?;
+        self.write_message(msg)
     }
 
+    /// Answers a retransmission request of the peer and sends
+    /// all data messages it has not confirmed again.
+    fn retransmit(&mut self) -> Result<(), RastaError> {
+        event!(debug, peer = self.sr.peer(), "Retransmission request");
+        for msg in self.sr.retransmission(self.timestamp())? {
+            self.write_message(msg)?;
+        }
+        self.flush()
+    }
+
+    /// Closes the connection with a disconnection request giving `reason`.
+    fn disconnect(&mut self, reason: DiscReason) -> Result<(), RastaError> {
+        let msg = self.sr.disconnection_request(self.timestamp(), reason);
+        self.sr.reset();
+        self.enter(RastaConnectionState::Closed);
+        self.write_message(msg)?;
+        self.flush()
+    }
+
+    fn report_incident(&self, kind: IncidentKind, msg: &Message) {
+        let mut report = IncidentReport::new(kind, msg, self.id, self.state, self.config.t_max);
+        report.peer = Some(msg.sender());
+        report.expected_sequence_number = self.sr.window().map(|window| window.sent);
+        report.round_trip_time = self.round_trip_time;
+        report.ack_latency_p99 = self.ack_latency.percentile(99.0);
+        self.incidents.record(report);
+    }
+
+    /// Sends a heartbeat and waits for the response of the peer.
+    /// Data that arrives in the meantime is kept until the application asks for it.
     pub fn send_heartbeat(&mut self) -> Result<(), RastaError> {
-        let (confirmed_seq_nr, seq_nr) = self.next_seq_nr();
-        let msg = Message::heartbeat(
-            self.peer,
-            self.id,
-            seq_nr,
-            confirmed_seq_nr,
-            self.timestamp(),
-            self.confirmed_timestamp,
-        );
-        self.server.write(&msg).map_err(RastaError::from)?;
-        let response = self.receive_message()?;
-        if response.message_type() == MessageType::HB {
-            self.seq_nr.replace(response.sequence_number());
-            self.confirmed_timestamp = response.timestamp();
+        self.write_heartbeat()?;
+        self.await_heartbeat()
+    }
+
+    /// Sends a heartbeat without waiting for the response if none was
+    /// sent for a [`heartbeat_interval`](Self::heartbeat_interval).
+    /// The response is consumed by the next read, and before anything else
+    /// is sent, so that the sequence numbers stay in step with the peer.
+    /// Returns whether a heartbeat was sent.
+    pub fn maintain(&mut self) -> Result<bool, RastaError> {
+        if self.state != RastaConnectionState::Up
+            || self.heartbeat_sent.is_some()
+            || self.elapsed_since(self.last_sent) < self.heartbeat_interval()
+        {
+            return Ok(false);
+        }
+        self.write_heartbeat()?;
+        self.flush()?;
+        Ok(true)
+    }
+
+    /// The time until [`maintain`](Self::maintain) sends the next heartbeat.
+    pub fn time_until_heartbeat(&self) -> Duration {
+        if self.heartbeat_sent.is_some() {
+            return self.heartbeat_interval();
+        }
+        self.heartbeat_interval()
+            .saturating_sub(self.elapsed_since(self.last_sent))
+    }
+
+    fn write_heartbeat(&mut self) -> Result<(), RastaError> {
+        self.await_heartbeat()?;
+        let msg = self.sr.heartbeat(self.timestamp());
+        let seq_nr = msg.sequence_number();
+        self.write_message(msg)?;
+        self.heartbeat_sent.replace((seq_nr, self.clock.now()));
+        Ok(())
+    }
+
+    /// Keeps `msg` until the application asks for it.
+    fn keep_pending(&mut self, msg: Message) -> Result<(), RastaError> {
+        let capacity = self.config.memory.pending_messages;
+        if self.pending.len() >= capacity {
+            return Err(RastaError::BufferFull {
+                buffer: "buffer of pending messages",
+                capacity,
+            });
+        }
+        self.pending.push_back(msg);
+        Ok(())
+    }
+
+    /// Reads until the peer has answered the outstanding heartbeat, if any.
+    fn await_heartbeat(&mut self) -> Result<(), RastaError> {
+        while self.heartbeat_sent.is_some() && self.state == RastaConnectionState::Up {
+            let msg = self.read_message()?;
+            if !matches!(msg.try_message_type(), Ok(MessageType::HB)) {
+                self.keep_pending(msg)?;
+            }
         }
         Ok(())
     }
@@ -404,10 +1551,165 @@ impl RastaConnection {
         self.state
     }
 
+    /// Returns the oldest buffered message if there is one, otherwise
+    /// waits for the next message from the peer. Messages of unknown types
+    /// are only returned with [`UnknownMessagePolicy::Forward`], so their
+    /// type has to be read with [`Message::try_message_type`].
     pub fn receive_message(&mut self) -> Result<Message, RastaError> {
-        let mut buf = vec![0; 1024];
-        let bytes_read = self.server.read(&mut buf).map_err(RastaError::from)?;
-        Ok(Message::from(&buf[..bytes_read]))
+        if let Some(msg) = self.pending.pop_front() {
+            return Ok(msg);
+        }
+        self.read_message()
+    }
+
+    /// Waits up to `timeout` for the next message from the peer and
+    /// returns `None` if none arrived. With a zero `timeout`, only
+    /// messages that have already been received are returned, so many
+    /// connections can be served by one thread.
+    pub fn poll_message(&mut self, timeout: Duration) -> Result<Option<Message>, RastaError> {
+        if let Some(msg) = self.pending.pop_front() {
+            return Ok(Some(msg));
+        }
+        // Buffered messages must not be written to a non-blocking socket
+        self.flush()?;
+        let tcp = self.server.get_ref().tcp();
+        if timeout.is_zero() {
+            tcp.set_nonblocking(true).map_err(RastaError::from)?;
+        } else {
+            tcp.set_read_timeout(Some(timeout))
+                .map_err(RastaError::from)?;
+        }
+        let result = self.read_message();
+        let tcp = self.server.get_ref().tcp();
+        tcp.set_nonblocking(false).map_err(RastaError::from)?;
+        tcp.set_read_timeout(Some(self.config.t_max))
+            .map_err(RastaError::from)?;
+        match result {
+            Ok(msg) => Ok(Some(msg)),
+            Err(RastaError::Timeout) => Ok(None),
+            Err(RastaError::IOError(e)) if e.kind() == ErrorKind::WouldBlock => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Returns a message that was received while no one was
+    /// waiting for it (e.g. in response to a heartbeat), if any.
+    pub fn take_buffered_message(&mut self) -> Option<Message> {
+        self.pending.pop_front()
+    }
+
+    fn read_message(&mut self) -> Result<Message, RastaError> {
+        self.flush()?;
+        loop {
+            let frame = loop {
+                if let Some(frame) = self.frames.next_frame() {
+                    break frame;
+                }
+                let mut buf = vec![0; MESSAGE_BUFFER_LENGTH];
+                let bytes_read = self
+                    .server
+                    .get_mut()
+                    .read(&mut buf)
+                    .map_err(RastaError::from)?;
+                if bytes_read == 0 {
+                    self.frames.clear();
+                    return Err(RastaError::from(io::Error::from(ErrorKind::UnexpectedEof)));
+                }
+                self.frames.extend(&buf[..bytes_read]);
+            };
+            let mut msg = Message::try_parse_passthrough(&frame)?;
+            msg.verify(&*self.config.safety_code)?;
+            self.trace.incoming(&msg);
+            // The connection response is checked when opening the connection
+            if self.sr.is_connected() {
+                match self.sr.receive(&msg) {
+                    Ok(Received::Accepted) => {}
+                    Ok(Received::Missing) => {
+                        event!(
+                            debug,
+                            peer = self.sr.peer(),
+                            received = msg.sequence_number(),
+                            "Requesting retransmission"
+                        );
+                        let request = self.sr.retransmission_request(self.timestamp());
+                        self.write_message(request)?;
+                        self.flush()?;
+                        continue;
+                    }
+                    Ok(Received::Discarded) => {
+                        event!(
+                            warn,
+                            peer = self.sr.peer(),
+                            received = msg.sequence_number(),
+                            "Discarding message out of sequence"
+                        );
+                        continue;
+                    }
+                    Err(e) => {
+                        event!(
+                            warn,
+                            peer = self.sr.peer(),
+                            received = msg.sequence_number(),
+                            confirmed = msg.confirmed_sequence_number(),
+                            "Invalid sequence number"
+                        );
+                        self.report_incident(IncidentKind::InvalidSequenceNumber, &msg);
+                        self.disconnect(DiscReason::SequenceNumberError)?;
+                        return Err(e);
+                    }
+                }
+                self.ack_latency
+                    .confirmed(msg.confirmed_sequence_number(), self.clock.now());
+            }
+            match msg.try_message_type() {
+                Ok(MessageType::RetrReq) => {
+                    self.retransmit()?;
+                    continue;
+                }
+                // The retransmitted messages follow the response
+                Ok(MessageType::RetrResp) => continue,
+                // The peer shut down
+                Ok(MessageType::DiscReq) => {
+                    event!(
+                        info,
+                        peer = self.sr.peer(),
+                        reason = ?msg.disconnect_reason(),
+                        "Disconnection requested by the peer"
+                    );
+                    self.sr.reset();
+                    self.enter(RastaConnectionState::Closed);
+                }
+                // Heartbeats also answer data messages, so only the
+                // heartbeat confirming the outstanding one answers it
+                Ok(MessageType::HB) => {
+                    if let Some((seq_nr, sent)) = self.heartbeat_sent {
+                        if msg.confirmed_sequence_number() == seq_nr {
+                            event!(
+                                trace,
+                                peer = self.sr.peer(),
+                                seq = seq_nr,
+                                "Heartbeat answered"
+                            );
+                            self.heartbeat_sent = None;
+                            self.record_round_trip(self.elapsed_since(sent));
+                        }
+                    }
+                }
+                Ok(_) => {}
+                Err(_) if self.config.unknown_messages == UnknownMessagePolicy::Forward => {}
+                Err(_) => {
+                    event!(
+                        debug,
+                        peer = self.sr.peer(),
+                        message_type = msg.message_type_raw(),
+                        "Dropping message of unknown type"
+                    );
+                    continue;
+                }
+            }
+            msg.retransmitted_to_data();
+            return Ok(msg);
+        }
     }
 
     pub fn run<F, D>(&mut self, peer: RastaId, mut message_fn: F) -> Result<(), RastaError>
@@ -415,20 +1717,33 @@ impl RastaConnection {
         F: FnMut(Option<Vec<u8>>) -> RastaCommand<D>,
         D: AsRef<[u8]>,
     {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!("rasta_connection", id = self.id, peer).entered();
         self.open_connection(peer)?;
         let mut previous_data = None;
         loop {
+            if previous_data.is_none() {
+                previous_data = self
+                    .take_buffered_message()
+                    .map(|msg| Vec::from(msg.data()));
+            }
             match message_fn(previous_data.take()) {
                 RastaCommand::Data(data) => {
                     self.send_data(data.as_ref())?;
                     let msg = self.receive_message()?;
-                    if msg.message_type() == MessageType::Data {
+                    if self.state == RastaConnectionState::Closed {
+                        break;
+                    }
+                    if matches!(msg.try_message_type(), Ok(MessageType::Data)) {
                         previous_data.replace(Vec::from(msg.data()));
                     }
                 }
                 RastaCommand::Wait => {
                     self.send_heartbeat()?;
-                    std::thread::sleep(RASTA_TIMEOUT_DURATION / 2);
+                    if self.state == RastaConnectionState::Closed {
+                        break;
+                    }
+                    self.clock.sleep(self.heartbeat_interval());
                 }
                 RastaCommand::Disconnect => {
                     self.close_connection()?;
@@ -440,13 +1755,974 @@ impl RastaConnection {
     }
 }
 
+#[cfg(feature = "std")]
 impl Drop for RastaConnection {
     fn drop(&mut self) {
         self.close_connection().unwrap();
     }
 }
 
+#[cfg(all(test, feature = "std"))]
 mod tests {
+    use std::{
+        io::{BufWriter, Read, Write},
+        net::TcpStream,
+        time::Duration,
+    };
+
+    use crate::{
+        clock::MockClock,
+        descriptor::EndpointRole,
+        events::{DisconnectReason, ListenerEvent},
+        framing::FrameBuffer,
+        incident::IncidentKind,
+        latency::AckLatency,
+        message::{DiscReason, Message, MessageType, DATA_OFFSET, MESSAGE_BUFFER_LENGTH},
+        safety::NoSafetyCode,
+        sr::SrState,
+        trace::TraceControl,
+        write_responses, write_sealed, ListenerWriter, OversizedSend, RastaConfig, RastaConnection,
+        RastaConnectionState, RastaError, RastaListener, RecoveryAction, N_SENDMAX,
+    };
+
+    fn read_frame(stream: &mut TcpStream) -> Message {
+        let mut frame = vec![0; MESSAGE_BUFFER_LENGTH];
+        stream.read_exact(&mut frame).unwrap();
+        Message::try_parse(&frame).unwrap()
+    }
+
     #[test]
     fn test_conn_req_len() {}
+
+    #[test]
+    fn test_connection_flushes_before_reading() {
+        let peer = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = peer.local_addr().unwrap();
+        let (queued, queued_rx) = std::sync::mpsc::channel();
+        let (checked, checked_rx) = std::sync::mpsc::channel();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = peer.accept().unwrap();
+            let request = read_frame(&mut stream);
+            let response =
+                Message::connection_response(1, 2, request.sequence_number(), 0, 0, N_SENDMAX);
+            stream.write_all(&response).unwrap();
+            queued_rx.recv().unwrap();
+            // The data is still buffered by the connection
+            stream
+                .set_read_timeout(Some(Duration::from_millis(50)))
+                .unwrap();
+            assert!(stream.read(&mut [0; MESSAGE_BUFFER_LENGTH]).is_err());
+            checked.send(()).unwrap();
+            stream.set_read_timeout(None).unwrap();
+            let data: Vec<_> = (0..3).map(|_| read_frame(&mut stream)).collect();
+            let heartbeat = Message::heartbeat(
+                1,
+                2,
+                response.sequence_number().wrapping_add(1),
+                data[2].sequence_number(),
+                0,
+                0,
+            );
+            stream.write_all(&heartbeat).unwrap();
+            data.iter()
+                .map(|msg| msg.data().to_vec())
+                .collect::<Vec<_>>()
+        });
+
+        let mut conn = RastaConnection::try_new(addr, 1).unwrap();
+        conn.open_connection(2).unwrap();
+        for n in 1..=3 {
+            conn.send_data(&[n]).unwrap();
+        }
+        queued.send(()).unwrap();
+        checked_rx.recv().unwrap();
+        // Blocks on reading the answer, so everything queued is written first
+        let msg = conn.receive_message().unwrap();
+        assert_eq!(msg.try_message_type().unwrap(), MessageType::HB);
+        assert_eq!(conn.unconfirmed_messages(), 0);
+        assert_eq!(server.join().unwrap(), [[1], [2], [3]]);
+    }
+
+    /// Counts the writes that reach the transport.
+    #[derive(Default)]
+    struct CountingWriter {
+        written: Vec<u8>,
+        writes: usize,
+    }
+
+    impl Write for CountingWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.writes += 1;
+            self.written.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_coalesced_writes() {
+        let mut sr = SrState::new(1, N_SENDMAX, usize::from(N_SENDMAX));
+        sr.accept(&Message::connection_request(1, 2, 0, N_SENDMAX), 0);
+        let mut writer =
+            ListenerWriter::new(CountingWriter::default(), sr, AckLatency::new(N_SENDMAX));
+        write_responses(
+            &mut writer,
+            &TraceControl::default(),
+            &MockClock::new(),
+            &NoSafetyCode,
+            [[1], [2], [3]],
+        )
+        .unwrap();
+        write_responses(
+            &mut writer,
+            &TraceControl::default(),
+            &MockClock::new(),
+            &NoSafetyCode,
+            None::<&[u8]>,
+        )
+        .unwrap();
+        // Nothing is written until the flush point
+        assert_eq!(writer.get_mut().writes, 0);
+        writer.flush().unwrap();
+        assert_eq!(writer.get_mut().writes, 1);
+        assert_eq!(writer.get_mut().written.len(), 4 * MESSAGE_BUFFER_LENGTH);
+    }
+
+    #[test]
+    fn test_recovery_action() {
+        use std::io::{Error, ErrorKind};
+
+        use crate::address::AddressErrors;
+
+        let addresses = || AddressErrors(vec![]);
+        let table = [
+            (RastaError::InvalidSeqNr, RecoveryAction::Reconnect),
+            (RastaError::InvalidConfirmedSeqNr, RecoveryAction::Reconnect),
+            (RastaError::StateError, RecoveryAction::Reconnect),
+            (RastaError::Timeout, RecoveryAction::Reconnect),
+            (RastaError::VersionMismatch, RecoveryAction::FailSafe),
+            (RastaError::UnknownMessageType(0), RecoveryAction::Retry),
+            (
+                RastaError::MessageTooLong { length: 2, max: 1 },
+                RecoveryAction::FailSafe,
+            ),
+            (
+                RastaError::PeerLimitExceeded {
+                    length: 2,
+                    max: 1,
+                    peer: 2,
+                },
+                RecoveryAction::FailSafe,
+            ),
+            (
+                RastaError::BufferFull {
+                    buffer: "send queue",
+                    capacity: 1,
+                },
+                RecoveryAction::Reconnect,
+            ),
+            (
+                RastaError::MemoryBudgetExceeded {
+                    required: 2,
+                    budget: 1,
+                },
+                RecoveryAction::FailSafe,
+            ),
+            (
+                RastaError::RetransmissionBufferTooSmall {
+                    capacity: 1,
+                    n_sendmax: 2,
+                },
+                RecoveryAction::FailSafe,
+            ),
+            (
+                RastaError::MessageTooShort { length: 1, min: 2 },
+                RecoveryAction::Retry,
+            ),
+            (RastaError::SafetyCodeMismatch, RecoveryAction::Reconnect),
+            (
+                RastaError::from(Error::from(ErrorKind::Interrupted)),
+                RecoveryAction::Retry,
+            ),
+            (
+                RastaError::from(Error::from(ErrorKind::WouldBlock)),
+                RecoveryAction::Retry,
+            ),
+            (
+                RastaError::from(Error::from(ErrorKind::ConnectionReset)),
+                RecoveryAction::Reconnect,
+            ),
+            (
+                RastaError::Resolve(Error::from(ErrorKind::NotFound)),
+                RecoveryAction::Reconnect,
+            ),
+            (RastaError::Connect(addresses()), RecoveryAction::Reconnect),
+            (RastaError::Bind(addresses()), RecoveryAction::FailSafe),
+            (
+                RastaError::Application {
+                    message: String::new(),
+                    recovery: RecoveryAction::ReinitialisePdi,
+                    source: None,
+                },
+                RecoveryAction::ReinitialisePdi,
+            ),
+            (RastaError::Other(String::new()), RecoveryAction::FailSafe),
+        ];
+        for (error, recovery) in table {
+            assert_eq!(error.recovery_action(), recovery, "{error:?}");
+        }
+        #[cfg(feature = "tls")]
+        assert_eq!(
+            RastaError::Tls(rustls::Error::General(String::new())).recovery_action(),
+            RecoveryAction::FailSafe
+        );
+        assert!(RecoveryAction::Reconnect > RecoveryAction::ReinitialisePdi);
+    }
+
+    #[test]
+    fn test_listener_coalesced_frames() {
+        let mut listener = RastaListener::try_new("127.0.0.1:0", 2).unwrap();
+        listener.set_clock(|| 42);
+        let addr = listener.listener.local_addr().unwrap();
+        let shutdown = listener.shutdown_handle();
+        let server = std::thread::spawn(move || listener.listen(|_| None::<Vec<u8>>));
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let request = Message::connection_request(2, 1, 0, N_SENDMAX);
+        let seq_nr = request.sequence_number();
+        let mut frames = Vec::from(&*request);
+        frames.extend_from_slice(&Message::heartbeat(2, 1, seq_nr + 1, seq_nr + 1, 0, 0));
+        stream.write_all(&frames).unwrap();
+
+        let mut response = vec![0; 2 * MESSAGE_BUFFER_LENGTH];
+        stream.read_exact(&mut response).unwrap();
+        let (first, second) = response.split_at(MESSAGE_BUFFER_LENGTH);
+        assert_eq!(
+            Message::from(first).try_message_type().unwrap(),
+            MessageType::ConnResp
+        );
+        assert_eq!(
+            Message::from(second).try_message_type().unwrap(),
+            MessageType::HB
+        );
+        assert_eq!(Message::from(second).timestamp(), 42);
+
+        shutdown.shutdown();
+        stream
+            .write_all(&Message::heartbeat(2, 1, seq_nr + 2, seq_nr + 2, 0, 0))
+            .unwrap();
+        assert!(server.join().unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_connection_retransmission() {
+        let peer = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = peer.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = peer.accept().unwrap();
+            let request = read_frame(&mut stream);
+            let response =
+                Message::connection_response(1, 2, request.sequence_number(), 0, 0, N_SENDMAX);
+            stream.write_all(&response).unwrap();
+            let data = read_frame(&mut stream);
+            let seq_nr = response.sequence_number();
+            stream
+                .write_all(&Message::retransmission_request(
+                    1,
+                    2,
+                    seq_nr.wrapping_add(1),
+                    data.sequence_number().wrapping_sub(1),
+                    0,
+                    0,
+                ))
+                .unwrap();
+            assert_eq!(
+                read_frame(&mut stream).try_message_type().unwrap(),
+                MessageType::RetrResp
+            );
+            let retransmitted = read_frame(&mut stream);
+            assert_eq!(
+                retransmitted.try_message_type().unwrap(),
+                MessageType::RetrData
+            );
+            assert_eq!(retransmitted.data(), data.data());
+            let confirmation = Message::retransmitted_data_message(
+                1,
+                2,
+                seq_nr.wrapping_add(2),
+                retransmitted.sequence_number(),
+                0,
+                0,
+                &[4],
+            )
+            .unwrap();
+            stream.write_all(&confirmation).unwrap();
+        });
+
+        let mut conn = RastaConnection::try_new(addr, 1).unwrap();
+        conn.open_connection(2).unwrap();
+        conn.send_data(&[1, 2, 3]).unwrap();
+        assert_eq!(conn.unconfirmed_messages(), 1);
+        let msg = conn.receive_message().unwrap();
+        assert_eq!(msg.try_message_type().unwrap(), MessageType::Data);
+        assert_eq!(msg.data(), &[4]);
+        assert_eq!(conn.unconfirmed_messages(), 0);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_connection_sequence_window() {
+        let peer = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = peer.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = peer.accept().unwrap();
+            let request = read_frame(&mut stream);
+            let confirmed = request.sequence_number();
+            let response = Message::connection_response(1, 2, confirmed, 0, 0, N_SENDMAX);
+            stream.write_all(&response).unwrap();
+            let seq_nr = response.sequence_number();
+            // The data message before this one was lost
+            let data = Message::data_message(1, 2, seq_nr.wrapping_add(2), confirmed, 0, 0, &[5]);
+            stream.write_all(&data.unwrap()).unwrap();
+            let request = read_frame(&mut stream);
+            assert_eq!(request.try_message_type().unwrap(), MessageType::RetrReq);
+            assert_eq!(request.confirmed_sequence_number(), seq_nr);
+            let confirmed = request.sequence_number();
+            let response =
+                Message::retransmission_response(1, 2, seq_nr.wrapping_add(1), confirmed, 0, 0);
+            stream.write_all(&response).unwrap();
+            let data = Message::retransmitted_data_message(
+                1,
+                2,
+                seq_nr.wrapping_add(2),
+                confirmed,
+                0,
+                0,
+                &[5],
+            );
+            stream.write_all(&data.unwrap()).unwrap();
+
+            // Far beyond the messages the connection buffers
+            let heartbeat = Message::heartbeat(1, 2, seq_nr.wrapping_add(70_000), confirmed, 0, 0);
+            stream.write_all(&heartbeat).unwrap();
+            read_frame(&mut stream)
+        });
+
+        let mut conn = RastaConnection::try_new(addr, 1).unwrap();
+        conn.open_connection(2).unwrap();
+        let msg = conn.receive_message().unwrap();
+        assert_eq!(msg.try_message_type().unwrap(), MessageType::Data);
+        assert_eq!(msg.data(), &[5]);
+        assert!(matches!(
+            conn.receive_message(),
+            Err(RastaError::InvalidSeqNr)
+        ));
+        assert_eq!(
+            conn.connection_state_request(),
+            RastaConnectionState::Closed
+        );
+        assert_eq!(
+            conn.incidents().latest().unwrap().kind,
+            IncidentKind::InvalidSequenceNumber
+        );
+        let disconnection = server.join().unwrap();
+        assert_eq!(
+            disconnection.try_message_type().unwrap(),
+            MessageType::DiscReq
+        );
+        assert_eq!(
+            disconnection.disconnect_reason(),
+            Some(DiscReason::SequenceNumberError)
+        );
+    }
+
+    #[test]
+    fn test_describe() {
+        let listener = RastaListener::try_new("127.0.0.1:0", 2).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let conn = RastaConnection::try_new(addr, 1).unwrap();
+
+        let descriptor = conn.describe();
+        assert_eq!(descriptor.role, EndpointRole::Connection);
+        assert_eq!(descriptor.id, 1);
+        assert!(descriptor.peers.is_empty());
+        assert_eq!(descriptor.peer_addr, Some(addr));
+        assert_eq!(descriptor.state, Some(RastaConnectionState::Down));
+        assert_eq!(descriptor.t_max, conn.config().t_max);
+
+        let descriptor = listener.describe();
+        assert_eq!(descriptor.role, EndpointRole::Listener);
+        assert_eq!(descriptor.local_addr, Some(addr));
+        assert_eq!(descriptor.state, None);
+    }
+
+    #[test]
+    fn test_peer_limits() {
+        let peer = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = peer.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = peer.accept().unwrap();
+            let request = read_frame(&mut stream);
+            assert_eq!(request.peer_limits().unwrap().max_data_length, 512);
+            let mut response =
+                Message::connection_response(1, 2, request.sequence_number(), 0, 0, N_SENDMAX);
+            response.advertise_max_data_length(16);
+            stream.write_all(&response).unwrap();
+            let lengths: Vec<_> = (0..2)
+                .map(|_| read_frame(&mut stream).data().len())
+                .collect();
+            assert_eq!(lengths, [16, 4]);
+        });
+
+        let config = RastaConfig {
+            max_data_length: 512,
+            ..RastaConfig::default()
+        };
+        let mut conn = RastaConnection::try_new_with_config(addr, 1, config).unwrap();
+        conn.open_connection(2).unwrap();
+        assert_eq!(conn.max_data_length(), 16);
+        assert!(matches!(
+            conn.send_data(&[0; 20]),
+            Err(RastaError::PeerLimitExceeded {
+                length: 20,
+                max: 16,
+                peer: 2
+            })
+        ));
+        assert_eq!(conn.unconfirmed_messages(), 0);
+        conn.config.oversized_send = OversizedSend::Split;
+        conn.send_data(&[0; 20]).unwrap();
+        conn.flush().unwrap();
+        assert_eq!(conn.unconfirmed_messages(), 2);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_listener_retransmission() {
+        let mut listener = RastaListener::try_new("127.0.0.1:0", 2).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let shutdown = listener.shutdown_handle();
+        let server = std::thread::spawn(move || listener.listen(|msg| Some(msg.data().to_vec())));
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let request = Message::connection_request(2, 1, 0, N_SENDMAX);
+        let seq_nr = request.sequence_number();
+        stream.write_all(&request).unwrap();
+        assert_eq!(
+            read_frame(&mut stream).try_message_type().unwrap(),
+            MessageType::ConnResp
+        );
+        stream
+            .write_all(&Message::retransmission_request(
+                2,
+                1,
+                seq_nr.wrapping_add(1),
+                seq_nr.wrapping_add(1),
+                0,
+                0,
+            ))
+            .unwrap();
+        let response = read_frame(&mut stream);
+        assert_eq!(response.try_message_type().unwrap(), MessageType::RetrResp);
+        let data = Message::retransmitted_data_message(
+            2,
+            1,
+            seq_nr.wrapping_add(2),
+            response.sequence_number(),
+            0,
+            0,
+            &[7],
+        )
+        .unwrap();
+        stream.write_all(&data).unwrap();
+        let echo = read_frame(&mut stream);
+        assert_eq!(echo.try_message_type().unwrap(), MessageType::Data);
+        assert_eq!(echo.data(), &[7]);
+
+        shutdown.shutdown();
+        stream
+            .write_all(&Message::heartbeat(
+                2,
+                1,
+                seq_nr.wrapping_add(3),
+                echo.sequence_number(),
+                0,
+                0,
+            ))
+            .unwrap();
+        assert!(server.join().unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_listener_events() {
+        let listener = RastaListener::try_new("127.0.0.1:0", 2).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut events = listener.events();
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let request = Message::connection_request(2, 1, 0, N_SENDMAX);
+        let seq_nr = request.sequence_number();
+        stream.write_all(&request).unwrap();
+        read_frame(&mut stream);
+        stream
+            .write_all(&Message::heartbeat(2, 1, seq_nr + 1, seq_nr + 1, 0, 0))
+            .unwrap();
+        read_frame(&mut stream);
+        stream
+            .write_all(&Message::data_message(2, 1, seq_nr + 2, seq_nr + 2, 0, 0, &[7]).unwrap())
+            .unwrap();
+        read_frame(&mut stream);
+        stream
+            .write_all(&Message::disconnection_request(
+                2,
+                1,
+                seq_nr + 3,
+                seq_nr + 2,
+                0,
+                0,
+            ))
+            .unwrap();
+
+        assert!(matches!(
+            events.next(),
+            Some(ListenerEvent::ConnectionOpened(1))
+        ));
+        assert!(matches!(events.next(), Some(ListenerEvent::Heartbeat(1))));
+        assert!(matches!(
+            events.next(),
+            Some(ListenerEvent::Data(msg)) if msg.data() == [7]
+        ));
+        assert!(matches!(
+            events.next(),
+            Some(ListenerEvent::Disconnected(
+                1,
+                DisconnectReason::PeerRequest
+            ))
+        ));
+        assert!(events.try_next().is_none());
+        assert!(events.shutdown().is_ok());
+    }
+
+    #[test]
+    fn test_listener_rejects_incompatible_version() {
+        let listener = RastaListener::try_new("127.0.0.1:0", 2).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut events = listener.events();
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let request = Message::connection_request(2, 1, 0, N_SENDMAX);
+        let seq_nr = request.sequence_number();
+        stream.write_all(&request).unwrap();
+        read_frame(&mut stream);
+        stream
+            .write_all(&Message::heartbeat(2, 1, seq_nr + 1, seq_nr + 1, 0, 0))
+            .unwrap();
+        read_frame(&mut stream);
+        // The peer restarts with another version of the protocol
+        let mut frame = request.to_vec();
+        frame[DATA_OFFSET..DATA_OFFSET + 4].copy_from_slice(b"0201");
+        stream.write_all(&frame).unwrap();
+        let response = read_frame(&mut stream);
+        assert_eq!(response.try_message_type().unwrap(), MessageType::DiscReq);
+        assert_eq!(
+            response.disconnect_reason(),
+            Some(DiscReason::ProtocolVersionError)
+        );
+
+        assert!(matches!(
+            events.next(),
+            Some(ListenerEvent::ConnectionOpened(1))
+        ));
+        assert!(matches!(events.next(), Some(ListenerEvent::Heartbeat(1))));
+        assert!(matches!(
+            events.next(),
+            Some(ListenerEvent::Disconnected(
+                1,
+                DisconnectReason::VersionMismatch
+            ))
+        ));
+        assert!(events.shutdown().is_ok());
+    }
+
+    #[test]
+    fn test_shutdown_sends_disconnection_request() {
+        let mut listener = RastaListener::try_new("127.0.0.1:0", 2).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let shutdown = listener.shutdown_handle();
+        let server = std::thread::spawn(move || listener.listen(|_| None::<Vec<u8>>));
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let request = Message::connection_request(2, 1, 0, N_SENDMAX);
+        let seq_nr = request.sequence_number();
+        stream.write_all(&request).unwrap();
+        read_frame(&mut stream);
+
+        shutdown.shutdown();
+        // The listener notices the shutdown after the next message
+        stream
+            .write_all(&Message::heartbeat(2, 1, seq_nr + 1, seq_nr + 1, 0, 0))
+            .unwrap();
+        let response = loop {
+            let response = read_frame(&mut stream);
+            if response.try_message_type().unwrap() != MessageType::HB {
+                break response;
+            }
+        };
+        assert_eq!(response.try_message_type().unwrap(), MessageType::DiscReq);
+        assert_eq!(response.disconnect_reason(), Some(DiscReason::UserRequest));
+        assert!(server.join().unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_listener_sequence_window() {
+        let mut listener = RastaListener::try_new("127.0.0.1:0", 2).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let incidents = listener.incidents();
+        let shutdown = listener.shutdown_handle();
+        let server = std::thread::spawn(move || listener.listen(|_| [[1], [2], [3]]));
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let request = Message::connection_request(2, 1, 0, N_SENDMAX);
+        let seq_nr = request.sequence_number();
+        stream.write_all(&request).unwrap();
+        assert_eq!(read_frame(&mut stream).sequence_number(), seq_nr + 1);
+        let heartbeat =
+            |n: u32, confirmed: u32| Message::heartbeat(2, 1, seq_nr + n, seq_nr + confirmed, 0, 0);
+        stream.write_all(&heartbeat(1, 1)).unwrap();
+        read_frame(&mut stream);
+        // Sent before the answer to the previous heartbeat arrived
+        stream.write_all(&heartbeat(2, 1)).unwrap();
+        read_frame(&mut stream);
+
+        let data = Message::data_message(2, 1, seq_nr + 3, seq_nr + 3, 0, 0, &[7]).unwrap();
+        stream.write_all(&data).unwrap();
+        for n in 4..7 {
+            assert_eq!(read_frame(&mut stream).sequence_number(), seq_nr + n);
+        }
+        // A duplicate is discarded without an answer
+        stream.write_all(&data).unwrap();
+        // Confirms the last of several responses
+        stream.write_all(&heartbeat(4, 6)).unwrap();
+        let response = read_frame(&mut stream);
+        assert_eq!(response.try_message_type().unwrap(), MessageType::HB);
+        assert_eq!(response.confirmed_sequence_number(), seq_nr + 4);
+
+        // Confirms a message that was never sent
+        stream.write_all(&heartbeat(5, 8)).unwrap();
+        let response = read_frame(&mut stream);
+        assert_eq!(response.try_message_type().unwrap(), MessageType::DiscReq);
+        assert_eq!(
+            response.disconnect_reason(),
+            Some(DiscReason::SequenceNumberError)
+        );
+        assert_eq!(
+            incidents.latest().unwrap().kind,
+            IncidentKind::InvalidSequenceNumber
+        );
+
+        // Only the peer was dropped
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(&request).unwrap();
+        let response = read_frame(&mut stream);
+        assert_eq!(response.try_message_type().unwrap(), MessageType::ConnResp);
+        assert_eq!(response.confirmed_sequence_number(), seq_nr);
+
+        shutdown.shutdown();
+        drop(stream);
+        let _ = TcpStream::connect(addr);
+        assert!(server.join().unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_listener_numbers_responses() {
+        let mut listener = RastaListener::try_new("127.0.0.1:0", 2).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let shutdown = listener.shutdown_handle();
+        let server = std::thread::spawn(move || {
+            listener.listen(|msg| match msg.data() {
+                [1] => vec![[1], [2]],
+                _ => Vec::new(),
+            })
+        });
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let request = Message::connection_request(2, 1, 0, N_SENDMAX);
+        let seq_nr = request.sequence_number();
+        stream.write_all(&request).unwrap();
+        let mut sent = vec![read_frame(&mut stream).sequence_number()];
+        // Data with two responses, data answered by a heartbeat, a
+        // retransmission request answered with the two unconfirmed
+        // data messages and a heartbeat
+        let confirmed = seq_nr + 1;
+        let messages = [
+            Message::data_message(2, 1, seq_nr + 1, confirmed, 0, 0, &[1]).unwrap(),
+            Message::data_message(2, 1, seq_nr + 2, confirmed, 0, 0, &[2]).unwrap(),
+            Message::retransmission_request(2, 1, seq_nr + 3, confirmed, 0, 0),
+            Message::heartbeat(2, 1, seq_nr + 4, confirmed, 0, 0),
+        ];
+        for (msg, responses) in messages.iter().zip([2, 1, 3, 1]) {
+            stream.write_all(msg).unwrap();
+            for _ in 0..responses {
+                sent.push(read_frame(&mut stream).sequence_number());
+            }
+        }
+        for pair in sent.windows(2) {
+            assert_eq!(pair[1], pair[0].wrapping_add(1));
+        }
+
+        shutdown.shutdown();
+        drop(stream);
+        let _ = TcpStream::connect(addr);
+        assert!(server.join().unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_listener_ack_latency() {
+        let clock = MockClock::new();
+        let mut listener = RastaListener::try_new("127.0.0.1:0", 2).unwrap();
+        listener.set_clock(clock.clone());
+        let addr = listener.local_addr().unwrap();
+        let shutdown = listener.shutdown_handle();
+        let latency = listener.ack_latency();
+        let server = std::thread::spawn(move || listener.listen(|_| None::<Vec<u8>>));
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let request = Message::connection_request(2, 1, 0, N_SENDMAX);
+        let seq_nr = request.sequence_number();
+        stream.write_all(&request).unwrap();
+        let response = read_frame(&mut stream);
+        // Measured on the clock of the listener
+        clock.advance(Duration::from_millis(20));
+        stream
+            .write_all(&Message::heartbeat(
+                2,
+                1,
+                seq_nr + 1,
+                response.sequence_number(),
+                0,
+                0,
+            ))
+            .unwrap();
+        read_frame(&mut stream);
+
+        let snapshot = latency.snapshot();
+        assert_eq!(snapshot.confirmed, 1);
+        assert_eq!(snapshot.outstanding, 1);
+        assert_eq!(snapshot.max, Some(Duration::from_millis(20)));
+
+        shutdown.shutdown();
+        drop(stream);
+        let _ = TcpStream::connect(addr);
+        assert!(server.join().unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_timeouts_with_mock_clock() {
+        let clock = MockClock::new();
+        let mut listener = RastaListener::try_new("127.0.0.1:0", 2).unwrap();
+        listener.set_clock(clock.clone());
+        let addr = listener.local_addr().unwrap();
+        let shutdown = listener.shutdown_handle();
+        let incidents = listener.incidents();
+        let server = std::thread::spawn(move || listener.listen(|_| None::<Vec<u8>>));
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let request = Message::connection_request(2, 1, 0, N_SENDMAX);
+        let seq_nr = request.sequence_number();
+        stream.write_all(&request).unwrap();
+        read_frame(&mut stream);
+        let heartbeat = |n: u32| Message::heartbeat(2, 1, seq_nr + n, seq_nr + n, 0, 0);
+        stream.write_all(&heartbeat(1)).unwrap();
+        assert_eq!(
+            read_frame(&mut stream).try_message_type().unwrap(),
+            MessageType::HB
+        );
+
+        // The next heartbeat is late on the clock of the listener
+        clock.advance(RastaConfig::default().t_max + Duration::from_millis(1));
+        stream.write_all(&heartbeat(2)).unwrap();
+        assert_eq!(
+            read_frame(&mut stream).try_message_type().unwrap(),
+            MessageType::DiscReq
+        );
+        assert_eq!(incidents.latest().unwrap().kind, IncidentKind::Timeout);
+
+        shutdown.shutdown();
+        // Wakes the listener unless it has already seen the shutdown
+        let _ = TcpStream::connect(addr);
+        assert!(server.join().unwrap().is_ok());
+
+        let peer = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = peer.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = peer.accept().unwrap();
+            let request = read_frame(&mut stream);
+            let response =
+                Message::connection_response(1, 2, request.sequence_number(), 0, 0, N_SENDMAX);
+            stream.write_all(&response).unwrap();
+            let heartbeat = read_frame(&mut stream);
+            stream
+                .write_all(&Message::heartbeat(
+                    1,
+                    2,
+                    response.sequence_number().wrapping_add(1),
+                    heartbeat.sequence_number(),
+                    0,
+                    0,
+                ))
+                .unwrap();
+            (heartbeat, stream)
+        });
+        let mut conn = RastaConnection::try_new(addr, 1).unwrap();
+        conn.set_clock(clock.clone());
+        conn.open_connection(2).unwrap();
+        // No time passes during the round trip on the mock clock
+        assert_eq!(conn.round_trip_time(), Some(Duration::ZERO));
+        let interval = conn.heartbeat_interval();
+        assert_eq!(conn.time_until_heartbeat(), interval);
+        assert!(!conn.maintain().unwrap());
+        clock.advance(interval);
+        assert!(conn.maintain().unwrap());
+        let (heartbeat, stream) = server.join().unwrap();
+        assert_eq!(heartbeat.try_message_type().unwrap(), MessageType::HB);
+        drop(conn);
+        drop(stream);
+    }
+
+    #[test]
+    fn test_listener_reconnect_after_timeout() {
+        let clock = MockClock::new();
+        let mut listener = RastaListener::try_new("127.0.0.1:0", 2).unwrap();
+        listener.set_clock(clock.clone());
+        let addr = listener.local_addr().unwrap();
+        let shutdown = listener.shutdown_handle();
+        let server = std::thread::spawn(move || listener.listen(|_| None::<Vec<u8>>));
+
+        let connect = || {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            let request = Message::connection_request(2, 1, 0, N_SENDMAX);
+            stream.write_all(&request).unwrap();
+            let response = read_frame(&mut stream);
+            assert_eq!(response.try_message_type().unwrap(), MessageType::ConnResp);
+            assert_eq!(
+                response.confirmed_sequence_number(),
+                request.sequence_number()
+            );
+            let heartbeat = Message::heartbeat(
+                2,
+                1,
+                request.sequence_number().wrapping_add(1),
+                response.sequence_number(),
+                0,
+                0,
+            );
+            stream.write_all(&heartbeat).unwrap();
+            assert_eq!(
+                read_frame(&mut stream).try_message_type().unwrap(),
+                MessageType::HB
+            );
+            (stream, heartbeat)
+        };
+        let (mut stream, heartbeat) = connect();
+        clock.advance(RastaConfig::default().t_max + Duration::from_millis(1));
+        stream.write_all(&heartbeat).unwrap();
+        let response = read_frame(&mut stream);
+        assert_eq!(response.try_message_type().unwrap(), MessageType::DiscReq);
+        assert_eq!(response.disconnect_reason(), Some(DiscReason::Timeout));
+        drop(stream);
+
+        // The next connection starts with new sequence numbers
+        let (stream, _) = connect();
+
+        shutdown.shutdown();
+        drop(stream);
+        let _ = TcpStream::connect(addr);
+        assert!(server.join().unwrap().is_ok());
+    }
+
+    /// Accepts at most a few bytes per call and is interrupted every
+    /// other call, like a socket whose send buffer is nearly full.
+    #[derive(Default)]
+    struct ThrottledWriter {
+        written: Vec<u8>,
+        calls: usize,
+    }
+
+    impl Write for ThrottledWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.calls += 1;
+            if self.calls.is_multiple_of(2) {
+                return Err(std::io::ErrorKind::Interrupted.into());
+            }
+            let n = buf.len().min(7);
+            self.written.extend_from_slice(&buf[..n]);
+            Ok(n)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_throttled_writes() {
+        let mut sr = SrState::new(1, N_SENDMAX, usize::from(N_SENDMAX));
+        let response = sr.accept(&Message::connection_request(1, 2, 0, N_SENDMAX), 0);
+        let first = response.sequence_number().wrapping_add(1);
+        let data: [&[u8]; 3] = [b"first", b"", b"third"];
+        let mut writer = ListenerWriter {
+            writer: BufWriter::with_capacity(16, ThrottledWriter::default()),
+            sr,
+            latency: AckLatency::new(N_SENDMAX),
+        };
+        write_responses(
+            &mut writer,
+            &TraceControl::default(),
+            &MockClock::new(),
+            &NoSafetyCode,
+            data,
+        )
+        .unwrap();
+        write_responses(
+            &mut writer,
+            &TraceControl::default(),
+            &MockClock::new(),
+            &NoSafetyCode,
+            None::<&[u8]>,
+        )
+        .unwrap();
+        writer.flush().unwrap();
+
+        let mut frames = FrameBuffer::default();
+        frames.extend(&writer.get_mut().written);
+        for (n, data) in data.iter().enumerate() {
+            let frame = frames.next_frame().unwrap();
+            let msg = Message::try_parse(&frame).unwrap();
+            assert_eq!(msg.try_message_type().unwrap(), MessageType::Data);
+            assert_eq!(msg.receiver(), 2);
+            assert_eq!(msg.sequence_number(), first + n as u32);
+            assert_eq!(msg.data(), *data);
+        }
+        let frame = frames.next_frame().unwrap();
+        let msg = Message::try_parse(&frame).unwrap();
+        assert_eq!(msg.try_message_type().unwrap(), MessageType::HB);
+        assert_eq!(msg.sequence_number(), first + 3);
+        assert!(frames.next_frame().is_none());
+
+        let truncated = Message::from(&Message::heartbeat(2, 1, 0, 0, 0, 0)[..100]);
+        let mut writer = ThrottledWriter::default();
+        assert!(matches!(
+            write_sealed(
+                &mut writer,
+                &TraceControl::default(),
+                &NoSafetyCode,
+                truncated
+            ),
+            Err(RastaError::MessageTooShort { length: 100, .. })
+        ));
+        assert!(writer.written.is_empty());
+    }
 }
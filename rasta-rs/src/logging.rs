@@ -0,0 +1,15 @@
+//! Events of the protocol implementation, such as connections being
+//! opened and closed or messages being dropped. With the `tracing` feature
+//! they are emitted through `tracing`, otherwise they are discarded.
+
+/// Emits a `tracing` event at `$level` (`error`, `warn`, `info`, `debug`
+/// or `trace`) with the fields and message of the `tracing` macros.
+/// Expands to nothing without the `tracing` feature.
+macro_rules! event {
+    ($level:ident, $($arg:tt)+) => {
+        #[cfg(feature = "tracing")]
+        tracing::$level!($($arg)+)
+    };
+}
+
+pub(crate) use event;
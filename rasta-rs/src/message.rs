@@ -1,25 +1,81 @@
-use std::ops::Deref;
+use alloc::{sync::Arc, vec, vec::Vec};
+use core::ops::Deref;
 
-use crate::RastaError;
+use rasta_core::check_length;
 
-pub type RastaId = u32;
+use crate::{safety::SafetyCode, RastaError};
+
+pub use rasta_core::{
+    layout::{
+        DATA_OFFSET, MAX_DATA_LENGTH, MESSAGE_BUFFER_LENGTH, MIN_MESSAGE_LENGTH,
+        SECURITY_CODE_LENGTH,
+    },
+    RastaId,
+};
 
 /// The current RaSTA version as defined by the standard.
 pub const RASTA_VERSION: [u8; 4] = [0x30, 0x33, 0x30, 0x31];
 
+/// The offset of N_SENDMAX in the data of connection requests and responses.
+const N_SENDMAX_OFFSET: usize = 5;
+/// The offset of the advertised maximum data length in the data of
+/// connection requests and responses. The standard reserves these bytes,
+/// so peers that do not advertise a limit send zero.
+const MAX_DATA_LENGTH_OFFSET: usize = 7;
+/// The offset of the reason in the data of disconnection requests,
+/// which follows two bytes of detailed information.
+const DISC_REASON_OFFSET: usize = 2;
+
+/// The receive capabilities a peer announced in its connection request or response.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PeerLimits {
+    /// The number of messages the peer buffers.
+    pub n_sendmax: u16,
+    /// The maximum amount of data the peer accepts in one message.
+    pub max_data_length: usize,
+}
+
+/// The header fields of a [`Message`], see [`Message::metadata`].
+/// Unlike messages, they can be serialized with the `serde` feature,
+/// e.g. to record the sequence of messages exchanged in a test.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MessageMetadata {
+    /// The value of the message type field, see [`MessageMetadata::try_message_type`].
+    pub message_type_raw: u16,
+    pub receiver: RastaId,
+    pub sender: RastaId,
+    pub sequence_number: u32,
+    pub confirmed_sequence_number: u32,
+    pub timestamp: u32,
+    pub confirmed_timestamp: u32,
+    /// The length of [`Message::data`].
+    pub data_length: usize,
+}
+
+impl MessageMetadata {
+    /// The type of the message, or [`RastaError::UnknownMessageType`]
+    /// for messages of unknown types that were passed through.
+    pub fn try_message_type(&self) -> Result<MessageType, RastaError> {
+        MessageType::try_from(self.message_type_raw)
+    }
+}
+
 /// The RaSTA message type. Messages are a thin wrapper around
 /// byte arrays. You should never have to construct messages by hand,
 /// instead using the associated functions on [`Message`] or
-/// the [`MessageBuilder`] type.
+/// the [`MessageBuilder`] type. The frame is available through
+/// [`Deref`] and [`Message::into_bytes`].
 pub struct Message {
-    pub content: Vec<u8>,
+    content: Vec<u8>,
     data_len: Option<usize>,
 }
 
 impl Default for Message {
     fn default() -> Self {
         Self {
-            content: vec![0; 1024],
+            content: vec![0; MESSAGE_BUFFER_LENGTH],
             data_len: None,
         }
     }
@@ -28,13 +84,12 @@ impl Default for Message {
 #[derive(Default)]
 pub struct MessageBuilder {
     msg: Message,
+    safety_code: Option<Arc<dyn SafetyCode>>,
 }
 
 impl MessageBuilder {
     pub fn new() -> Self {
-        Self {
-            msg: Message::default(),
-        }
+        Self::default()
     }
 
     pub fn length(mut self, len: u16) -> Self {
@@ -89,47 +144,169 @@ impl MessageBuilder {
         self
     }
 
-    pub fn build(self) -> Message {
+    /// Computes the security code with `safety_code` when the message
+    /// is built, replacing a code set with [`MessageBuilder::security_code`].
+    pub fn safety_code(mut self, safety_code: Arc<dyn SafetyCode>) -> Self {
+        self.safety_code.replace(safety_code);
+        self
+    }
+
+    pub fn build(mut self) -> Message {
+        if let Some(safety_code) = &self.safety_code {
+            self.msg.seal(&**safety_code);
+        }
         self.msg
     }
 }
 
 impl Message {
+    /// Checks that `frame` can be read as a [`Message`] without panicking:
+    /// the header is complete, the message type is known, the length field
+    /// is at least the minimum for that type and all data it announces
+    /// is present.
+    pub fn validate_frame(frame: &[u8]) -> Result<(), RastaError> {
+        if frame.len() < DATA_OFFSET {
+            return Err(RastaError::MessageTooShort {
+                length: frame.len(),
+                min: DATA_OFFSET,
+            });
+        }
+        let message_type = MessageType::try_from(u16::from_be_bytes([frame[3], frame[4]]))?;
+        let length = u16::from_be_bytes([frame[0], frame[1]]);
+        let min_length = message_type.min_length();
+        if length < min_length {
+            return Err(RastaError::MessageTooShort {
+                length: length.into(),
+                min: min_length.into(),
+            });
+        }
+        let data_end = DATA_OFFSET + usize::from(length.saturating_sub(MIN_MESSAGE_LENGTH));
+        if frame.len() < data_end {
+            return Err(RastaError::MessageTooShort {
+                length: frame.len(),
+                min: data_end,
+            });
+        }
+        Ok(())
+    }
+
+    /// Parses an untrusted buffer, such as a received frame, and fails
+    /// instead of panicking on any input. Besides the checks of
+    /// [`Message::validate_frame`], the buffer has to be exactly one frame
+    /// of [`MESSAGE_BUFFER_LENGTH`] bytes and the length field has to be
+    /// valid for the message type, so that all announced data ends before
+    /// the security code.
+    pub fn try_parse(buf: &[u8]) -> Result<Self, RastaError> {
+        let msg = Self::try_parse_passthrough(buf)?;
+        msg.try_message_type()?;
+        Ok(msg)
+    }
+
+    /// Like [`Message::try_parse`], but accepts frames of unknown message
+    /// types with the length bounds of data messages, so that bridges can
+    /// forward them. Use [`Message::try_message_type`] on the result.
+    pub fn try_parse_passthrough(buf: &[u8]) -> Result<Self, RastaError> {
+        check_length(buf.len(), MESSAGE_BUFFER_LENGTH..=MESSAGE_BUFFER_LENGTH)?;
+        let message_type = MessageType::try_from(u16::from_be_bytes([buf[3], buf[4]]))
+            .unwrap_or(MessageType::Data);
+        let length = u16::from_be_bytes([buf[0], buf[1]]);
+        check_length(
+            length.into(),
+            message_type.min_length().into()..=message_type.max_length().into(),
+        )?;
+        Ok(Self::from(buf))
+    }
+
+    /// The value of the length field for a message carrying `data`.
+    fn length_for(data: &[u8]) -> Result<u16, RastaError> {
+        let too_long = || RastaError::MessageTooLong {
+            length: data.len(),
+            max: MAX_DATA_LENGTH,
+        };
+        if data.len() > MAX_DATA_LENGTH {
+            return Err(too_long());
+        }
+        u16::try_from(data.len())
+            .ok()
+            .and_then(|len| len.checked_add(MIN_MESSAGE_LENGTH))
+            .ok_or_else(too_long)
+    }
+
     pub fn length(&self) -> u16 {
-        u16::from_ne_bytes(self.content[0..2].try_into().unwrap())
+        u16::from_be_bytes(self.content[0..2].try_into().unwrap())
     }
 
+    /// The type of the message.
+    ///
+    /// # Panics
+    ///
+    /// If the type is unknown, which only messages that were not parsed
+    /// with [`Message::try_parse`] can have. Use
+    /// [`Message::try_message_type`] for any other message.
+    #[deprecated(note = "panics on unknown message types, use `try_message_type` instead")]
     pub fn message_type(&self) -> MessageType {
-        let msg_type = u16::from_ne_bytes(self.content[3..5].try_into().unwrap());
-        MessageType::try_from(msg_type).unwrap()
+        self.try_message_type().unwrap()
+    }
+
+    /// The type of the message, or [`RastaError::UnknownMessageType`].
+    pub fn try_message_type(&self) -> Result<MessageType, RastaError> {
+        MessageType::try_from(self.message_type_raw())
+    }
+
+    /// The value of the message type field, whether it is known or not.
+    pub fn message_type_raw(&self) -> u16 {
+        u16::from_be_bytes(self.content[3..5].try_into().unwrap())
     }
 
     pub fn receiver(&self) -> RastaId {
-        u32::from_ne_bytes(self.content[6..10].try_into().unwrap())
+        u32::from_be_bytes(self.content[6..10].try_into().unwrap())
     }
 
     pub fn sender(&self) -> RastaId {
-        u32::from_ne_bytes(self.content[10..14].try_into().unwrap())
+        u32::from_be_bytes(self.content[10..14].try_into().unwrap())
     }
 
     pub fn sequence_number(&self) -> u32 {
-        u32::from_ne_bytes(self.content[15..19].try_into().unwrap())
+        u32::from_be_bytes(self.content[15..19].try_into().unwrap())
     }
 
     pub fn confirmed_sequence_number(&self) -> u32 {
-        u32::from_ne_bytes(self.content[19..23].try_into().unwrap())
+        u32::from_be_bytes(self.content[19..23].try_into().unwrap())
     }
 
     pub fn timestamp(&self) -> u32 {
-        u32::from_ne_bytes(self.content[24..28].try_into().unwrap())
+        u32::from_be_bytes(self.content[24..28].try_into().unwrap())
     }
 
     pub fn confirmed_timestamp(&self) -> u32 {
-        u32::from_ne_bytes(self.content[29..33].try_into().unwrap())
+        u32::from_be_bytes(self.content[29..33].try_into().unwrap())
+    }
+
+    /// The header fields of the message.
+    pub fn metadata(&self) -> MessageMetadata {
+        MessageMetadata {
+            message_type_raw: self.message_type_raw(),
+            receiver: self.receiver(),
+            sender: self.sender(),
+            sequence_number: self.sequence_number(),
+            confirmed_sequence_number: self.confirmed_sequence_number(),
+            timestamp: self.timestamp(),
+            confirmed_timestamp: self.confirmed_timestamp(),
+            data_length: self.data().len(),
+        }
+    }
+
+    /// Takes the frame of the message.
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.content
     }
 
+    /// The data carried by the message. Empty if the message was built without data.
     pub fn data(&self) -> &[u8] {
-        &self.content[34..(34 + self.data_len.unwrap())]
+        match self.data_len {
+            Some(len) => &self.content[DATA_OFFSET..(DATA_OFFSET + len)],
+            None => &[],
+        }
     }
 
     pub fn security_code(&self) -> &[u8] {
@@ -137,6 +314,108 @@ impl Message {
         &self.content[(len - 8)..len]
     }
 
+    /// The part of the message covered by the safety code:
+    /// the header and the data announced by the length field.
+    fn protected(&self) -> &[u8] {
+        let end = DATA_OFFSET + usize::from(self.length().saturating_sub(MIN_MESSAGE_LENGTH));
+        &self.content[..end.min(self.content.len() - SECURITY_CODE_LENGTH)]
+    }
+
+    /// Writes the safety code of the message to its security code field.
+    pub fn seal(&mut self, safety_code: &dyn SafetyCode) {
+        let code = safety_code.compute(self.protected());
+        let len = self.content.len();
+        self.content[(len - SECURITY_CODE_LENGTH)..].copy_from_slice(&code);
+    }
+
+    /// Checks the security code field against the safety code of the message.
+    pub fn verify(&self, safety_code: &dyn SafetyCode) -> Result<(), RastaError> {
+        if safety_code.compute(self.protected()) == self.security_code() {
+            Ok(())
+        } else {
+            Err(RastaError::SafetyCodeMismatch)
+        }
+    }
+
+    /// Announces the maximum amount of data the sender accepts in one message.
+    /// Only has an effect on connection requests and responses.
+    pub fn advertise_max_data_length(&mut self, max: usize) {
+        if matches!(
+            self.try_message_type(),
+            Ok(MessageType::ConnReq | MessageType::ConnResp)
+        ) && self.data().len() >= MAX_DATA_LENGTH_OFFSET + 2
+        {
+            let max = u16::try_from(max.min(MAX_DATA_LENGTH)).unwrap();
+            let offset = DATA_OFFSET + MAX_DATA_LENGTH_OFFSET;
+            self.content[offset..offset + 2].copy_from_slice(&max.to_be_bytes());
+        }
+    }
+
+    /// The receive capabilities announced in a connection request or response.
+    /// Peers that do not advertise a maximum data length accept [`MAX_DATA_LENGTH`].
+    pub fn peer_limits(&self) -> Option<PeerLimits> {
+        if !matches!(
+            self.try_message_type(),
+            Ok(MessageType::ConnReq | MessageType::ConnResp)
+        ) {
+            return None;
+        }
+        let data = self.data();
+        let n_sendmax = data.get(N_SENDMAX_OFFSET..N_SENDMAX_OFFSET + 2)?;
+        let max_data_length = data.get(MAX_DATA_LENGTH_OFFSET..MAX_DATA_LENGTH_OFFSET + 2)?;
+        let max_data_length = match u16::from_be_bytes(max_data_length.try_into().unwrap()) {
+            0 => MAX_DATA_LENGTH,
+            max => usize::from(max).min(MAX_DATA_LENGTH),
+        };
+        Some(PeerLimits {
+            n_sendmax: u16::from_be_bytes(n_sendmax.try_into().unwrap()),
+            max_data_length,
+        })
+    }
+
+    /// Sets why the connection is closed.
+    /// Only has an effect on disconnection requests.
+    pub fn set_disconnect_reason(&mut self, reason: DiscReason) {
+        if let Some(offset) = self.disconnect_reason_offset() {
+            self.content[offset..offset + 2].copy_from_slice(&(reason as u16).to_be_bytes());
+        }
+    }
+
+    /// Why the sender of a disconnection request closes the connection.
+    /// Reasons the standard does not define are `None`.
+    pub fn disconnect_reason(&self) -> Option<DiscReason> {
+        let offset = self.disconnect_reason_offset()?;
+        let reason = u16::from_be_bytes(self.content[offset..offset + 2].try_into().unwrap());
+        DiscReason::try_from(reason).ok()
+    }
+
+    /// Where the reason of a disconnection request is, going by the length
+    /// field: disconnection requests are built without data.
+    fn disconnect_reason_offset(&self) -> Option<usize> {
+        let offset = DATA_OFFSET + DISC_REASON_OFFSET;
+        (matches!(self.try_message_type(), Ok(MessageType::DiscReq))
+            && usize::from(self.length().saturating_sub(MIN_MESSAGE_LENGTH))
+                >= DISC_REASON_OFFSET + 2
+            && self.content.len() >= offset + 2)
+            .then_some(offset)
+    }
+
+    /// Turns a retransmitted data message into a regular one, so that
+    /// its data is handled like any other once it has been verified.
+    #[cfg(feature = "std")]
+    pub(crate) fn retransmitted_to_data(&mut self) {
+        if self.message_type_raw() == MessageType::RetrData as u16 {
+            self.content[3..5].copy_from_slice(&(MessageType::Data as u16).to_be_bytes());
+        }
+    }
+
+    /// Replaces the sequence number, e.g. the random initial one of a
+    /// connection request. The message has to be sealed afterwards.
+    #[cfg(feature = "std")]
+    pub(crate) fn set_sequence_number(&mut self, sequence_number: u32) {
+        self.content[15..19].copy_from_slice(&sequence_number.to_be_bytes());
+    }
+
     pub fn connection_request(
         receiver: RastaId,
         sender: RastaId,
@@ -145,12 +424,11 @@ impl Message {
     ) -> Self {
         let mut data = [0; 14];
         data[..4].copy_from_slice(&RASTA_VERSION);
-        data[5..7].copy_from_slice(&n_sendmax.to_be_bytes());
-        let initial_seq_nr = if cfg!(feature = "rand") {
-            rand::random()
-        } else {
-            4
-        };
+        data[N_SENDMAX_OFFSET..N_SENDMAX_OFFSET + 2].copy_from_slice(&n_sendmax.to_be_bytes());
+        #[cfg(feature = "rand")]
+        let initial_seq_nr = rand::random();
+        #[cfg(not(feature = "rand"))]
+        let initial_seq_nr = 4;
         MessageBuilder::new()
             .length(50)
             .message_type(MessageType::ConnReq)
@@ -175,8 +453,8 @@ impl Message {
     ) -> Self {
         let mut data = [0; 14];
         data[..4].copy_from_slice(&RASTA_VERSION);
-        data[5..7].copy_from_slice(&n_sendmax.to_be_bytes());
-        let sequence_number = confirmed_sequence_number + 1;
+        data[N_SENDMAX_OFFSET..N_SENDMAX_OFFSET + 2].copy_from_slice(&n_sendmax.to_be_bytes());
+        let sequence_number = confirmed_sequence_number.wrapping_add(1);
         MessageBuilder::new()
             .length(50)
             .message_type(MessageType::ConnResp)
@@ -287,9 +565,9 @@ impl Message {
         timestamp: u32,
         confirmed_timestamp: u32,
         data: &[u8],
-    ) -> Self {
-        MessageBuilder::new()
-            .length((36 + data.len()) as u16)
+    ) -> Result<Self, RastaError> {
+        Ok(MessageBuilder::new()
+            .length(Self::length_for(data)?)
             .message_type(MessageType::Data)
             .receiver(receiver)
             .sender(sender)
@@ -299,7 +577,7 @@ impl Message {
             .confirmed_timestamp(confirmed_timestamp)
             .data(data)
             .security_code(&[0; 8])
-            .build()
+            .build())
     }
 
     pub fn retransmitted_data_message(
@@ -310,9 +588,9 @@ impl Message {
         timestamp: u32,
         confirmed_timestamp: u32,
         data: &[u8],
-    ) -> Self {
-        MessageBuilder::new()
-            .length((36 + data.len()) as u16)
+    ) -> Result<Self, RastaError> {
+        Ok(MessageBuilder::new()
+            .length(Self::length_for(data)?)
             .message_type(MessageType::RetrData)
             .receiver(receiver)
             .sender(sender)
@@ -322,20 +600,23 @@ impl Message {
             .confirmed_timestamp(confirmed_timestamp)
             .data(data)
             .security_code(&[0; 8])
-            .build()
+            .build())
     }
 }
 
+/// Copies a frame without validating it, so it is only meant for trusted
+/// buffers. Data announced by the length field beyond the end of the frame
+/// is cut off, and accessors may panic on buffers shorter than the header.
+/// See [`Message::try_parse`] for a checked conversion.
 impl From<&[u8]> for Message {
     fn from(val: &[u8]) -> Self {
         let mut content = Vec::new();
         content.extend_from_slice(val);
-        let length = u16::from_ne_bytes(content[0..2].try_into().unwrap());
-        let data_len = length - 36;
-        Self {
-            content,
-            data_len: Some(data_len.into()),
-        }
+        let data_len = (val.len() >= DATA_OFFSET).then(|| {
+            let length = u16::from_be_bytes([val[0], val[1]]);
+            usize::from(length.saturating_sub(MIN_MESSAGE_LENGTH)).min(val.len() - DATA_OFFSET)
+        });
+        Self { content, data_len }
     }
 }
 
@@ -347,7 +628,8 @@ impl Deref for Message {
     }
 }
 
-#[derive(PartialEq, Eq, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u16)]
 pub enum MessageType {
     ConnReq = 6200,
@@ -360,6 +642,27 @@ pub enum MessageType {
     RetrData = 6241,
 }
 
+impl MessageType {
+    /// The smallest valid value of the length field for this message type.
+    pub fn min_length(&self) -> u16 {
+        match self {
+            Self::ConnReq | Self::ConnResp => 50,
+            Self::DiscReq => 40,
+            _ => MIN_MESSAGE_LENGTH,
+        }
+    }
+
+    /// The largest valid value of the length field for this message type.
+    /// Only data messages carry a variable amount of data.
+    pub fn max_length(&self) -> u16 {
+        match self {
+            // MAX_DATA_LENGTH fits into the length field
+            Self::Data | Self::RetrData => MIN_MESSAGE_LENGTH + MAX_DATA_LENGTH as u16,
+            _ => self.min_length(),
+        }
+    }
+}
+
 impl TryFrom<u16> for MessageType {
     type Error = RastaError;
 
@@ -373,9 +676,272 @@ impl TryFrom<u16> for MessageType {
             6220 => Ok(Self::HB),
             6240 => Ok(Self::Data),
             6241 => Ok(Self::RetrData),
-            n => Err(RastaError::Other(format!(
-                "Value {n} is not a valid Message Type"
-            ))),
+            n => Err(RastaError::UnknownMessageType(n)),
+        }
+    }
+}
+
+/// The reasons for closing a connection given in disconnection requests.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u16)]
+pub enum DiscReason {
+    UserRequest = 0,
+    UnexpectedMessage = 2,
+    SequenceNumberError = 3,
+    Timeout = 4,
+    ServiceNotAllowed = 5,
+    ProtocolVersionError = 6,
+    RetransmissionFailed = 7,
+    ProtocolSequenceError = 8,
+}
+
+impl TryFrom<u16> for DiscReason {
+    type Error = u16;
+
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::UserRequest),
+            2 => Ok(Self::UnexpectedMessage),
+            3 => Ok(Self::SequenceNumberError),
+            4 => Ok(Self::Timeout),
+            5 => Ok(Self::ServiceNotAllowed),
+            6 => Ok(Self::ProtocolVersionError),
+            7 => Ok(Self::RetransmissionFailed),
+            8 => Ok(Self::ProtocolSequenceError),
+            n => Err(n),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_header(msg: &Message, length: u16, message_type: MessageType) {
+        assert_eq!(msg.length(), length);
+        assert_eq!(msg.try_message_type().unwrap(), message_type);
+        assert_eq!(msg.receiver(), 1);
+        assert_eq!(msg.sender(), 2);
+    }
+
+    #[test]
+    fn test_validate_frame() {
+        let msg = Message::connection_request(1, 2, 3, 4);
+        assert!(Message::validate_frame(&msg).is_ok());
+        assert!(Message::validate_frame(&msg[..10]).is_err());
+        assert!(Message::validate_frame(&msg[..40]).is_err());
+
+        let mut truncated = msg.content.clone();
+        truncated[0..2].copy_from_slice(&40u16.to_be_bytes());
+        assert!(Message::validate_frame(&truncated).is_err());
+
+        let mut unknown = msg.content.clone();
+        unknown[3..5].copy_from_slice(&1u16.to_be_bytes());
+        assert!(Message::validate_frame(&unknown).is_err());
+    }
+
+    #[test]
+    fn test_try_parse() {
+        let messages = [
+            Message::connection_request(1, 2, 3, 4),
+            Message::connection_response(1, 2, 3, 4, 5, 6),
+            Message::retransmission_request(1, 2, 3, 4, 5, 6),
+            Message::retransmission_response(1, 2, 3, 4, 5, 6),
+            Message::disconnection_request(1, 2, 3, 4, 5, 6),
+            Message::heartbeat(1, 2, 3, 4, 5, 6),
+            Message::data_message(1, 2, 3, 4, 5, 6, &[7; MAX_DATA_LENGTH]).unwrap(),
+        ];
+        for msg in &messages {
+            assert_eq!(&*Message::try_parse(msg).unwrap(), &**msg);
+            // No truncation or change of the header panics
+            for end in 0..msg.len() {
+                assert!(Message::try_parse(&msg[..end]).is_err());
+            }
+            for offset in 0..DATA_OFFSET {
+                for byte in [0x00, 0x01, 0x7f, 0xff] {
+                    let mut frame = msg.content.clone();
+                    frame[offset] = byte;
+                    if let Ok(parsed) = Message::try_parse(&frame) {
+                        parsed.data();
+                        parsed.security_code();
+                    }
+                }
+            }
+        }
+
+        // Data running into the security code
+        let mut frame = messages[6].content.clone();
+        frame[0..2]
+            .copy_from_slice(&(MIN_MESSAGE_LENGTH + MAX_DATA_LENGTH as u16 + 1).to_be_bytes());
+        assert!(matches!(
+            Message::try_parse(&frame),
+            Err(RastaError::MessageTooLong { .. })
+        ));
+        // Heartbeats carry no data
+        let mut frame = messages[5].content.clone();
+        frame[0..2].copy_from_slice(&(MIN_MESSAGE_LENGTH + 2).to_be_bytes());
+        assert!(Message::try_parse(&frame).is_err());
+        let mut frame = messages[5].content.clone();
+        frame.push(0);
+        assert!(Message::try_parse(&frame).is_err());
+    }
+
+    #[test]
+    fn test_unknown_message_type() {
+        let mut frame = Message::data_message(1, 2, 3, 4, 5, 6, &[7; 8])
+            .unwrap()
+            .content;
+        frame[3..5].copy_from_slice(&6300u16.to_be_bytes());
+        assert!(matches!(
+            Message::try_parse(&frame),
+            Err(RastaError::UnknownMessageType(6300))
+        ));
+        let msg = Message::try_parse_passthrough(&frame).unwrap();
+        assert_eq!(msg.message_type_raw(), 6300);
+        assert!(msg.try_message_type().is_err());
+        assert_eq!(msg.data(), &[7; 8]);
+        let metadata = msg.metadata();
+        assert_eq!(metadata.message_type_raw, 6300);
+        assert!(matches!(
+            metadata.try_message_type(),
+            Err(RastaError::UnknownMessageType(6300))
+        ));
+        assert_eq!(metadata.sequence_number, 3);
+        assert_eq!(metadata.data_length, 8);
+    }
+
+    #[test]
+    fn test_default_message_has_no_data() {
+        assert!(Message::default().data().is_empty());
+        assert!(MessageBuilder::new().build().data().is_empty());
+    }
+
+    #[test]
+    fn test_connection_request_accessors() {
+        let msg = Message::connection_request(1, 2, 3, 4);
+        assert_header(&msg, 50, MessageType::ConnReq);
+        assert_eq!(msg.confirmed_sequence_number(), 0);
+        assert_eq!(msg.timestamp(), 3);
+        assert_eq!(msg.confirmed_timestamp(), 0);
+        assert_eq!(msg.data().len(), 14);
+        assert_eq!(&msg.data()[..4], &RASTA_VERSION);
+    }
+
+    #[test]
+    fn test_connection_response_accessors() {
+        let msg = Message::connection_response(1, 2, 3, 4, 5, 6);
+        assert_header(&msg, 50, MessageType::ConnResp);
+        assert_eq!(msg.sequence_number(), 4);
+        assert_eq!(msg.confirmed_sequence_number(), 3);
+        assert_eq!(msg.timestamp(), 4);
+        assert_eq!(msg.confirmed_timestamp(), 5);
+        assert_eq!(&msg.data()[..4], &RASTA_VERSION);
+    }
+
+    #[test]
+    fn test_peer_limits() {
+        let mut msg = Message::connection_response(1, 2, 3, 4, 5, 6);
+        let limits = msg.peer_limits().unwrap();
+        assert_eq!(limits.n_sendmax, 6);
+        assert_eq!(limits.max_data_length, MAX_DATA_LENGTH);
+        msg.advertise_max_data_length(100);
+        assert_eq!(msg.peer_limits().unwrap().max_data_length, 100);
+        msg.advertise_max_data_length(MAX_DATA_LENGTH + 1);
+        assert_eq!(msg.peer_limits().unwrap().max_data_length, MAX_DATA_LENGTH);
+        assert!(Message::heartbeat(1, 2, 3, 4, 5, 6).peer_limits().is_none());
+    }
+
+    #[test]
+    fn test_disconnect_reason() {
+        let mut msg = Message::disconnection_request(1, 2, 3, 4, 5, 6);
+        assert_eq!(msg.disconnect_reason(), Some(DiscReason::UserRequest));
+        msg.set_disconnect_reason(DiscReason::SequenceNumberError);
+        assert_eq!(
+            msg.disconnect_reason(),
+            Some(DiscReason::SequenceNumberError)
+        );
+        let parsed = Message::try_parse(&msg).unwrap();
+        assert_eq!(parsed.data(), &[0, 0, 0, 3]);
+        assert_eq!(
+            parsed.disconnect_reason(),
+            Some(DiscReason::SequenceNumberError)
+        );
+        let mut msg = Message::heartbeat(1, 2, 3, 4, 5, 6);
+        msg.set_disconnect_reason(DiscReason::Timeout);
+        assert_eq!(msg.disconnect_reason(), None);
+        assert_eq!(msg.security_code(), &[0; 8]);
+    }
+
+    #[test]
+    fn test_messages_without_data_accessors() {
+        let messages = [
+            (
+                Message::retransmission_request(1, 2, 3, 4, 5, 6),
+                36,
+                MessageType::RetrReq,
+            ),
+            (
+                Message::retransmission_response(1, 2, 3, 4, 5, 6),
+                36,
+                MessageType::RetrResp,
+            ),
+            (Message::heartbeat(1, 2, 3, 4, 5, 6), 36, MessageType::HB),
+            (
+                Message::disconnection_request(1, 2, 3, 4, 5, 6),
+                40,
+                MessageType::DiscReq,
+            ),
+        ];
+        for (msg, length, message_type) in messages {
+            assert_header(&msg, length, message_type);
+            assert_eq!(msg.sequence_number(), 3);
+            assert_eq!(msg.confirmed_sequence_number(), 4);
+            assert_eq!(msg.timestamp(), 5);
+            assert_eq!(msg.confirmed_timestamp(), 6);
+            assert!(msg.data().is_empty());
+            assert_eq!(msg.security_code(), &[0; 8]);
+        }
+    }
+
+    #[test]
+    fn test_data_message_accessors() {
+        let messages = [
+            (
+                Message::data_message(1, 2, 3, 4, 5, 6, &[7, 8]).unwrap(),
+                MessageType::Data,
+            ),
+            (
+                Message::retransmitted_data_message(1, 2, 3, 4, 5, 6, &[7, 8]).unwrap(),
+                MessageType::RetrData,
+            ),
+        ];
+        for (msg, message_type) in messages {
+            assert_header(&msg, 38, message_type);
+            assert_eq!(msg.sequence_number(), 3);
+            assert_eq!(msg.confirmed_sequence_number(), 4);
+            assert_eq!(msg.data(), &[7, 8]);
+            let parsed = Message::from(&msg[..]);
+            assert_eq!(parsed.data(), &[7, 8]);
         }
     }
+
+    #[test]
+    fn test_length_arithmetic() {
+        let too_long = [0; MAX_DATA_LENGTH + 1];
+        assert!(matches!(
+            Message::data_message(1, 2, 3, 4, 5, 6, &too_long),
+            Err(RastaError::MessageTooLong { .. })
+        ));
+        assert!(Message::data_message(1, 2, 3, 4, 5, 6, &too_long[1..]).is_ok());
+
+        let mut short = Message::heartbeat(1, 2, 3, 4, 5, 6).content;
+        short[0..2].copy_from_slice(&2u16.to_be_bytes());
+        assert!(Message::try_parse(&short).is_err());
+        assert!(Message::from(&short[..]).data().is_empty());
+        assert!(Message::from(&short[..1]).data().is_empty());
+
+        let response = Message::connection_response(1, 2, u32::MAX, 0, 0, 1);
+        assert_eq!(response.sequence_number(), 0);
+    }
 }
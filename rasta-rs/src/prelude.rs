@@ -0,0 +1,11 @@
+//! The types needed by most users of RaSTA connections.
+//! Import them all with `use rasta_rs::prelude::*;`.
+
+#[cfg(feature = "std")]
+pub use crate::{
+    config::RastaConfig, RastaCommand, RastaConnection, RastaConnectionState, RastaListener,
+};
+pub use crate::{
+    message::{Message, MessageType, RastaId},
+    RastaError, RecoveryAction,
+};
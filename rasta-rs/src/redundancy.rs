@@ -0,0 +1,446 @@
+//! The redundancy layer (RedL) of RaSTA over UDP. A [`RedundancyChannel`]
+//! sends every message over several UDP transport channels at once, so
+//! that the loss of a channel or of single datagrams goes unnoticed by the
+//! safety layer. The receiving side delivers each message once and in
+//! order: duplicates received over another channel are discarded, and
+//! messages that overtook a lost one are deferred until the gap is closed
+//! or the defer timeout has passed.
+//!
+//! Every message is prefixed with the RedL header: the length of the PDU
+//! (2 bytes), a reserved field (2 bytes) and the redundancy sequence
+//! number (4 bytes), followed by an optional check code. Like the rest of
+//! the crate, all fields are big endian.
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    io::ErrorKind,
+    net::{SocketAddr, UdpSocket},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    message::{Message, MESSAGE_BUFFER_LENGTH},
+    RastaError,
+};
+
+/// The length of the RedL header preceding each message.
+pub const REDUNDANCY_HEADER_LENGTH: usize = 8;
+
+/// How often [`RedundancyChannel::receive`] polls the transport channels.
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// The check code appended to each RedL PDU to detect corruption on
+/// channels that do not protect their payload themselves.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CheckCode {
+    #[default]
+    None,
+    /// CRC-32 as used by Ethernet (polynomial `0x04C11DB7`).
+    Crc32,
+}
+
+impl CheckCode {
+    pub fn len(&self) -> usize {
+        match self {
+            Self::None => 0,
+            Self::Crc32 => 4,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn compute(&self, pdu: &[u8]) -> Vec<u8> {
+        match self {
+            Self::None => Vec::new(),
+            Self::Crc32 => crc32(pdu).to_be_bytes().to_vec(),
+        }
+    }
+}
+
+/// Settings of a [`RedundancyChannel`]. Both peers have
+/// to use the same [`CheckCode`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct RedundancyConfig {
+    pub check_code: CheckCode,
+    /// The number of messages kept while an earlier one is missing
+    /// (N_deferqueue). If the queue overflows, the gap is skipped.
+    pub defer_queue_size: usize,
+    /// How long a message waits for a missing predecessor (T_seq)
+    /// before the gap is skipped.
+    pub defer_timeout: Duration,
+}
+
+impl Default for RedundancyConfig {
+    fn default() -> Self {
+        Self {
+            check_code: CheckCode::None,
+            defer_queue_size: 4,
+            defer_timeout: Duration::from_millis(50),
+        }
+    }
+}
+
+/// Counters of a [`RedundancyChannel`], e.g. to detect
+/// a transport channel that stopped working.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RedundancyStats {
+    /// Copies of already delivered messages.
+    pub duplicates: u64,
+    /// Messages that arrived before a missing predecessor.
+    pub deferred: u64,
+    /// Messages that were never received on any channel.
+    pub lost: u64,
+    /// Datagrams that were too short or failed the check code.
+    pub malformed: u64,
+}
+
+/// The ordering of received messages by their redundancy sequence number.
+#[derive(Default)]
+struct Sequencer {
+    config: RedundancyConfig,
+    expected: u32,
+    deferred: BTreeMap<u32, (Instant, Message)>,
+    ready: VecDeque<Message>,
+    stats: RedundancyStats,
+}
+
+impl Sequencer {
+    /// The distance of `sequence_number` ahead of the expected one,
+    /// or `None` if it lies behind and was therefore seen before.
+    fn ahead(&self, sequence_number: u32) -> Option<u32> {
+        let distance = sequence_number.wrapping_sub(self.expected);
+        (distance <= u32::MAX / 2).then_some(distance)
+    }
+
+    fn accept(&mut self, sequence_number: u32, msg: Message, now: Instant) {
+        match self.ahead(sequence_number) {
+            None => self.stats.duplicates += 1,
+            Some(0) => {
+                self.deliver(sequence_number, msg);
+                self.release_consecutive();
+            }
+            Some(_) if self.deferred.contains_key(&sequence_number) => {
+                self.stats.duplicates += 1;
+            }
+            Some(_) => {
+                self.stats.deferred += 1;
+                self.deferred.insert(sequence_number, (now, msg));
+                if self.deferred.len() > self.config.defer_queue_size {
+                    self.skip_gap();
+                }
+            }
+        }
+    }
+
+    /// Skips the gap before the oldest deferred message
+    /// once it has waited for longer than the defer timeout.
+    fn expire(&mut self, now: Instant) {
+        while self
+            .oldest_deferred()
+            .is_some_and(|received| now.duration_since(received) >= self.config.defer_timeout)
+        {
+            self.skip_gap();
+        }
+    }
+
+    fn oldest_deferred(&self) -> Option<Instant> {
+        let expected = self.expected;
+        self.deferred
+            .iter()
+            .min_by_key(|(sequence_number, _)| sequence_number.wrapping_sub(expected))
+            .map(|(_, (received, _))| *received)
+    }
+
+    fn skip_gap(&mut self) {
+        let expected = self.expected;
+        let Some(next) = self
+            .deferred
+            .keys()
+            .copied()
+            .min_by_key(|sequence_number| sequence_number.wrapping_sub(expected))
+        else {
+            return;
+        };
+        self.stats.lost += u64::from(next.wrapping_sub(expected));
+        let (_, msg) = self.deferred.remove(&next).unwrap();
+        self.deliver(next, msg);
+        self.release_consecutive();
+    }
+
+    fn deliver(&mut self, sequence_number: u32, msg: Message) {
+        self.ready.push_back(msg);
+        self.expected = sequence_number.wrapping_add(1);
+    }
+
+    fn release_consecutive(&mut self) {
+        while let Some((_, msg)) = self.deferred.remove(&self.expected) {
+            self.deliver(self.expected, msg);
+        }
+    }
+}
+
+/// Sends and receives RaSTA messages over several redundant UDP
+/// transport channels. The `n`th local socket sends to and receives
+/// from the `n`th remote address.
+///
+/// The channel only carries messages; the safety layer (sequence numbers
+/// and timestamps of the messages themselves) is left to the application.
+pub struct RedundancyChannel {
+    sockets: Vec<UdpSocket>,
+    config: RedundancyConfig,
+    next_sequence_number: u32,
+    sequencer: Sequencer,
+}
+
+impl RedundancyChannel {
+    /// Binds one UDP socket per address in `local` and connects it to the
+    /// address at the same position in `remote`.
+    pub fn bind(
+        local: &[SocketAddr],
+        remote: &[SocketAddr],
+        config: RedundancyConfig,
+    ) -> Result<Self, RastaError> {
+        if local.is_empty() || local.len() != remote.len() {
+            return Err(RastaError::Other(
+                "Every transport channel needs a local and a remote address".to_string(),
+            ));
+        }
+        let sockets = local
+            .iter()
+            .zip(remote)
+            .map(|(local, remote)| {
+                let socket = UdpSocket::bind(local)?;
+                socket.connect(remote)?;
+                socket.set_nonblocking(true)?;
+                Ok(socket)
+            })
+            .collect::<std::io::Result<Vec<_>>>()
+            .map_err(RastaError::from)?;
+        Ok(Self {
+            sockets,
+            config,
+            next_sequence_number: 0,
+            sequencer: Sequencer {
+                config,
+                ..Default::default()
+            },
+        })
+    }
+
+    /// The addresses the transport channels are bound to,
+    /// e.g. to learn the ports chosen when binding to port 0.
+    pub fn local_addrs(&self) -> Result<Vec<SocketAddr>, RastaError> {
+        self.sockets
+            .iter()
+            .map(|socket| socket.local_addr().map_err(RastaError::from))
+            .collect()
+    }
+
+    pub fn config(&self) -> RedundancyConfig {
+        self.config
+    }
+
+    pub fn stats(&self) -> RedundancyStats {
+        self.sequencer.stats
+    }
+
+    /// Sends `msg` over all transport channels. Fails only
+    /// if it could not be sent over any of them.
+    pub fn send(&mut self, msg: &Message) -> Result<(), RastaError> {
+        let pdu = self.encode(self.next_sequence_number, msg)?;
+        self.next_sequence_number = self.next_sequence_number.wrapping_add(1);
+        let mut last_error = None;
+        let mut sent = false;
+        for socket in &self.sockets {
+            match socket.send(&pdu) {
+                Ok(_) => sent = true,
+                Err(e) => last_error = Some(e),
+            }
+        }
+        match last_error {
+            Some(e) if !sent => Err(RastaError::from(e)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Waits up to `timeout` for the next message in sequence and
+    /// returns `None` if none arrived. While waiting, the transport
+    /// channels are polled every millisecond.
+    pub fn receive(&mut self, timeout: Duration) -> Result<Option<Message>, RastaError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            self.poll_sockets()?;
+            self.sequencer.expire(Instant::now());
+            if let Some(msg) = self.sequencer.ready.pop_front() {
+                return Ok(Some(msg));
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Ok(None);
+            }
+            thread::sleep(remaining.min(POLL_INTERVAL));
+        }
+    }
+
+    /// Restarts the sequence numbers on both directions,
+    /// e.g. before the safety layer opens a new connection.
+    pub fn reset(&mut self) {
+        self.next_sequence_number = 0;
+        self.sequencer = Sequencer {
+            config: self.config,
+            ..Default::default()
+        };
+    }
+
+    fn poll_sockets(&mut self) -> Result<(), RastaError> {
+        let mut buf = vec![0; REDUNDANCY_HEADER_LENGTH + MESSAGE_BUFFER_LENGTH + 4];
+        for socket in &self.sockets {
+            loop {
+                let len = match socket.recv(&mut buf) {
+                    Ok(len) => len,
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                    // The peer's socket on this channel is not bound (yet)
+                    Err(e) if e.kind() == ErrorKind::ConnectionRefused => break,
+                    Err(e) => return Err(RastaError::from(e)),
+                };
+                match decode(&buf[..len], self.config.check_code) {
+                    Some((sequence_number, msg)) => {
+                        self.sequencer.accept(sequence_number, msg, Instant::now())
+                    }
+                    None => self.sequencer.stats.malformed += 1,
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn encode(&self, sequence_number: u32, msg: &Message) -> Result<Vec<u8>, RastaError> {
+        let length = REDUNDANCY_HEADER_LENGTH + msg.len() + self.config.check_code.len();
+        let length = u16::try_from(length).map_err(|_| RastaError::MessageTooLong {
+            length,
+            max: u16::MAX.into(),
+        })?;
+        let mut pdu = Vec::with_capacity(length.into());
+        pdu.extend_from_slice(&length.to_be_bytes());
+        pdu.extend_from_slice(&[0, 0]);
+        pdu.extend_from_slice(&sequence_number.to_be_bytes());
+        pdu.extend_from_slice(msg);
+        let check_code = self.config.check_code.compute(&pdu);
+        pdu.extend_from_slice(&check_code);
+        Ok(pdu)
+    }
+}
+
+/// Splits a received PDU into its sequence number and message.
+fn decode(pdu: &[u8], check_code: CheckCode) -> Option<(u32, Message)> {
+    let length = usize::from(u16::from_be_bytes(pdu.get(..2)?.try_into().ok()?));
+    if length != pdu.len() || length < REDUNDANCY_HEADER_LENGTH + check_code.len() {
+        return None;
+    }
+    let (pdu, code) = pdu.split_at(length - check_code.len());
+    if check_code.compute(pdu) != code {
+        return None;
+    }
+    let sequence_number = u32::from_be_bytes(pdu[4..8].try_into().unwrap());
+    let msg = Message::try_parse(&pdu[REDUNDANCY_HEADER_LENGTH..]).ok()?;
+    Some((sequence_number, msg))
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = u32::MAX;
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB88320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::{SocketAddr, UdpSocket},
+        time::{Duration, Instant},
+    };
+
+    use super::{crc32, CheckCode, RedundancyChannel, RedundancyConfig, Sequencer};
+    use crate::message::Message;
+
+    fn heartbeat(sequence_number: u32) -> Message {
+        Message::heartbeat(1, 2, sequence_number, 0, 0, 0)
+    }
+
+    #[test]
+    fn test_sequencer_orders_and_deduplicates() {
+        let mut sequencer = Sequencer {
+            config: RedundancyConfig::default(),
+            ..Default::default()
+        };
+        let now = Instant::now();
+        sequencer.accept(0, heartbeat(0), now);
+        sequencer.accept(0, heartbeat(0), now);
+        sequencer.accept(2, heartbeat(2), now);
+        sequencer.accept(1, heartbeat(1), now);
+        let delivered: Vec<u32> = sequencer
+            .ready
+            .drain(..)
+            .map(|msg| msg.sequence_number())
+            .collect();
+        assert_eq!(delivered, vec![0, 1, 2]);
+        assert_eq!(sequencer.stats.duplicates, 1);
+
+        // 3 is lost, 4 is delivered once the defer timeout has passed
+        sequencer.accept(4, heartbeat(4), now);
+        sequencer.expire(now + Duration::from_millis(10));
+        assert!(sequencer.ready.is_empty());
+        sequencer.expire(now + Duration::from_millis(50));
+        assert_eq!(sequencer.ready.pop_front().unwrap().sequence_number(), 4);
+        assert_eq!(sequencer.stats.lost, 1);
+        sequencer.accept(3, heartbeat(3), now);
+        assert!(sequencer.ready.is_empty());
+    }
+
+    #[test]
+    fn test_redundant_channels() {
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+        // Reserve two free ports for each side
+        let free_addrs = || -> Vec<SocketAddr> {
+            let sockets = [(); 2].map(|_| UdpSocket::bind("127.0.0.1:0").unwrap());
+            sockets.iter().map(|s| s.local_addr().unwrap()).collect()
+        };
+        let (sender_addrs, receiver_addrs) = (free_addrs(), free_addrs());
+        let config = RedundancyConfig {
+            check_code: CheckCode::Crc32,
+            ..Default::default()
+        };
+        let mut sender = RedundancyChannel::bind(&sender_addrs, &receiver_addrs, config).unwrap();
+        let mut receiver = RedundancyChannel::bind(&receiver_addrs, &sender_addrs, config).unwrap();
+
+        for sequence_number in 0..3 {
+            sender.send(&heartbeat(sequence_number)).unwrap();
+        }
+        for sequence_number in 0..3 {
+            let msg = receiver.receive(Duration::from_secs(1)).unwrap().unwrap();
+            assert_eq!(msg.sequence_number(), sequence_number);
+        }
+        assert!(receiver
+            .receive(Duration::from_millis(10))
+            .unwrap()
+            .is_none());
+        assert_eq!(receiver.stats().duplicates, 3);
+    }
+}
@@ -0,0 +1,361 @@
+//! A bounded blocking queue used to hand received messages from the
+//! socket thread to a worker thread
+//! (see [`RastaListener::listen_buffered`](crate::RastaListener::listen_buffered)).
+//!
+//! ## Closing
+//!
+//! A [`RingBuffer`] can be shared by any number of producers and consumers.
+//! Closing it never loses an item that was accepted by [`RingBuffer::push`]:
+//! pushes fail from then on, while the items buffered before can still be
+//! taken, one by one with [`RingBuffer::pop`] or all at once with
+//! [`RingBuffer::drain`]. Only once the buffer is closed and empty do the
+//! receiving methods report [`RingBufferClosed`].
+//!
+//! Blocking calls are woken up by [`RingBuffer::close`] and tolerate
+//! spurious wakeups: they only return early once an item or the
+//! close is actually there, and [`RingBuffer::pop_timeout`] keeps
+//! waiting for the rest of its timeout otherwise.
+
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Condvar, Mutex, MutexGuard,
+    },
+    time::Duration,
+};
+
+/// What happens when a message arrives while the buffer is full.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum OverflowPolicy {
+    /// Stop reading from the socket until the worker has caught up.
+    #[default]
+    Block,
+    /// Discard the message that just arrived.
+    DropNewest,
+    /// Discard the oldest buffered message to make room.
+    DropOldest,
+}
+
+/// Fill level statistics of a [`RingBuffer`]. Clones share the same counters,
+/// so a handle can be kept to observe a buffer owned by another thread.
+#[derive(Clone, Debug, Default)]
+pub struct RingBufferMetrics {
+    inner: Arc<MetricsInner>,
+}
+
+#[derive(Debug, Default)]
+struct MetricsInner {
+    len: AtomicUsize,
+    high_watermark: AtomicUsize,
+    pushed: AtomicU64,
+    dropped: AtomicU64,
+}
+
+impl RingBufferMetrics {
+    /// The number of currently buffered messages.
+    pub fn len(&self) -> usize {
+        self.inner.len.load(Ordering::Relaxed)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The highest number of messages that were buffered at the same time.
+    pub fn high_watermark(&self) -> usize {
+        self.inner.high_watermark.load(Ordering::Relaxed)
+    }
+
+    /// The number of messages deposited in the buffer, including dropped ones.
+    pub fn pushed(&self) -> u64 {
+        self.inner.pushed.load(Ordering::Relaxed)
+    }
+
+    /// The number of messages discarded due to the [`OverflowPolicy`].
+    pub fn dropped(&self) -> u64 {
+        self.inner.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Resets the high watermark to the current fill level.
+    pub fn reset_high_watermark(&self) {
+        self.inner
+            .high_watermark
+            .store(self.len(), Ordering::Relaxed);
+    }
+
+    fn update_len(&self, len: usize) {
+        self.inner.len.store(len, Ordering::Relaxed);
+        self.inner.high_watermark.fetch_max(len, Ordering::Relaxed);
+    }
+}
+
+/// The buffer has been closed, so nothing can be pushed anymore
+/// or, once it is drained, taken from it.
+#[derive(Debug, thiserror::Error)]
+#[error("Ring buffer closed")]
+pub struct RingBufferClosed;
+
+struct State<T> {
+    items: VecDeque<T>,
+    closed: bool,
+}
+
+/// A bounded queue that producers and consumers share by reference.
+pub struct RingBuffer<T> {
+    state: Mutex<State<T>>,
+    not_empty: Condvar,
+    not_full: Condvar,
+    capacity: usize,
+    policy: OverflowPolicy,
+    metrics: RingBufferMetrics,
+}
+
+impl<T> RingBuffer<T> {
+    /// Creates a buffer holding at most `capacity` (at least one) items.
+    pub fn new(capacity: usize, policy: OverflowPolicy, metrics: RingBufferMetrics) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            state: Mutex::new(State {
+                items: VecDeque::with_capacity(capacity),
+                closed: false,
+            }),
+            not_empty: Condvar::new(),
+            not_full: Condvar::new(),
+            capacity,
+            policy,
+            metrics,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn metrics(&self) -> &RingBufferMetrics {
+        &self.metrics
+    }
+
+    /// Deposits `item`, applying the overflow policy if the buffer is full.
+    pub fn push(&self, item: T) -> Result<(), RingBufferClosed> {
+        let mut state = self.state.lock().unwrap();
+        if state.closed {
+            return Err(RingBufferClosed);
+        }
+        if state.items.len() >= self.capacity {
+            match self.policy {
+                OverflowPolicy::Block => {
+                    state = self
+                        .not_full
+                        .wait_while(state, |s| !s.closed && s.items.len() >= self.capacity)
+                        .unwrap();
+                    if state.closed {
+                        return Err(RingBufferClosed);
+                    }
+                }
+                OverflowPolicy::DropNewest => {
+                    self.metrics.inner.pushed.fetch_add(1, Ordering::Relaxed);
+                    self.metrics.inner.dropped.fetch_add(1, Ordering::Relaxed);
+                    return Ok(());
+                }
+                OverflowPolicy::DropOldest => {
+                    state.items.pop_front();
+                    self.metrics.inner.dropped.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+        // Only counted now, as a blocked push may still fail on close
+        self.metrics.inner.pushed.fetch_add(1, Ordering::Relaxed);
+        state.items.push_back(item);
+        self.metrics.update_len(state.items.len());
+        self.not_empty.notify_one();
+        Ok(())
+    }
+
+    /// Takes the oldest item, waiting for one to arrive.
+    /// Returns `None` once the buffer is closed and drained.
+    pub fn pop(&self) -> Option<T> {
+        let mut state = self
+            .not_empty
+            .wait_while(self.state.lock().unwrap(), |s| {
+                !s.closed && s.items.is_empty()
+            })
+            .unwrap();
+        let item = state.items.pop_front();
+        self.metrics.update_len(state.items.len());
+        self.not_full.notify_one();
+        item
+    }
+
+    /// Takes the oldest item, waiting at most `timeout` for one to arrive.
+    /// Returns `Ok(None)` if the timeout passed,
+    /// and fails once the buffer is closed and drained.
+    pub fn pop_timeout(&self, timeout: Duration) -> Result<Option<T>, RingBufferClosed> {
+        let (state, _) = self
+            .not_empty
+            .wait_timeout_while(self.state.lock().unwrap(), timeout, |s| {
+                !s.closed && s.items.is_empty()
+            })
+            .unwrap();
+        self.take(state)
+    }
+
+    /// Takes the oldest item without waiting. Returns `Ok(None)` if the
+    /// buffer is empty, and fails once it is closed and drained.
+    pub fn try_pop(&self) -> Result<Option<T>, RingBufferClosed> {
+        self.take(self.state.lock().unwrap())
+    }
+
+    fn take(&self, mut state: MutexGuard<State<T>>) -> Result<Option<T>, RingBufferClosed> {
+        match state.items.pop_front() {
+            Some(item) => {
+                self.metrics.update_len(state.items.len());
+                self.not_full.notify_one();
+                Ok(Some(item))
+            }
+            None if state.closed => Err(RingBufferClosed),
+            None => Ok(None),
+        }
+    }
+
+    /// Takes all buffered items without waiting, oldest first.
+    /// After [`RingBuffer::close`], this surfaces everything that
+    /// was pushed but not yet popped.
+    pub fn drain(&self) -> Vec<T> {
+        let mut state = self.state.lock().unwrap();
+        let items: Vec<T> = state.items.drain(..).collect();
+        self.metrics.update_len(0);
+        self.not_full.notify_all();
+        items
+    }
+
+    /// Wakes up both sides. Further pushes fail, while already
+    /// buffered items can still be popped.
+    pub fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.not_empty.notify_all();
+        self.not_full.notify_all();
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.state.lock().unwrap().closed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Mutex,
+        },
+        thread,
+        time::Duration,
+    };
+
+    use super::{OverflowPolicy, RingBuffer, RingBufferClosed, RingBufferMetrics};
+
+    #[test]
+    fn test_overflow_policies() {
+        let newest = RingBuffer::new(2, OverflowPolicy::DropNewest, RingBufferMetrics::default());
+        for i in 0..3 {
+            newest.push(i).unwrap();
+        }
+        assert_eq!(newest.metrics().pushed(), 3);
+        assert_eq!(newest.metrics().dropped(), 1);
+        assert_eq!(newest.metrics().high_watermark(), 2);
+        assert_eq!(newest.pop(), Some(0));
+
+        let oldest = RingBuffer::new(2, OverflowPolicy::DropOldest, RingBufferMetrics::default());
+        for i in 0..3 {
+            oldest.push(i).unwrap();
+        }
+        assert_eq!(oldest.pop(), Some(1));
+        assert_eq!(oldest.pop(), Some(2));
+        oldest.close();
+        assert_eq!(oldest.pop(), None);
+        assert!(oldest.push(3).is_err());
+    }
+
+    #[test]
+    fn test_blocked_push_counted_once_deposited() {
+        let ring = RingBuffer::new(1, OverflowPolicy::Block, RingBufferMetrics::default());
+        ring.push(0).unwrap();
+        thread::scope(|s| {
+            let blocked = s.spawn(|| ring.push(1));
+            thread::sleep(Duration::from_millis(20));
+            assert_eq!(ring.metrics().pushed(), 1);
+            ring.close();
+            assert!(blocked.join().unwrap().is_err());
+        });
+        assert_eq!(ring.metrics().pushed(), 1);
+    }
+
+    #[test]
+    fn test_close_loses_nothing() {
+        const PRODUCERS: usize = 8;
+        const CONSUMERS: usize = 8;
+        const ITEMS: usize = 2000;
+
+        let ring = RingBuffer::new(4, OverflowPolicy::Block, RingBufferMetrics::default());
+        let accepted = Mutex::new(Vec::new());
+        let received = Mutex::new(Vec::new());
+        let stop = AtomicBool::new(false);
+        thread::scope(|s| {
+            for producer in 0..PRODUCERS {
+                let (ring, accepted, stop) = (&ring, &accepted, &stop);
+                s.spawn(move || {
+                    for i in 0..ITEMS {
+                        let item = producer * ITEMS + i;
+                        if ring.push(item).is_err() {
+                            assert!(stop.load(Ordering::Relaxed));
+                            break;
+                        }
+                        accepted.lock().unwrap().push(item);
+                    }
+                });
+            }
+            for consumer in 0..CONSUMERS {
+                let (ring, received) = (&ring, &received);
+                s.spawn(move || {
+                    let mut taken = Vec::new();
+                    // Mix waiting forever, short timeouts and polling
+                    loop {
+                        let item = match consumer % 3 {
+                            0 => ring.pop().map(Some).ok_or(RingBufferClosed),
+                            1 => ring.pop_timeout(Duration::from_micros(50)),
+                            _ => ring.try_pop(),
+                        };
+                        match item {
+                            Ok(Some(item)) => taken.push(item),
+                            Ok(None) => thread::yield_now(),
+                            Err(RingBufferClosed) => break,
+                        }
+                        // Leave items behind for the final drain
+                        if taken.len() >= ITEMS / 2 {
+                            break;
+                        }
+                    }
+                    received.lock().unwrap().extend(taken);
+                });
+            }
+            thread::sleep(Duration::from_millis(20));
+            stop.store(true, Ordering::Relaxed);
+            ring.close();
+        });
+        assert!(ring.is_closed());
+        let mut received = received.into_inner().unwrap();
+        received.extend(ring.drain());
+        assert!(ring.try_pop().is_err());
+        assert!(ring.pop_timeout(Duration::from_secs(10)).is_err());
+
+        // Every accepted item was taken exactly once, and pushes
+        // that were still blocked when the buffer closed are not counted
+        let mut accepted = accepted.into_inner().unwrap();
+        assert_eq!(ring.metrics().pushed(), accepted.len() as u64);
+        accepted.sort_unstable();
+        received.sort_unstable();
+        assert_eq!(received, accepted);
+    }
+}
@@ -0,0 +1,389 @@
+//! Running several endpoints together.
+//!
+//! An element controller usually talks to several partners at once, each
+//! over its own listener or connection. A [`Runtime`] runs every endpoint
+//! on its own thread, starts and stops them together, restarts endpoints
+//! that failed according to a [`ReconnectPolicy`] and aggregates what
+//! happens to them into one stream of [`RuntimeEvent`]s.
+
+use std::{
+    net::ToSocketAddrs,
+    sync::{mpsc, Arc, Condvar, Mutex},
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    config::RastaConfig,
+    logging::event,
+    message::{Message, RastaId},
+    supervisor::{RastaSupervisor, ReconnectPolicy, SupervisorEvent},
+    RastaError, RastaListener, RecoveryAction,
+};
+
+/// What happened to an endpoint of a [`Runtime`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum EndpointEvent {
+    /// The endpoint was started for the `attempt`th time.
+    Started { attempt: u32 },
+    /// The endpoint returned without an error or was stopped.
+    Stopped,
+    /// The endpoint failed and is restarted unless the policy is exhausted.
+    Failed {
+        reason: String,
+        recovery: RecoveryAction,
+    },
+    /// The endpoint is not restarted anymore after `attempts` attempts.
+    GaveUp { attempts: u32 },
+    /// An event of the connection of an endpoint added with
+    /// [`Runtime::add_supervisor`].
+    Connection(SupervisorEvent),
+}
+
+/// An [`EndpointEvent`] of the endpoint called `endpoint`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RuntimeEvent {
+    pub endpoint: String,
+    pub kind: EndpointEvent,
+}
+
+/// The subscribers of the events of a [`Runtime`].
+#[derive(Clone, Default)]
+struct EventBus(Arc<Mutex<Vec<mpsc::Sender<RuntimeEvent>>>>);
+
+impl EventBus {
+    fn emit(&self, endpoint: &str, kind: EndpointEvent) {
+        let event = RuntimeEvent {
+            endpoint: endpoint.to_string(),
+            kind,
+        };
+        // Receivers that were dropped are unsubscribed
+        self.0
+            .lock()
+            .unwrap()
+            .retain(|sender| sender.send(event.clone()).is_ok());
+    }
+}
+
+#[derive(Default)]
+struct StopState {
+    stopped: bool,
+    hooks: Vec<Box<dyn FnOnce() + Send>>,
+}
+
+/// Shared between a [`Runtime`] and its endpoints to request them to stop.
+#[derive(Clone, Default)]
+struct StopSignal(Arc<(Mutex<StopState>, Condvar)>);
+
+impl StopSignal {
+    fn stop(&self) {
+        let hooks = {
+            let mut state = self.0 .0.lock().unwrap();
+            state.stopped = true;
+            std::mem::take(&mut state.hooks)
+        };
+        self.0 .1.notify_all();
+        for hook in hooks {
+            hook();
+        }
+    }
+
+    fn is_stopped(&self) -> bool {
+        self.0 .0.lock().unwrap().stopped
+    }
+
+    fn on_stop(&self, hook: Box<dyn FnOnce() + Send>) {
+        let mut state = self.0 .0.lock().unwrap();
+        if state.stopped {
+            drop(state);
+            hook();
+        } else {
+            state.hooks.push(hook);
+        }
+    }
+
+    /// Waits up to `timeout` and returns whether the stop was requested.
+    fn wait(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.0 .0.lock().unwrap();
+        while !state.stopped {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break;
+            }
+            state = self.0 .1.wait_timeout(state, remaining).unwrap().0;
+        }
+        state.stopped
+    }
+}
+
+/// Passed to a running endpoint of a [`Runtime`].
+pub struct EndpointContext {
+    name: String,
+    stop: StopSignal,
+}
+
+impl EndpointContext {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Whether the runtime is stopping. Endpoints that loop on their
+    /// own should return once it is.
+    pub fn is_stopped(&self) -> bool {
+        self.stop.is_stopped()
+    }
+
+    /// Calls `hook` when the runtime stops, e.g. to shut a listener down
+    /// with its [`ShutdownHandle`](crate::shutdown::ShutdownHandle).
+    /// Runs `hook` right away if the runtime is already stopping.
+    pub fn on_stop<F>(&self, hook: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.stop.on_stop(Box::new(hook));
+    }
+
+    /// Waits up to `timeout` for the runtime to stop and returns whether it does.
+    pub fn wait_for_stop(&self, timeout: Duration) -> bool {
+        self.stop.wait(timeout)
+    }
+}
+
+type EndpointFn = Box<dyn FnMut(&EndpointContext) -> Result<(), RastaError> + Send>;
+
+/// Runs several endpoints on their own threads, see the [module documentation](self).
+///
+/// ```rust,no_run
+/// # use rasta_rs::{config::RastaConfig, runtime::Runtime};
+/// let mut runtime = Runtime::new();
+/// for (name, port, id) in [("left", 8888, 1), ("right", 8889, 2)] {
+///     runtime.add_listener(name, ("0.0.0.0", port), id, RastaConfig::default(), |msg| {
+///         Some(msg.data().to_vec())
+///     });
+/// }
+/// let events = runtime.events();
+/// runtime.start();
+/// for event in events {
+///     println!("{}: {:?}", event.endpoint, event.kind);
+/// }
+/// ```
+#[derive(Default)]
+pub struct Runtime {
+    endpoints: Vec<(String, EndpointFn)>,
+    policy: ReconnectPolicy,
+    events: EventBus,
+    stop: StopSignal,
+    threads: Vec<JoinHandle<(String, EndpointFn)>>,
+}
+
+impl Runtime {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How often and after which delay failed endpoints are restarted.
+    pub fn with_restart_policy(mut self, policy: ReconnectPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// A channel receiving the events of every endpoint from now on.
+    pub fn events(&mut self) -> mpsc::Receiver<RuntimeEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.events.0.lock().unwrap().push(sender);
+        receiver
+    }
+
+    /// Adds an endpoint that is run by calling `run` until it returns.
+    /// `run` is called again to restart the endpoint after it failed.
+    pub fn add_endpoint<F>(&mut self, name: &str, run: F)
+    where
+        F: FnMut(&EndpointContext) -> Result<(), RastaError> + Send + 'static,
+    {
+        self.endpoints.push((name.to_string(), Box::new(run)));
+    }
+
+    /// Adds a [`RastaListener`] that is bound to `addr` whenever it is
+    /// started and calls `on_receive` like [`RastaListener::listen`].
+    pub fn add_listener<A, F, I, D>(
+        &mut self,
+        name: &str,
+        addr: A,
+        id: RastaId,
+        config: RastaConfig,
+        mut on_receive: F,
+    ) where
+        A: ToSocketAddrs + Send + 'static,
+        F: FnMut(Message) -> I + Send + 'static,
+        I: IntoIterator<Item = D>,
+        D: AsRef<[u8]>,
+    {
+        self.add_endpoint(name, move |context| {
+            let mut listener = RastaListener::try_new_with_config(&addr, id, config.clone())?;
+            let shutdown = listener.shutdown_handle();
+            context.on_stop(move || shutdown.shutdown());
+            listener.listen(&mut on_receive)
+        });
+    }
+
+    /// Adds an endpoint that runs `run` with `supervisor`. The events of
+    /// the supervisor are forwarded as [`EndpointEvent::Connection`].
+    pub fn add_supervisor<F>(&mut self, name: &str, mut supervisor: RastaSupervisor, mut run: F)
+    where
+        F: FnMut(&mut RastaSupervisor, &EndpointContext) -> Result<(), RastaError> + Send + 'static,
+    {
+        let events = self.events.clone();
+        let endpoint = name.to_string();
+        supervisor.on_event(move |event| {
+            events.emit(&endpoint, EndpointEvent::Connection(event.clone()))
+        });
+        self.add_endpoint(name, move |context| run(&mut supervisor, context));
+    }
+
+    /// Whether the endpoints have been started and not stopped yet.
+    pub fn is_running(&self) -> bool {
+        !self.threads.is_empty()
+    }
+
+    /// Starts every endpoint on its own thread. Does nothing if the
+    /// endpoints are already running.
+    pub fn start(&mut self) {
+        if self.is_running() {
+            return;
+        }
+        self.stop = StopSignal::default();
+        for (name, mut run) in self.endpoints.drain(..) {
+            let context = EndpointContext {
+                name,
+                stop: self.stop.clone(),
+            };
+            let events = self.events.clone();
+            let policy = self.policy;
+            self.threads.push(std::thread::spawn(move || {
+                supervise_endpoint(&mut run, &context, &events, policy);
+                (context.name, run)
+            }));
+        }
+    }
+
+    /// Stops every endpoint and waits for their threads to finish. The
+    /// endpoints can be started again afterwards.
+    pub fn stop(&mut self) {
+        self.stop.stop();
+        for thread in self.threads.drain(..) {
+            if let Ok(endpoint) = thread.join() {
+                self.endpoints.push(endpoint);
+            } else {
+                // The endpoint is lost, its state may be inconsistent
+                event!(error, "Endpoint thread panicked");
+            }
+        }
+    }
+}
+
+/// Runs `run` until it returns successfully, the runtime stops or the
+/// policy is exhausted.
+fn supervise_endpoint(
+    run: &mut EndpointFn,
+    context: &EndpointContext,
+    events: &EventBus,
+    policy: ReconnectPolicy,
+) {
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        events.emit(&context.name, EndpointEvent::Started { attempt });
+        let error = match run(context) {
+            Ok(()) => break,
+            Err(_) if context.is_stopped() => break,
+            Err(error) => error,
+        };
+        event!(warn, endpoint = context.name, attempt, %error, "Endpoint failed");
+        let recovery = error.recovery_action();
+        events.emit(
+            &context.name,
+            EndpointEvent::Failed {
+                reason: error.to_string(),
+                recovery,
+            },
+        );
+        if recovery == RecoveryAction::FailSafe
+            || policy.max_attempts.is_some_and(|max| attempt >= max)
+        {
+            events.emit(&context.name, EndpointEvent::GaveUp { attempts: attempt });
+            return;
+        }
+        if context.wait_for_stop(policy.delay) {
+            break;
+        }
+    }
+    events.emit(&context.name, EndpointEvent::Stopped);
+}
+
+impl Drop for Runtime {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{supervisor::ReconnectPolicy, RastaError, RecoveryAction};
+
+    use super::{EndpointEvent, Runtime};
+
+    #[test]
+    fn test_runtime_restarts_endpoints() {
+        let mut runtime = Runtime::new().with_restart_policy(ReconnectPolicy {
+            delay: Duration::from_millis(10),
+            max_attempts: Some(3),
+        });
+        let mut failures = 0;
+        runtime.add_endpoint("flaky", move |context| {
+            if failures < 1 {
+                failures += 1;
+                return Err(RastaError::Timeout);
+            }
+            while !context.wait_for_stop(Duration::from_secs(10)) {}
+            Ok(())
+        });
+        runtime.add_endpoint("broken", |_| Err(RastaError::Timeout));
+        let events = runtime.events();
+        runtime.start();
+        assert!(runtime.is_running());
+
+        let mut flaky = Vec::new();
+        let mut broken = Vec::new();
+        while broken.len() < 7 || flaky.len() < 3 {
+            let event = events.recv_timeout(Duration::from_secs(5)).unwrap();
+            match event.endpoint.as_str() {
+                "flaky" => flaky.push(event.kind),
+                _ => broken.push(event.kind),
+            }
+        }
+        assert_eq!(broken[6], EndpointEvent::GaveUp { attempts: 3 });
+        assert_eq!(
+            flaky,
+            [
+                EndpointEvent::Started { attempt: 1 },
+                EndpointEvent::Failed {
+                    reason: RastaError::Timeout.to_string(),
+                    recovery: RecoveryAction::Reconnect,
+                },
+                EndpointEvent::Started { attempt: 2 },
+            ]
+        );
+
+        runtime.stop();
+        assert!(!runtime.is_running());
+        let stopped: Vec<_> = events.try_iter().collect();
+        assert_eq!(stopped.len(), 1);
+        assert_eq!(stopped[0].endpoint, "flaky");
+        assert_eq!(stopped[0].kind, EndpointEvent::Stopped);
+    }
+}
@@ -0,0 +1,202 @@
+//! The safety code protecting each message against corruption and
+//! masquerade. It is computed over the header and the data of a message
+//! with a key shared by both peers and written to its security code
+//! field. Select the algorithm with
+//! [`RastaConfig::safety_code`](crate::RastaConfig::safety_code).
+
+use alloc::{format, vec::Vec};
+use core::fmt::Debug;
+
+use blake2::{digest::consts::U8, digest::Mac, Blake2bMac};
+
+use crate::{message::SECURITY_CODE_LENGTH, RastaError};
+
+/// Computes the safety code of a message.
+pub trait SafetyCode: Send + Sync + Debug {
+    /// The safety code of `pdu`, the protected part of a message.
+    fn compute(&self, pdu: &[u8]) -> [u8; SECURITY_CODE_LENGTH];
+}
+
+/// Leaves the security code empty (all zeros), as allowed
+/// for connections that are protected by other means.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoSafetyCode;
+
+impl SafetyCode for NoSafetyCode {
+    fn compute(&self, _pdu: &[u8]) -> [u8; SECURITY_CODE_LENGTH] {
+        [0; SECURITY_CODE_LENGTH]
+    }
+}
+
+/// The MD4 safety code of the RaSTA standard. The key consists of the
+/// initial values of the MD4 state, and the lower half of the digest
+/// is used as the security code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Md4SafetyCode {
+    pub initial_values: [u32; 4],
+}
+
+impl Default for Md4SafetyCode {
+    /// Uses the initial values of plain MD4.
+    fn default() -> Self {
+        Self {
+            initial_values: [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476],
+        }
+    }
+}
+
+impl SafetyCode for Md4SafetyCode {
+    fn compute(&self, pdu: &[u8]) -> [u8; SECURITY_CODE_LENGTH] {
+        let digest = md4(self.initial_values, pdu);
+        digest[..SECURITY_CODE_LENGTH].try_into().unwrap()
+    }
+}
+
+/// A keyed BLAKE2b safety code with an output of eight bytes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Blake2SafetyCode {
+    key: Vec<u8>,
+}
+
+impl Blake2SafetyCode {
+    /// The longest key BLAKE2b accepts.
+    pub const MAX_KEY_LENGTH: usize = 64;
+
+    pub fn new(key: &[u8]) -> Result<Self, RastaError> {
+        if key.len() > Self::MAX_KEY_LENGTH {
+            return Err(RastaError::Other(format!(
+                "BLAKE2b keys are limited to {} bytes",
+                Self::MAX_KEY_LENGTH
+            )));
+        }
+        Ok(Self { key: key.to_vec() })
+    }
+}
+
+impl SafetyCode for Blake2SafetyCode {
+    fn compute(&self, pdu: &[u8]) -> [u8; SECURITY_CODE_LENGTH] {
+        let mut mac = <Blake2bMac<U8> as Mac>::new_from_slice(&self.key).unwrap();
+        mac.update(pdu);
+        mac.finalize().into_bytes().into()
+    }
+}
+
+/// MD4 (RFC 1320) starting from `state` instead of the standard initial values.
+fn md4(mut state: [u32; 4], data: &[u8]) -> [u8; 16] {
+    fn f(x: u32, y: u32, z: u32) -> u32 {
+        (x & y) | (!x & z)
+    }
+    fn g(x: u32, y: u32, z: u32) -> u32 {
+        (x & y) | (x & z) | (y & z)
+    }
+    fn h(x: u32, y: u32, z: u32) -> u32 {
+        x ^ y ^ z
+    }
+
+    let mut padded = data.to_vec();
+    padded.push(0x80);
+    while padded.len() % 64 != 56 {
+        padded.push(0);
+    }
+    padded.extend_from_slice(&((data.len() as u64).wrapping_mul(8)).to_le_bytes());
+
+    for block in padded.chunks_exact(64) {
+        let mut x = [0u32; 16];
+        for (word, bytes) in x.iter_mut().zip(block.chunks_exact(4)) {
+            *word = u32::from_le_bytes(bytes.try_into().unwrap());
+        }
+        let [mut a, mut b, mut c, mut d] = state;
+        for i in 0..4 {
+            let k = 4 * i;
+            let round = |v: u32, w: u32| v.wrapping_add(w);
+            a = round(a.wrapping_add(f(b, c, d)), x[k]).rotate_left(3);
+            d = round(d.wrapping_add(f(a, b, c)), x[k + 1]).rotate_left(7);
+            c = round(c.wrapping_add(f(d, a, b)), x[k + 2]).rotate_left(11);
+            b = round(b.wrapping_add(f(c, d, a)), x[k + 3]).rotate_left(19);
+        }
+        for i in 0..4 {
+            let round = |v: u32, w: u32| v.wrapping_add(w).wrapping_add(0x5a827999);
+            a = round(a.wrapping_add(g(b, c, d)), x[i]).rotate_left(3);
+            d = round(d.wrapping_add(g(a, b, c)), x[i + 4]).rotate_left(5);
+            c = round(c.wrapping_add(g(d, a, b)), x[i + 8]).rotate_left(9);
+            b = round(b.wrapping_add(g(c, d, a)), x[i + 12]).rotate_left(13);
+        }
+        for i in [0, 2, 1, 3] {
+            let round = |v: u32, w: u32| v.wrapping_add(w).wrapping_add(0x6ed9eba1);
+            a = round(a.wrapping_add(h(b, c, d)), x[i]).rotate_left(3);
+            d = round(d.wrapping_add(h(a, b, c)), x[i + 8]).rotate_left(9);
+            c = round(c.wrapping_add(h(d, a, b)), x[i + 4]).rotate_left(11);
+            b = round(b.wrapping_add(h(c, d, a)), x[i + 12]).rotate_left(15);
+        }
+        for (value, round) in state.iter_mut().zip([a, b, c, d]) {
+            *value = value.wrapping_add(round);
+        }
+    }
+
+    let mut digest = [0; 16];
+    for (bytes, value) in digest.chunks_exact_mut(4).zip(state) {
+        bytes.copy_from_slice(&value.to_le_bytes());
+    }
+    digest
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::{md4, Blake2SafetyCode, Md4SafetyCode, SafetyCode};
+    use crate::message::{Message, MessageBuilder, MessageType};
+
+    #[test]
+    fn test_md4_vectors() {
+        let initial = Md4SafetyCode::default().initial_values;
+        let hex = |digest: [u8; 16]| {
+            digest
+                .iter()
+                .map(|b| format!("{b:02x}"))
+                .collect::<String>()
+        };
+        assert_eq!(hex(md4(initial, b"")), "31d6cfe0d16ae931b73c59d7e0c089c0");
+        assert_eq!(
+            hex(md4(initial, b"abc")),
+            "a448017aaf21d8525fc10ae87aa6729d"
+        );
+        assert_eq!(
+            hex(md4(
+                initial,
+                b"12345678901234567890123456789012345678901234567890123456789012345678901234567890"
+            )),
+            "e33b4ddc9c38f2199c3e7b164fcc0536"
+        );
+    }
+
+    #[test]
+    fn test_seal_and_verify() {
+        let codes: [&dyn SafetyCode; 2] = [
+            &Md4SafetyCode::default(),
+            &Blake2SafetyCode::new(b"shared key").unwrap(),
+        ];
+        for code in codes {
+            let mut msg = Message::heartbeat(1, 2, 3, 4, 5, 6);
+            msg.seal(code);
+            assert_ne!(msg.security_code(), &[0; 8]);
+            assert!(msg.verify(code).is_ok());
+            let mut frame = msg.into_bytes();
+            frame[20] ^= 1;
+            assert!(Message::from(frame.as_slice()).verify(code).is_err());
+        }
+        assert!(Blake2SafetyCode::new(&[0; 65]).is_err());
+
+        let code = Arc::new(Md4SafetyCode::default());
+        let built = MessageBuilder::new()
+            .length(36)
+            .message_type(MessageType::HB)
+            .safety_code(code.clone())
+            .build();
+        assert!(built.verify(&*code).is_ok());
+        let other_key = Md4SafetyCode {
+            initial_values: [1, 2, 3, 4],
+        };
+        assert!(built.verify(&other_key).is_err());
+    }
+}
@@ -0,0 +1,152 @@
+//! The acceptance checks for the sequence numbers (SN) and confirmed
+//! sequence numbers (CS) of received messages defined by the RaSTA
+//! standard. Sequence numbers wrap around, so all comparisons are made
+//! modulo 2^32: a number comes after another if it is less than half
+//! the number space ahead of it.
+
+/// sn_in_seq: `sn` is the sequence number expected next (SN_R).
+pub fn sn_in_seq(sn: u32, expected: u32) -> bool {
+    sn == expected
+}
+
+/// cs_in_seq: `cs` confirms exactly the last message sent, as the
+/// first confirmation after a connection response has to.
+pub fn cs_in_seq(cs: u32, sent: u32) -> bool {
+    cs == sent
+}
+
+/// cs_valid: `cs` lies between the last confirmed sequence number (CS_R)
+/// and the last one sent, so it neither goes back behind an earlier
+/// confirmation nor confirms a message that was never sent.
+pub fn cs_valid(cs: u32, confirmed: u32, sent: u32) -> bool {
+    cs.wrapping_sub(confirmed) <= sent.wrapping_sub(confirmed)
+}
+
+/// Whether sequence number `a` comes after `b`.
+pub fn is_after(a: u32, b: u32) -> bool {
+    a != b && a.wrapping_sub(b) <= u32::MAX / 2
+}
+
+/// How a received message relates to the sequence numbers of its
+/// connection, see [`SequenceWindow::check`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SequenceCheck {
+    /// The message is the one expected next and its confirmation is valid.
+    InSequence,
+    /// The message is ahead of the one expected next by at most
+    /// N_SENDMAX, so the messages in between are missing.
+    Missing,
+    /// The message comes before the one expected next, e.g. a duplicate
+    /// or a message that has been retransmitted already. It is discarded.
+    Stale,
+    /// The message is further ahead than the peer may send without
+    /// waiting for a confirmation.
+    OutOfRange,
+    /// The message confirms a sequence number that was never sent or
+    /// comes before an earlier confirmation.
+    InvalidConfirmation,
+}
+
+/// The sequence numbers received messages are checked against.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SequenceWindow {
+    /// The sequence number expected in the next message (SN_R).
+    pub expected: u32,
+    /// The sequence number the peer confirmed last (CS_R).
+    pub confirmed: u32,
+    /// The latest sequence number sent to the peer.
+    pub sent: u32,
+}
+
+impl SequenceWindow {
+    /// The window after answering the message `received` with the message
+    /// `sent`, which the next message of the peer has to confirm exactly.
+    pub fn new(received: u32, sent: u32) -> Self {
+        Self {
+            expected: received.wrapping_add(1),
+            confirmed: sent,
+            sent,
+        }
+    }
+
+    /// Checks the sequence number `sn` and confirmed sequence number `cs`
+    /// of a received message. Stale messages are recognised before their
+    /// confirmation is checked, as it may be outdated as well.
+    pub fn check(&self, sn: u32, cs: u32, n_sendmax: u16) -> SequenceCheck {
+        let ahead = sn.wrapping_sub(self.expected);
+        if ahead > u32::MAX / 2 {
+            SequenceCheck::Stale
+        } else if ahead > u32::from(n_sendmax) {
+            SequenceCheck::OutOfRange
+        } else if !cs_valid(cs, self.confirmed, self.sent) {
+            SequenceCheck::InvalidConfirmation
+        } else if sn_in_seq(sn, self.expected) {
+            SequenceCheck::InSequence
+        } else {
+            SequenceCheck::Missing
+        }
+    }
+
+    /// Records that the message `sn` was sent, if it is the latest one.
+    pub fn sent(&mut self, sn: u32) {
+        if is_after(sn, self.sent) {
+            self.sent = sn;
+        }
+    }
+
+    /// Moves the window past the accepted message `sn` confirming `cs`.
+    pub fn accept(&mut self, sn: u32, cs: u32) {
+        self.expected = sn.wrapping_add(1);
+        self.confirmed = cs;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cs_in_seq, cs_valid, is_after, SequenceCheck, SequenceWindow};
+
+    #[test]
+    fn test_sequence_checks_wrap_around() {
+        assert!(is_after(0, u32::MAX));
+        assert!(!is_after(u32::MAX, 0));
+        assert!(!is_after(5, 5));
+        assert!(cs_in_seq(u32::MAX, u32::MAX));
+        // Confirmations between CS_R and the last message sent,
+        // across the wrap-around
+        assert!(cs_valid(u32::MAX, u32::MAX - 1, 1));
+        assert!(cs_valid(0, u32::MAX - 1, 1));
+        assert!(cs_valid(1, u32::MAX - 1, 1));
+        assert!(!cs_valid(2, u32::MAX - 1, 1));
+        assert!(!cs_valid(u32::MAX - 2, u32::MAX - 1, 1));
+
+        let mut window = SequenceWindow::new(u32::MAX - 1, u32::MAX);
+        assert_eq!(window.expected, u32::MAX);
+        assert_eq!(
+            window.check(u32::MAX, u32::MAX - 1, 10),
+            SequenceCheck::InvalidConfirmation
+        );
+        assert_eq!(
+            window.check(u32::MAX, u32::MAX, 10),
+            SequenceCheck::InSequence
+        );
+        window.accept(u32::MAX, u32::MAX);
+        assert_eq!(window.expected, 0);
+
+        window.sent(1);
+        window.sent(0);
+        assert_eq!(window.sent, 1);
+        // A heartbeat sent before the answer arrived still confirms
+        // the previous message
+        assert_eq!(window.check(0, u32::MAX, 10), SequenceCheck::InSequence);
+        assert_eq!(window.check(0, 1, 10), SequenceCheck::InSequence);
+        assert_eq!(window.check(0, 2, 10), SequenceCheck::InvalidConfirmation);
+        assert_eq!(window.check(3, 0, 10), SequenceCheck::Missing);
+        assert_eq!(window.check(10, 0, 10), SequenceCheck::Missing);
+        assert_eq!(window.check(11, 0, 10), SequenceCheck::OutOfRange);
+        // Duplicates are stale even if their confirmation is outdated
+        assert_eq!(
+            window.check(u32::MAX, u32::MAX - 5, 10),
+            SequenceCheck::Stale
+        );
+    }
+}
@@ -0,0 +1,43 @@
+//! Graceful termination of a running [`RastaListener`](crate::RastaListener).
+
+use std::{
+    net::{SocketAddr, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+/// Requests a [`RastaListener`](crate::RastaListener) to stop. Connected
+/// peers are sent a disconnection request (reason: user request) before
+/// the sockets are closed, so they do not have to wait for a timeout.
+///
+/// Clones share the same request, so a handle can be moved to another
+/// thread (e.g. a signal handler) while the listener is running.
+#[derive(Clone, Debug)]
+pub struct ShutdownHandle {
+    requested: Arc<AtomicBool>,
+    addr: Option<SocketAddr>,
+}
+
+impl ShutdownHandle {
+    pub(crate) fn new(requested: Arc<AtomicBool>, addr: Option<SocketAddr>) -> Self {
+        Self { requested, addr }
+    }
+
+    /// Requests the shutdown. The listener disconnects its peer after
+    /// the next received message, or once no message arrived for T_max.
+    /// A listener that is shut down stays shut down.
+    pub fn shutdown(&self) {
+        self.requested.store(true, Ordering::Relaxed);
+        // Wake up a listener waiting for new connections
+        if let Some(addr) = self.addr {
+            let _ = TcpStream::connect_timeout(&addr, Duration::from_millis(100));
+        }
+    }
+
+    pub fn is_requested(&self) -> bool {
+        self.requested.load(Ordering::Relaxed)
+    }
+}
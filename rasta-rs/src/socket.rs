@@ -0,0 +1,142 @@
+//! Options of the TCP sockets of connections and of the connections
+//! accepted by listeners, see [`RastaConfig::socket`](crate::RastaConfig::socket).
+//! Signalling networks often prioritise traffic by its DSCP marking and
+//! expect buffers and keepalive probes to be tuned to their links.
+
+use std::{io, net::TcpStream, time::Duration};
+
+/// Options applied to every TCP socket of an endpoint once it is connected
+/// or accepted. Options that are `None` keep the default of the operating
+/// system. `TCP_NODELAY` is controlled by [`RastaConfig::nodelay`](crate::RastaConfig::nodelay).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct SocketOptions {
+    /// The time to live (IPv4) or hop limit (IPv6) of outgoing packets.
+    pub ttl: Option<u32>,
+    /// The Differentiated Services Code Point (0 to 63) of outgoing packets,
+    /// written to the IPv4 type of service or IPv6 traffic class.
+    pub dscp: Option<u8>,
+    /// The size of the kernel receive buffer (`SO_RCVBUF`) in bytes.
+    pub receive_buffer_size: Option<usize>,
+    /// The size of the kernel send buffer (`SO_SNDBUF`) in bytes.
+    pub send_buffer_size: Option<usize>,
+    /// Enables TCP keepalive probes once the connection
+    /// has been idle for this long.
+    pub keepalive: Option<Duration>,
+}
+
+/// The largest value of the six bit DSCP field.
+pub const MAX_DSCP: u8 = 63;
+
+impl SocketOptions {
+    /// Applies the options to `stream`.
+    pub fn apply(&self, stream: &TcpStream) -> io::Result<()> {
+        if self.dscp.is_some_and(|dscp| dscp > MAX_DSCP) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("DSCP exceeds {MAX_DSCP}"),
+            ));
+        }
+        self.apply_to(stream)
+    }
+
+    #[cfg(not(target_os = "wasi"))]
+    fn apply_to(&self, stream: &TcpStream) -> io::Result<()> {
+        let socket = socket2::SockRef::from(stream);
+        let ipv6 = stream.local_addr()?.is_ipv6();
+        if let Some(ttl) = self.ttl {
+            if ipv6 {
+                socket.set_unicast_hops_v6(ttl)?;
+            } else {
+                socket.set_ttl(ttl)?;
+            }
+        }
+        if let Some(dscp) = self.dscp {
+            // The two least significant bits carry ECN
+            let traffic_class = u32::from(dscp) << 2;
+            if ipv6 {
+                set_traffic_class_v6(&socket, traffic_class)?;
+            } else {
+                socket.set_tos(traffic_class)?;
+            }
+        }
+        if let Some(size) = self.receive_buffer_size {
+            socket.set_recv_buffer_size(size)?;
+        }
+        if let Some(size) = self.send_buffer_size {
+            socket.set_send_buffer_size(size)?;
+        }
+        if let Some(idle) = self.keepalive {
+            socket.set_tcp_keepalive(&socket2::TcpKeepalive::new().with_time(idle))?;
+        }
+        Ok(())
+    }
+
+    #[cfg(target_os = "wasi")]
+    fn apply_to(&self, _stream: &TcpStream) -> io::Result<()> {
+        if *self == Self::default() {
+            Ok(())
+        } else {
+            Err(io::Error::from(io::ErrorKind::Unsupported))
+        }
+    }
+}
+
+#[cfg(any(
+    target_os = "android",
+    target_os = "freebsd",
+    target_os = "linux",
+    target_os = "macos",
+))]
+fn set_traffic_class_v6(socket: &socket2::SockRef, traffic_class: u32) -> io::Result<()> {
+    socket.set_tclass_v6(traffic_class)
+}
+
+#[cfg(not(any(
+    target_os = "android",
+    target_os = "freebsd",
+    target_os = "linux",
+    target_os = "macos",
+    target_os = "wasi",
+)))]
+fn set_traffic_class_v6(_socket: &socket2::SockRef, _traffic_class: u32) -> io::Result<()> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::{TcpListener, TcpStream},
+        time::Duration,
+    };
+
+    use super::SocketOptions;
+
+    #[test]
+    fn test_apply_socket_options() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let options = SocketOptions {
+            ttl: Some(32),
+            dscp: Some(46),
+            receive_buffer_size: Some(64 * 1024),
+            send_buffer_size: Some(64 * 1024),
+            keepalive: Some(Duration::from_secs(10)),
+        };
+        options.apply(&stream).unwrap();
+        let socket = socket2::SockRef::from(&stream);
+        assert_eq!(stream.ttl().unwrap(), 32);
+        assert_eq!(socket.tos().unwrap(), 46 << 2);
+        assert!(socket.keepalive().unwrap());
+        assert!(SocketOptions {
+            dscp: Some(64),
+            ..Default::default()
+        }
+        .apply(&stream)
+        .is_err());
+    }
+}
@@ -0,0 +1,349 @@
+//! The state of the safety and retransmission (SR) layer of one side of a
+//! connection: the sequence numbers of both directions and the data
+//! messages the peer has not confirmed yet. [`SrState`] does no I/O and
+//! reads no clock, so [`RastaConnection`](crate::RastaConnection),
+//! [`RastaListener`](crate::RastaListener) and
+//! [`StepDriver`](crate::step::StepDriver) number, check and retransmit
+//! messages the same way.
+
+use std::collections::VecDeque;
+
+use crate::{
+    message::{DiscReason, Message, MessageType, RastaId},
+    sequence::{cs_in_seq, SequenceCheck, SequenceWindow},
+    RastaError,
+};
+
+/// What became of a message passed to [`SrState::receive`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Received {
+    /// The message is the one expected next and was accepted.
+    Accepted,
+    /// Messages before this one are missing. It was discarded, and
+    /// a retransmission has to be requested from the peer.
+    Missing,
+    /// The message was discarded, e.g. because it is a duplicate or
+    /// arrived while waiting for a retransmission.
+    Discarded,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct SrState {
+    id: RastaId,
+    peer: RastaId,
+    n_sendmax: u16,
+    /// The number of unconfirmed data messages the peer accepts,
+    /// as announced in its connection request or response.
+    peer_n_sendmax: u16,
+    /// The number of unconfirmed data messages kept for retransmission.
+    capacity: usize,
+    /// The sequence number of the next message to the peer (SN_T).
+    seq_nr: u32,
+    /// The sequence numbers received messages are checked against,
+    /// once the connection has been set up.
+    window: Option<SequenceWindow>,
+    /// The timestamp of the last message accepted from the peer.
+    confirmed_timestamp: u32,
+    /// Whether a retransmission was requested. Messages from the
+    /// peer are discarded until it responds.
+    retransmission_requested: bool,
+    /// The sequence numbers and data of sent data messages
+    /// that the peer has not confirmed yet.
+    unconfirmed: VecDeque<(u32, Vec<u8>)>,
+}
+
+impl SrState {
+    pub fn new(id: RastaId, n_sendmax: u16, capacity: usize) -> Self {
+        Self {
+            id,
+            peer: 0,
+            n_sendmax,
+            peer_n_sendmax: n_sendmax,
+            capacity,
+            seq_nr: 0,
+            window: None,
+            confirmed_timestamp: 0,
+            retransmission_requested: false,
+            unconfirmed: VecDeque::new(),
+        }
+    }
+
+    pub fn id(&self) -> RastaId {
+        self.id
+    }
+
+    pub fn peer(&self) -> RastaId {
+        self.peer
+    }
+
+    /// Whether the connection has been set up, so that
+    /// messages from the peer are checked.
+    pub fn is_connected(&self) -> bool {
+        self.window.is_some()
+    }
+
+    pub fn window(&self) -> Option<SequenceWindow> {
+        self.window
+    }
+
+    pub fn unconfirmed_messages(&self) -> usize {
+        self.unconfirmed.len()
+    }
+
+    /// Forgets the connection, e.g. after it was closed.
+    pub fn reset(&mut self) {
+        self.window = None;
+        self.retransmission_requested = false;
+        self.unconfirmed.clear();
+    }
+
+    /// Records that the connection request `request` was sent to its receiver.
+    pub fn requested(&mut self, request: &Message) {
+        self.reset();
+        self.peer = request.receiver();
+        self.seq_nr = request.sequence_number().wrapping_add(1);
+    }
+
+    /// Answers the connection request `request`, starting a new connection
+    /// regardless of any earlier one. The response still has to be sent.
+    pub fn accept(&mut self, request: &Message, timestamp: u32) -> Message {
+        self.reset();
+        self.peer = request.sender();
+        self.peer_n_sendmax = self.announced_n_sendmax(request);
+        let response = Message::connection_response(
+            self.peer,
+            self.id,
+            request.sequence_number(),
+            timestamp,
+            request.timestamp(),
+            self.n_sendmax,
+        );
+        self.seq_nr = response.sequence_number().wrapping_add(1);
+        self.window = Some(SequenceWindow::new(
+            request.sequence_number(),
+            response.sequence_number(),
+        ));
+        self.confirmed_timestamp = request.timestamp();
+        response
+    }
+
+    /// Handles the connection response to the request sent last,
+    /// which has to confirm the request.
+    pub fn connected(&mut self, response: &Message) -> Result<(), RastaError> {
+        let request = self.seq_nr.wrapping_sub(1);
+        if !cs_in_seq(response.confirmed_sequence_number(), request) {
+            return Err(RastaError::InvalidConfirmedSeqNr);
+        }
+        self.peer = response.sender();
+        self.peer_n_sendmax = self.announced_n_sendmax(response);
+        self.window = Some(SequenceWindow::new(response.sequence_number(), request));
+        self.confirmed_timestamp = response.timestamp();
+        Ok(())
+    }
+
+    /// The N_SENDMAX of the peer, or the own one if it announced none.
+    fn announced_n_sendmax(&self, msg: &Message) -> u16 {
+        msg.peer_limits()
+            .map_or(self.n_sendmax, |limits| limits.n_sendmax)
+    }
+
+    /// The number of data messages that may be unconfirmed at once:
+    /// no more than the peer accepts and the send buffer holds, so that
+    /// every message the peer may request again can be retransmitted.
+    pub fn send_window(&self) -> usize {
+        self.capacity.min(usize::from(self.peer_n_sendmax))
+    }
+
+    /// Checks the sequence numbers of `msg` from the peer. Messages in
+    /// sequence are accepted and release the data messages they confirm.
+    /// A sequence number too far ahead or an invalid confirmation is a
+    /// protocol violation, after which the connection has to be closed.
+    pub fn receive(&mut self, msg: &Message) -> Result<Received, RastaError> {
+        let Some(window) = &mut self.window else {
+            return Err(RastaError::StateError);
+        };
+        let (sn, cs) = (msg.sequence_number(), msg.confirmed_sequence_number());
+        if self.retransmission_requested {
+            if !matches!(msg.try_message_type(), Ok(MessageType::RetrResp)) {
+                return Ok(Received::Discarded);
+            }
+            // The retransmitted messages follow the response
+            self.retransmission_requested = false;
+            window.expected = sn;
+        }
+        match window.check(sn, cs, self.n_sendmax) {
+            SequenceCheck::InSequence => {}
+            SequenceCheck::Missing => {
+                self.retransmission_requested = true;
+                return Ok(Received::Missing);
+            }
+            SequenceCheck::Stale => return Ok(Received::Discarded),
+            SequenceCheck::OutOfRange => return Err(RastaError::InvalidSeqNr),
+            SequenceCheck::InvalidConfirmation => return Err(RastaError::InvalidConfirmedSeqNr),
+        }
+        window.accept(sn, cs);
+        self.confirmed_timestamp = msg.timestamp();
+        self.release_confirmed(cs);
+        Ok(Received::Accepted)
+    }
+
+    /// Drops the data messages up to `confirmed_seq_nr` from the send buffer.
+    fn release_confirmed(&mut self, confirmed_seq_nr: u32) {
+        while self
+            .unconfirmed
+            .front()
+            .is_some_and(|(seq_nr, _)| confirmed_seq_nr.wrapping_sub(*seq_nr) <= u32::MAX / 2)
+        {
+            self.unconfirmed.pop_front();
+        }
+    }
+
+    /// The sequence number of the last message accepted from the peer.
+    fn confirmed_seq_nr(&self) -> u32 {
+        self.window
+            .map_or(0, |window| window.expected.wrapping_sub(1))
+    }
+
+    /// Takes the sequence number of the next message to the peer.
+    fn next_seq_nr(&mut self) -> u32 {
+        let seq_nr = self.seq_nr;
+        self.seq_nr = seq_nr.wrapping_add(1);
+        if let Some(window) = &mut self.window {
+            window.sent(seq_nr);
+        }
+        seq_nr
+    }
+
+    pub fn heartbeat(&mut self, timestamp: u32) -> Message {
+        Message::heartbeat(
+            self.peer,
+            self.id,
+            self.next_seq_nr(),
+            self.confirmed_seq_nr(),
+            timestamp,
+            self.confirmed_timestamp,
+        )
+    }
+
+    pub fn disconnection_request(&mut self, timestamp: u32, reason: DiscReason) -> Message {
+        let mut msg = Message::disconnection_request(
+            self.peer,
+            self.id,
+            self.next_seq_nr(),
+            self.confirmed_seq_nr(),
+            timestamp,
+            self.confirmed_timestamp,
+        );
+        msg.set_disconnect_reason(reason);
+        msg
+    }
+
+    pub fn retransmission_request(&mut self, timestamp: u32) -> Message {
+        Message::retransmission_request(
+            self.peer,
+            self.id,
+            self.next_seq_nr(),
+            self.confirmed_seq_nr(),
+            timestamp,
+            self.confirmed_timestamp,
+        )
+    }
+
+    /// A data message carrying `data`, which is kept until the peer confirms it.
+    /// Fails with [`RastaError::BufferFull`] if the [send window](Self::send_window)
+    /// is exhausted, since a message that cannot be retransmitted must not be sent.
+    pub fn data(&mut self, timestamp: u32, data: &[u8]) -> Result<Message, RastaError> {
+        let window = self.send_window();
+        if self.unconfirmed.len() >= window {
+            return Err(RastaError::BufferFull {
+                buffer: "buffer of unconfirmed messages",
+                capacity: window,
+            });
+        }
+        let seq_nr = self.seq_nr;
+        let msg = Message::data_message(
+            self.peer,
+            self.id,
+            seq_nr,
+            self.confirmed_seq_nr(),
+            timestamp,
+            self.confirmed_timestamp,
+            data,
+        )?;
+        self.next_seq_nr();
+        self.unconfirmed.push_back((seq_nr, data.to_vec()));
+        Ok(msg)
+    }
+
+    /// Answers a retransmission request of the peer: the response is
+    /// followed by all data messages the peer has not confirmed yet.
+    pub fn retransmission(&mut self, timestamp: u32) -> Result<Vec<Message>, RastaError> {
+        let mut messages = vec![Message::retransmission_response(
+            self.peer,
+            self.id,
+            self.next_seq_nr(),
+            self.confirmed_seq_nr(),
+            timestamp,
+            self.confirmed_timestamp,
+        )];
+        for (_, data) in std::mem::take(&mut self.unconfirmed) {
+            let seq_nr = self.next_seq_nr();
+            messages.push(Message::retransmitted_data_message(
+                self.peer,
+                self.id,
+                seq_nr,
+                self.confirmed_seq_nr(),
+                timestamp,
+                self.confirmed_timestamp,
+                &data,
+            )?);
+            self.unconfirmed.push_back((seq_nr, data));
+        }
+        Ok(messages)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SrState;
+    use crate::{message::Message, RastaError};
+
+    #[test]
+    fn test_send_window() {
+        // Limited by the send buffer
+        let mut sr = SrState::new(1, 10, 2);
+        let request = Message::connection_request(1, 2, 0, 10);
+        let response = sr.accept(&request, 0);
+        let first = sr.data(0, &[1]).unwrap();
+        sr.data(0, &[2]).unwrap();
+        assert!(matches!(
+            sr.data(0, &[3]),
+            Err(RastaError::BufferFull { capacity: 2, .. })
+        ));
+        assert_eq!(sr.unconfirmed_messages(), 2);
+        // The refused message took no sequence number
+        assert_eq!(
+            sr.heartbeat(0).sequence_number(),
+            response.sequence_number().wrapping_add(3)
+        );
+
+        // A confirmation makes room again
+        let heartbeat = Message::heartbeat(
+            1,
+            2,
+            request.sequence_number().wrapping_add(1),
+            first.sequence_number(),
+            0,
+            0,
+        );
+        sr.receive(&heartbeat).unwrap();
+        sr.data(0, &[3]).unwrap();
+
+        // Limited by the peer
+        let mut sr = SrState::new(1, 10, 10);
+        sr.accept(&Message::connection_request(1, 2, 0, 1), 0);
+        assert_eq!(sr.send_window(), 1);
+        sr.data(0, &[1]).unwrap();
+        assert!(sr.data(0, &[2]).is_err());
+    }
+}
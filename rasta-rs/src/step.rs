@@ -0,0 +1,496 @@
+//! A deterministic driver of the safety and retransmission (SR) layer
+//! for debuggers, simulations and model checkers.
+//!
+//! A [`StepDriver`] holds the state of one side of a connection, but does
+//! no I/O and never reads a clock. Received frames are passed in with
+//! [`StepDriver::feed_frame`], time only passes with
+//! [`StepDriver::advance_time`], and the frames to send, the received data
+//! and the state changes are taken out with [`StepDriver::poll_output`].
+//! Every call completes one step, so the same sequence of calls always
+//! produces the same outputs, and a driver can be cloned to explore several
+//! continuations from the same state, e.g. frames being lost or delayed.
+//!
+//! The sequence numbers and unconfirmed data are kept in the same SR state
+//! as in [`RastaConnection`](crate::RastaConnection) and
+//! [`RastaListener`](crate::RastaListener), so a gap in the sequence numbers
+//! of the peer is closed with a retransmission request and a violation
+//! closes the connection the same way. Unlike the endpoints, whose responder
+//! is up as soon as it has answered, the driver follows the specification in
+//! only entering `Up` once the initiator has sent its first heartbeat.
+
+use std::{collections::VecDeque, time::Duration};
+
+use crate::{
+    descriptor::EndpointRole,
+    diagram,
+    logging::event,
+    message::{DiscReason, Message, MessageType, RastaId, RASTA_VERSION},
+    sr::{Received, SrState},
+    RastaConfig, RastaConnectionState, RastaError, UnknownMessagePolicy,
+};
+
+crate::state_transitions! {
+    /// The state changes of a [`StepDriver`].
+    pub const STEP_TRANSITIONS: [RastaConnectionState] = [
+        Down => Start on "open / ConnReq",
+        Down => Closed on "ConnReq of another version / close",
+        Start => Up on "ConnResp / HB",
+        Start => Closed on "DiscReq / T_max / protocol error / close",
+        Up => Closed on "DiscReq / T_max / protocol error / close",
+    ];
+}
+
+/// What a [`StepDriver`] asks its environment to do.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StepOutput {
+    /// Send the sealed frame to the peer.
+    Frame(Vec<u8>),
+    /// Deliver data received from the peer to the application.
+    Data(Vec<u8>),
+    /// Pass a frame of an unknown message type to the application, see
+    /// [`UnknownMessagePolicy::Forward`](crate::UnknownMessagePolicy::Forward).
+    Unknown(Vec<u8>),
+    /// The connection entered the state.
+    StateChanged(RastaConnectionState),
+}
+
+/// One side of a RaSTA connection, driven step by step.
+/// See the [module documentation](self) for an overview.
+///
+/// Outgoing messages are timestamped with the milliseconds that have passed
+/// on the clock of the driver, and heartbeats are sent at the interval
+/// [`RastaConfig::heartbeat_interval`] gives for a round-trip time of zero.
+#[derive(Clone)]
+pub struct StepDriver {
+    role: EndpointRole,
+    config: RastaConfig,
+    state: RastaConnectionState,
+    /// The time that has passed since the driver was created.
+    now: Duration,
+    /// The sequence numbers and unconfirmed data of the connection.
+    sr: SrState,
+    last_received: Duration,
+    last_sent: Duration,
+    outputs: VecDeque<StepOutput>,
+}
+
+impl StepDriver {
+    /// Creates a driver in the `Down` state whose clock is at zero.
+    pub fn new(id: RastaId, config: RastaConfig) -> Self {
+        Self {
+            role: EndpointRole::Listener,
+            sr: SrState::new(id, config.n_sendmax, config.memory.unconfirmed_messages),
+            config,
+            state: RastaConnectionState::Down,
+            now: Duration::ZERO,
+            last_received: Duration::ZERO,
+            last_sent: Duration::ZERO,
+            outputs: VecDeque::new(),
+        }
+    }
+
+    pub fn state(&self) -> RastaConnectionState {
+        self.state
+    }
+
+    /// The RaSTA ID of the peer, once it is known.
+    pub fn peer(&self) -> RastaId {
+        self.sr.peer()
+    }
+
+    /// The time that has passed on the clock of the driver.
+    pub fn now(&self) -> Duration {
+        self.now
+    }
+
+    /// The number of sent data messages the peer has not confirmed yet.
+    pub fn unconfirmed_messages(&self) -> usize {
+        self.sr.unconfirmed_messages()
+    }
+
+    /// Takes the next output, oldest first.
+    pub fn poll_output(&mut self) -> Option<StepOutput> {
+        self.outputs.pop_front()
+    }
+
+    /// Sends a connection request to `peer`. The initial sequence number
+    /// is given by the caller instead of being random, so that runs can
+    /// be reproduced.
+    pub fn open(&mut self, peer: RastaId, sequence_number: u32) -> Result<(), RastaError> {
+        if self.state != RastaConnectionState::Down {
+            return Err(RastaError::StateError);
+        }
+        self.role = EndpointRole::Connection;
+        let mut msg = Message::connection_request(
+            peer,
+            self.sr.id(),
+            self.timestamp(),
+            self.config.n_sendmax,
+        );
+        msg.set_sequence_number(sequence_number);
+        msg.advertise_max_data_length(self.config.max_data_length);
+        self.sr.requested(&msg);
+        // T_max applies to the response as well
+        self.last_received = self.now;
+        self.send(msg);
+        self.enter(RastaConnectionState::Start);
+        Ok(())
+    }
+
+    /// Sends `data` to the peer and keeps it until the peer confirms it.
+    pub fn send_data(&mut self, data: &[u8]) -> Result<(), RastaError> {
+        if self.state != RastaConnectionState::Up {
+            return Err(RastaError::StateError);
+        }
+        let msg = self.sr.data(self.timestamp(), data)?;
+        self.send(msg);
+        Ok(())
+    }
+
+    /// Closes the connection, telling the peer if it has been contacted.
+    pub fn close(&mut self) {
+        match self.state {
+            RastaConnectionState::Start | RastaConnectionState::Up => {
+                self.disconnect(DiscReason::UserRequest)
+            }
+            RastaConnectionState::Down => self.enter(RastaConnectionState::Closed),
+            RastaConnectionState::Closed => {}
+        }
+    }
+
+    /// Lets `by` pass on the clock of the driver and runs the timers once:
+    /// the connection is closed if the peer has been silent for longer than
+    /// [`RastaConfig::t_max`], otherwise a heartbeat is sent if nothing
+    /// has been sent for the heartbeat interval.
+    pub fn advance_time(&mut self, by: Duration) {
+        self.now += by;
+        match self.state {
+            RastaConnectionState::Start | RastaConnectionState::Up
+                if self.now - self.last_received > self.config.t_max =>
+            {
+                event!(warn, peer = self.sr.peer(), "Peer exceeded T_max");
+                self.disconnect(DiscReason::Timeout);
+            }
+            RastaConnectionState::Up
+                if self.now - self.last_sent >= self.config.heartbeat_interval(Duration::ZERO) =>
+            {
+                let heartbeat = self.sr.heartbeat(self.timestamp());
+                self.send(heartbeat);
+            }
+            _ => {}
+        }
+    }
+
+    /// Processes one frame received from the peer. Frames that are
+    /// malformed, carry a wrong safety code or are not expected in the
+    /// current state are discarded with an error, as are frames of unknown
+    /// message types unless they are forwarded. Protocol violations
+    /// close the connection before the error is returned.
+    pub fn feed_frame(&mut self, frame: &[u8]) -> Result<(), RastaError> {
+        let msg = Message::try_parse_passthrough(frame)?;
+        msg.verify(&*self.config.safety_code)?;
+        let message_type = match msg.try_message_type() {
+            Ok(message_type) => Some(message_type),
+            Err(_)
+                if self.config.unknown_messages == UnknownMessagePolicy::Forward
+                    && self.state == RastaConnectionState::Up =>
+            {
+                None
+            }
+            Err(e) => return Err(e),
+        };
+        match (self.state, self.role, message_type) {
+            (RastaConnectionState::Down, _, Some(MessageType::ConnReq)) => self.accept(&msg),
+            (
+                RastaConnectionState::Start,
+                EndpointRole::Connection,
+                Some(MessageType::ConnResp),
+            ) => self.connected(&msg),
+            (RastaConnectionState::Start, EndpointRole::Listener, _)
+            | (RastaConnectionState::Up, _, _) => self.receive(msg, message_type),
+            _ => Err(RastaError::StateError),
+        }
+    }
+
+    /// Answers the connection request `msg` as the responder.
+    fn accept(&mut self, msg: &Message) -> Result<(), RastaError> {
+        self.role = EndpointRole::Listener;
+        self.last_received = self.now;
+        let mut response = self.sr.accept(msg, self.timestamp());
+        if msg.data().get(..4) != Some(&RASTA_VERSION[..]) {
+            event!(
+                warn,
+                peer = msg.sender(),
+                "Rejecting connection with incompatible version"
+            );
+            self.disconnect(DiscReason::ProtocolVersionError);
+            return Err(RastaError::VersionMismatch);
+        }
+        response.advertise_max_data_length(self.config.max_data_length);
+        self.send(response);
+        self.enter(RastaConnectionState::Start);
+        Ok(())
+    }
+
+    /// Handles the connection response `msg` as the initiator.
+    fn connected(&mut self, msg: &Message) -> Result<(), RastaError> {
+        self.last_received = self.now;
+        if let Err(e) = self.sr.connected(msg) {
+            self.disconnect(DiscReason::SequenceNumberError);
+            return Err(e);
+        }
+        if msg.data().get(..4) != Some(&RASTA_VERSION[..]) {
+            self.disconnect(DiscReason::ProtocolVersionError);
+            return Err(RastaError::VersionMismatch);
+        }
+        self.enter(RastaConnectionState::Up);
+        event!(info, peer = self.sr.peer(), "Connected");
+        // The responder enters Up with the first heartbeat
+        let heartbeat = self.sr.heartbeat(self.timestamp());
+        self.send(heartbeat);
+        Ok(())
+    }
+
+    /// Handles `msg` once the connection has been set up. `message_type`
+    /// is `None` for messages of unknown types that are forwarded.
+    fn receive(
+        &mut self,
+        msg: Message,
+        message_type: Option<MessageType>,
+    ) -> Result<(), RastaError> {
+        match self.sr.receive(&msg) {
+            Ok(Received::Accepted) => {}
+            Ok(Received::Missing) => {
+                event!(
+                    debug,
+                    peer = self.sr.peer(),
+                    received = msg.sequence_number(),
+                    "Requesting retransmission"
+                );
+                let request = self.sr.retransmission_request(self.timestamp());
+                self.send(request);
+                return Ok(());
+            }
+            // A duplicate or a message that was already retransmitted
+            Ok(Received::Discarded) => return Err(RastaError::InvalidSeqNr),
+            Err(e) => {
+                event!(
+                    warn,
+                    peer = self.sr.peer(),
+                    received = msg.sequence_number(),
+                    confirmed = msg.confirmed_sequence_number(),
+                    "Invalid sequence number"
+                );
+                self.disconnect(DiscReason::SequenceNumberError);
+                return Err(e);
+            }
+        }
+        if self.state == RastaConnectionState::Start
+            && !matches!(message_type, Some(MessageType::HB | MessageType::DiscReq))
+        {
+            self.disconnect(DiscReason::UnexpectedMessage);
+            return Err(RastaError::StateError);
+        }
+        self.last_received = self.now;
+        let Some(message_type) = message_type else {
+            self.outputs
+                .push_back(StepOutput::Unknown(msg.into_bytes()));
+            return Ok(());
+        };
+        match message_type {
+            MessageType::Data | MessageType::RetrData => {
+                self.outputs
+                    .push_back(StepOutput::Data(msg.data().to_vec()));
+            }
+            MessageType::HB => {
+                if self.state == RastaConnectionState::Start {
+                    self.enter(RastaConnectionState::Up);
+                    event!(info, peer = self.sr.peer(), "Connection established");
+                }
+            }
+            MessageType::RetrReq => {
+                for msg in self.sr.retransmission(self.timestamp())? {
+                    self.send(msg);
+                }
+            }
+            MessageType::RetrResp => {}
+            MessageType::DiscReq => {
+                event!(
+                    info,
+                    peer = self.sr.peer(),
+                    reason = ?msg.disconnect_reason(),
+                    "Disconnection requested by the peer"
+                );
+                self.sr.reset();
+                self.enter(RastaConnectionState::Closed);
+            }
+            MessageType::ConnReq | MessageType::ConnResp => {
+                self.disconnect(DiscReason::UnexpectedMessage);
+                return Err(RastaError::StateError);
+            }
+        }
+        Ok(())
+    }
+
+    fn disconnect(&mut self, reason: DiscReason) {
+        let msg = self.sr.disconnection_request(self.timestamp(), reason);
+        self.send(msg);
+        self.sr.reset();
+        self.enter(RastaConnectionState::Closed);
+    }
+
+    /// Seals `msg` and queues it for the peer.
+    fn send(&mut self, mut msg: Message) {
+        msg.seal(&*self.config.safety_code);
+        self.last_sent = self.now;
+        self.outputs.push_back(StepOutput::Frame(msg.into_bytes()));
+    }
+
+    fn timestamp(&self) -> u32 {
+        self.now.as_millis() as u32
+    }
+
+    /// Changes the state along one of the [`STEP_TRANSITIONS`].
+    fn enter(&mut self, state: RastaConnectionState) {
+        debug_assert!(
+            diagram::allows(STEP_TRANSITIONS, self.state, state),
+            "Undocumented transition from {:?} to {state:?}",
+            self.state
+        );
+        self.state = state;
+        self.outputs.push_back(StepOutput::StateChanged(state));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{StepDriver, StepOutput};
+    use crate::{
+        message::{Message, MessageType},
+        RastaConfig, RastaConnectionState, RastaError,
+    };
+
+    /// Takes all outputs of `driver`, splitting off the frames.
+    fn drain(driver: &mut StepDriver) -> (Vec<Vec<u8>>, Vec<StepOutput>) {
+        let mut frames = Vec::new();
+        let mut others = Vec::new();
+        while let Some(output) = driver.poll_output() {
+            match output {
+                StepOutput::Frame(frame) => frames.push(frame),
+                output => others.push(output),
+            }
+        }
+        (frames, others)
+    }
+
+    /// Feeds `frames` to `to` and takes its outputs.
+    fn deliver(frames: Vec<Vec<u8>>, to: &mut StepDriver) -> (Vec<Vec<u8>>, Vec<StepOutput>) {
+        for frame in frames {
+            to.feed_frame(&frame).unwrap();
+        }
+        drain(to)
+    }
+
+    fn connected() -> (StepDriver, StepDriver) {
+        let mut initiator = StepDriver::new(1, RastaConfig::default());
+        let mut responder = StepDriver::new(2, RastaConfig::default());
+        initiator.open(2, 100).unwrap();
+        let (request, _) = drain(&mut initiator);
+        let (response, _) = deliver(request, &mut responder);
+        let (heartbeat, outputs) = deliver(response, &mut initiator);
+        assert_eq!(
+            outputs,
+            [StepOutput::StateChanged(RastaConnectionState::Up)]
+        );
+        let (_, outputs) = deliver(heartbeat, &mut responder);
+        assert_eq!(
+            outputs,
+            [StepOutput::StateChanged(RastaConnectionState::Up)]
+        );
+        (initiator, responder)
+    }
+
+    #[test]
+    fn test_step_timers() {
+        let (mut initiator, mut responder) = connected();
+        initiator.send_data(&[1, 2, 3]).unwrap();
+        let (_, outputs) = deliver(drain(&mut initiator).0, &mut responder);
+        assert_eq!(outputs, [StepOutput::Data(vec![1, 2, 3])]);
+        assert_eq!(initiator.unconfirmed_messages(), 1);
+
+        // The heartbeat interval is T_max / 2 by default
+        let interval = RastaConfig::default().heartbeat_interval(Duration::ZERO);
+        responder.advance_time(interval);
+        let (frames, _) = drain(&mut responder);
+        let heartbeat = Message::from(frames[0].as_slice());
+        assert_eq!(heartbeat.timestamp(), interval.as_millis() as u32);
+        initiator.advance_time(interval);
+        initiator.feed_frame(&frames[0]).unwrap();
+        assert_eq!(initiator.unconfirmed_messages(), 0);
+
+        // The responder does not hear from the initiator in time
+        responder.advance_time(RastaConfig::default().t_max);
+        let (frames, outputs) = drain(&mut responder);
+        assert_eq!(
+            outputs,
+            [StepOutput::StateChanged(RastaConnectionState::Closed)]
+        );
+        initiator.feed_frame(&frames[0]).unwrap();
+        assert_eq!(initiator.state(), RastaConnectionState::Closed);
+        assert!(matches!(
+            initiator.send_data(&[4]),
+            Err(RastaError::StateError)
+        ));
+    }
+
+    #[test]
+    fn test_step_n_sendmax() {
+        let config = RastaConfig {
+            n_sendmax: 20,
+            ..Default::default()
+        };
+        let mut initiator = StepDriver::new(1, config.clone());
+        let mut responder = StepDriver::new(2, config);
+        initiator.open(2, 100).unwrap();
+        let (request, _) = drain(&mut initiator);
+        let limits = Message::from(request[0].as_slice()).peer_limits().unwrap();
+        assert_eq!(limits.n_sendmax, 20);
+        let (response, _) = deliver(request, &mut responder);
+        let limits = Message::from(response[0].as_slice()).peer_limits().unwrap();
+        assert_eq!(limits.n_sendmax, 20);
+    }
+
+    #[test]
+    fn test_step_retransmission() {
+        let (mut initiator, mut responder) = connected();
+        initiator.send_data(&[1]).unwrap();
+        initiator.send_data(&[2]).unwrap();
+        let (frames, _) = drain(&mut initiator);
+        let mut replay = responder.clone();
+
+        // The first data message is lost
+        responder.feed_frame(&frames[1]).unwrap();
+        let (requests, outputs) = drain(&mut responder);
+        assert!(outputs.is_empty());
+        let request = Message::from(requests[0].as_slice());
+        assert_eq!(request.try_message_type().unwrap(), MessageType::RetrReq);
+        // Every run from the same state yields the same outputs
+        replay.feed_frame(&frames[1]).unwrap();
+        assert_eq!(drain(&mut replay).0, requests);
+
+        initiator.feed_frame(&requests[0]).unwrap();
+        let (_, outputs) = deliver(drain(&mut initiator).0, &mut responder);
+        assert_eq!(
+            outputs,
+            [StepOutput::Data(vec![1]), StepOutput::Data(vec![2])]
+        );
+        // The original message arrives late
+        assert!(matches!(
+            responder.feed_frame(&frames[0]),
+            Err(RastaError::InvalidSeqNr)
+        ));
+        assert_eq!(responder.state(), RastaConnectionState::Up);
+    }
+}
@@ -0,0 +1,427 @@
+//! Automatic re-establishment of dropped connections.
+//!
+//! A [`RastaConnection`] is unusable once its TCP connection broke or the
+//! peer stopped answering. A [`RastaSupervisor`] owns the parameters of a
+//! connection and replaces it with a new one, starting over with a fresh
+//! connection request, whenever an operation fails with an error whose
+//! [`RecoveryAction`] is [`Reconnect`](RecoveryAction::Reconnect). The
+//! application is told about every change through [`SupervisorEvent`]s.
+
+use std::{
+    net::{SocketAddr, ToSocketAddrs},
+    sync::mpsc,
+    time::Duration,
+};
+
+use crate::{
+    address, config::RastaConfig, logging::event, message::Message, message::RastaId,
+    RastaConnection, RastaConnectionState, RastaError, RecoveryAction,
+};
+
+/// When and how often a [`RastaSupervisor`] tries to connect.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ReconnectPolicy {
+    /// The time between two failed attempts.
+    pub delay: Duration,
+    /// The number of attempts before giving up, or `None` to keep trying.
+    pub max_attempts: Option<u32>,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            delay: Duration::from_secs(1),
+            max_attempts: None,
+        }
+    }
+}
+
+/// A change of the connection of a [`RastaSupervisor`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SupervisorEvent {
+    /// The connection to `peer` is up after `attempt` attempts.
+    Connected { peer: RastaId, attempt: u32 },
+    /// The connection to `peer` broke or was closed by the peer.
+    /// It is re-established by the next operation.
+    Disconnected { peer: RastaId, reason: String },
+    /// Attempt number `attempt` to connect to `peer` failed.
+    ConnectFailed {
+        peer: RastaId,
+        attempt: u32,
+        reason: String,
+    },
+    /// The supervisor stopped trying to connect to `peer`, either because
+    /// the [`ReconnectPolicy`] is exhausted or because reconnecting is not
+    /// expected to help. The next operation starts over.
+    GaveUp { peer: RastaId, attempts: u32 },
+}
+
+type EventHandler = Box<dyn FnMut(&SupervisorEvent) + Send>;
+
+/// Owns a [`RastaConnection`] to `peer` and re-establishes it when it fails.
+///
+/// ```rust,no_run
+/// # use std::time::Duration;
+/// # use rasta_rs::supervisor::{RastaSupervisor, ReconnectPolicy};
+/// # fn main() -> Result<(), rasta_rs::RastaError> {
+/// let mut supervisor = RastaSupervisor::new("127.0.0.1:8888", 1234, 5678)?
+///     .with_policy(ReconnectPolicy {
+///         delay: Duration::from_millis(500),
+///         max_attempts: Some(10),
+///     });
+/// supervisor.on_event(|event| println!("{event:?}"));
+/// supervisor.send_data(&[1, 2, 3, 4])?;
+/// while let Some(msg) = supervisor.poll_message(Duration::from_millis(100))? {
+///     dbg!(msg.data());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct RastaSupervisor {
+    server: Vec<SocketAddr>,
+    id: RastaId,
+    peer: RastaId,
+    config: RastaConfig,
+    policy: ReconnectPolicy,
+    connection: Option<RastaConnection>,
+    handlers: Vec<EventHandler>,
+}
+
+impl RastaSupervisor {
+    /// Supervises the connection from `id` to `peer` at `server`. The
+    /// address is resolved once; no connection is made until the first
+    /// operation or [`connect`](Self::connect).
+    pub fn new<S: ToSocketAddrs>(
+        server: S,
+        id: RastaId,
+        peer: RastaId,
+    ) -> Result<Self, RastaError> {
+        Ok(Self {
+            server: address::resolve(server)?,
+            id,
+            peer,
+            config: RastaConfig::default(),
+            policy: ReconnectPolicy::default(),
+            connection: None,
+            handlers: Vec::new(),
+        })
+    }
+
+    /// The configuration of every connection made from now on.
+    pub fn with_config(mut self, config: RastaConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn with_policy(mut self, policy: ReconnectPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    pub fn id(&self) -> RastaId {
+        self.id
+    }
+
+    pub fn peer(&self) -> RastaId {
+        self.peer
+    }
+
+    pub fn policy(&self) -> ReconnectPolicy {
+        self.policy
+    }
+
+    /// Calls `handler` with every event, on the thread the event occurs on.
+    pub fn on_event<F>(&mut self, handler: F)
+    where
+        F: FnMut(&SupervisorEvent) + Send + 'static,
+    {
+        self.handlers.push(Box::new(handler));
+    }
+
+    /// A channel receiving every event from now on.
+    pub fn events(&mut self) -> mpsc::Receiver<SupervisorEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.on_event(move |event| {
+            // The receiver may have been dropped
+            let _ = sender.send(event.clone());
+        });
+        receiver
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connection.is_some()
+    }
+
+    /// Connects if there is no connection, trying according to the
+    /// [`ReconnectPolicy`]. Fails with the error of the last attempt.
+    pub fn connect(&mut self) -> Result<(), RastaError> {
+        if self.connection.is_some() {
+            return Ok(());
+        }
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let error = match self.try_connect() {
+                Ok(connection) => {
+                    self.connection.replace(connection);
+                    self.emit(SupervisorEvent::Connected {
+                        peer: self.peer,
+                        attempt,
+                    });
+                    return Ok(());
+                }
+                Err(error) => error,
+            };
+            event!(warn, peer = self.peer, attempt, %error, "Connecting failed");
+            self.emit(SupervisorEvent::ConnectFailed {
+                peer: self.peer,
+                attempt,
+                reason: error.to_string(),
+            });
+            if error.recovery_action() == RecoveryAction::FailSafe
+                || self.policy.max_attempts.is_some_and(|max| attempt >= max)
+            {
+                self.emit(SupervisorEvent::GaveUp {
+                    peer: self.peer,
+                    attempts: attempt,
+                });
+                return Err(error);
+            }
+            std::thread::sleep(self.policy.delay);
+        }
+    }
+
+    fn try_connect(&self) -> Result<RastaConnection, RastaError> {
+        let mut connection =
+            RastaConnection::try_new_with_config(&self.server[..], self.id, self.config.clone())?;
+        let result = connection.open_connection(self.peer);
+        if result.is_err() || connection.connection_state_request() != RastaConnectionState::Up {
+            connection.abandon();
+        }
+        result?;
+        if connection.connection_state_request() != RastaConnectionState::Up {
+            return Err(RastaError::StateError);
+        }
+        Ok(connection)
+    }
+
+    /// The current connection, connecting first if necessary.
+    pub fn connection(&mut self) -> Result<&mut RastaConnection, RastaError> {
+        self.connect()?;
+        Ok(self.connection.as_mut().expect("connected"))
+    }
+
+    /// Sends `data` and flushes it. If the connection broke, it is
+    /// re-established and `data` is sent once more on the new connection.
+    pub fn send_data(&mut self, data: &[u8]) -> Result<(), RastaError> {
+        let send = |connection: &mut RastaConnection| {
+            connection.send_data(data)?;
+            connection.flush()
+        };
+        self.connect()?;
+        match self.supervise(send) {
+            Err(_) if !self.is_connected() => {
+                event!(info, peer = self.peer, "Sending again after reconnecting");
+                self.supervise(send)
+            }
+            result => result,
+        }
+    }
+
+    /// Sends a heartbeat if one is due and waits up to `timeout` for the
+    /// next message, see [`RastaConnection::poll_message`]. Returns `None`
+    /// if no message arrived or the connection was re-established.
+    pub fn poll_message(&mut self, timeout: Duration) -> Result<Option<Message>, RastaError> {
+        self.connect()?;
+        let result = self.supervise(|connection| {
+            connection.maintain()?;
+            connection.poll_message(timeout)
+        });
+        match result {
+            Err(_) if !self.is_connected() => self.connect().map(|_| None),
+            Ok(Some(_))
+                if self.connection.as_ref().is_some_and(|connection| {
+                    connection.connection_state_request() == RastaConnectionState::Closed
+                }) =>
+            {
+                self.connection.take();
+                self.emit(SupervisorEvent::Disconnected {
+                    peer: self.peer,
+                    reason: "Disconnection requested by the peer".to_string(),
+                });
+                Ok(None)
+            }
+            result => result,
+        }
+    }
+
+    /// Closes the connection without re-establishing it. The next
+    /// operation connects again.
+    pub fn close(&mut self) -> Result<(), RastaError> {
+        match self.connection.take() {
+            Some(mut connection) => {
+                let result = connection.close_connection();
+                if result.is_err() {
+                    connection.abandon();
+                }
+                result
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Runs `operation` on the connection and drops the connection if
+    /// the error requires reconnecting.
+    fn supervise<T, F>(&mut self, operation: F) -> Result<T, RastaError>
+    where
+        F: FnOnce(&mut RastaConnection) -> Result<T, RastaError>,
+    {
+        let connection = self.connection()?;
+        match operation(connection) {
+            Err(error) if error.recovery_action() >= RecoveryAction::Reconnect => {
+                if let Some(mut connection) = self.connection.take() {
+                    connection.abandon();
+                }
+                event!(warn, peer = self.peer, %error, "Connection lost");
+                self.emit(SupervisorEvent::Disconnected {
+                    peer: self.peer,
+                    reason: error.to_string(),
+                });
+                Err(error)
+            }
+            result => result,
+        }
+    }
+
+    fn emit(&mut self, event: SupervisorEvent) {
+        for handler in &mut self.handlers {
+            handler(&event);
+        }
+    }
+}
+
+impl std::fmt::Debug for RastaSupervisor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RastaSupervisor")
+            .field("server", &self.server)
+            .field("id", &self.id)
+            .field("peer", &self.peer)
+            .field("policy", &self.policy)
+            .field("connected", &self.is_connected())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::{TcpListener, TcpStream},
+        time::Duration,
+    };
+
+    use crate::{
+        message::{Message, MessageType, MESSAGE_BUFFER_LENGTH},
+        RastaError, N_SENDMAX,
+    };
+
+    use super::{RastaSupervisor, ReconnectPolicy, SupervisorEvent};
+
+    fn read_frame(stream: &mut TcpStream) -> Message {
+        let mut frame = vec![0; MESSAGE_BUFFER_LENGTH];
+        stream.read_exact(&mut frame).unwrap();
+        Message::try_parse(&frame).unwrap()
+    }
+
+    fn accept(peer: &TcpListener) -> TcpStream {
+        let (mut stream, _) = peer.accept().unwrap();
+        let request = read_frame(&mut stream);
+        assert_eq!(request.try_message_type().unwrap(), MessageType::ConnReq);
+        let response =
+            Message::connection_response(1, 2, request.sequence_number(), 0, 0, N_SENDMAX);
+        stream.write_all(&response).unwrap();
+        stream
+    }
+
+    #[test]
+    fn test_supervisor_reconnects() {
+        let peer = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = peer.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            // The first connection breaks right away
+            drop(accept(&peer));
+            let mut stream = accept(&peer);
+            let data = read_frame(&mut stream);
+            assert_eq!(data.try_message_type().unwrap(), MessageType::Data);
+            assert_eq!(data.data(), &[1, 2, 3]);
+            assert_eq!(
+                read_frame(&mut stream).try_message_type().unwrap(),
+                MessageType::DiscReq
+            );
+        });
+
+        let mut supervisor = RastaSupervisor::new(addr, 1, 2).unwrap();
+        let events = supervisor.events();
+        supervisor.connect().unwrap();
+        assert!(supervisor
+            .poll_message(Duration::from_millis(200))
+            .unwrap()
+            .is_none());
+        supervisor.send_data(&[1, 2, 3]).unwrap();
+        supervisor.close().unwrap();
+        server.join().unwrap();
+
+        let events: Vec<_> = events.try_iter().collect();
+        assert_eq!(events.len(), 3);
+        assert_eq!(
+            events[0],
+            SupervisorEvent::Connected {
+                peer: 2,
+                attempt: 1
+            }
+        );
+        assert!(matches!(
+            events[1],
+            SupervisorEvent::Disconnected { peer: 2, .. }
+        ));
+        assert_eq!(
+            events[2],
+            SupervisorEvent::Connected {
+                peer: 2,
+                attempt: 1
+            }
+        );
+    }
+
+    #[test]
+    fn test_supervisor_gives_up() {
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let mut supervisor =
+            RastaSupervisor::new(addr, 1, 2)
+                .unwrap()
+                .with_policy(ReconnectPolicy {
+                    delay: Duration::from_millis(10),
+                    max_attempts: Some(2),
+                });
+        let events = supervisor.events();
+        assert!(matches!(
+            supervisor.send_data(&[1]),
+            Err(RastaError::Connect(_))
+        ));
+        assert!(!supervisor.is_connected());
+        let events: Vec<_> = events.try_iter().collect();
+        assert!(matches!(
+            events[..],
+            [
+                SupervisorEvent::ConnectFailed { attempt: 1, .. },
+                SupervisorEvent::ConnectFailed { attempt: 2, .. },
+                SupervisorEvent::GaveUp { attempts: 2, .. },
+            ]
+        ));
+    }
+}
@@ -0,0 +1,71 @@
+//! TLS settings for running RaSTA over an untrusted network
+//! (requires the `tls` feature). Set [`RastaConfig::tls`](crate::RastaConfig::tls)
+//! to wrap the TCP connections of a [`RastaConnection`](crate::RastaConnection)
+//! or [`RastaListener`](crate::RastaListener) in TLS. Everything above
+//! the transport behaves exactly as without TLS.
+
+use std::sync::Arc;
+
+use rustls::{
+    pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer, ServerName},
+    ClientConfig, RootCertStore, ServerConfig,
+};
+
+use crate::RastaError;
+
+/// Which side of the TLS handshake an endpoint takes, together with its settings.
+#[derive(Clone, Debug)]
+pub enum TlsConfig {
+    /// Used by [`RastaConnection`](crate::RastaConnection)s. The certificate
+    /// of the listener is verified against `server_name`.
+    Client {
+        config: Arc<ClientConfig>,
+        server_name: ServerName<'static>,
+    },
+    /// Used by [`RastaListener`](crate::RastaListener)s.
+    Server(Arc<ServerConfig>),
+}
+
+impl TlsConfig {
+    pub fn client(config: Arc<ClientConfig>, server_name: &str) -> Result<Self, RastaError> {
+        let server_name = ServerName::try_from(server_name.to_string())
+            .map_err(|e| RastaError::Other(e.to_string()))?;
+        Ok(Self::Client {
+            config,
+            server_name,
+        })
+    }
+
+    pub fn server(config: Arc<ServerConfig>) -> Self {
+        Self::Server(config)
+    }
+
+    /// A client configuration trusting the PEM encoded CA certificates in `ca_pem`.
+    pub fn client_from_pem(ca_pem: &[u8], server_name: &str) -> Result<Self, RastaError> {
+        let mut roots = RootCertStore::empty();
+        for cert in CertificateDer::pem_slice_iter(ca_pem) {
+            roots.add(cert.map_err(pem_error)?)?;
+        }
+        let config = ClientConfig::builder()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        Self::client(Arc::new(config), server_name)
+    }
+
+    /// A server configuration presenting the PEM encoded certificate
+    /// chain `cert_pem` with the private key `key_pem`.
+    pub fn server_from_pem(cert_pem: &[u8], key_pem: &[u8]) -> Result<Self, RastaError> {
+        let certs = CertificateDer::pem_slice_iter(cert_pem)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(pem_error)?;
+        let key = PrivateKeyDer::from_pem_slice(key_pem).map_err(pem_error)?;
+        let config = ServerConfig::builder()
+            .with_no_client_auth()
+            .with_single_cert(certs, key)?;
+        Ok(Self::server(Arc::new(config)))
+    }
+}
+
+fn pem_error(e: rustls::pki_types::pem::Error) -> RastaError {
+    RastaError::Tls(rustls::Error::General(e.to_string()))
+}
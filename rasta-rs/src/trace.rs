@@ -0,0 +1,190 @@
+//! Tracing of the messages exchanged on a connection. The verbosity
+//! can be changed at runtime, both for all peers and for single peers,
+//! e.g. to inspect the traffic of one element during commissioning.
+//! Traced messages are passed to the sink set with
+//! [`TraceControl::set_sink`]. Without one, they are printed to stderr,
+//! or emitted as `tracing` events with the `tracing` feature.
+
+use std::{
+    collections::HashMap,
+    fmt::{self, Write},
+    sync::{Arc, RwLock},
+};
+
+use crate::message::{Message, RastaId};
+
+/// How much of each message is traced.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TraceLevel {
+    /// Nothing is traced.
+    #[default]
+    Off,
+    /// Message type, sender, receiver, sequence numbers and timestamps.
+    Headers,
+    /// The headers and the user data of data messages.
+    Payload,
+}
+
+/// Receives every traced line, see [`TraceControl::set_sink`].
+pub type TraceSink = Arc<dyn Fn(&str) + Send + Sync>;
+
+#[derive(Default)]
+struct Levels {
+    default: TraceLevel,
+    peers: HashMap<RastaId, TraceLevel>,
+    sink: Option<TraceSink>,
+}
+
+impl fmt::Debug for Levels {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Levels")
+            .field("default", &self.default)
+            .field("peers", &self.peers)
+            .field("sink", &self.sink.is_some())
+            .finish()
+    }
+}
+
+/// Formats data as lowercase hex digits.
+struct Hex<'a>(&'a [u8]);
+
+impl fmt::Display for Hex<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.iter().try_for_each(|byte| write!(f, "{byte:02x}"))
+    }
+}
+
+/// A shared handle to the trace levels of a [`RastaConnection`](crate::RastaConnection)
+/// or [`RastaListener`](crate::RastaListener). Clones control the same
+/// endpoint, so a handle can be kept to adjust the levels while the
+/// endpoint is running on another thread.
+#[derive(Clone, Debug, Default)]
+pub struct TraceControl {
+    inner: Arc<RwLock<Levels>>,
+}
+
+impl TraceControl {
+    pub fn new(level: TraceLevel) -> Self {
+        let control = Self::default();
+        control.set_level(level);
+        control
+    }
+
+    /// The level used for peers without a level of their own.
+    pub fn level(&self) -> TraceLevel {
+        self.inner.read().unwrap().default
+    }
+
+    pub fn set_level(&self, level: TraceLevel) {
+        self.inner.write().unwrap().default = level;
+    }
+
+    /// Overrides the level for messages exchanged with `peer`.
+    pub fn set_peer_level(&self, peer: RastaId, level: TraceLevel) {
+        self.inner.write().unwrap().peers.insert(peer, level);
+    }
+
+    /// Makes `peer` use the default level again.
+    pub fn clear_peer_level(&self, peer: RastaId) {
+        self.inner.write().unwrap().peers.remove(&peer);
+    }
+
+    /// Passes the traced lines to `sink` instead of the default output,
+    /// e.g. to write them to a log file or show them in a user interface.
+    pub fn set_sink<F>(&self, sink: F)
+    where
+        F: Fn(&str) + Send + Sync + 'static,
+    {
+        self.inner.write().unwrap().sink = Some(Arc::new(sink));
+    }
+
+    /// Traces to the default output again.
+    pub fn clear_sink(&self) {
+        self.inner.write().unwrap().sink = None;
+    }
+
+    /// The level that applies to messages exchanged with `peer`.
+    pub fn level_for(&self, peer: RastaId) -> TraceLevel {
+        let levels = self.inner.read().unwrap();
+        levels.peers.get(&peer).copied().unwrap_or(levels.default)
+    }
+
+    pub(crate) fn incoming(&self, msg: &Message) {
+        self.trace("<-", msg.sender(), msg);
+    }
+
+    pub(crate) fn outgoing(&self, msg: &Message) {
+        self.trace("->", msg.receiver(), msg);
+    }
+
+    fn trace(&self, direction: &str, peer: RastaId, msg: &Message) {
+        let level = self.level_for(peer);
+        if level == TraceLevel::Off {
+            return;
+        }
+        let message_type = match msg.try_message_type() {
+            Ok(message_type) => format!("{message_type:?}"),
+            Err(_) => format!("Unknown({})", msg.message_type_raw()),
+        };
+        let mut line = format!(
+            "[RaSTA {direction} {peer}] {message_type} seq={} cseq={} ts={} cts={}",
+            msg.sequence_number(),
+            msg.confirmed_sequence_number(),
+            msg.timestamp(),
+            msg.confirmed_timestamp(),
+        );
+        if level == TraceLevel::Payload && !msg.data().is_empty() {
+            // Writing to a string cannot fail
+            let _ = write!(line, " data={}", Hex(msg.data()));
+        }
+        let sink = self.inner.read().unwrap().sink.clone();
+        match sink {
+            Some(sink) => sink(&line),
+            #[cfg(feature = "tracing")]
+            None => tracing::info!(target: "rasta_rs::trace", "{line}"),
+            #[cfg(not(feature = "tracing"))]
+            None => eprintln!("{line}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::{TraceControl, TraceLevel};
+    use crate::message::Message;
+
+    #[test]
+    fn test_peer_level() {
+        let control = TraceControl::new(TraceLevel::Headers);
+        let handle = control.clone();
+        handle.set_peer_level(42, TraceLevel::Payload);
+        assert_eq!(control.level_for(42), TraceLevel::Payload);
+        assert_eq!(control.level_for(7), TraceLevel::Headers);
+        handle.clear_peer_level(42);
+        handle.set_level(TraceLevel::Off);
+        assert_eq!(control.level_for(42), TraceLevel::Off);
+    }
+
+    #[test]
+    fn test_sink() {
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let control = TraceControl::new(TraceLevel::Payload);
+        let sink = lines.clone();
+        control.set_sink(move |line| sink.lock().unwrap().push(line.to_string()));
+        let msg = Message::data_message(1, 2, 3, 4, 5, 6, &[0x01, 0xab, 0xff]).unwrap();
+        control.outgoing(&msg);
+        control.set_peer_level(2, TraceLevel::Headers);
+        control.incoming(&msg);
+        control.set_level(TraceLevel::Off);
+        control.outgoing(&msg);
+        assert_eq!(
+            *lines.lock().unwrap(),
+            [
+                "[RaSTA -> 1] Data seq=3 cseq=4 ts=5 cts=6 data=01abff",
+                "[RaSTA <- 2] Data seq=3 cseq=4 ts=5 cts=6",
+            ]
+        );
+    }
+}
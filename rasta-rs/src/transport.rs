@@ -0,0 +1,266 @@
+//! The byte stream RaSTA messages are exchanged over.
+
+use std::{
+    io::{ErrorKind, Read, Write},
+    net::{Shutdown, TcpStream},
+    time::{Duration, Instant},
+};
+
+#[cfg(feature = "tls")]
+use rustls::{ClientConnection, ServerConnection, StreamOwned};
+
+use crate::{config::WriteRetryPolicy, logging::event, RastaConfig, RastaError};
+
+/// A TCP connection, optionally wrapped in TLS. Writes failing with a
+/// transient error are retried as configured by
+/// [`RastaConfig::write_retry`].
+pub(crate) struct Transport {
+    stream: Stream,
+    write_retry: WriteRetryPolicy,
+}
+
+enum Stream {
+    Tcp(TcpStream),
+    #[cfg(feature = "tls")]
+    TlsClient(Box<StreamOwned<ClientConnection, TcpStream>>),
+    #[cfg(feature = "tls")]
+    TlsServer(Box<StreamOwned<ServerConnection, TcpStream>>),
+}
+
+impl Transport {
+    fn new(stream: Stream, config: &RastaConfig) -> Self {
+        Self {
+            stream,
+            write_retry: config.write_retry,
+        }
+    }
+
+    /// Wraps an outgoing connection as requested by `config`.
+    pub(crate) fn client(stream: TcpStream, config: &RastaConfig) -> Result<Self, RastaError> {
+        #[cfg(feature = "tls")]
+        match &config.tls {
+            Some(crate::tls::TlsConfig::Client {
+                config: tls,
+                server_name,
+            }) => {
+                let conn = ClientConnection::new(tls.clone(), server_name.clone())?;
+                let stream = Stream::TlsClient(Box::new(StreamOwned::new(conn, stream)));
+                return Ok(Self::new(stream, config));
+            }
+            Some(crate::tls::TlsConfig::Server(_)) => {
+                return Err(RastaError::Other(
+                    "Connections need a TLS client configuration".to_string(),
+                ))
+            }
+            None => {}
+        }
+        Ok(Self::new(Stream::Tcp(stream), config))
+    }
+
+    /// Wraps an accepted connection as requested by `config`.
+    pub(crate) fn server(stream: TcpStream, config: &RastaConfig) -> Result<Self, RastaError> {
+        #[cfg(feature = "tls")]
+        match &config.tls {
+            Some(crate::tls::TlsConfig::Server(tls)) => {
+                let conn = ServerConnection::new(tls.clone())?;
+                let stream = Stream::TlsServer(Box::new(StreamOwned::new(conn, stream)));
+                return Ok(Self::new(stream, config));
+            }
+            Some(crate::tls::TlsConfig::Client { .. }) => {
+                return Err(RastaError::Other(
+                    "Listeners need a TLS server configuration".to_string(),
+                ))
+            }
+            None => {}
+        }
+        Ok(Self::new(Stream::Tcp(stream), config))
+    }
+
+    /// The underlying TCP connection.
+    pub(crate) fn tcp(&self) -> &TcpStream {
+        match &self.stream {
+            Stream::Tcp(stream) => stream,
+            #[cfg(feature = "tls")]
+            Stream::TlsClient(stream) => stream.get_ref(),
+            #[cfg(feature = "tls")]
+            Stream::TlsServer(stream) => stream.get_ref(),
+        }
+    }
+
+    /// Whether data has already been received and can be read without
+    /// waiting for the socket.
+    pub(crate) fn has_buffered_data(&mut self) -> bool {
+        match &mut self.stream {
+            Stream::Tcp(_) => false,
+            #[cfg(feature = "tls")]
+            Stream::TlsClient(stream) => stream
+                .conn
+                .process_new_packets()
+                .is_ok_and(|state| state.plaintext_bytes_to_read() > 0),
+            #[cfg(feature = "tls")]
+            Stream::TlsServer(stream) => stream
+                .conn
+                .process_new_packets()
+                .is_ok_and(|state| state.plaintext_bytes_to_read() > 0),
+        }
+    }
+
+    /// Closes the sending direction and waits up to `grace_period`
+    /// for the peer to close the connection, so that data written
+    /// before is delivered instead of being discarded by a reset.
+    pub(crate) fn close(&mut self, grace_period: Duration) -> std::io::Result<()> {
+        match &mut self.stream {
+            Stream::Tcp(_) => {}
+            #[cfg(feature = "tls")]
+            Stream::TlsClient(stream) => {
+                stream.conn.send_close_notify();
+                stream.flush()?;
+            }
+            #[cfg(feature = "tls")]
+            Stream::TlsServer(stream) => {
+                stream.conn.send_close_notify();
+                stream.flush()?;
+            }
+        }
+        let mut tcp = self.tcp();
+        match tcp.shutdown(Shutdown::Write) {
+            // The peer has already closed the connection
+            Err(e) if e.kind() == ErrorKind::NotConnected => return Ok(()),
+            result => result?,
+        }
+        let deadline = Instant::now() + grace_period;
+        let mut buf = [0; 1024];
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Ok(());
+            }
+            tcp.set_read_timeout(Some(remaining))?;
+            match tcp.read(&mut buf) {
+                Ok(0) | Err(_) => return Ok(()),
+                Ok(_) => {}
+            }
+        }
+    }
+
+    /// A second handle for reading, if the transport allows reading
+    /// and writing independently. TLS sessions do not.
+    pub(crate) fn try_clone_reader(&self) -> Option<TcpStream> {
+        match &self.stream {
+            Stream::Tcp(stream) => stream.try_clone().ok(),
+            #[cfg(feature = "tls")]
+            _ => None,
+        }
+    }
+}
+
+impl Read for Transport {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match &mut self.stream {
+            Stream::Tcp(stream) => stream.read(buf),
+            #[cfg(feature = "tls")]
+            Stream::TlsClient(stream) => stream.read(buf),
+            #[cfg(feature = "tls")]
+            Stream::TlsServer(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Transport {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let stream = &mut self.stream;
+        retry_transient(&self.write_retry, || match stream {
+            Stream::Tcp(stream) => stream.write(buf),
+            #[cfg(feature = "tls")]
+            Stream::TlsClient(stream) => stream.write(buf),
+            #[cfg(feature = "tls")]
+            Stream::TlsServer(stream) => stream.write(buf),
+        })
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        let stream = &mut self.stream;
+        retry_transient(&self.write_retry, || match stream {
+            Stream::Tcp(stream) => stream.flush(),
+            #[cfg(feature = "tls")]
+            Stream::TlsClient(stream) => stream.flush(),
+            #[cfg(feature = "tls")]
+            Stream::TlsServer(stream) => stream.flush(),
+        })
+    }
+}
+
+/// Repeats `op` while it fails with `WouldBlock` or `Interrupted`,
+/// waiting as `policy` specifies. Other errors and the last transient
+/// error once the retries are used up are returned.
+fn retry_transient<T>(
+    policy: &WriteRetryPolicy,
+    mut op: impl FnMut() -> std::io::Result<T>,
+) -> std::io::Result<T> {
+    let mut retry = 0;
+    loop {
+        match op() {
+            Err(e)
+                if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::Interrupted)
+                    && retry < policy.max_retries =>
+            {
+                let backoff = policy.backoff(retry);
+                event!(
+                    debug,
+                    "Transient write error {}, retrying in {:?}",
+                    e,
+                    backoff
+                );
+                std::thread::sleep(backoff);
+                retry += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Error, ErrorKind},
+        time::Duration,
+    };
+
+    use super::retry_transient;
+    use crate::config::WriteRetryPolicy;
+
+    #[test]
+    fn test_retry_transient() {
+        let policy = WriteRetryPolicy {
+            max_retries: 3,
+            initial_backoff: Duration::ZERO,
+            max_backoff: Duration::ZERO,
+        };
+        let mut attempts = 0;
+        let result = retry_transient(&policy, || {
+            attempts += 1;
+            if attempts < 4 {
+                Err(Error::from(ErrorKind::WouldBlock))
+            } else {
+                Ok(attempts)
+            }
+        });
+        assert_eq!(result.unwrap(), 4);
+
+        attempts = 0;
+        let result: std::io::Result<()> = retry_transient(&policy, || {
+            attempts += 1;
+            Err(Error::from(ErrorKind::Interrupted))
+        });
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::Interrupted);
+        assert_eq!(attempts, 4);
+
+        attempts = 0;
+        let result: std::io::Result<()> = retry_transient(&policy, || {
+            attempts += 1;
+            Err(Error::from(ErrorKind::ConnectionReset))
+        });
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::ConnectionReset);
+        assert_eq!(attempts, 1);
+    }
+}
@@ -1,6 +1,5 @@
-use rasta_rs::RastaListener;
+use sci_rs::prelude::*;
 use sci_rs::scils::SCILSBrightness;
-use sci_rs::{SCIListener, SCIMessageType, SCITelegram};
 use std::net::SocketAddr;
 
 fn main() {
@@ -12,19 +11,13 @@ fn main() {
 
     receiver
         .listen(|telegram| {
-            println!(
-                "Received Telegram: {}",
-                telegram.message_type.try_as_scils_message_type().unwrap()
-            );
-            dbg!(&telegram.sender);
-            dbg!(&telegram.receiver);
-            dbg!(telegram.payload.used);
-            if telegram.message_type == SCIMessageType::scils_change_brightness() {
-                let change = SCILSBrightness::try_from(telegram.payload.data[0]).unwrap();
+            println!("Received Telegram: {telegram}");
+            if telegram.message_type() == SCIMessageType::scils_change_brightness() {
+                let change = SCILSBrightness::try_from(telegram.payload()[0]).unwrap();
                 luminosity = change;
                 Some(SCITelegram::scils_brightness_status(
-                    &*telegram.receiver,
-                    &*telegram.sender,
+                    telegram.receiver(),
+                    telegram.sender(),
                     luminosity,
                 ))
             } else {
@@ -1,9 +1,8 @@
 use std::net::SocketAddr;
 
-use rasta_rs::RastaListener;
 use sci_rs::{
+    prelude::*,
     scip::{SCIPointLocation, SCIPointTargetLocation},
-    SCIListener, SCIMessageType, SCITelegram,
 };
 
 fn main() {
@@ -14,15 +13,9 @@ fn main() {
     let mut location = SCIPointLocation::PointLocationLeft;
     receiver
         .listen(|telegram| {
-            println!(
-                "Received Telegram: {}",
-                telegram.message_type.try_as_scip_message_type().unwrap()
-            );
-            dbg!(telegram.sender);
-            dbg!(telegram.receiver);
-            dbg!(telegram.payload.used);
-            if telegram.message_type == SCIMessageType::scip_change_location() {
-                let change = SCIPointTargetLocation::try_from(telegram.payload.data[0]).unwrap();
+            println!("Received Telegram: {telegram}");
+            if telegram.message_type() == SCIMessageType::scip_change_location() {
+                let change = SCIPointTargetLocation::try_from(telegram.payload()[0]).unwrap();
                 match change {
                     SCIPointTargetLocation::PointLocationChangeToRight => {
                         location = SCIPointLocation::PointLocationRight
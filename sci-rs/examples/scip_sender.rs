@@ -1,9 +1,8 @@
 use std::{collections::HashMap, net::SocketAddr};
 
-use rasta_rs::RastaConnection;
 use sci_rs::{
+    prelude::*,
     scip::{SCIPointLocation, SCIPointTargetLocation},
-    SCICommand, SCIConnection, SCIMessageType, SCITelegram,
 };
 
 fn main() {
@@ -16,9 +15,9 @@ fn main() {
     sender
         .run("S", |data| {
             if let Some(data) = data {
-                dbg!(data.message_type);
-                if data.message_type == SCIMessageType::scip_location_status() {
-                    let location = SCIPointLocation::try_from(data.payload.data[0]).unwrap();
+                println!("Received Telegram: {data}");
+                if data.message_type() == SCIMessageType::scip_location_status() {
+                    let location = SCIPointLocation::try_from(data.payload()[0]).unwrap();
                     println!("Point is now at {location:?}");
                     next_direction = if location == SCIPointLocation::PointLocationLeft {
                         SCIPointTargetLocation::PointLocationChangeToRight
@@ -0,0 +1,18 @@
+//! Renders the state machines of the RaSTA and PDI layers as Graphviz
+//! digraphs, e.g. `cargo run --example state_diagrams --features rasta | dot -Tsvg -O`.
+
+use rasta_rs::{diagram, step::STEP_TRANSITIONS, SR_TRANSITIONS};
+use sci_rs::pdi::{PDI_INITIATOR_TRANSITIONS, PDI_RESPONDER_TRANSITIONS};
+
+fn main() {
+    print!("{}", diagram::dot("sr", SR_TRANSITIONS));
+    print!("{}", diagram::dot("sr_step", STEP_TRANSITIONS));
+    print!(
+        "{}",
+        diagram::dot("pdi_initiator", PDI_INITIATOR_TRANSITIONS)
+    );
+    print!(
+        "{}",
+        diagram::dot("pdi_responder", PDI_RESPONDER_TRANSITIONS)
+    );
+}
@@ -0,0 +1,157 @@
+//! Runs the same SCI-P exchange over the available transports and
+//! reports the latency and jitter of each, to guide deployment choices.
+//!
+//! The UDP run uses the redundancy layer with two transport channels
+//! on the loopback interface and exchanges the telegrams as plain data
+//! messages. It leaves out the safety and retransmission layer, i.e.
+//! connection handling, confirmations and heartbeats, so its numbers
+//! are not directly comparable to the TCP run. The redundancy counters
+//! of both sides are reported alongside to show what the channels lost.
+
+use std::{
+    collections::HashMap,
+    net::{SocketAddr, UdpSocket},
+    thread,
+    time::{Duration, Instant},
+};
+
+use rasta_rs::{
+    message::Message,
+    redundancy::{RedundancyChannel, RedundancyConfig, RedundancyStats},
+    RastaConnection, RastaListener,
+};
+use sci_rs::{
+    scip::{SCIPointLocation, SCIPointTargetLocation},
+    SCIConnection, SCIListener, SCIMessageType, SCITelegram,
+};
+
+const EXCHANGES: usize = 200;
+
+struct Stats {
+    samples: Vec<Duration>,
+}
+
+impl Stats {
+    fn report(&self, transport: &str) {
+        let mut sorted = self.samples.clone();
+        sorted.sort();
+        let mean = sorted.iter().sum::<Duration>() / sorted.len() as u32;
+        // Mean difference between consecutive round trips
+        let jitter = self
+            .samples
+            .windows(2)
+            .map(|pair| pair[0].abs_diff(pair[1]))
+            .sum::<Duration>()
+            / (self.samples.len() - 1) as u32;
+        println!(
+            "{transport}: {} exchanges, min {:?}, mean {mean:?}, p99 {:?}, max {:?}, jitter {jitter:?}",
+            sorted.len(),
+            sorted[0],
+            sorted[sorted.len() * 99 / 100],
+            sorted[sorted.len() - 1],
+        );
+    }
+}
+
+fn run_tcp(addr: &str) -> Stats {
+    let mut receiver = SCIListener::new(RastaListener::try_new(addr, 1337).unwrap(), "S".into());
+    let shutdown = receiver.shutdown_handle();
+    let server = thread::spawn(move || {
+        receiver.listen(|telegram| {
+            (telegram.message_type() == SCIMessageType::scip_change_location()).then(|| {
+                SCITelegram::location_status("S", "C", SCIPointLocation::PointLocationLeft)
+            })
+        })
+    });
+
+    let conn = RastaConnection::try_new(addr, 42).unwrap();
+    let mapping = HashMap::from([("S".to_string(), 1337)]);
+    let mut sender = SCIConnection::try_new(conn, "C".to_string(), mapping).unwrap();
+    let mut samples = Vec::with_capacity(EXCHANGES);
+    for _ in 0..EXCHANGES {
+        let start = Instant::now();
+        sender
+            .send_telegram(SCITelegram::change_location(
+                "C",
+                "S",
+                SCIPointTargetLocation::PointLocationChangeToLeft,
+            ))
+            .unwrap();
+        sender.receive_telegram().unwrap();
+        samples.push(start.elapsed());
+    }
+    drop(sender);
+    shutdown.shutdown();
+    server.join().unwrap().unwrap();
+    Stats { samples }
+}
+
+fn report_redundancy(side: &str, stats: RedundancyStats) {
+    println!(
+        "  {side}: lost {}, duplicates {}, deferred {}, malformed {}",
+        stats.lost, stats.duplicates, stats.deferred, stats.malformed,
+    );
+}
+
+fn run_udp() -> (Stats, RedundancyStats, RedundancyStats) {
+    // Two free local ports for each side, one per transport channel
+    let free_addrs = || -> Vec<SocketAddr> {
+        let sockets = [(); 2].map(|_| UdpSocket::bind("127.0.0.1:0").unwrap());
+        sockets.iter().map(|s| s.local_addr().unwrap()).collect()
+    };
+    let (client_addrs, server_addrs) = (free_addrs(), free_addrs());
+    let config = RedundancyConfig::default();
+    let mut server_channel = RedundancyChannel::bind(&server_addrs, &client_addrs, config).unwrap();
+    let mut client_channel = RedundancyChannel::bind(&client_addrs, &server_addrs, config).unwrap();
+
+    let server = thread::spawn(move || {
+        for sequence_number in 0..EXCHANGES as u32 {
+            let request = server_channel
+                .receive(Duration::from_secs(1))
+                .unwrap()
+                .expect("Request lost");
+            let telegram = SCITelegram::try_from(request.data()).unwrap();
+            assert_eq!(
+                telegram.message_type(),
+                SCIMessageType::scip_change_location()
+            );
+            let response: Vec<u8> =
+                SCITelegram::location_status("S", "C", SCIPointLocation::PointLocationLeft).into();
+            let response =
+                Message::data_message(42, 1337, sequence_number, 0, 0, 0, &response).unwrap();
+            server_channel.send(&response).unwrap();
+        }
+        server_channel.stats()
+    });
+
+    let mut samples = Vec::with_capacity(EXCHANGES);
+    for sequence_number in 0..EXCHANGES as u32 {
+        let start = Instant::now();
+        let request: Vec<u8> = SCITelegram::change_location(
+            "C",
+            "S",
+            SCIPointTargetLocation::PointLocationChangeToLeft,
+        )
+        .into();
+        let request = Message::data_message(1337, 42, sequence_number, 0, 0, 0, &request).unwrap();
+        client_channel.send(&request).unwrap();
+        client_channel
+            .receive(Duration::from_secs(1))
+            .unwrap()
+            .expect("Response lost");
+        samples.push(start.elapsed());
+    }
+    let server_stats = server.join().unwrap();
+    (Stats { samples }, client_channel.stats(), server_stats)
+}
+
+fn main() {
+    let addr = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "127.0.0.1:8888".to_string());
+    run_tcp(&addr).report("TCP");
+    let (stats, client, server) = run_udp();
+    stats.report("UDP (2 channels, without safety and retransmission layer)");
+    report_redundancy("client", client);
+    report_redundancy("server", server);
+}
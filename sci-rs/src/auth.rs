@@ -0,0 +1,150 @@
+//! # Authorization
+//!
+//! Rules deciding whether a received telegram may reach the application,
+//! e.g. to accept force clear commands only from certain senders or only
+//! in certain operational modes. Rules are registered with
+//! [`SCIListener::authorize`](crate::SCIListener::authorize) or
+//! [`SCIConnection::authorize`](crate::SCIConnection::authorize) and are
+//! consulted before any application callback or hook.
+
+use crate::{baseline::BaselineProfile, ProtocolType, SCICloseReason, SCIMessageType, SCITelegram};
+
+/// What an endpoint knows about the peer a telegram came from.
+pub struct PeerContext<'a> {
+    /// The SCI name of the receiving endpoint.
+    pub local_name: &'a str,
+    /// The peer the connection was established with, if known.
+    pub expected_peer: Option<&'a str>,
+    pub negotiated_version: u8,
+    pub baseline: BaselineProfile,
+}
+
+/// The outcome of an authorization rule.
+#[derive(Clone)]
+pub enum Decision {
+    /// Pass the telegram on to the application.
+    Accept,
+    /// Silently drop the telegram.
+    Reject,
+    /// Drop the telegram and send the given telegram to the peer instead,
+    /// e.g. a command rejection or a close telegram.
+    RejectAndReport(SCITelegram),
+}
+
+impl Decision {
+    /// Drops `telegram` and closes the connection with
+    /// [`SCICloseReason::ContentTelegramError`].
+    pub fn reject_and_close(telegram: &SCITelegram, context: &PeerContext) -> Self {
+        Self::RejectAndReport(SCITelegram::close(
+            telegram.protocol_type,
+            context.local_name,
+            &telegram.sender,
+            SCICloseReason::ContentTelegramError,
+        ))
+    }
+
+    pub fn is_accepted(&self) -> bool {
+        matches!(self, Self::Accept)
+    }
+}
+
+/// An authorization rule.
+pub type AuthorizationHook = Box<dyn FnMut(&SCITelegram, &PeerContext) -> Decision + Send>;
+
+/// The authorization rules of an endpoint.
+#[derive(Default)]
+pub struct Authorizer {
+    rules: Vec<(Option<(ProtocolType, SCIMessageType)>, AuthorizationHook)>,
+}
+
+impl Authorizer {
+    /// Adds a rule for telegrams of `message_type` in `protocol_type`.
+    pub fn add<F>(&mut self, protocol_type: ProtocolType, message_type: SCIMessageType, hook: F)
+    where
+        F: FnMut(&SCITelegram, &PeerContext) -> Decision + Send + 'static,
+    {
+        self.rules
+            .push((Some((protocol_type, message_type)), Box::new(hook)));
+    }
+
+    /// Adds a rule for all telegrams.
+    pub fn add_for_all<F>(&mut self, hook: F)
+    where
+        F: FnMut(&SCITelegram, &PeerContext) -> Decision + Send + 'static,
+    {
+        self.rules.push((None, Box::new(hook)));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Consults all matching rules in the order they were added.
+    /// The first one that does not accept the telegram decides.
+    pub fn decide(&mut self, telegram: &SCITelegram, context: &PeerContext) -> Decision {
+        for (filter, hook) in self.rules.iter_mut() {
+            let applies = filter.as_ref().is_none_or(|(protocol_type, message_type)| {
+                *protocol_type == telegram.protocol_type && *message_type == telegram.message_type
+            });
+            if !applies {
+                continue;
+            }
+            let decision = hook(telegram, context);
+            if !decision.is_accepted() {
+                return decision;
+            }
+        }
+        Decision::Accept
+    }
+}
+
+#[cfg(all(test, feature = "scitds"))]
+mod tests {
+    use super::{Authorizer, Decision, PeerContext};
+    use crate::{
+        baseline::BaselineProfile,
+        scitds::{FCMode, RejectionReason},
+        ProtocolType, SCIMessageType, SCITelegram, SCI_VERSION,
+    };
+
+    #[test]
+    fn test_authorize_force_clear() {
+        let mut authorizer = Authorizer::default();
+        authorizer.add(
+            ProtocolType::SCIProtocolTDS,
+            SCIMessageType::scitds_fc(),
+            |telegram, context| {
+                if telegram.sender == "ILS" {
+                    Decision::Accept
+                } else {
+                    Decision::RejectAndReport(SCITelegram::command_rejected(
+                        context.local_name,
+                        &telegram.sender,
+                        RejectionReason::Operational,
+                    ))
+                }
+            },
+        );
+        let context = PeerContext {
+            local_name: "TVPS",
+            expected_peer: None,
+            negotiated_version: SCI_VERSION,
+            baseline: BaselineProfile::default(),
+        };
+        let allowed = SCITelegram::fc("ILS", "TVPS", FCMode::P);
+        assert!(authorizer.decide(&allowed, &context).is_accepted());
+        let denied = SCITelegram::fc("Other", "TVPS", FCMode::P);
+        match authorizer.decide(&denied, &context) {
+            Decision::RejectAndReport(report) => {
+                assert_eq!(
+                    report.message_type,
+                    SCIMessageType::scitds_command_rejected()
+                );
+                assert_eq!(report.receiver, "Other");
+            }
+            _ => panic!("Force clear from Other must be rejected"),
+        }
+        let other = SCITelegram::drfc("Other", "TVPS");
+        assert!(authorizer.decide(&other, &context).is_accepted());
+    }
+}
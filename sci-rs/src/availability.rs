@@ -0,0 +1,161 @@
+//! Announcement and supervision of the availability of elements with
+//! PDI Available and PDI Not Available telegrams. An [`Availability`]
+//! handle keeps the availability of the local endpoint, which an
+//! [`SCIListener`](crate::SCIListener) announces to its peer whenever it
+//! changes, and the availability last announced by each peer, which
+//! applications receive as [`AvailabilityEvent`]s.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+};
+
+use crate::{ProtocolType, SCIAvailability, SCITelegram};
+
+/// A peer announced a different availability than before.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AvailabilityEvent {
+    pub peer: String,
+    pub availability: SCIAvailability,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    local: SCIAvailability,
+    /// Whether `local` still has to be announced to the peer.
+    pending: bool,
+    /// The peer the local availability is announced to, once its PDI is up.
+    peer: Option<(String, ProtocolType)>,
+    peers: HashMap<String, SCIAvailability>,
+    subscribers: Vec<Sender<AvailabilityEvent>>,
+}
+
+/// A shared handle to the availability of an endpoint and its peers.
+/// Clones refer to the same state, so one can be kept to change the
+/// availability from another thread while the endpoint is listening.
+#[derive(Clone, Debug, Default)]
+pub struct Availability {
+    inner: Arc<Mutex<State>>,
+}
+
+impl Availability {
+    /// The availability of the local endpoint. Endpoints start available.
+    pub fn local(&self) -> SCIAvailability {
+        self.inner.lock().unwrap().local
+    }
+
+    /// Changes the availability of the local endpoint. A change is
+    /// announced to the peer with the next telegram or tick of the
+    /// endpoint, or once the PDI is up if no peer is connected.
+    pub fn set(&self, availability: SCIAvailability) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.local != availability {
+            inner.local = availability;
+            inner.pending = true;
+        }
+    }
+
+    /// The availability `peer` announced last, or `None` if it never did.
+    pub fn of(&self, peer: &str) -> Option<SCIAvailability> {
+        self.inner.lock().unwrap().peers.get(peer).copied()
+    }
+
+    /// Returns a receiver for all availability changes of peers from now on.
+    pub fn subscribe(&self) -> Receiver<AvailabilityEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.inner.lock().unwrap().subscribers.push(sender);
+        receiver
+    }
+
+    /// Records the availability announced by `telegram`, if any.
+    pub(crate) fn observe(&self, telegram: &SCITelegram) {
+        let Some(availability) = telegram.availability() else {
+            return;
+        };
+        let mut inner = self.inner.lock().unwrap();
+        if inner.peers.insert(telegram.sender.clone(), availability) == Some(availability) {
+            return;
+        }
+        let event = AvailabilityEvent {
+            peer: telegram.sender.clone(),
+            availability,
+        };
+        inner
+            .subscribers
+            .retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
+
+    /// Sets the peer whose PDI is up, or `None` if there is none. Peers
+    /// assume that elements are available once the PDI is up, so a new
+    /// peer is only told if the endpoint is not available.
+    pub(crate) fn connect(&self, peer: Option<(&str, ProtocolType)>) {
+        let mut inner = self.inner.lock().unwrap();
+        let changed = match (&inner.peer, peer) {
+            (Some((current, _)), Some((peer, _))) => current != peer,
+            (None, None) => false,
+            _ => true,
+        };
+        if changed {
+            inner.peer = peer.map(|(peer, protocol_type)| (peer.to_string(), protocol_type));
+            inner.pending = inner.local == SCIAvailability::NotAvailable;
+        }
+    }
+
+    /// The telegram announcing a change of the local availability from
+    /// `name` to the connected peer, if one is due.
+    pub(crate) fn announcement(&self, name: &str) -> Option<SCITelegram> {
+        let mut inner = self.inner.lock().unwrap();
+        let (peer, protocol_type) = inner.peer.as_ref().filter(|_| inner.pending)?;
+        let telegram =
+            SCITelegram::availability_announcement(*protocol_type, name, peer, inner.local);
+        inner.pending = false;
+        Some(telegram)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Availability, AvailabilityEvent};
+    use crate::{ProtocolType, SCIAvailability, SCIMessageType, SCITelegram};
+
+    #[test]
+    fn test_availability() {
+        let availability = Availability::default();
+        availability.set(SCIAvailability::NotAvailable);
+        assert!(availability.announcement("S").is_none());
+
+        availability.connect(Some(("C", ProtocolType::SCIProtocolP)));
+        let announcement = availability.announcement("S").unwrap();
+        assert_eq!(
+            announcement.message_type,
+            SCIMessageType::pdi_not_available()
+        );
+        assert_eq!(announcement.receiver, "C");
+        assert!(availability.announcement("S").is_none());
+
+        availability.set(SCIAvailability::NotAvailable);
+        assert!(availability.announcement("S").is_none());
+        availability.set(SCIAvailability::Available);
+        assert_eq!(
+            availability.announcement("S").unwrap().availability(),
+            Some(SCIAvailability::Available)
+        );
+
+        let events = availability.subscribe();
+        let not_available = SCITelegram::not_available(ProtocolType::SCIProtocolP, "C", "S");
+        availability.observe(&not_available);
+        availability.observe(&not_available);
+        assert_eq!(
+            events.try_recv().unwrap(),
+            AvailabilityEvent {
+                peer: "C".to_string(),
+                availability: SCIAvailability::NotAvailable,
+            }
+        );
+        assert!(events.try_recv().is_err());
+        assert_eq!(availability.of("C"), Some(SCIAvailability::NotAvailable));
+    }
+}
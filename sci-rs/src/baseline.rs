@@ -0,0 +1,79 @@
+//! # EULYNX Baselines
+//!
+//! EULYNX baselines differ in which PDI telegrams exist and how strictly
+//! payloads are checked. A [`BaselineProfile`] is selected per endpoint
+//! so that one application can talk to elements of different baselines.
+
+use crate::{ParseMode, ProtocolType, SCIMessageType};
+
+/// The EULYNX baseline an endpoint communicates with.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum BaselineProfile {
+    /// Baseline 3: no PDI availability reporting or reset,
+    /// version responses without checksum and lenient payload lengths.
+    BL3,
+    /// Baseline 4: the full PDI message set as implemented by this crate.
+    #[default]
+    BL4,
+}
+
+impl BaselineProfile {
+    /// Whether the version response carries a checksum of the configuration data.
+    pub fn version_response_has_checksum(&self) -> bool {
+        matches!(self, Self::BL4)
+    }
+
+    /// Whether payloads have to match their expected length exactly.
+    /// Lenient profiles ignore trailing bytes.
+    pub fn strict_payload_length(&self) -> bool {
+        matches!(self, Self::BL4)
+    }
+
+    /// How payloads of elements of this baseline are parsed.
+    pub fn parse_mode(&self) -> ParseMode {
+        match self {
+            Self::BL3 => ParseMode::Lenient,
+            Self::BL4 => ParseMode::Strict,
+        }
+    }
+
+    /// Whether telegrams of `message_type` may be exchanged in this baseline.
+    pub fn supports(&self, protocol_type: ProtocolType, message_type: SCIMessageType) -> bool {
+        match self {
+            Self::BL3 => {
+                message_type != SCIMessageType::pdi_available()
+                    && message_type != SCIMessageType::pdi_not_available()
+                    && message_type != SCIMessageType::pdi_reset()
+                    && !is_point_auxiliary_telegram(protocol_type, message_type)
+            }
+            Self::BL4 => true,
+        }
+    }
+
+    /// The checksum to send in a version response: `checksum` if the
+    /// baseline supports it, nothing otherwise.
+    pub fn version_response_checksum<'a>(&self, checksum: &'a [u8]) -> &'a [u8] {
+        if self.version_response_has_checksum() {
+            checksum
+        } else {
+            &[]
+        }
+    }
+}
+
+/// The heating and auxiliary equipment status of points was added in BL4.
+#[cfg(feature = "scip")]
+fn is_point_auxiliary_telegram(protocol_type: ProtocolType, message_type: SCIMessageType) -> bool {
+    protocol_type == ProtocolType::SCIProtocolP
+        && (message_type == SCIMessageType::scip_heating_status()
+            || message_type == SCIMessageType::scip_auxiliary_status())
+}
+
+#[cfg(not(feature = "scip"))]
+fn is_point_auxiliary_telegram(
+    _protocol_type: ProtocolType,
+    _message_type: SCIMessageType,
+) -> bool {
+    false
+}
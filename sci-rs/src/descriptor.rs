@@ -0,0 +1,22 @@
+//! Snapshots of SCI endpoints for inventories and monitoring,
+//! see [`SCIConnection::describe`](crate::SCIConnection::describe) and
+//! [`SCIListener::describe`](crate::SCIListener::describe).
+
+use rasta_rs::EndpointDescriptor;
+
+use crate::{baseline::BaselineProfile, pdi::PdiState};
+
+/// The names, protocol settings and underlying RaSTA endpoint
+/// of an SCI endpoint at the time it was described.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SCIEndpointDescriptor {
+    pub name: String,
+    /// The SCI name of the peer the endpoint last exchanged telegrams with.
+    pub peer: Option<String>,
+    pub baseline: BaselineProfile,
+    pub negotiated_version: u8,
+    /// The state of the PDI if the endpoint runs in managed mode.
+    pub pdi_state: Option<PdiState>,
+    pub rasta: EndpointDescriptor,
+}
@@ -0,0 +1,274 @@
+//! # Diagnostics
+//!
+//! Telegrams that do not exactly match this implementation are not always
+//! rejected: newer SCI versions may append extension bytes, lenient
+//! baselines may omit optional fields and reserved fields are passed
+//! through. [`inspect`] finds such anomalies in a telegram, and endpoints
+//! report those of the telegrams they receive to a [`DiagnosticSink`]
+//! (see e.g. [`SCIListener::set_diagnostic_sink`](crate::SCIListener::set_diagnostic_sink)).
+//! Closures, the [`Journal`] and, with the `tracing` feature,
+//! [`TracingSink`] can be used as sinks.
+
+use std::fmt::Display;
+
+use crate::{journal::Journal, ProtocolType, SCIMessageType, SCITelegram};
+
+/// A non-fatal deviation of a telegram from the layout
+/// known to this implementation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Anomaly {
+    /// The payload is longer than expected, e.g. because the
+    /// peer appends extensions of a newer SCI version.
+    TrailingBytes { expected: usize, actual: usize },
+    /// Optional fields are missing from the end of the payload.
+    MissingOptionalBytes { expected: usize, actual: usize },
+    /// A field reserved for future use holds `value`.
+    ReservedValue { field: &'static str, value: u8 },
+}
+
+impl Display for Anomaly {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::TrailingBytes { expected, actual } => {
+                write!(f, "{} unknown trailing byte(s)", actual - expected)
+            }
+            Self::MissingOptionalBytes { expected, actual } => {
+                write!(f, "{} optional byte(s) missing", expected - actual)
+            }
+            Self::ReservedValue { field, value } => {
+                write!(f, "reserved {field} set to {value:#04x}")
+            }
+        }
+    }
+}
+
+/// An [`Anomaly`] together with the telegram it was found in.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub protocol_type: ProtocolType,
+    pub message_type: SCIMessageType,
+    pub sender: String,
+    pub receiver: String,
+    pub anomaly: Anomaly,
+}
+
+impl Diagnostic {
+    pub fn new(telegram: &SCITelegram, anomaly: Anomaly) -> Self {
+        Self {
+            protocol_type: telegram.protocol_type,
+            message_type: telegram.message_type,
+            sender: telegram.sender.trim_end_matches('_').to_string(),
+            receiver: telegram.receiver.trim_end_matches('_').to_string(),
+            anomaly,
+        }
+    }
+}
+
+impl Display for Diagnostic {
+    /// Formats the diagnostic as e.g.
+    /// `2 unknown trailing byte(s) in SCIProtocolTDS 0x0007 from T`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} in {:?} {:#06x} from {}",
+            self.anomaly,
+            self.protocol_type,
+            u16::from(self.message_type),
+            self.sender
+        )
+    }
+}
+
+/// Receives the diagnostics of an endpoint.
+pub trait DiagnosticSink: Send + Sync {
+    fn report(&self, diagnostic: &Diagnostic);
+}
+
+impl<F> DiagnosticSink for F
+where
+    F: Fn(&Diagnostic) + Send + Sync,
+{
+    fn report(&self, diagnostic: &Diagnostic) {
+        self(diagnostic)
+    }
+}
+
+/// Keeps diagnostics next to the recorded telegrams,
+/// see [`Journal::diagnostics`].
+impl DiagnosticSink for Journal {
+    fn report(&self, diagnostic: &Diagnostic) {
+        self.record_diagnostic(diagnostic.clone());
+    }
+}
+
+/// Emits diagnostics as `tracing` warnings.
+#[cfg(feature = "tracing")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TracingSink;
+
+#[cfg(feature = "tracing")]
+impl DiagnosticSink for TracingSink {
+    fn report(&self, diagnostic: &Diagnostic) {
+        tracing::warn!(
+            protocol = ?diagnostic.protocol_type,
+            message_type = u16::from(diagnostic.message_type),
+            sender = diagnostic.sender.as_str(),
+            receiver = diagnostic.receiver.as_str(),
+            anomaly = %diagnostic.anomaly,
+            "Telegram anomaly"
+        );
+    }
+}
+
+/// The length of the mandatory part and of the complete payload
+/// of telegrams whose payload has a fixed layout.
+fn payload_layout(telegram: &SCITelegram) -> Option<(usize, usize)> {
+    let message_type = telegram.message_type;
+    if message_type == SCIMessageType::pdi_version_check()
+        || message_type == SCIMessageType::pdi_close()
+    {
+        return Some((1, 1));
+    }
+    match telegram.protocol_type {
+        #[cfg(feature = "scip")]
+        ProtocolType::SCIProtocolP => {
+            if message_type == SCIMessageType::scip_change_location()
+                || message_type == SCIMessageType::scip_location_status()
+            {
+                Some((1, 1))
+            } else if message_type == SCIMessageType::scip_heating_status() {
+                Some((2, 2))
+            } else {
+                None
+            }
+        }
+        #[cfg(feature = "scils")]
+        ProtocolType::SCIProtocolLS => {
+            use crate::scils::{MANDATORY_SIGNAL_ASPECT_LENGTH, SIGNAL_ASPECT_LENGTH};
+
+            if message_type == SCIMessageType::scils_show_signal_aspect()
+                || message_type == SCIMessageType::scils_signal_aspect_status()
+            {
+                Some((MANDATORY_SIGNAL_ASPECT_LENGTH, SIGNAL_ASPECT_LENGTH))
+            } else if message_type == SCIMessageType::scils_change_brightness()
+                || message_type == SCIMessageType::scils_brightness_status()
+            {
+                Some((1, 1))
+            } else {
+                None
+            }
+        }
+        #[cfg(feature = "scitds")]
+        ProtocolType::SCIProtocolTDS => {
+            (message_type == SCIMessageType::scitds_tvps_occupancy_status()).then_some((7, 7))
+        }
+        _ => None,
+    }
+}
+
+/// The reserved bytes of the DB nationally specified information of
+/// signal aspects. Other national profiles define no reserved bytes.
+#[cfg(feature = "scils")]
+fn reserved_values(telegram: &SCITelegram, anomalies: &mut Vec<Anomaly>) {
+    use crate::scils::{DbNationalInformation, MANDATORY_SIGNAL_ASPECT_LENGTH};
+
+    if telegram.protocol_type != ProtocolType::SCIProtocolLS
+        || (telegram.message_type != SCIMessageType::scils_show_signal_aspect()
+            && telegram.message_type != SCIMessageType::scils_signal_aspect_status())
+    {
+        return;
+    }
+    let Some(information) = telegram
+        .payload
+        .get(MANDATORY_SIGNAL_ASPECT_LENGTH..MANDATORY_SIGNAL_ASPECT_LENGTH + 9)
+        .and_then(|information| <[u8; 9]>::try_from(information).ok())
+    else {
+        return;
+    };
+    if let Ok(information) = DbNationalInformation::try_from(information) {
+        if let Some(value) = information.reserved.into_iter().find(|value| *value != 0) {
+            anomalies.push(Anomaly::ReservedValue {
+                field: "national information",
+                value,
+            });
+        }
+    }
+}
+
+#[cfg(not(feature = "scils"))]
+fn reserved_values(_telegram: &SCITelegram, _anomalies: &mut Vec<Anomaly>) {}
+
+/// Finds the anomalies of `telegram`. Only telegrams with a fixed
+/// payload layout are checked for missing and trailing bytes.
+pub fn inspect(telegram: &SCITelegram) -> Vec<Anomaly> {
+    let mut anomalies = Vec::new();
+    if let Some((mandatory, expected)) = payload_layout(telegram) {
+        let actual = telegram.payload.len();
+        if actual > expected {
+            anomalies.push(Anomaly::TrailingBytes { expected, actual });
+        } else if actual >= mandatory && actual < expected {
+            anomalies.push(Anomaly::MissingOptionalBytes { expected, actual });
+        }
+    }
+    reserved_values(telegram, &mut anomalies);
+    anomalies
+}
+
+/// Reports the anomalies of `telegram` to `sink`.
+pub fn report(telegram: &SCITelegram, sink: &dyn DiagnosticSink) {
+    for anomaly in inspect(telegram) {
+        sink.report(&Diagnostic::new(telegram, anomaly));
+    }
+}
+
+#[cfg(all(test, feature = "scitds"))]
+mod tests {
+    use std::sync::Mutex;
+
+    use crate::{
+        journal::Journal,
+        scitds::{ChangeTrigger, DisturbanceStatus, FillingLevel, OccupancyStatus, POMStatus},
+        SCIPayload, SCITelegram,
+    };
+
+    use super::{inspect, report, Anomaly, Diagnostic};
+
+    #[test]
+    fn test_report_trailing_bytes() {
+        let mut telegram = SCITelegram::tvps_occupancy_status(
+            "T",
+            "I",
+            OccupancyStatus::Vacant,
+            false,
+            FillingLevel::default(),
+            POMStatus::NotApplicable,
+            DisturbanceStatus::Operational,
+            ChangeTrigger::PassingDetected,
+        );
+        assert!(inspect(&telegram).is_empty());
+        let mut payload = telegram.payload.to_vec();
+        payload.extend([0xAA, 0xBB]);
+        telegram.payload = SCIPayload::from_slice(&payload);
+
+        let reported = Mutex::new(Vec::new());
+        report(&telegram, &|diagnostic: &Diagnostic| {
+            reported.lock().unwrap().push(diagnostic.clone())
+        });
+        let reported = reported.into_inner().unwrap();
+        assert_eq!(
+            reported[0].anomaly,
+            Anomaly::TrailingBytes {
+                expected: 7,
+                actual: 9
+            }
+        );
+        assert_eq!(
+            reported[0].to_string(),
+            "2 unknown trailing byte(s) in SCIProtocolTDS 0x0007 from T"
+        );
+
+        let journal = Journal::new();
+        report(&telegram, &journal);
+        assert_eq!(journal.diagnostics(), reported);
+    }
+}
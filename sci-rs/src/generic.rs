@@ -0,0 +1,102 @@
+//! # Generic SCI Protocols
+//!
+//! A container for SCI protocols that are not implemented natively, e.g.
+//! prototypes of interlocking-to-interlocking communication. Telegrams of
+//! a registered protocol id are parsed as [`ProtocolType::Generic`] and
+//! their message types and payloads are passed through unchanged. The PDI
+//! telegrams (version check, initialisation, close, ...) are shared with
+//! the native protocols, so the usual connection handling applies.
+
+use alloc::string::ToString;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use super::{ProtocolType, SCIMessageType, SCIPayload, SCITelegram, SciError};
+
+static REGISTERED: [AtomicBool; 256] = [const { AtomicBool::new(false) }; 256];
+
+/// Makes telegrams with `protocol_id` parse as [`ProtocolType::Generic`].
+/// Ids of natively supported protocols are rejected with
+/// [`SciError::NativeProtocol`], since they keep parsing as those.
+pub fn register_protocol(protocol_id: u8) -> Result<(), SciError> {
+    check_not_native(protocol_id)?;
+    REGISTERED[protocol_id as usize].store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+pub fn unregister_protocol(protocol_id: u8) {
+    REGISTERED[protocol_id as usize].store(false, Ordering::Relaxed);
+}
+
+pub fn is_registered(protocol_id: u8) -> bool {
+    REGISTERED[protocol_id as usize].load(Ordering::Relaxed)
+}
+
+/// Fails if telegrams with `protocol_id` parse as a native protocol,
+/// so that generic telegrams survive a round trip.
+fn check_not_native(protocol_id: u8) -> Result<(), SciError> {
+    match ProtocolType::try_from(protocol_id) {
+        Ok(ProtocolType::Generic(_)) | Err(_) => Ok(()),
+        Ok(_) => Err(SciError::NativeProtocol(protocol_id)),
+    }
+}
+
+impl SCIMessageType {
+    /// A message type of a generic protocol.
+    pub const fn generic(value: u16) -> Self {
+        Self(value)
+    }
+}
+
+impl SCITelegram {
+    /// Creates a telegram of the generic protocol `protocol_id`
+    /// carrying `payload` unchanged. Fails with [`SciError::NativeProtocol`]
+    /// for the ids of natively supported protocols.
+    pub fn generic(
+        protocol_id: u8,
+        message_type: u16,
+        sender: &str,
+        receiver: &str,
+        payload: &[u8],
+    ) -> Result<Self, SciError> {
+        check_not_native(protocol_id)?;
+        Ok(Self {
+            protocol_type: ProtocolType::Generic(protocol_id),
+            message_type: SCIMessageType::generic(message_type),
+            sender: sender.to_string(),
+            receiver: receiver.to_string(),
+            payload: SCIPayload::try_from_slice(payload)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ProtocolType, SCIMessageType, SCITelegram, SciError};
+
+    #[test]
+    fn test_generic_roundtrip() {
+        let telegram = SCITelegram::generic(0xA0, 0x0101, "IXL1", "IXL2", &[1, 2, 3]).unwrap();
+        let bytes = Vec::from(telegram);
+        assert!(SCITelegram::try_from(bytes.as_slice()).is_err());
+
+        super::register_protocol(0xA0).unwrap();
+        let parsed = SCITelegram::try_from(bytes.as_slice()).unwrap();
+        assert_eq!(parsed.protocol_type, ProtocolType::Generic(0xA0));
+        assert_eq!(parsed.message_type, SCIMessageType::generic(0x0101));
+        assert_eq!(&*parsed.payload, &[1, 2, 3]);
+        super::unregister_protocol(0xA0);
+    }
+
+    #[test]
+    fn test_native_protocol_ids() {
+        assert!(matches!(
+            super::register_protocol(0x40),
+            Err(SciError::NativeProtocol(0x40))
+        ));
+        assert!(!super::is_registered(0x40));
+        assert!(matches!(
+            SCITelegram::generic(0x40, 0x0001, "IXL1", "P", &[]),
+            Err(SciError::NativeProtocol(0x40))
+        ));
+    }
+}
@@ -0,0 +1,168 @@
+//! # History
+//!
+//! Keeps the most recent telegrams exchanged with each peer, so that
+//! the situation leading up to a failure can be dumped for diagnostics.
+//! Unlike a [`Journal`](crate::journal::Journal), the history is bounded
+//! and always enabled.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt::Write,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use crate::{
+    journal::{Direction, JournalEntry},
+    SCITelegram,
+};
+
+/// The number of telegrams kept per peer by default.
+pub const DEFAULT_HISTORY_LENGTH: usize = 50;
+/// The number of peers kept by default.
+pub const DEFAULT_HISTORY_PEERS: usize = 256;
+
+/// A shared handle to the recent telegrams of an endpoint, grouped by
+/// the SCI name of the peer. Clones refer to the same history, so a
+/// clone can be kept to take snapshots while the endpoint is running.
+#[derive(Clone)]
+pub struct History {
+    start: Instant,
+    capacity: usize,
+    max_peers: usize,
+    peers: Arc<Mutex<HashMap<String, VecDeque<JournalEntry>>>>,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new(DEFAULT_HISTORY_LENGTH)
+    }
+}
+
+impl History {
+    /// Creates a history that keeps the last `capacity` telegrams
+    /// of up to [`DEFAULT_HISTORY_PEERS`] peers.
+    pub fn new(capacity: usize) -> Self {
+        Self::with_max_peers(capacity, DEFAULT_HISTORY_PEERS)
+    }
+
+    /// Creates a history that keeps the last `capacity` telegrams of up to
+    /// `max_peers` peers. When another peer is recorded, the peer whose
+    /// latest telegram is the oldest is forgotten.
+    pub fn with_max_peers(capacity: usize, max_peers: usize) -> Self {
+        Self {
+            start: Instant::now(),
+            capacity,
+            max_peers,
+            peers: Arc::default(),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn max_peers(&self) -> usize {
+        self.max_peers
+    }
+
+    /// Records `telegram` for its sender if it is incoming,
+    /// or for its receiver if it is outgoing.
+    pub fn record(&self, direction: Direction, telegram: &SCITelegram) {
+        if self.capacity == 0 || self.max_peers == 0 {
+            return;
+        }
+        let peer = match direction {
+            Direction::Incoming => &telegram.sender,
+            Direction::Outgoing => &telegram.receiver,
+        };
+        let mut peers = self.peers.lock().unwrap();
+        if !peers.contains_key(peer) && peers.len() >= self.max_peers {
+            let stalest = peers
+                .iter()
+                .min_by_key(|(_, entries)| entries.back().map(|entry| entry.elapsed))
+                .map(|(peer, _)| peer.clone());
+            if let Some(stalest) = stalest {
+                peers.remove(&stalest);
+            }
+        }
+        let entries = peers.entry(peer.clone()).or_default();
+        if entries.len() == self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(JournalEntry {
+            elapsed: self.start.elapsed(),
+            direction,
+            telegram: telegram.clone(),
+        });
+    }
+
+    /// The names of all peers with recorded telegrams.
+    pub fn peers(&self) -> Vec<String> {
+        self.peers.lock().unwrap().keys().cloned().collect()
+    }
+
+    /// Returns the recorded telegrams of `peer`, oldest first.
+    pub fn snapshot(&self, peer: &str) -> Vec<JournalEntry> {
+        self.peers
+            .lock()
+            .unwrap()
+            .get(peer)
+            .map(|entries| entries.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Formats the recorded telegrams of `peer`, one per line.
+    pub fn dump(&self, peer: &str) -> String {
+        let mut dump = String::new();
+        for entry in self.snapshot(peer) {
+            let _ = writeln!(dump, "{entry}");
+        }
+        dump
+    }
+
+    /// Forgets the telegrams of `peer`.
+    pub fn clear(&self, peer: &str) {
+        self.peers.lock().unwrap().remove(peer);
+    }
+}
+
+#[cfg(all(test, feature = "scip"))]
+mod tests {
+    use crate::{
+        journal::Direction,
+        scip::{SCIPointLocation, SCIPointTargetLocation},
+        SCITelegram,
+    };
+
+    use super::History;
+
+    #[test]
+    fn test_bounded_history() {
+        let history = History::new(2);
+        let command = SCITelegram::change_location(
+            "I",
+            "P",
+            SCIPointTargetLocation::PointLocationChangeToRight,
+        );
+        let status = SCITelegram::location_status("P", "I", SCIPointLocation::PointLocationRight);
+        history.record(Direction::Outgoing, &command);
+        history.record(Direction::Incoming, &status);
+        history.record(Direction::Outgoing, &command);
+        let entries = history.snapshot("P");
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].direction, Direction::Incoming);
+        assert_eq!(history.dump("P").lines().count(), 2);
+        assert!(history.snapshot("I").is_empty());
+
+        let history = History::with_max_peers(2, 2);
+        for peer in ["P1", "P2", "P1", "P3"] {
+            let status =
+                SCITelegram::location_status(peer, "I", SCIPointLocation::PointLocationRight);
+            history.record(Direction::Incoming, &status);
+        }
+        let mut peers = history.peers();
+        peers.sort();
+        assert_eq!(peers, ["P1", "P3"]);
+    }
+}
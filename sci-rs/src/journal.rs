@@ -0,0 +1,192 @@
+//! # Journal
+//!
+//! Records the telegrams exchanged by an SCI endpoint together with
+//! the time they were sent or received. Journals can be replayed
+//! against handlers using [`crate::replay`]. A journal keeps at most
+//! [`Journal::capacity`] entries and discards the oldest to make room.
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{diagnostics::Diagnostic, SCITelegram};
+
+/// The number of entries a journal keeps by default.
+pub const DEFAULT_JOURNAL_CAPACITY: usize = 100_000;
+
+/// Whether a telegram was received or sent by the journaling endpoint.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Direction {
+    Incoming,
+    Outgoing,
+}
+
+/// A single recorded telegram. With the `serde` feature, recorded
+/// entries can be stored e.g. as JSON and replayed with
+/// [`Journal::from_entries`].
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct JournalEntry {
+    /// The time since the journal was created.
+    pub elapsed: Duration,
+    pub direction: Direction,
+    pub telegram: SCITelegram,
+}
+
+impl std::fmt::Display for JournalEntry {
+    /// Formats the entry as e.g. `12.345s <- SCIProtocolP: LocationStatus P -> I [01]`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let arrow = match self.direction {
+            Direction::Incoming => "<-",
+            Direction::Outgoing => "->",
+        };
+        write!(
+            f,
+            "{:.3}s {arrow} {}",
+            self.elapsed.as_secs_f64(),
+            self.telegram
+        )
+    }
+}
+
+/// A shared handle to a list of recorded telegrams. Clones of a
+/// journal record into the same list, so one clone can be passed
+/// to an endpoint while another is used to inspect the entries.
+#[derive(Clone)]
+pub struct Journal {
+    start: Instant,
+    inner: Arc<Mutex<JournalInner>>,
+}
+
+struct JournalInner {
+    entries: VecDeque<JournalEntry>,
+    diagnostics: VecDeque<Diagnostic>,
+    capacity: usize,
+    discarded: usize,
+}
+
+impl JournalInner {
+    fn push(&mut self, entry: JournalEntry) {
+        if self.capacity == 0 {
+            self.discarded += 1;
+            return;
+        }
+        if self.entries.len() >= self.capacity {
+            self.entries.pop_front();
+            self.discarded += 1;
+        }
+        self.entries.push_back(entry);
+    }
+}
+
+impl Default for Journal {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Journal {
+    pub fn new() -> Self {
+        Self::with_capacity(DEFAULT_JOURNAL_CAPACITY)
+    }
+
+    /// Creates a journal that keeps the last `capacity` entries.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            start: Instant::now(),
+            inner: Arc::new(Mutex::new(JournalInner {
+                entries: VecDeque::new(),
+                diagnostics: VecDeque::new(),
+                capacity,
+                discarded: 0,
+            })),
+        }
+    }
+
+    /// Creates a journal from previously recorded entries.
+    /// It keeps at least as many entries as it was created from.
+    pub fn from_entries(entries: Vec<JournalEntry>) -> Self {
+        let journal = Self::with_capacity(entries.len().max(DEFAULT_JOURNAL_CAPACITY));
+        journal.inner.lock().unwrap().entries = entries.into();
+        journal
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.inner.lock().unwrap().capacity
+    }
+
+    /// Lowers the capacity to at most `capacity`, discarding the oldest
+    /// entries that no longer fit. Endpoints limit attached journals to
+    /// their [`MemoryBudget`](rasta_rs::config::MemoryBudget) this way.
+    #[cfg(feature = "rasta")]
+    pub(crate) fn limit_capacity(&self, capacity: usize) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.capacity = inner.capacity.min(capacity);
+        while inner.entries.len() > inner.capacity {
+            inner.entries.pop_front();
+            inner.discarded += 1;
+        }
+        let excess = inner.diagnostics.len().saturating_sub(inner.capacity);
+        inner.diagnostics.drain(..excess);
+    }
+
+    /// The number of entries discarded to make room for newer ones.
+    pub fn discarded(&self) -> usize {
+        self.inner.lock().unwrap().discarded
+    }
+
+    pub fn record(&self, direction: Direction, telegram: &SCITelegram) {
+        let entry = JournalEntry {
+            elapsed: self.start.elapsed(),
+            direction,
+            telegram: telegram.clone(),
+        };
+        self.inner.lock().unwrap().push(entry);
+    }
+
+    /// Keeps `diagnostic`, see [`Journal::diagnostics`].
+    pub fn record_diagnostic(&self, diagnostic: Diagnostic) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.diagnostics.len() >= inner.capacity {
+            inner.diagnostics.pop_front();
+        }
+        if inner.capacity > 0 {
+            inner.diagnostics.push_back(diagnostic);
+        }
+    }
+
+    /// The diagnostics reported to the journal when it is used as a
+    /// [`DiagnosticSink`](crate::diagnostics::DiagnosticSink). The journal
+    /// keeps as many of the latest diagnostics as it keeps entries.
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        self.inner
+            .lock()
+            .unwrap()
+            .diagnostics
+            .iter()
+            .cloned()
+            .collect()
+    }
+
+    /// Returns a snapshot of all entries recorded so far.
+    pub fn entries(&self) -> Vec<JournalEntry> {
+        self.inner.lock().unwrap().entries.iter().cloned().collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.entries.clear();
+        inner.diagnostics.clear();
+    }
+}
@@ -0,0 +1,121 @@
+//! # Legacy Frames
+//!
+//! The original `rasta_rs::sci` implementation wrote the message type as a
+//! single byte, so its telegrams are one byte shorter than current ones.
+//! This module detects and decodes such frames, e.g. to replay sessions
+//! recorded with it. It is meant for diagnostics only: the regular parser
+//! ([`SCITelegram::try_from`]) never accepts legacy frames.
+//!
+//! Since all message types fit into one byte, the high byte of a current
+//! message type is always zero, while a legacy frame has the first
+//! character of the sender name in its place.
+
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use core::time::Duration;
+
+#[cfg(feature = "std")]
+use crate::journal::{Direction, Journal, JournalEntry};
+use crate::{
+    parse_protocol_type, SCITelegram, SciError, SCI_HEADER_LENGTH, SCI_MESSAGE_TYPE_OFFSET,
+};
+
+/// The length of the header of a legacy frame.
+pub const LEGACY_HEADER_LENGTH: usize = SCI_HEADER_LENGTH - 1;
+
+/// Whether `data` looks like a frame with a single-byte message type.
+pub fn is_legacy_frame(data: &[u8]) -> bool {
+    data.len() >= LEGACY_HEADER_LENGTH
+        && parse_protocol_type(data).is_ok()
+        && data[SCI_MESSAGE_TYPE_OFFSET + 1] != 0
+}
+
+/// Converts a legacy frame to the current encoding.
+pub fn upgrade_frame(data: &[u8]) -> Result<Vec<u8>, SciError> {
+    if data.len() < LEGACY_HEADER_LENGTH {
+        return Err(SciError::InvalidTelegramLength(data.len()));
+    }
+    let mut frame = Vec::with_capacity(data.len() + 1);
+    frame.extend_from_slice(&data[..=SCI_MESSAGE_TYPE_OFFSET]);
+    frame.push(0);
+    frame.extend_from_slice(&data[SCI_MESSAGE_TYPE_OFFSET + 1..]);
+    Ok(frame)
+}
+
+/// Decodes a legacy frame.
+pub fn parse_legacy(data: &[u8]) -> Result<SCITelegram, SciError> {
+    SCITelegram::try_from(upgrade_frame(data)?.as_slice())
+}
+
+/// Decodes a legacy or a current frame, whichever `data` looks like.
+pub fn parse_compatible(data: &[u8]) -> Result<SCITelegram, SciError> {
+    if is_legacy_frame(data) {
+        parse_legacy(data)
+    } else {
+        SCITelegram::try_from(data)
+    }
+}
+
+/// Builds a journal from recorded frames in either encoding, so that
+/// legacy sessions can be passed to [`crate::replay`].
+#[cfg(feature = "std")]
+pub fn journal_from_frames<I, D>(frames: I) -> Result<Journal, SciError>
+where
+    I: IntoIterator<Item = (Duration, Direction, D)>,
+    D: AsRef<[u8]>,
+{
+    let entries = frames
+        .into_iter()
+        .map(|(elapsed, direction, data)| {
+            Ok(JournalEntry {
+                elapsed,
+                direction,
+                telegram: parse_compatible(data.as_ref())?,
+            })
+        })
+        .collect::<Result<_, SciError>>()?;
+    Ok(Journal::from_entries(entries))
+}
+
+#[cfg(all(test, feature = "scip", feature = "std"))]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{
+        journal::Direction,
+        scip::{SCIPointLocation, SCIPointTargetLocation},
+        SCIMessageType, SCITelegram, SCI_MESSAGE_TYPE_OFFSET,
+    };
+
+    #[test]
+    fn test_legacy_frames() {
+        let current: Vec<u8> = SCITelegram::change_location(
+            "I",
+            "P1",
+            SCIPointTargetLocation::PointLocationChangeToRight,
+        )
+        .into();
+        let mut legacy = current.clone();
+        legacy.remove(SCI_MESSAGE_TYPE_OFFSET + 1);
+        assert!(super::is_legacy_frame(&legacy));
+        assert!(!super::is_legacy_frame(&current));
+        assert!(SCITelegram::try_from(legacy.as_slice()).is_err());
+
+        let telegram = super::parse_compatible(&legacy).unwrap();
+        assert_eq!(
+            telegram.message_type,
+            SCIMessageType::scip_change_location()
+        );
+        assert_eq!(Vec::<u8>::from(telegram), current);
+        assert!(super::parse_legacy(&legacy[..20]).is_err());
+
+        let status: Vec<u8> =
+            SCITelegram::location_status("P1", "I", SCIPointLocation::PointLocationRight).into();
+        let journal = super::journal_from_frames([
+            (Duration::ZERO, Direction::Outgoing, legacy),
+            (Duration::from_millis(20), Direction::Incoming, status),
+        ])
+        .unwrap();
+        assert_eq!(journal.len(), 2);
+    }
+}
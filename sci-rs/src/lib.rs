@@ -4,26 +4,98 @@
 //! to communicate with track elements such as points and signals.
 //! `rasta-rs` provides support for SCI-LS, SCI-P and SCI-TDS at the moment.
 
+#![cfg_attr(not(any(test, feature = "std")), no_std)]
+
+extern crate alloc;
+
+use alloc::{
+    borrow::Cow,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::{
+    fmt::Display,
+    ops::{Deref, DerefMut},
+};
+
 #[cfg(feature = "rasta")]
-use std::collections::HashMap;
-use std::{fmt::Display, ops::Deref};
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError, SyncSender},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
 
+#[cfg(feature = "rasta")]
+use auth::{Authorizer, Decision, PeerContext};
+#[cfg(feature = "rasta")]
+use availability::Availability;
+use baseline::BaselineProfile;
+#[cfg(feature = "rasta")]
+use descriptor::SCIEndpointDescriptor;
+#[cfg(feature = "rasta")]
+use diagnostics::DiagnosticSink;
+#[cfg(feature = "rasta")]
+use history::History;
+#[cfg(feature = "rasta")]
+use journal::{Direction, Journal};
+#[cfg(feature = "rasta")]
+use liveness::Watchdog;
+#[cfg(feature = "rasta")]
+use logging::event;
+#[cfg(feature = "rasta")]
+use managed::ManagedConfig;
+#[cfg(feature = "rasta")]
+use pdi::{PdiOutcome, PdiRole, PdiState, PdiStateMachine};
+#[cfg(feature = "rasta")]
+use peers::{Allowlist, PeerMapping};
 #[cfg(feature = "rasta")]
 use rasta_rs::{
-    message::RastaId, RastaConnection, RastaConnectionState, RastaError, RastaListener,
-    RASTA_TIMEOUT_DURATION,
+    events::ListenerEvent,
+    incident::IncidentLog,
+    latency::AckLatency,
+    message::{Message, MessageType, RastaId},
+    shutdown::ShutdownHandle,
+    trace::TraceControl,
+    RastaConnection, RastaConnectionState, RastaError, RastaListener, RecoveryAction,
 };
+#[cfg(feature = "scigio")]
+use scigio::SciGioError;
 #[cfg(feature = "scils")]
 use scils::SciLsError;
 #[cfg(feature = "scip")]
 use scip::SciPError;
+#[cfg(feature = "scirbc")]
+use scirbc::SciRbcError;
+#[cfg(feature = "scitcs")]
+use scitcs::SciTcsError;
 #[cfg(feature = "scitds")]
 use scitds::SciTdsError;
+use smallvec::SmallVec;
+#[cfg(feature = "rasta")]
+use stats::TelegramStats;
+#[cfg(feature = "rasta")]
+use stream::{QueuedTelegram, SCITelegramSender, SendTag, TelegramStream};
+#[cfg(feature = "rasta")]
+use version_check::VersionCheck;
 
 /// Helper macro to generate enums with numeric values including a [TryFrom] implementation
+#[cfg(any(
+    feature = "scigio",
+    feature = "scip",
+    feature = "scirbc",
+    feature = "scitcs",
+    feature = "scitds"
+))]
 macro_rules! enumerate {
     ($name:ident, $repr:ty, $error:expr, {$($variant:ident = $value:literal),*}) => {
         #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
         #[repr($repr)]
         pub enum $name {
             $($variant = $value,)*
@@ -42,6 +114,7 @@ macro_rules! enumerate {
     };
     ($name:ident, $doc:literal, $repr:ty, $error:expr, {$($variant:ident = $value:literal),*}) => {
         #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
         #[doc = $doc]
         #[repr($repr)]
         pub enum $name {
@@ -61,42 +134,67 @@ macro_rules! enumerate {
     };
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, thiserror::Error)]
 pub enum SciError {
+    #[error("Unknown Protocol {0:x}")]
     UnknownProtocol(u8),
+    #[error("Protocol {0:x} is supported natively")]
+    NativeProtocol(u8),
+    #[error("Unknown Message Type {0:x}")]
     UnknownMessageType(u16),
+    #[error("Unknown Version Check Result {0:x}")]
     UnknownVersionCheckResult(u8),
+    #[error("Unknown Close Reason {0:x}")]
     UnknownCloseReason(u8),
+    #[error("Invalid Telegram Length {0}")]
+    InvalidTelegramLength(usize),
+    #[error("Name {0:?} exceeds {SCI_NAME_LENGTH} bytes")]
+    NameTooLong(String),
+    #[cfg(feature = "rasta")]
+    #[error("RaSTA message of type {0} carries no telegram")]
+    NoTelegram(u16),
+    #[error("Payload of {length} bytes exceeds the maximum of {max} bytes")]
+    PayloadTooLong { length: usize, max: usize },
+    #[error("Checksum of {length} bytes exceeds the maximum of {max} bytes")]
+    ChecksumTooLong { length: usize, max: usize },
+    #[error("Message Type {0:x} is not supported by the selected baseline")]
+    UnsupportedMessageType(u16),
+    #[error("Telegram from {actual} where {expected} was expected")]
+    UnexpectedSender { expected: String, actual: String },
+    #[error("Message Type {0:x} was not authorized")]
+    Unauthorized(u16),
+    #[error("Configuration checksum {received:02x?} does not match {expected:02x?}")]
+    ChecksumMismatch {
+        expected: Vec<u8>,
+        received: Vec<u8>,
+    },
+    #[error("PDI was closed: {0:?}")]
+    PdiClosed(SCICloseReason),
+    #[cfg(feature = "rasta")]
+    #[error("Message Type {message_type:x} is not allowed in PDI state {state:?}")]
+    PdiStateViolation { state: PdiState, message_type: u16 },
+    #[error("Peer closed the RaSTA connection")]
+    PeerDisconnected,
     #[cfg(feature = "scils")]
+    #[error(transparent)]
     Ls(SciLsError),
+    #[cfg(feature = "scigio")]
+    #[error(transparent)]
+    Gio(SciGioError),
     #[cfg(feature = "scip")]
+    #[error(transparent)]
     P(SciPError),
+    #[cfg(feature = "scirbc")]
+    #[error(transparent)]
+    Rbc(SciRbcError),
+    #[cfg(feature = "scitcs")]
+    #[error(transparent)]
+    Tcs(SciTcsError),
     #[cfg(feature = "scitds")]
+    #[error(transparent)]
     Tds(SciTdsError),
 }
 
-impl Display for SciError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let reason = match self {
-            SciError::UnknownProtocol(p) => format!("Unknown Protocol {:x}", p),
-            SciError::UnknownMessageType(m) => format!("Unknown Message Type {:x}", m),
-            SciError::UnknownVersionCheckResult(v) => {
-                format!("Unknown Version Check Result {:x}", v)
-            }
-            SciError::UnknownCloseReason(c) => format!("Unknown Close Reason {:x}", c),
-            #[cfg(feature = "scils")]
-            SciError::Ls(l) => l.to_string(),
-            #[cfg(feature = "scip")]
-            SciError::P(p) => p.to_string(),
-            #[cfg(feature = "scitds")]
-            SciError::Tds(tds) => tds.to_string(),
-        };
-        write!(f, "{}", reason)
-    }
-}
-
-impl std::error::Error for SciError {}
-
 #[cfg(feature = "scils")]
 impl From<SciLsError> for SciError {
     fn from(value: SciLsError) -> Self {
@@ -104,6 +202,13 @@ impl From<SciLsError> for SciError {
     }
 }
 
+#[cfg(feature = "scigio")]
+impl From<SciGioError> for SciError {
+    fn from(value: SciGioError) -> Self {
+        SciError::Gio(value)
+    }
+}
+
 #[cfg(feature = "scip")]
 impl From<SciPError> for SciError {
     fn from(value: SciPError) -> Self {
@@ -111,6 +216,20 @@ impl From<SciPError> for SciError {
     }
 }
 
+#[cfg(feature = "scirbc")]
+impl From<SciRbcError> for SciError {
+    fn from(value: SciRbcError) -> Self {
+        SciError::Rbc(value)
+    }
+}
+
+#[cfg(feature = "scitcs")]
+impl From<SciTcsError> for SciError {
+    fn from(value: SciTcsError) -> Self {
+        SciError::Tcs(value)
+    }
+}
+
 #[cfg(feature = "scitds")]
 impl From<SciTdsError> for SciError {
     fn from(value: SciTdsError) -> Self {
@@ -118,36 +237,216 @@ impl From<SciTdsError> for SciError {
     }
 }
 
+#[cfg(feature = "rasta")]
+impl SciError {
+    /// The suggested reaction of an SCI endpoint to this error.
+    /// Malformed or unexpected telegrams violate the PDI protocol,
+    /// errors on the sending side only affect the failed operation.
+    pub fn recovery_action(&self) -> RecoveryAction {
+        match self {
+            Self::PayloadTooLong { .. } | Self::NameTooLong(_) | Self::Unauthorized(_) => {
+                RecoveryAction::Retry
+            }
+            Self::ChecksumMismatch { .. }
+            | Self::PdiClosed(
+                SCICloseReason::OtherVersionRequired | SCICloseReason::ChecksumMismatch,
+            ) => RecoveryAction::FailSafe,
+            Self::PeerDisconnected => RecoveryAction::Reconnect,
+            _ => RecoveryAction::ReinitialisePdi,
+        }
+    }
+
+    /// The reason to send in the close telegram
+    /// if this error ends the PDI, if any.
+    pub fn close_reason(&self) -> Option<SCICloseReason> {
+        match self {
+            Self::ChecksumMismatch { .. } => Some(SCICloseReason::ChecksumMismatch),
+            Self::PdiClosed(reason) => Some(*reason),
+            _ => None,
+        }
+    }
+
+    /// The SCI error an SCI endpoint failed with, e.g. to find the reason
+    /// of a [`SciError::PdiClosed`] that ended [`SCIConnection::run`].
+    pub fn from_rasta_error(error: &RastaError) -> Option<&SciError> {
+        std::error::Error::source(error)?.downcast_ref()
+    }
+}
+
 #[cfg(feature = "rasta")]
 impl From<SciError> for RastaError {
     fn from(value: SciError) -> Self {
-        Self::Other(format!("{:?}", value))
+        Self::Application {
+            message: value.to_string(),
+            recovery: value.recovery_action(),
+            source: Some(Box::new(value)),
+        }
     }
 }
 
+#[cfg(feature = "std")]
+pub mod auth;
+#[cfg(feature = "rasta")]
+pub mod availability;
+pub mod baseline;
+#[cfg(feature = "rasta")]
+pub mod descriptor;
+#[cfg(feature = "std")]
+pub mod diagnostics;
+#[cfg(feature = "generic")]
+pub mod generic;
+#[cfg(feature = "std")]
+pub mod history;
+#[cfg(feature = "std")]
+pub mod journal;
+pub mod legacy;
+#[cfg(feature = "rasta")]
+pub mod liveness;
+#[cfg(feature = "rasta")]
+mod logging;
+#[cfg(feature = "rasta")]
+pub mod managed;
+#[cfg(feature = "rasta")]
+pub mod pdi;
+#[cfg(feature = "rasta")]
+pub mod peers;
+#[cfg(feature = "rasta")]
+pub mod pool;
+pub mod prelude;
+#[cfg(feature = "std")]
+pub mod replay;
+#[cfg(feature = "scigio")]
+pub mod scigio;
 #[cfg(feature = "scils")]
 pub mod scils;
 #[cfg(feature = "scip")]
 pub mod scip;
+#[cfg(feature = "scirbc")]
+pub mod scirbc;
+#[cfg(feature = "scitcs")]
+pub mod scitcs;
 #[cfg(feature = "scitds")]
 pub mod scitds;
+#[cfg(feature = "sim")]
+pub mod sim;
+#[cfg(feature = "std")]
+pub mod stats;
+#[cfg(feature = "rasta")]
+pub mod stream;
+#[cfg(feature = "test-support")]
+pub mod testing;
+#[cfg(feature = "rasta")]
+pub mod version_check;
 
 /// The current version of this SCI implementation.
 pub const SCI_VERSION: u8 = 0x01;
 
-pub(crate) fn str_to_sci_name(name: &str) -> Vec<u8> {
-    let mut new_name = vec![b'_'; 20];
-    if name.len() < 20 {
-        new_name[..name.len()].clone_from_slice(name.as_bytes());
+pub use rasta_core::{
+    layout::{
+        SCI_HEADER_LENGTH, SCI_MAX_PAYLOAD_LENGTH, SCI_MESSAGE_TYPE_OFFSET, SCI_NAME_LENGTH,
+        SCI_PROTOCOL_TYPE_OFFSET, SCI_RECEIVER_OFFSET, SCI_SENDER_OFFSET,
+    },
+    SciName,
+};
+
+/// The length up to which an [`SCIPayload`] is stored without allocating.
+/// This is the maximum payload length of all protocols except SCI-TDS.
+pub const SCI_INLINE_PAYLOAD_LENGTH: usize = 85;
+/// The maximum length of the checksum in a version response, i.e. the
+/// payload without the result, version and checksum length bytes.
+pub const SCI_MAX_CHECKSUM_LENGTH: usize = SCI_INLINE_PAYLOAD_LENGTH - 3;
+
+/// How strictly payloads of existing elements are parsed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ParseMode {
+    /// Payloads have to contain every field of the specification.
+    #[default]
+    Strict,
+    /// Optional fields missing from the end of a payload are filled with
+    /// zeros, for elements that predate them.
+    Lenient,
+}
+
+/// Splits a payload into the `length` bytes understood by this implementation
+/// and trailing extension bytes. Later SCI versions may append optional bytes
+/// to existing payloads, so extensions are only accepted if the peer uses a
+/// newer `version` than [`SCI_VERSION`]. Returns `None` if the payload is
+/// too short or has unexpected trailing bytes.
+pub fn split_extensions(payload: &[u8], length: usize, version: u8) -> Option<(&[u8], &[u8])> {
+    if payload.len() < length || (payload.len() > length && version <= SCI_VERSION) {
+        None
     } else {
-        new_name[..20].clone_from_slice(&name.as_bytes()[..20])
+        Some(payload.split_at(length))
     }
-    new_name
+}
+
+/// The header of an SCI telegram, borrowing from the raw telegram bytes.
+/// Use [`parse_header`] to inspect telegrams without copying their payload.
+#[derive(Clone, Debug)]
+pub struct SciHeader<'a> {
+    pub protocol_type: ProtocolType,
+    pub message_type: SCIMessageType,
+    pub sender: Cow<'a, str>,
+    pub receiver: Cow<'a, str>,
+    pub payload: &'a [u8],
+}
+
+/// Reads the protocol type of a raw SCI telegram.
+pub fn parse_protocol_type(data: &[u8]) -> Result<ProtocolType, SciError> {
+    let protocol_type = data
+        .get(SCI_PROTOCOL_TYPE_OFFSET)
+        .ok_or(SciError::InvalidTelegramLength(data.len()))?;
+    ProtocolType::try_from(*protocol_type)
+}
+
+/// Whether `data` starts with the protocol type of a supported SCI protocol.
+/// Other data can be handed to a raw data handler, see
+/// [`SCIConnection::set_raw_data_handler`].
+pub fn is_sci_telegram(data: &[u8]) -> bool {
+    parse_protocol_type(data).is_ok()
+}
+
+/// Reads the raw message type of an SCI telegram without resolving it
+/// against a protocol.
+pub fn parse_message_type_raw(data: &[u8]) -> Result<u16, SciError> {
+    let message_type = data
+        .get(SCI_MESSAGE_TYPE_OFFSET..SCI_MESSAGE_TYPE_OFFSET + 2)
+        .ok_or(SciError::InvalidTelegramLength(data.len()))?;
+    Ok(u16::from_le_bytes(message_type.try_into().unwrap()))
+}
+
+/// Reads the sender and receiver names of a raw SCI telegram.
+pub fn parse_names(data: &[u8]) -> Result<(Cow<'_, str>, Cow<'_, str>), SciError> {
+    if data.len() < SCI_HEADER_LENGTH {
+        return Err(SciError::InvalidTelegramLength(data.len()));
+    }
+    Ok((
+        String::from_utf8_lossy(&data[SCI_SENDER_OFFSET..SCI_RECEIVER_OFFSET]),
+        String::from_utf8_lossy(&data[SCI_RECEIVER_OFFSET..SCI_HEADER_LENGTH]),
+    ))
+}
+
+/// Parses the header of a raw SCI telegram. The payload is
+/// returned as a slice of `data`.
+pub fn parse_header(data: &[u8]) -> Result<SciHeader<'_>, SciError> {
+    let protocol_type = parse_protocol_type(data)?;
+    let message_type =
+        SCIMessageType::try_from_protocol(protocol_type, parse_message_type_raw(data)?)?;
+    let (sender, receiver) = parse_names(data)?;
+    Ok(SciHeader {
+        protocol_type,
+        message_type,
+        sender,
+        receiver,
+        payload: &data[SCI_HEADER_LENGTH..],
+    })
 }
 
 /// Constants to represent SCI Protocol types.
 #[repr(u8)]
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ProtocolType {
     SCIProtocolAIS = 0x01,
     SCIProtocolTDS = 0x20,
@@ -158,6 +457,42 @@ pub enum ProtocolType {
     SCIProtocolTCS = 0x70,
     SCIProtocolGIO = 0x90,
     SCIProtocolELX = 0xC0,
+    /// A protocol registered with [`generic::register_protocol`].
+    #[cfg(feature = "generic")]
+    Generic(u8),
+}
+
+impl From<ProtocolType> for u8 {
+    fn from(value: ProtocolType) -> Self {
+        match value {
+            ProtocolType::SCIProtocolAIS => 0x01,
+            ProtocolType::SCIProtocolTDS => 0x20,
+            ProtocolType::SCIProtocolLS => 0x30,
+            ProtocolType::SCIProtocolP => 0x40,
+            ProtocolType::SCIProtocolRBC => 0x50,
+            ProtocolType::SCIProtocolLX => 0x60,
+            ProtocolType::SCIProtocolTCS => 0x70,
+            ProtocolType::SCIProtocolGIO => 0x90,
+            ProtocolType::SCIProtocolELX => 0xC0,
+            #[cfg(feature = "generic")]
+            ProtocolType::Generic(id) => id,
+        }
+    }
+}
+
+impl ProtocolType {
+    /// The maximum payload length of telegrams of this protocol.
+    /// Status telegrams of SCI-TDS and telegrams of generic protocols may
+    /// use all of [`SCI_MAX_PAYLOAD_LENGTH`], the payloads of the other
+    /// protocols fit into [`SCI_INLINE_PAYLOAD_LENGTH`].
+    pub const fn max_payload_length(&self) -> usize {
+        match self {
+            ProtocolType::SCIProtocolTDS => SCI_MAX_PAYLOAD_LENGTH,
+            #[cfg(feature = "generic")]
+            ProtocolType::Generic(_) => SCI_MAX_PAYLOAD_LENGTH,
+            _ => SCI_INLINE_PAYLOAD_LENGTH,
+        }
+    }
 }
 
 impl TryFrom<u8> for ProtocolType {
@@ -168,6 +503,15 @@ impl TryFrom<u8> for ProtocolType {
             0x20 => Ok(Self::SCIProtocolTDS),
             0x40 => Ok(Self::SCIProtocolP),
             0x30 => Ok(Self::SCIProtocolLS),
+            // Without the module, RBC telegrams can still be passed through as generic ones
+            #[cfg(feature = "scirbc")]
+            0x50 => Ok(Self::SCIProtocolRBC),
+            #[cfg(feature = "scitcs")]
+            0x70 => Ok(Self::SCIProtocolTCS),
+            #[cfg(feature = "scigio")]
+            0x90 => Ok(Self::SCIProtocolGIO),
+            #[cfg(feature = "generic")]
+            v if generic::is_registered(v) => Ok(Self::Generic(v)),
             v => Err(SciError::UnknownProtocol(v)),
         }
     }
@@ -177,10 +521,12 @@ impl TryFrom<u8> for ProtocolType {
 /// protocols may use overlapping integer
 /// representations, this is not a enum, but a
 /// newtype with associated functions.
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SCIMessageType(u16);
 
 /// Automatically implement the associated functions for message types.
+#[doc(hidden)]
 #[macro_export]
 macro_rules! impl_sci_message_type {
     ($(($msg:tt, $id:tt)),*) => {
@@ -207,6 +553,39 @@ impl_sci_message_type!(
 );
 
 impl SCIMessageType {
+    /// Resolves a raw message type against the message types of `protocol_type`.
+    #[cfg_attr(
+        not(any(
+            feature = "scigio",
+            feature = "scip",
+            feature = "scils",
+            feature = "scirbc",
+            feature = "scitcs",
+            feature = "scitds",
+            feature = "generic"
+        )),
+        allow(unused_variables)
+    )]
+    pub fn try_from_protocol(protocol_type: ProtocolType, value: u16) -> Result<Self, SciError> {
+        match protocol_type {
+            #[cfg(feature = "scip")]
+            ProtocolType::SCIProtocolP => Self::try_as_scip_message_type_from(value),
+            #[cfg(feature = "scils")]
+            ProtocolType::SCIProtocolLS => Self::try_as_scils_message_type_from(value),
+            #[cfg(feature = "scirbc")]
+            ProtocolType::SCIProtocolRBC => Self::try_as_scirbc_message_type_from(value),
+            #[cfg(feature = "scitcs")]
+            ProtocolType::SCIProtocolTCS => Self::try_as_scitcs_message_type_from(value),
+            #[cfg(feature = "scigio")]
+            ProtocolType::SCIProtocolGIO => Self::try_as_scigio_message_type_from(value),
+            #[cfg(feature = "scitds")]
+            ProtocolType::SCIProtocolTDS => Self::try_as_scitds_message_type_from(value),
+            #[cfg(feature = "generic")]
+            ProtocolType::Generic(_) => Ok(Self::generic(value)),
+            p => Err(SciError::UnknownProtocol(p.into())),
+        }
+    }
+
     pub fn try_as_sci_message_type(&self) -> Result<&str, SciError> {
         match self.0 {
             0x0024 => Ok("VersionRequest"),
@@ -246,6 +625,8 @@ impl SCIMessageType {
         match self.0 {
             0x0001 => Ok("ChangeLocation"),
             0x000B => Ok("LocationStatus"),
+            0x0010 => Ok("HeatingStatus"),
+            0x0011 => Ok("AuxiliaryStatus"),
             _ => self.try_as_sci_message_type(),
         }
     }
@@ -255,6 +636,8 @@ impl SCIMessageType {
         match value {
             0x0001 => Ok(Self::scip_change_location()),
             0x000B => Ok(Self::scip_location_status()),
+            0x0010 => Ok(Self::scip_heating_status()),
+            0x0011 => Ok(Self::scip_auxiliary_status()),
             _ => Self::try_as_sci_message_type_from(value),
         }
     }
@@ -266,6 +649,7 @@ impl SCIMessageType {
             0x0002 => Ok("ChangeBrightness"),
             0x0003 => Ok("SignalAspectStatus"),
             0x0004 => Ok("BrightnessStatus"),
+            0x0005 => Ok("CommandRejected"),
             _ => self.try_as_sci_message_type(),
         }
     }
@@ -277,6 +661,77 @@ impl SCIMessageType {
             0x0002 => Ok(Self::scils_change_brightness()),
             0x0003 => Ok(Self::scils_signal_aspect_status()),
             0x0004 => Ok(Self::scils_brightness_status()),
+            0x0005 => Ok(Self::scils_command_rejected()),
+            _ => Self::try_as_sci_message_type_from(value),
+        }
+    }
+
+    #[cfg(feature = "scirbc")]
+    pub fn try_as_scirbc_message_type(&self) -> Result<&str, SciError> {
+        match self.0 {
+            0x0001 => Ok("RouteStatus"),
+            0x0002 => Ok("SignalStatus"),
+            0x0003 => Ok("EmergencyStop"),
+            0x0004 => Ok("RevokeEmergencyStop"),
+            0x0005 => Ok("CommandRejected"),
+            _ => self.try_as_sci_message_type(),
+        }
+    }
+
+    #[cfg(feature = "scirbc")]
+    pub fn try_as_scirbc_message_type_from(value: u16) -> Result<Self, SciError> {
+        match value {
+            0x0001 => Ok(Self::scirbc_route_status()),
+            0x0002 => Ok(Self::scirbc_signal_status()),
+            0x0003 => Ok(Self::scirbc_emergency_stop()),
+            0x0004 => Ok(Self::scirbc_revoke_emergency_stop()),
+            0x0005 => Ok(Self::scirbc_command_rejected()),
+            _ => Self::try_as_sci_message_type_from(value),
+        }
+    }
+
+    #[cfg(feature = "scitcs")]
+    pub fn try_as_scitcs_message_type(&self) -> Result<&str, SciError> {
+        match self.0 {
+            0x0001 => Ok("TrainPosition"),
+            0x0002 => Ok("SectionStatus"),
+            0x0003 => Ok("SpeedRestriction"),
+            0x0004 => Ok("RevokeSpeedRestriction"),
+            0x0005 => Ok("CommandRejected"),
+            _ => self.try_as_sci_message_type(),
+        }
+    }
+
+    #[cfg(feature = "scitcs")]
+    pub fn try_as_scitcs_message_type_from(value: u16) -> Result<Self, SciError> {
+        match value {
+            0x0001 => Ok(Self::scitcs_train_position()),
+            0x0002 => Ok(Self::scitcs_section_status()),
+            0x0003 => Ok(Self::scitcs_speed_restriction()),
+            0x0004 => Ok(Self::scitcs_revoke_speed_restriction()),
+            0x0005 => Ok(Self::scitcs_command_rejected()),
+            _ => Self::try_as_sci_message_type_from(value),
+        }
+    }
+
+    #[cfg(feature = "scigio")]
+    pub fn try_as_scigio_message_type(&self) -> Result<&str, SciError> {
+        match self.0 {
+            0x0001 => Ok("SetOutputs"),
+            0x0002 => Ok("InputStatus"),
+            0x0003 => Ok("OutputStatus"),
+            0x0004 => Ok("CommandRejected"),
+            _ => self.try_as_sci_message_type(),
+        }
+    }
+
+    #[cfg(feature = "scigio")]
+    pub fn try_as_scigio_message_type_from(value: u16) -> Result<Self, SciError> {
+        match value {
+            0x0001 => Ok(Self::scigio_set_outputs()),
+            0x0002 => Ok(Self::scigio_input_status()),
+            0x0003 => Ok(Self::scigio_output_status()),
+            0x0004 => Ok(Self::scigio_command_rejected()),
             _ => Self::try_as_sci_message_type_from(value),
         }
     }
@@ -322,7 +777,8 @@ impl From<SCIMessageType> for u16 {
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
 pub enum SCIVersionCheckResult {
     NotAllowedToUse = 0,
@@ -336,14 +792,55 @@ impl TryFrom<u8> for SCIVersionCheckResult {
     fn try_from(value: u8) -> Result<Self, Self::Error> {
         match value {
             0 => Ok(Self::NotAllowedToUse),
-            1 => Ok(Self::VersionsAreEqual),
+            1 => Ok(Self::VersionsAreNotEqual),
             2 => Ok(Self::VersionsAreEqual),
             v => Err(SciError::UnknownVersionCheckResult(v)),
         }
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+/// The payload of [`SCITelegram::version_response`]. Responses without
+/// the checksum length, as sent by some BL3 elements, carry an empty
+/// checksum.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct VersionResponsePayload {
+    pub result: SCIVersionCheckResult,
+    pub version: u8,
+    pub checksum: Vec<u8>,
+}
+
+impl TryFrom<&SCIPayload> for VersionResponsePayload {
+    type Error = SciError;
+
+    fn try_from(value: &SCIPayload) -> Result<Self, Self::Error> {
+        let [result, version, rest @ ..] = &value[..] else {
+            return Err(SciError::InvalidTelegramLength(value.len()));
+        };
+        let checksum = match rest.split_first() {
+            None => Vec::new(),
+            Some((&length, checksum)) => checksum
+                .get(..length as usize)
+                .ok_or(SciError::InvalidTelegramLength(value.len()))?
+                .to_vec(),
+        };
+        Ok(Self {
+            result: SCIVersionCheckResult::try_from(*result)?,
+            version: *version,
+            checksum,
+        })
+    }
+}
+
+impl TryFrom<SCIPayload> for VersionResponsePayload {
+    type Error = SciError;
+
+    fn try_from(value: SCIPayload) -> Result<Self, Self::Error> {
+        Self::try_from(&value)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 #[repr(u8)]
 pub enum SCICloseReason {
     ProtocolError = 1,
@@ -372,76 +869,262 @@ impl TryFrom<u8> for SCICloseReason {
     }
 }
 
-/// The payload of an [`SCITelegram`]. Usually constructed from
-/// a slice using [`SCIPayload::from_slice`].
-#[derive(Clone, Copy)]
-pub struct SCIPayload {
-    pub data: [u8; 85],
-    pub used: usize,
+/// Whether the elements behind an endpoint can currently be used, as
+/// announced with PDI Available and PDI Not Available telegrams.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SCIAvailability {
+    #[default]
+    Available,
+    NotAvailable,
 }
 
+/// The payload of an [`SCITelegram`]. Usually constructed from
+/// a slice using [`SCIPayload::from_slice`]. Payloads of up to
+/// [`SCI_INLINE_PAYLOAD_LENGTH`] bytes are stored inline,
+/// longer ones on the heap.
+#[derive(Clone, Default, PartialEq, Eq, Hash)]
+pub struct SCIPayload(SmallVec<[u8; SCI_INLINE_PAYLOAD_LENGTH]>);
+
 impl Deref for SCIPayload {
     type Target = [u8];
 
     fn deref(&self) -> &Self::Target {
-        &self.data[..self.used]
+        &self.0
     }
 }
 
-impl Default for SCIPayload {
-    fn default() -> Self {
-        Self {
-            data: [0; 85],
-            used: 0,
+impl DerefMut for SCIPayload {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+/// Prints the payload as hex bytes, e.g. `[01 a0]`.
+impl core::fmt::Debug for SCIPayload {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("[")?;
+        for (i, byte) in self.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            write!(f, "{byte:02x}")?;
         }
+        f.write_str("]")
+    }
+}
+
+/// Payloads are serialized as a sequence of bytes.
+#[cfg(feature = "serde")]
+impl serde::Serialize for SCIPayload {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for SCIPayload {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let data = Vec::<u8>::deserialize(deserializer)?;
+        Self::try_from_slice(&data).map_err(serde::de::Error::custom)
     }
 }
 
 impl SCIPayload {
+    /// Creates a payload from `data`, failing if it is longer
+    /// than [`SCI_MAX_PAYLOAD_LENGTH`].
+    pub fn try_from_slice(data: &[u8]) -> Result<Self, SciError> {
+        if data.len() > SCI_MAX_PAYLOAD_LENGTH {
+            return Err(SciError::PayloadTooLong {
+                length: data.len(),
+                max: SCI_MAX_PAYLOAD_LENGTH,
+            });
+        }
+        Ok(Self::from_slice(data))
+    }
+
+    /// Creates a payload from `data`. Whether the payload fits into a
+    /// telegram is checked with [`SCITelegram::validate_payload_length`]
+    /// before it is sent.
     pub fn from_slice(data: &[u8]) -> Self {
-        let mut payload = Self {
-            used: data.len(),
-            ..Default::default()
-        };
-        payload.data[..data.len()].copy_from_slice(data);
-        payload
+        Self(SmallVec::from_slice(data))
+    }
+
+    /// Whether the payload is stored without a heap allocation.
+    pub fn is_inline(&self) -> bool {
+        !self.0.spilled()
     }
 }
 
 /// An SCI message. You should construct these using the generic
-/// and protocol-specific associated functions.
+/// and protocol-specific associated functions, or [`SCITelegram::new`]
+/// for message types this crate does not know. The fields are read
+/// through accessors, so that their representation can change.
 #[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SCITelegram {
-    pub protocol_type: ProtocolType,
-    pub message_type: SCIMessageType,
-    pub sender: String,
-    pub receiver: String,
-    pub payload: SCIPayload,
+    pub(crate) protocol_type: ProtocolType,
+    pub(crate) message_type: SCIMessageType,
+    pub(crate) sender: String,
+    pub(crate) receiver: String,
+    pub(crate) payload: SCIPayload,
+}
+
+impl SCITelegram {
+    /// Creates a telegram from its parts. The names are padded
+    /// when the telegram is serialized.
+    pub fn new(
+        protocol_type: ProtocolType,
+        message_type: SCIMessageType,
+        sender: &str,
+        receiver: &str,
+        payload: SCIPayload,
+    ) -> Self {
+        Self {
+            protocol_type,
+            message_type,
+            sender: sender.to_string(),
+            receiver: receiver.to_string(),
+            payload,
+        }
+    }
+
+    pub fn protocol_type(&self) -> ProtocolType {
+        self.protocol_type
+    }
+
+    pub fn message_type(&self) -> SCIMessageType {
+        self.message_type
+    }
+
+    /// The name of the sender. Names parsed from the wire keep their padding.
+    pub fn sender(&self) -> &str {
+        &self.sender
+    }
+
+    /// The name of the receiver. Names parsed from the wire keep their padding.
+    pub fn receiver(&self) -> &str {
+        &self.receiver
+    }
+
+    pub fn payload(&self) -> &SCIPayload {
+        &self.payload
+    }
+
+    pub fn payload_mut(&mut self) -> &mut SCIPayload {
+        &mut self.payload
+    }
+
+    pub fn into_payload(self) -> SCIPayload {
+        self.payload
+    }
+
+    /// The name of the message type in the telegram's protocol,
+    /// or `None` if the protocol does not define the message type
+    /// or is not enabled.
+    pub fn message_type_name(&self) -> Option<&str> {
+        let name = match self.protocol_type {
+            #[cfg(feature = "scitds")]
+            ProtocolType::SCIProtocolTDS => self.message_type.try_as_scitds_message_type(),
+            #[cfg(feature = "scils")]
+            ProtocolType::SCIProtocolLS => self.message_type.try_as_scils_message_type(),
+            #[cfg(feature = "scip")]
+            ProtocolType::SCIProtocolP => self.message_type.try_as_scip_message_type(),
+            #[cfg(feature = "scirbc")]
+            ProtocolType::SCIProtocolRBC => self.message_type.try_as_scirbc_message_type(),
+            #[cfg(feature = "scitcs")]
+            ProtocolType::SCIProtocolTCS => self.message_type.try_as_scitcs_message_type(),
+            #[cfg(feature = "scigio")]
+            ProtocolType::SCIProtocolGIO => self.message_type.try_as_scigio_message_type(),
+            _ => self.message_type.try_as_sci_message_type(),
+        };
+        name.ok()
+    }
+}
+
+/// Writes the name of the message type, or its value if it is unknown.
+struct MessageTypeName<'a>(&'a SCITelegram);
+
+impl Display for MessageTypeName<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self.0.message_type_name() {
+            Some(name) => f.write_str(name),
+            None => write!(f, "{:#06x}", u16::from(self.0.message_type)),
+        }
+    }
+}
+
+/// Names parsed from the wire keep their padding, which is left out
+/// when telegrams are printed.
+fn trim_sci_name(name: &str) -> &str {
+    name.trim_end_matches('_')
 }
 
+/// Prints the protocol, the message type, sender and receiver
+/// and the payload in hex, e.g.
+/// `SCIProtocolP: ChangeLocation C -> S [01]`.
 impl Display for SCITelegram {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         write!(
             f,
-            "{:?}: {}",
+            "{:?}: {} {} -> {} {:?}",
             self.protocol_type,
-            match self.protocol_type {
-                #[cfg(feature = "scitds")]
-                ProtocolType::SCIProtocolTDS =>
-                    self.message_type.try_as_scitds_message_type().unwrap(),
-                #[cfg(feature = "scils")]
-                ProtocolType::SCIProtocolLS =>
-                    self.message_type.try_as_scils_message_type().unwrap(),
-                #[cfg(feature = "scip")]
-                ProtocolType::SCIProtocolP => self.message_type.try_as_scip_message_type().unwrap(),
-                _ => "Unsupported",
-            }
+            MessageTypeName(self),
+            trim_sci_name(&self.sender),
+            trim_sci_name(&self.receiver),
+            self.payload
         )
     }
 }
 
+/// Telegrams are equal if they are sent as the same bytes. Names are
+/// compared as they appear on the wire, i.e. padded and truncated to
+/// [`SCI_NAME_LENGTH`], so a received telegram equals the one it was
+/// sent as.
+impl PartialEq for SCITelegram {
+    fn eq(&self, other: &Self) -> bool {
+        self.protocol_type == other.protocol_type
+            && self.message_type == other.message_type
+            && self.payload == other.payload
+            && SciName::truncated(&self.sender) == SciName::truncated(&other.sender)
+            && SciName::truncated(&self.receiver) == SciName::truncated(&other.receiver)
+    }
+}
+
+impl Eq for SCITelegram {}
+
+impl core::hash::Hash for SCITelegram {
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+        self.protocol_type.hash(state);
+        self.message_type.hash(state);
+        SciName::truncated(&self.sender).hash(state);
+        SciName::truncated(&self.receiver).hash(state);
+        self.payload.hash(state);
+    }
+}
+
+impl core::fmt::Debug for SCITelegram {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("SCITelegram")
+            .field("protocol_type", &self.protocol_type)
+            .field(
+                "message_type",
+                &format_args!(
+                    "{} ({:#06x})",
+                    MessageTypeName(self),
+                    u16::from(self.message_type)
+                ),
+            )
+            .field("sender", &trim_sci_name(&self.sender))
+            .field("receiver", &trim_sci_name(&self.receiver))
+            .field("payload", &self.payload)
+            .finish()
+    }
+}
+
 /// Automatically implement the associated functions for messages
 /// with no payload.
+#[doc(hidden)]
 #[macro_export]
 macro_rules! impl_sci_messages_without_payload {
     ($protocol_type:expr, ($(($message:ident, $message_type:expr)),*)) => {
@@ -477,6 +1160,7 @@ impl SCITelegram {
         }
     }
 
+    /// Fails if `checksum` is longer than [`SCI_MAX_CHECKSUM_LENGTH`].
     pub fn version_response(
         protocol_type: ProtocolType,
         sender: &str,
@@ -484,16 +1168,32 @@ impl SCITelegram {
         version: u8,
         version_check_result: SCIVersionCheckResult,
         checksum: &[u8],
-    ) -> Self {
+    ) -> Result<Self, SciError> {
+        if checksum.len() > SCI_MAX_CHECKSUM_LENGTH {
+            return Err(SciError::ChecksumTooLong {
+                length: checksum.len(),
+                max: SCI_MAX_CHECKSUM_LENGTH,
+            });
+        }
         let mut payload_data = vec![version_check_result as u8, version, checksum.len() as u8];
         payload_data.append(&mut Vec::from(checksum));
-        Self {
+        Ok(Self {
             protocol_type,
             message_type: SCIMessageType::pdi_version_response(),
             sender: sender.to_string(),
             receiver: receiver.to_string(),
             payload: SCIPayload::from_slice(&payload_data),
+        })
+    }
+
+    /// The configuration checksum of a version response, or `None`
+    /// for other telegrams and truncated responses.
+    pub fn version_response_checksum(&self) -> Option<&[u8]> {
+        if self.message_type != SCIMessageType::pdi_version_response() {
+            return None;
         }
+        let length = *self.payload.get(2)? as usize;
+        self.payload.get(3..3 + length)
     }
 
     pub fn initialisation_request(
@@ -553,6 +1253,60 @@ impl SCITelegram {
         }
     }
 
+    /// The reason of a close telegram, or `None` for other
+    /// telegrams and unknown reasons.
+    pub fn close_reason(&self) -> Option<SCICloseReason> {
+        if self.message_type != SCIMessageType::pdi_close() {
+            return None;
+        }
+        SCICloseReason::try_from(*self.payload.first()?).ok()
+    }
+
+    pub fn available(protocol_type: ProtocolType, sender: &str, receiver: &str) -> Self {
+        Self {
+            protocol_type,
+            message_type: SCIMessageType::pdi_available(),
+            sender: sender.to_string(),
+            receiver: receiver.to_string(),
+            payload: SCIPayload::default(),
+        }
+    }
+
+    pub fn not_available(protocol_type: ProtocolType, sender: &str, receiver: &str) -> Self {
+        Self {
+            protocol_type,
+            message_type: SCIMessageType::pdi_not_available(),
+            sender: sender.to_string(),
+            receiver: receiver.to_string(),
+            payload: SCIPayload::default(),
+        }
+    }
+
+    /// The availability telegram announcing `availability`.
+    pub fn availability_announcement(
+        protocol_type: ProtocolType,
+        sender: &str,
+        receiver: &str,
+        availability: SCIAvailability,
+    ) -> Self {
+        match availability {
+            SCIAvailability::Available => Self::available(protocol_type, sender, receiver),
+            SCIAvailability::NotAvailable => Self::not_available(protocol_type, sender, receiver),
+        }
+    }
+
+    /// The availability announced by a PDI Available or Not Available
+    /// telegram, or `None` for other telegrams.
+    pub fn availability(&self) -> Option<SCIAvailability> {
+        if self.message_type == SCIMessageType::pdi_available() {
+            Some(SCIAvailability::Available)
+        } else if self.message_type == SCIMessageType::pdi_not_available() {
+            Some(SCIAvailability::NotAvailable)
+        } else {
+            None
+        }
+    }
+
     pub fn release_for_maintenance(
         protocol_type: ProtocolType,
         sender: &str,
@@ -578,52 +1332,131 @@ impl SCITelegram {
     }
 }
 
-impl TryFrom<&[u8]> for SCITelegram {
-    type Error = SciError;
-
-    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
-        let protocol_type = ProtocolType::try_from(value[0])?;
-        let message_type_as_u16 = u16::from_le_bytes(value[1..3].try_into().unwrap());
-        let message_type = match protocol_type {
-            #[cfg(feature = "scip")]
-            ProtocolType::SCIProtocolP => {
-                SCIMessageType::try_as_scip_message_type_from(message_type_as_u16)?
-            }
-            #[cfg(feature = "scils")]
-            ProtocolType::SCIProtocolLS => {
-                SCIMessageType::try_as_scils_message_type_from(message_type_as_u16)?
-            }
-            #[cfg(feature = "scitds")]
-            ProtocolType::SCIProtocolTDS => {
-                SCIMessageType::try_as_scitds_message_type_from(message_type_as_u16)?
-            }
-            _ => unimplemented!(),
+impl SCITelegram {
+    /// Checks the payload against the maximum of the telegram's protocol.
+    pub fn validate_payload_length(&self) -> Result<(), SciError> {
+        let max = self.protocol_type.max_payload_length();
+        if self.payload.len() > max {
+            Err(SciError::PayloadTooLong {
+                length: self.payload.len(),
+                max,
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Checks whether the telegram may be exchanged in `baseline`.
+    pub fn validate_baseline(&self, baseline: BaselineProfile) -> Result<(), SciError> {
+        if baseline.supports(self.protocol_type, self.message_type) {
+            Ok(())
+        } else {
+            Err(SciError::UnsupportedMessageType(self.message_type.into()))
+        }
+    }
+
+    /// The length of the telegram on the wire.
+    pub fn wire_length(&self) -> usize {
+        SCI_HEADER_LENGTH + self.payload.len()
+    }
+
+    /// Returns the SCI version carried by a version check or version response.
+    pub fn sci_version(&self) -> Option<u8> {
+        if self.message_type == SCIMessageType::pdi_version_check() {
+            self.payload.first().copied()
+        } else if self.message_type == SCIMessageType::pdi_version_response() {
+            self.payload.get(1).copied()
+        } else {
+            None
+        }
+    }
+}
+
+impl TryFrom<&[u8]> for SCITelegram {
+    type Error = SciError;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        let header = parse_header(value)?;
+        let telegram = Self {
+            protocol_type: header.protocol_type,
+            message_type: header.message_type,
+            sender: header.sender.into_owned(),
+            receiver: header.receiver.into_owned(),
+            payload: SCIPayload::try_from_slice(header.payload)?,
         };
-        Ok(Self {
-            protocol_type,
-            message_type,
-            sender: String::from_utf8_lossy(&value[3..23]).to_string(),
-            receiver: String::from_utf8_lossy(&value[23..43]).to_string(),
-            payload: SCIPayload::from_slice(&value[43..]),
-        })
+        telegram.validate_payload_length()?;
+        Ok(telegram)
     }
 }
 
 impl From<SCITelegram> for Vec<u8> {
     fn from(val: SCITelegram) -> Self {
-        let mut data = vec![val.protocol_type as u8];
+        let mut data = vec![u8::from(val.protocol_type)];
         let message_type: u16 = val.message_type.into();
         data.append(&mut message_type.to_le_bytes().to_vec());
-        data.append(&mut str_to_sci_name(&val.sender));
-        data.append(&mut str_to_sci_name(&val.receiver));
-        if val.payload.used > 0 {
-            let mut payload = Vec::from(val.payload.as_ref());
-            data.append(&mut payload);
-        }
+        data.extend_from_slice(&SciName::truncated(&val.sender));
+        data.extend_from_slice(&SciName::truncated(&val.receiver));
+        data.extend_from_slice(&val.payload);
         data
     }
 }
 
+/// Extracts the telegram carried by a data message,
+/// e.g. one returned by [`RastaConnection::receive_message`].
+#[cfg(feature = "rasta")]
+impl TryFrom<&Message> for SCITelegram {
+    type Error = SciError;
+
+    fn try_from(value: &Message) -> Result<Self, Self::Error> {
+        match value.try_message_type() {
+            Ok(MessageType::Data | MessageType::RetrData) => Self::try_from(value.data()),
+            _ => Err(SciError::NoTelegram(value.message_type_raw())),
+        }
+    }
+}
+
+/// The header fields of the RaSTA data message that carries
+/// a telegram, see [`MessageFromSci::from_sci`].
+#[cfg(feature = "rasta")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SequenceState {
+    pub receiver: RastaId,
+    pub sender: RastaId,
+    pub sequence_number: u32,
+    pub confirmed_sequence_number: u32,
+    pub timestamp: u32,
+    pub confirmed_timestamp: u32,
+}
+
+/// Wraps telegrams in RaSTA messages. Import it to call `Message::from_sci`.
+#[cfg(feature = "rasta")]
+pub trait MessageFromSci: Sized {
+    /// Builds a data message carrying `telegram` with the header fields of
+    /// `seq_state`. Names that do not fit into their field and telegrams
+    /// that do not fit into one message are rejected instead of cut off.
+    fn from_sci(telegram: &SCITelegram, seq_state: SequenceState) -> Result<Self, RastaError>;
+}
+
+#[cfg(feature = "rasta")]
+impl MessageFromSci for Message {
+    fn from_sci(telegram: &SCITelegram, seq_state: SequenceState) -> Result<Self, RastaError> {
+        for name in [&telegram.sender, &telegram.receiver] {
+            if SciName::new(name).is_err() {
+                return Err(SciError::NameTooLong(name.clone()).into());
+            }
+        }
+        Message::data_message(
+            seq_state.receiver,
+            seq_state.sender,
+            seq_state.sequence_number,
+            seq_state.confirmed_sequence_number,
+            seq_state.timestamp,
+            seq_state.confirmed_timestamp,
+            &Vec::from(telegram.clone()),
+        )
+    }
+}
+
 /// The SCI equivalent of [`rasta_rs::RastaCommand`].
 #[cfg(feature = "rasta")]
 #[derive(Clone)]
@@ -633,113 +1466,1471 @@ pub enum SCICommand {
     Disconnect,
 }
 
+/// Provides the current status of an element. Registered with
+/// [`SCIListener::set_status_provider`], it is used to answer
+/// status requests automatically.
+pub trait StatusProvider {
+    /// Returns the status telegrams that are sent between
+    /// Status Begin and Status Finish.
+    fn status(&mut self, sender: &str, receiver: &str) -> Vec<SCITelegram>;
+}
+
+impl<F> StatusProvider for F
+where
+    F: FnMut(&str, &str) -> Vec<SCITelegram>,
+{
+    fn status(&mut self, sender: &str, receiver: &str) -> Vec<SCITelegram> {
+        self(sender, receiver)
+    }
+}
+
+/// Answers an initialisation request with Status Begin, the status
+/// of `provider` and Status Finish. Other telegrams are not answered.
+#[cfg(feature = "rasta")]
+fn status_responses(
+    name: &str,
+    provider: &mut dyn StatusProvider,
+    telegram: &SCITelegram,
+) -> Vec<SCITelegram> {
+    if telegram.message_type != SCIMessageType::pdi_initialisation_request() {
+        return Vec::new();
+    }
+    let protocol_type = telegram.protocol_type;
+    let peer = telegram.sender.as_str();
+    let mut responses = vec![SCITelegram::initialisation_response(
+        protocol_type,
+        name,
+        peer,
+    )];
+    responses.append(&mut provider.status(name, peer));
+    responses.push(SCITelegram::initialisation_completed(
+        protocol_type,
+        name,
+        peer,
+    ));
+    responses
+}
+
 /// A listening SCI endpoint built on top of [`RastaListener`].
-/// [`SCIPListener::listen`] follows the same conventions as
+/// [`SCIListener::listen`] follows the same conventions as
 /// [`RastaListener::listen`].
+///
+/// If a [`StatusProvider`] is registered, status requests are answered
+/// automatically with Status Begin, the provided status telegrams and
+/// Status Finish. The callback still observes the request.
+///
+/// In managed mode (see [`SCIListener::set_managed`]), the listener
+/// answers the PDI protocol by itself and the callback only receives
+/// application telegrams.
+///
+/// Close telegrams of a peer do not end [`SCIListener::listen`]. They are
+/// recorded with their reason in the [`SCIListener::incidents`].
 #[cfg(feature = "rasta")]
 pub struct SCIListener {
     listener: RastaListener,
     name: String,
+    status_provider: Option<Box<dyn StatusProvider + Send>>,
+    negotiated_version: u8,
+    journal: Option<Journal>,
+    baseline: BaselineProfile,
+    allowlist: Option<Allowlist>,
+    strict: bool,
+    peer: Option<String>,
+    authorizer: Authorizer,
+    pdi: Option<PdiStateMachine>,
+    version_check: Option<VersionCheck>,
+    history: History,
+    stats: TelegramStats,
+    raw_data_handler: Option<SCIRawDataResponder>,
+    parse_error_handler: Option<SCIParseErrorHandler>,
+    unknown_sender_handler: Option<SCIUnknownSenderHandler>,
+    diagnostic_sink: Option<Arc<dyn DiagnosticSink>>,
+    availability: Availability,
 }
 
 #[cfg(feature = "rasta")]
 impl SCIListener {
     pub fn new(listener: RastaListener, name: String) -> Self {
-        Self { listener, name }
+        let memory = listener.config().memory;
+        Self {
+            listener,
+            name,
+            status_provider: None,
+            negotiated_version: SCI_VERSION,
+            journal: None,
+            baseline: BaselineProfile::default(),
+            allowlist: None,
+            strict: false,
+            peer: None,
+            authorizer: Authorizer::default(),
+            pdi: None,
+            version_check: None,
+            history: History::with_max_peers(memory.history_length, memory.history_peers),
+            stats: TelegramStats::default(),
+            raw_data_handler: None,
+            parse_error_handler: None,
+            unknown_sender_handler: None,
+            diagnostic_sink: None,
+            availability: Availability::default(),
+        }
+    }
+
+    /// Adds an authorization rule for telegrams of `message_type`.
+    /// Rejected telegrams do not reach the status provider or the callback.
+    pub fn authorize<F>(
+        &mut self,
+        protocol_type: ProtocolType,
+        message_type: SCIMessageType,
+        hook: F,
+    ) where
+        F: FnMut(&SCITelegram, &PeerContext) -> Decision + Send + 'static,
+    {
+        self.authorizer.add(protocol_type, message_type, hook);
+    }
+
+    /// Adds an authorization rule for all telegrams.
+    pub fn authorize_all<F>(&mut self, hook: F)
+    where
+        F: FnMut(&SCITelegram, &PeerContext) -> Decision + Send + 'static,
+    {
+        self.authorizer.add_for_all(hook);
+    }
+
+    /// In strict mode, telegrams whose sender differs from the peer that
+    /// started the connection with a version check are answered with a
+    /// close telegram ([`SCICloseReason::ContentTelegramError`]) and
+    /// not passed to the callback. The peer is forgotten when the RaSTA
+    /// connection ends, so the next connection may come from another element.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    pub fn is_strict(&self) -> bool {
+        self.strict
+    }
+
+    /// Only accepts telegrams from peers on `allowlist`. The list can
+    /// be changed through another clone while the listener is running.
+    pub fn set_allowlist(&mut self, allowlist: Allowlist) {
+        self.allowlist.replace(allowlist);
+    }
+
+    pub fn baseline_profile(&self) -> BaselineProfile {
+        self.baseline
+    }
+
+    /// Selects the baseline of the peers. Telegrams the baseline
+    /// does not support are dropped.
+    pub fn set_baseline_profile(&mut self, baseline: BaselineProfile) {
+        self.baseline = baseline;
+        if let Some(pdi) = &mut self.pdi {
+            pdi.set_baseline(baseline);
+        }
     }
 
     pub fn name(&self) -> &str {
         &self.name
     }
 
-    pub fn listen<F>(&mut self, mut on_receive: F) -> Result<(), RastaError>
+    /// Controls the tracing of the underlying RaSTA messages,
+    /// see [`RastaListener::trace`].
+    pub fn trace(&self) -> TraceControl {
+        self.listener.trace()
+    }
+
+    /// The incidents of the underlying RaSTA listener,
+    /// including close telegrams of the peers.
+    pub fn incidents(&self) -> IncidentLog {
+        self.listener.incidents()
+    }
+
+    /// The acknowledgement latencies of the current peer of the
+    /// underlying RaSTA listener, see [`RastaListener::ack_latency`].
+    pub fn ack_latency(&self) -> AckLatency {
+        self.listener.ack_latency()
+    }
+
+    /// A handle to stop the listener from another thread,
+    /// see [`RastaListener::shutdown_handle`].
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.listener.shutdown_handle()
+    }
+
+    /// The SCI version announced by the peer in its version check.
+    /// Pass it to the `try_from_versioned` parsers of payload types.
+    pub fn negotiated_version(&self) -> u8 {
+        self.negotiated_version
+    }
+
+    /// Records all received and sent telegrams in `journal`. Its
+    /// capacity is reduced to
+    /// [`MemoryBudget::journal_entries`](rasta_rs::MemoryBudget::journal_entries)
+    /// if it is larger.
+    pub fn set_journal(&mut self, journal: Journal) {
+        journal.limit_capacity(self.listener.config().memory.journal_entries);
+        self.journal.replace(journal);
+    }
+
+    /// A handle to the recent telegrams exchanged with each peer.
+    pub fn history(&self) -> History {
+        self.history.clone()
+    }
+
+    /// Replaces the history, e.g. to keep more telegrams per peer
+    /// than [`history::DEFAULT_HISTORY_LENGTH`].
+    pub fn set_history(&mut self, history: History) {
+        self.history = history;
+    }
+
+    /// A handle to the counters of the telegrams exchanged so far.
+    pub fn stats(&self) -> TelegramStats {
+        self.stats.clone()
+    }
+
+    /// Replaces the counters, e.g. to aggregate several endpoints.
+    pub fn set_stats(&mut self, stats: TelegramStats) {
+        self.stats = stats;
+    }
+
+    pub fn set_status_provider<P>(&mut self, provider: P)
     where
-        F: FnMut(SCITelegram) -> Option<SCITelegram>,
+        P: StatusProvider + Send + 'static,
     {
-        self.listener.listen(|data| {
-            if let Some(response) = (on_receive)(SCITelegram::try_from(data.data()).unwrap()) {
-                let data: Vec<u8> = response.into();
-                Some(data)
-            } else {
-                None
+        self.status_provider.replace(Box::new(provider));
+    }
+
+    /// Enables managed mode. Version checks are answered using the
+    /// version and checksum of `config`, initialisation requests with
+    /// the status of the [`StatusProvider`] (or no status if none is
+    /// registered), and close and timeout telegrams are consumed.
+    /// None of these telegrams reach the callback. Telegrams that are
+    /// not allowed in the current [`PdiState`] are answered by closing
+    /// the PDI with [`SCICloseReason::ProtocolError`].
+    pub fn set_managed(&mut self, config: ManagedConfig) {
+        self.pdi.replace(
+            PdiStateMachine::new(PdiRole::Responder, &self.name, config)
+                .with_baseline(self.baseline),
+        );
+    }
+
+    pub fn is_managed(&self) -> bool {
+        self.pdi.is_some()
+    }
+
+    /// The state of the PDI in managed mode.
+    pub fn pdi_state(&self) -> Option<PdiState> {
+        self.pdi.as_ref().map(PdiStateMachine::state)
+    }
+
+    /// Answers version checks using `check` without entering managed
+    /// mode. The version checks do not reach the callback. In managed
+    /// mode, version checks are answered according to the
+    /// [`ManagedConfig`] instead.
+    pub fn set_version_check(&mut self, check: VersionCheck) {
+        self.version_check.replace(check);
+    }
+
+    /// A snapshot of the names, protocol settings and RaSTA endpoint of the listener.
+    pub fn describe(&self) -> SCIEndpointDescriptor {
+        SCIEndpointDescriptor {
+            name: self.name.clone(),
+            peer: self.peer.clone(),
+            baseline: self.baseline,
+            negotiated_version: self.negotiated_version,
+            pdi_state: self.pdi_state(),
+            rasta: self.listener.describe(),
+        }
+    }
+
+    /// Hands received data that is not an SCI telegram (see [`is_sci_telegram`])
+    /// to `handler` instead of dropping it. The returned data is sent back
+    /// to the peer as is. This allows plain RaSTA data to be exchanged
+    /// with a neighbouring system over the same connection.
+    pub fn set_raw_data_handler<F>(&mut self, handler: F)
+    where
+        F: FnMut(&[u8]) -> Vec<Vec<u8>> + Send + 'static,
+    {
+        self.raw_data_handler.replace(Box::new(handler));
+    }
+
+    /// Passes received telegrams that cannot be parsed, e.g. because they
+    /// are too short or of an unknown message type, to `handler`.
+    /// Such telegrams are dropped and the connection stays open.
+    pub fn set_parse_error_handler<F>(&mut self, handler: F)
+    where
+        F: FnMut(&SciError, &[u8]) + Send + 'static,
+    {
+        self.parse_error_handler.replace(Box::new(handler));
+    }
+
+    /// Passes telegrams from senders that are not on the allowlist (see
+    /// [`SCIListener::set_allowlist`]) to `handler`, together with the raw
+    /// data they were parsed from, before they are dropped.
+    pub fn set_unknown_sender_handler<F>(&mut self, handler: F)
+    where
+        F: FnMut(&SCITelegram, &[u8]) + Send + 'static,
+    {
+        self.unknown_sender_handler.replace(Box::new(handler));
+    }
+
+    /// Reports anomalies of received telegrams, such as unknown trailing
+    /// bytes, to `sink`. Such telegrams are handled as usual.
+    pub fn set_diagnostic_sink(&mut self, sink: impl DiagnosticSink + 'static) {
+        self.diagnostic_sink.replace(Arc::new(sink));
+    }
+
+    /// A handle to the availability of the listener and its peer. Keep a
+    /// clone to change the availability while listening, or to subscribe
+    /// to the availability announced by the peer.
+    pub fn availability(&self) -> Availability {
+        self.availability.clone()
+    }
+
+    /// Changes the availability of the listener. Once the PDI with the
+    /// peer is up (or, outside of managed mode, once the peer sent a
+    /// version check), every change is announced with a PDI Available or
+    /// Not Available telegram together with the next response or tick.
+    pub fn set_availability(&self, availability: SCIAvailability) {
+        self.availability.set(availability);
+    }
+
+    /// Handles incoming telegrams. `on_receive` may respond with any number
+    /// of telegrams, e.g. a [`Vec`] to send a rejection followed by a status,
+    /// or an [`Option`] for at most one response.
+    pub fn listen<F, I>(&mut self, on_receive: F) -> Result<(), RastaError>
+    where
+        F: FnMut(SCITelegram) -> I,
+        I: IntoIterator<Item = SCITelegram>,
+    {
+        self.serve(None, on_receive, Vec::new)
+    }
+
+    /// Like [`SCIListener::listen`], but additionally calls `on_tick` at
+    /// most once per `interval` while the peer is connected, interleaved
+    /// with the processing of its heartbeats. The returned telegrams are
+    /// sent to the peer, which allows cyclic behaviour such as periodic
+    /// status reports on the listener side.
+    pub fn listen_with_tick<F, I, T, J>(
+        &mut self,
+        interval: Duration,
+        on_receive: F,
+        on_tick: T,
+    ) -> Result<(), RastaError>
+    where
+        F: FnMut(SCITelegram) -> I,
+        I: IntoIterator<Item = SCITelegram>,
+        T: FnMut() -> J,
+        J: IntoIterator<Item = SCITelegram>,
+    {
+        self.serve(Some(interval), on_receive, on_tick)
+    }
+
+    fn serve<F, I, T, J>(
+        &mut self,
+        tick_interval: Option<Duration>,
+        mut on_receive: F,
+        mut on_tick: T,
+    ) -> Result<(), RastaError>
+    where
+        F: FnMut(SCITelegram) -> I,
+        I: IntoIterator<Item = SCITelegram>,
+        T: FnMut() -> J,
+        J: IntoIterator<Item = SCITelegram>,
+    {
+        // Every RaSTA connection starts a new handshake with its own peer
+        let reconnected = Arc::new(AtomicBool::new(false));
+        let hook_reconnected = reconnected.clone();
+        self.listener.set_event_hook(move |event| {
+            if matches!(
+                event,
+                ListenerEvent::ConnectionOpened(_) | ListenerEvent::Disconnected(..)
+            ) {
+                hook_reconnected.store(true, Ordering::Relaxed);
             }
-        })
+        });
+        let name = &self.name;
+        let status_provider = &mut self.status_provider;
+        let negotiated_version = &mut self.negotiated_version;
+        let journal = &self.journal;
+        let history = &self.history;
+        let stats = &self.stats;
+        let record = |direction: Direction, telegram: &SCITelegram| {
+            logging::telegram(direction, telegram);
+            if let Some(journal) = journal {
+                journal.record(direction, telegram);
+            }
+            history.record(direction, telegram);
+            stats.record(direction, telegram);
+        };
+        let baseline = self.baseline;
+        let allowlist = &self.allowlist;
+        let strict = self.strict;
+        let peer = &mut self.peer;
+        let authorizer = &mut self.authorizer;
+        let pdi = &mut self.pdi;
+        let version_check = &self.version_check;
+        let raw_data_handler = &mut self.raw_data_handler;
+        let parse_error_handler = &mut self.parse_error_handler;
+        let unknown_sender_handler = &mut self.unknown_sender_handler;
+        let diagnostic_sink = &self.diagnostic_sink;
+        let availability = &self.availability;
+        let incidents = self.listener.incidents();
+        let local_id = self.listener.id();
+        let t_max = self.listener.config().t_max;
+        let handle = |data: Message| {
+            if reconnected.swap(false, Ordering::Relaxed) {
+                peer.take();
+            }
+            if let Some(handler) = raw_data_handler {
+                if !is_sci_telegram(data.data()) {
+                    return handler(data.data());
+                }
+            }
+            let telegram = match SCITelegram::try_from(data.data()) {
+                Ok(telegram) => telegram,
+                Err(error) => {
+                    event!(warn, %error, "Dropping malformed telegram");
+                    if let Some(handler) = parse_error_handler {
+                        handler(&error, data.data());
+                    }
+                    return Vec::new();
+                }
+            };
+            record(Direction::Incoming, &telegram);
+            if let Some(sink) = diagnostic_sink {
+                diagnostics::report(&telegram, &**sink);
+            }
+            if let Some(allowlist) = allowlist {
+                if !allowlist.allows_sender(&telegram.sender) {
+                    event!(
+                        warn,
+                        sender = telegram.sender.as_str(),
+                        "Dropping telegram from unknown peer"
+                    );
+                    if let Some(handler) = unknown_sender_handler {
+                        handler(&telegram, data.data());
+                    }
+                    return Vec::new();
+                }
+            }
+            if telegram.message_type == SCIMessageType::pdi_close() {
+                incidents.record(pdi::close_incident(
+                    &data,
+                    &telegram,
+                    local_id,
+                    RastaConnectionState::Up,
+                    t_max,
+                ));
+            }
+            if strict && peer.as_ref().is_some_and(|peer| *peer != telegram.sender) {
+                event!(
+                    warn,
+                    sender = telegram.sender.as_str(),
+                    expected = ?peer,
+                    "Rejecting telegram from unexpected sender"
+                );
+                let close = SCITelegram::close(
+                    telegram.protocol_type,
+                    name,
+                    &telegram.sender,
+                    SCICloseReason::ContentTelegramError,
+                );
+                record(Direction::Outgoing, &close);
+                return vec![Vec::from(close)];
+            }
+            // The first version check decides the peer of the connection
+            if telegram.message_type == SCIMessageType::pdi_version_check() && peer.is_none() {
+                peer.replace(telegram.sender.clone());
+            }
+            if telegram.validate_baseline(baseline).is_err() {
+                return Vec::new();
+            }
+            let context = PeerContext {
+                local_name: name,
+                expected_peer: peer.as_deref(),
+                negotiated_version: *negotiated_version,
+                baseline,
+            };
+            match authorizer.decide(&telegram, &context) {
+                Decision::Accept => {}
+                Decision::Reject => return Vec::new(),
+                Decision::RejectAndReport(report) => {
+                    record(Direction::Outgoing, &report);
+                    return vec![Vec::from(report)];
+                }
+            }
+            if let Some(version) = telegram.sci_version() {
+                *negotiated_version = version;
+            }
+            availability.observe(&telegram);
+            let protocol_type = telegram.protocol_type;
+            let mut responses = Vec::new();
+            let deliver = match pdi.as_mut() {
+                Some(pdi) => {
+                    let sender = telegram.sender.clone();
+                    let status = || {
+                        status_provider
+                            .as_mut()
+                            .map(|provider| provider.status(name, &sender))
+                            .unwrap_or_default()
+                    };
+                    match pdi.handle(&telegram, status) {
+                        PdiOutcome::Deliver => true,
+                        PdiOutcome::Respond(mut telegrams) => {
+                            responses.append(&mut telegrams);
+                            false
+                        }
+                        #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+                        PdiOutcome::Close { telegram, error } => {
+                            event!(info, %error, "Closing PDI");
+                            responses.push(telegram);
+                            peer.take();
+                            false
+                        }
+                        PdiOutcome::PeerClosed(_) => {
+                            peer.take();
+                            false
+                        }
+                    }
+                }
+                None if telegram.message_type == SCIMessageType::pdi_version_check()
+                    && version_check.is_some() =>
+                {
+                    let check = version_check.as_ref().unwrap();
+                    match check.respond(name, &telegram, baseline) {
+                        Ok(response) => responses.push(response),
+                        #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+                        Err(error) => {
+                            event!(warn, %error, "Closing PDI");
+                            responses.push(SCITelegram::close(
+                                telegram.protocol_type,
+                                name,
+                                &telegram.sender,
+                                SCICloseReason::ProtocolError,
+                            ));
+                        }
+                    }
+                    false
+                }
+                None => {
+                    if let Some(provider) = status_provider.as_mut() {
+                        responses.append(&mut status_responses(name, provider.as_mut(), &telegram));
+                    }
+                    true
+                }
+            };
+            if deliver {
+                responses.extend((on_receive)(telegram));
+            }
+            let up = pdi
+                .as_ref()
+                .map_or(peer.is_some(), |pdi| pdi.state() == PdiState::Up);
+            availability.connect(
+                peer.as_deref()
+                    .filter(|_| up)
+                    .map(|peer| (peer, protocol_type)),
+            );
+            responses.extend(availability.announcement(name));
+            responses.retain(|response| response.validate_baseline(baseline).is_ok());
+            for response in responses.iter() {
+                if let Some(pdi) = pdi.as_mut() {
+                    pdi.sent(response);
+                }
+                record(Direction::Outgoing, response);
+            }
+            responses
+                .into_iter()
+                .map(Vec::<u8>::from)
+                .collect::<Vec<_>>()
+        };
+        let tick = || {
+            let telegrams: Vec<SCITelegram> = availability
+                .announcement(name)
+                .into_iter()
+                .chain(on_tick())
+                .filter(|telegram| telegram.validate_baseline(baseline).is_ok())
+                .collect();
+            for telegram in telegrams.iter() {
+                record(Direction::Outgoing, telegram);
+            }
+            telegrams
+                .into_iter()
+                .map(Vec::<u8>::from)
+                .collect::<Vec<_>>()
+        };
+        // Without a tick interval of the application, every message of the
+        // peer is an opportunity to announce a change of the availability
+        self.listener
+            .listen_with_tick(tick_interval.unwrap_or(Duration::ZERO), handle, tick)
     }
 }
 
+/// The name used for [`SCIListener`] in earlier documentation.
+#[cfg(feature = "rasta")]
+#[deprecated(note = "use `SCIListener` (or `sci_rs::prelude`) instead")]
+pub type SCIPListener = SCIListener;
+
+/// The name used for [`SCIConnection`] in earlier documentation.
+#[cfg(feature = "rasta")]
+#[deprecated(note = "use `SCIConnection` (or `sci_rs::prelude`) instead")]
+pub type SCIPConnection = SCIConnection;
+
+/// A hook that observes every telegram received by an [`SCIConnection`].
+#[cfg(feature = "rasta")]
+pub type SCIReceiveHook = Box<dyn FnMut(&SCITelegram) + Send>;
+
+/// Receives the data of an [`SCIConnection`] that is not an SCI telegram.
+#[cfg(feature = "rasta")]
+pub type SCIRawDataHandler = Box<dyn FnMut(&[u8]) + Send>;
+
+/// Answers the data received by an [`SCIListener`] that is not an SCI telegram.
+#[cfg(feature = "rasta")]
+type SCIRawDataResponder = Box<dyn FnMut(&[u8]) -> Vec<Vec<u8>> + Send>;
+
+/// Receives the telegrams an [`SCIListener`] failed to parse, together with
+/// the raw data they were parsed from.
+#[cfg(feature = "rasta")]
+pub type SCIParseErrorHandler = Box<dyn FnMut(&SciError, &[u8]) + Send>;
+
+/// Receives the telegrams of senders an endpoint does not know, together
+/// with the raw data they were parsed from, see
+/// [`SCIListener::set_unknown_sender_handler`] and
+/// [`SCIConnection::set_unknown_sender_handler`].
+#[cfg(feature = "rasta")]
+pub type SCIUnknownSenderHandler = Box<dyn FnMut(&SCITelegram, &[u8]) + Send>;
+
 /// A sending SCI endpoint built on top of [`RastaConnection`].
-/// [`SCIPConnection::run`] follows the same conventions as
+/// [`SCIConnection::run`] follows the same conventions as
 /// [`RastaConnection::run`] but using the [`SCICommand`] type
 /// for control flow.
+///
+/// Receive hooks registered with [`SCIConnection::add_receive_hook`]
+/// see every incoming telegram, including those that arrive before
+/// [`SCIConnection::run`] starts. Such early telegrams are buffered
+/// and passed to the first invocations of the `run` callback.
+///
+/// A close telegram or disconnection request of the peer ends
+/// [`SCIConnection::run`] with [`SciError::PdiClosed`] carrying the
+/// close reason or [`SciError::PeerDisconnected`], which can be recovered
+/// from the returned [`RastaError`] with [`SciError::from_rasta_error`].
+/// The same errors are returned by [`SCIConnection::receive_telegram`]
+/// and the [`TelegramStream`]. Receive hooks still observe close
+/// telegrams, which are also recorded in the [`SCIConnection::incidents`].
+///
+/// In managed mode (see [`SCIConnection::set_managed`]), opening the
+/// connection also starts the PDI, and only application telegrams are
+/// returned to the application.
+///
+/// Every exchange with the peer is reported to the connection's
+/// [`Watchdog`], see [`SCIConnection::watchdog`].
 #[cfg(feature = "rasta")]
 pub struct SCIConnection {
     conn: RastaConnection,
     name: String,
-    sci_name_rasta_id_mapping: HashMap<String, RastaId>,
+    sci_name_rasta_id_mapping: PeerMapping,
+    receive_hooks: Vec<SCIReceiveHook>,
+    raw_data_handler: Option<SCIRawDataHandler>,
+    negotiated_version: u8,
+    journal: Option<Journal>,
+    baseline: BaselineProfile,
+    outgoing: (SyncSender<QueuedTelegram>, Receiver<QueuedTelegram>),
+    next_tag: Arc<Mutex<u64>>,
+    last_sent: Option<SendTag>,
+    strict: bool,
+    peer: Option<String>,
+    authorizer: Authorizer,
+    pdi: Option<PdiStateMachine>,
+    pub(crate) pending: VecDeque<SCITelegram>,
+    history: History,
+    stats: TelegramStats,
+    watchdog: Watchdog,
+    availability: Availability,
+    unknown_sender_handler: Option<SCIUnknownSenderHandler>,
+    diagnostic_sink: Option<Arc<dyn DiagnosticSink>>,
 }
 
 #[cfg(feature = "rasta")]
 impl SCIConnection {
+    /// Wraps `conn`, which may either still be down or already be up.
+    /// A connection that is down is opened on first use, using
+    /// `sci_name_rasta_id_mapping` to find the RaSTA ID of the peer.
+    /// An open connection is used as is.
     pub fn try_new(
         conn: RastaConnection,
         name: String,
-        sci_name_rasta_id_mapping: HashMap<String, RastaId>,
+        sci_name_rasta_id_mapping: impl Into<PeerMapping>,
     ) -> Result<Self, RastaError> {
-        if conn.connection_state_request() == RastaConnectionState::Down {
+        if matches!(
+            conn.connection_state_request(),
+            RastaConnectionState::Down | RastaConnectionState::Up
+        ) {
+            let memory = conn.config().memory;
             Ok(Self {
                 conn,
                 name,
-                sci_name_rasta_id_mapping,
+                sci_name_rasta_id_mapping: sci_name_rasta_id_mapping.into(),
+                receive_hooks: Vec::new(),
+                raw_data_handler: None,
+                negotiated_version: SCI_VERSION,
+                journal: None,
+                baseline: BaselineProfile::default(),
+                outgoing: mpsc::sync_channel(memory.send_queue.max(1)),
+                next_tag: Arc::default(),
+                last_sent: None,
+                strict: false,
+                peer: None,
+                authorizer: Authorizer::default(),
+                pdi: None,
+                pending: VecDeque::new(),
+                history: History::with_max_peers(memory.history_length, memory.history_peers),
+                stats: TelegramStats::default(),
+                watchdog: Watchdog::default(),
+                availability: Availability::default(),
+                unknown_sender_handler: None,
+                diagnostic_sink: None,
             })
         } else {
             Err(RastaError::StateError)
         }
     }
 
+    /// Wraps a connection that was already opened elsewhere, e.g. by
+    /// a custom handshake. Since the connection is never opened again,
+    /// no mapping of SCI names to RaSTA IDs is needed.
+    pub fn from_open_connection(conn: RastaConnection, name: String) -> Result<Self, RastaError> {
+        if conn.connection_state_request() == RastaConnectionState::Up {
+            Self::try_new(conn, name, PeerMapping::new())
+        } else {
+            Err(RastaError::StateError)
+        }
+    }
+
     pub fn name(&self) -> &str {
         &self.name
     }
 
-    pub fn send_telegram(&mut self, telegram: SCITelegram) -> Result<(), RastaError> {
-        if self.conn.connection_state_request() == RastaConnectionState::Down {
-            let receiver = self
-                .sci_name_rasta_id_mapping
-                .get(&telegram.receiver)
-                .ok_or(RastaError::Other("Missing Rasta ID".to_string()))?;
-            self.conn.open_connection(*receiver)?;
+    /// A handle to the mapping of SCI names to RaSTA IDs. Entries can be
+    /// added or removed through it at any time; they are used the next
+    /// time a connection has to be opened.
+    pub fn peer_mapping(&self) -> PeerMapping {
+        self.sci_name_rasta_id_mapping.clone()
+    }
+
+    /// Passes received telegrams whose sender is not in the peer mapping
+    /// to `handler`, together with the raw data they were parsed from.
+    /// They are handled as usual afterwards. Connections without a
+    /// mapping, see [`SCIConnection::from_open_connection`], report nothing.
+    pub fn set_unknown_sender_handler<F>(&mut self, handler: F)
+    where
+        F: FnMut(&SCITelegram, &[u8]) + Send + 'static,
+    {
+        self.unknown_sender_handler.replace(Box::new(handler));
+    }
+
+    /// Controls the tracing of the underlying RaSTA messages,
+    /// see [`RastaConnection::trace`].
+    pub fn trace(&self) -> TraceControl {
+        self.conn.trace()
+    }
+
+    /// The incidents of the underlying RaSTA connection,
+    /// including close telegrams of the peer.
+    pub fn incidents(&self) -> IncidentLog {
+        self.conn.incidents()
+    }
+
+    /// The acknowledgement latencies of the underlying RaSTA
+    /// connection, see [`RastaConnection::ack_latency`].
+    pub fn ack_latency(&self) -> AckLatency {
+        self.conn.ack_latency()
+    }
+
+    /// The SCI version announced by the peer in its version response.
+    /// Pass it to the `try_from_versioned` parsers of payload types.
+    pub fn negotiated_version(&self) -> u8 {
+        self.negotiated_version
+    }
+
+    /// Records all received and sent telegrams in `journal`. Its
+    /// capacity is reduced to
+    /// [`MemoryBudget::journal_entries`](rasta_rs::MemoryBudget::journal_entries)
+    /// if it is larger.
+    pub fn set_journal(&mut self, journal: Journal) {
+        journal.limit_capacity(self.conn.config().memory.journal_entries);
+        self.journal.replace(journal);
+    }
+
+    /// A handle to the recent telegrams exchanged with each peer.
+    pub fn history(&self) -> History {
+        self.history.clone()
+    }
+
+    /// Replaces the history, e.g. to keep more telegrams per peer
+    /// than [`history::DEFAULT_HISTORY_LENGTH`].
+    pub fn set_history(&mut self, history: History) {
+        self.history = history;
+    }
+
+    /// A handle to the counters of the telegrams exchanged so far.
+    pub fn stats(&self) -> TelegramStats {
+        self.stats.clone()
+    }
+
+    /// Replaces the counters, e.g. to aggregate several endpoints.
+    pub fn set_stats(&mut self, stats: TelegramStats) {
+        self.stats = stats;
+    }
+
+    /// A handle to the liveness of the peer. Subscribe to it to learn
+    /// when the peer is late or lost while the socket is still open.
+    pub fn watchdog(&self) -> Watchdog {
+        self.watchdog.clone()
+    }
+
+    /// Replaces the watchdog, e.g. to share one between several
+    /// connections or to use other [`liveness::LivenessThresholds`].
+    pub fn set_watchdog(&mut self, watchdog: Watchdog) {
+        self.watchdog = watchdog;
+    }
+
+    /// A handle to the availability announced by the peer, e.g. to
+    /// subscribe to its changes.
+    pub fn availability(&self) -> Availability {
+        self.availability.clone()
+    }
+
+    /// Reports anomalies of received telegrams, such as unknown trailing
+    /// bytes, to `sink`. Such telegrams are handled as usual.
+    pub fn set_diagnostic_sink(&mut self, sink: impl DiagnosticSink + 'static) {
+        self.diagnostic_sink.replace(Arc::new(sink));
+    }
+
+    pub fn baseline_profile(&self) -> BaselineProfile {
+        self.baseline
+    }
+
+    /// Selects the baseline of the peer. Telegrams the baseline does
+    /// not support are rejected when sending and receiving.
+    pub fn set_baseline_profile(&mut self, baseline: BaselineProfile) {
+        self.baseline = baseline;
+        if let Some(pdi) = &mut self.pdi {
+            pdi.set_baseline(baseline);
         }
-        let data: Vec<u8> = telegram.into();
-        self.conn.send_data(data.as_slice())?;
-        Ok(())
     }
 
-    pub fn receive_telegram(&mut self) -> Result<SCITelegram, RastaError> {
-        let msg = self.conn.receive_message()?;
-        SCITelegram::try_from(msg.data()).map_err(|e| e.into())
+    /// In strict mode, telegrams whose sender is not the peer this
+    /// connection talks to are answered with a close telegram
+    /// ([`SCICloseReason::ContentTelegramError`]) and rejected with
+    /// [`SciError::UnexpectedSender`].
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    pub fn is_strict(&self) -> bool {
+        self.strict
+    }
+
+    /// Adds an authorization rule for telegrams of `message_type`.
+    /// Rejected telegrams are not passed to hooks or the application
+    /// and are reported as [`SciError::Unauthorized`].
+    pub fn authorize<F>(
+        &mut self,
+        protocol_type: ProtocolType,
+        message_type: SCIMessageType,
+        hook: F,
+    ) where
+        F: FnMut(&SCITelegram, &PeerContext) -> Decision + Send + 'static,
+    {
+        self.authorizer.add(protocol_type, message_type, hook);
     }
 
-    pub fn run<F>(&mut self, peer: &str, mut telegram_fn: F) -> Result<(), RastaError>
+    /// Adds an authorization rule for all telegrams.
+    pub fn authorize_all<F>(&mut self, hook: F)
     where
-        F: FnMut(Option<SCITelegram>) -> SCICommand,
+        F: FnMut(&SCITelegram, &PeerContext) -> Decision + Send + 'static,
     {
-        if self.conn.connection_state_request() == RastaConnectionState::Down {
-            let receiver = self
-                .sci_name_rasta_id_mapping
-                .get(peer)
-                .ok_or(RastaError::Other("Missing Rasta ID".to_string()))?;
-            self.conn.open_connection(*receiver)?;
+        self.authorizer.add_for_all(hook);
+    }
+
+    /// Enables managed mode. Whenever the RaSTA connection is opened,
+    /// the PDI is started with a version check and an initialisation
+    /// request using the data in `config`. Status telegrams received
+    /// during the initialisation are passed to the application afterwards.
+    ///
+    /// If the peer announces a different version, the PDI is closed and
+    /// [`SciError::PdiClosed`] is returned. If it announces a checksum other
+    /// than the one in `config`, the PDI is closed with
+    /// [`SCICloseReason::ChecksumMismatch`] and
+    /// [`SciError::ChecksumMismatch`] is returned. A close telegram of the
+    /// peer is reported as [`SciError::PdiClosed`]. Other protocol telegrams
+    /// are consumed. Telegrams that are not allowed in the current
+    /// [`PdiState`] close the PDI with [`SCICloseReason::ProtocolError`]
+    /// and are reported as [`SciError::PdiStateViolation`].
+    pub fn set_managed(&mut self, config: ManagedConfig) {
+        self.pdi.replace(
+            PdiStateMachine::new(PdiRole::Initiator, &self.name, config)
+                .with_baseline(self.baseline),
+        );
+    }
+
+    pub fn is_managed(&self) -> bool {
+        self.pdi.is_some()
+    }
+
+    /// The state of the PDI in managed mode.
+    pub fn pdi_state(&self) -> Option<PdiState> {
+        self.pdi.as_ref().map(PdiStateMachine::state)
+    }
+
+    /// A snapshot of the names, protocol settings and RaSTA endpoint of the connection.
+    pub fn describe(&self) -> SCIEndpointDescriptor {
+        SCIEndpointDescriptor {
+            name: self.name.clone(),
+            peer: self.peer.clone(),
+            baseline: self.baseline,
+            negotiated_version: self.negotiated_version,
+            pdi_state: self.pdi_state(),
+            rasta: self.conn.describe(),
         }
+    }
+
+    /// Registers a hook that is called for every received telegram when it
+    /// is decoded. Telegrams that arrive before they are asked for, e.g.
+    /// while waiting for a heartbeat response, are buffered undecoded, so
+    /// a hook registered before [`SCIConnection::run`] still observes them.
+    /// Only the telegrams of a managed PDI handshake are decoded while
+    /// opening the connection.
+    pub fn add_receive_hook<F>(&mut self, hook: F)
+    where
+        F: FnMut(&SCITelegram) + Send + 'static,
+    {
+        self.receive_hooks.push(Box::new(hook));
+    }
+
+    /// Hands received data that is not an SCI telegram (see [`is_sci_telegram`])
+    /// to `handler` instead of failing to decode it. Use
+    /// [`SCIConnection::send_raw_data`] to answer.
+    pub fn set_raw_data_handler<F>(&mut self, handler: F)
+    where
+        F: FnMut(&[u8]) + Send + 'static,
+    {
+        self.raw_data_handler.replace(Box::new(handler));
+    }
+
+    /// Sends `data` as a plain RaSTA data message, e.g. to a neighbouring
+    /// system that does not speak SCI. Unlike telegrams, raw data is not
+    /// queued behind the telegrams submitted through [`SCIConnection::sender`].
+    pub fn send_raw_data(&mut self, data: &[u8]) -> Result<(), RastaError> {
+        if let Some(peer) = self.peer.clone() {
+            self.open(&peer)?;
+        }
+        if self.conn.connection_state_request() != RastaConnectionState::Up {
+            return Err(RastaError::StateError);
+        }
+        self.conn.send_data(data)
+    }
+
+    /// Opens the underlying RaSTA connection to `peer` if it is not open yet.
+    /// Telegrams received before [`SCIConnection::run`] is called are buffered.
+    pub fn open(&mut self, peer: &str) -> Result<(), RastaError> {
+        if self.peer.as_deref() != Some(peer) {
+            self.peer.replace(peer.to_string());
+        }
+        if self.conn.connection_state_request() == RastaConnectionState::Down {
+            let receiver = self
+                .sci_name_rasta_id_mapping
+                .get(peer)
+                .ok_or(RastaError::Other("Missing Rasta ID".to_string()))?;
+            event!(info, peer, rasta_id = receiver, "Opening connection");
+            let opened = self.conn.open_connection(receiver);
+            self.supervise(opened)?;
+            if self.pdi.is_some() {
+                self.start_pdi(peer)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn start_pdi(&mut self, peer: &str) -> Result<(), RastaError> {
+        let Some(pdi) = self.pdi.as_mut() else {
+            return Ok(());
+        };
+        // A new RaSTA connection always starts with a closed PDI
+        pdi.reset();
+        let version_check = pdi.start(peer)?;
+        event!(info, peer, "Establishing PDI");
+        self.write_telegram(version_check)?;
+        while self.pdi_state() != Some(PdiState::Up) {
+            let received = self.conn.receive_message();
+            let msg = self.supervise(received)?;
+            if !matches!(msg.try_message_type(), Ok(MessageType::Data)) {
+                continue;
+            }
+            let telegram = self.decode(msg.data())?;
+            if telegram.message_type == SCIMessageType::pdi_close() {
+                self.report_close(&msg, &telegram);
+            }
+            if let Some(telegram) = self.advance_pdi(telegram)? {
+                self.keep_pending(telegram)?;
+            }
+        }
+        event!(info, peer, "PDI established");
+        Ok(())
+    }
+
+    /// Passes a received telegram to the PDI state machine in managed
+    /// mode and sends its responses. Returns the telegram if it is meant
+    /// for the application.
+    fn advance_pdi(&mut self, telegram: SCITelegram) -> Result<Option<SCITelegram>, RastaError> {
+        let Some(pdi) = self.pdi.as_mut() else {
+            return Ok(Some(telegram));
+        };
+        match pdi.handle(&telegram, Vec::new) {
+            PdiOutcome::Deliver => Ok(Some(telegram)),
+            PdiOutcome::Respond(responses) => {
+                for response in responses {
+                    self.write_telegram(response)?;
+                }
+                Ok(None)
+            }
+            PdiOutcome::Close { telegram, error } => {
+                self.write_telegram(telegram)?;
+                self.flush()?;
+                Err(error.into())
+            }
+            PdiOutcome::PeerClosed(error) => Err(error.into()),
+        }
+    }
+
+    /// Performs the version check with `peer` without entering managed
+    /// mode: sends a version check and waits up to
+    /// [`VersionCheck::timeout`] for the response, keeping other telegrams
+    /// for [`SCIConnection::poll_telegram`]. Returns the version of the
+    /// peer. If the peer reports different versions or its checksum does
+    /// not match the one of the checksum provider, the PDI is closed.
+    /// In managed mode, the version check is part of
+    /// [`SCIConnection::open`] and this fails with [`RastaError::StateError`].
+    pub fn version_check(&mut self, peer: &str, check: &VersionCheck) -> Result<u8, RastaError> {
+        if self.pdi.is_some() {
+            return Err(RastaError::StateError);
+        }
+        self.write_telegram(check.request(&self.name.clone(), peer))?;
+        self.flush()?;
+        let deadline = Instant::now() + check.timeout();
+        loop {
+            self.maintain()?;
+            let wait = deadline.saturating_duration_since(Instant::now());
+            if wait.is_zero() {
+                return Err(RastaError::Timeout);
+            }
+            let wait = wait.min(self.conn.time_until_heartbeat());
+            let Some(polled) = self.conn.poll_message(wait).transpose() else {
+                continue;
+            };
+            let msg = self.supervise(polled)?;
+            if !matches!(
+                msg.try_message_type(),
+                Ok(MessageType::Data | MessageType::DiscReq)
+            ) {
+                continue;
+            }
+            let Some(telegram) = self.accept(&msg)? else {
+                continue;
+            };
+            if telegram.message_type != SCIMessageType::pdi_version_response()
+                || telegram.sender.trim_end_matches('_') != peer
+            {
+                self.keep_pending(telegram)?;
+                continue;
+            }
+            return match check.verify(&telegram, self.baseline) {
+                Ok(version) => {
+                    self.negotiated_version = version;
+                    Ok(version)
+                }
+                Err(error) => {
+                    let reason = error
+                        .close_reason()
+                        .unwrap_or(SCICloseReason::ProtocolError);
+                    self.write_telegram(SCITelegram::close(
+                        telegram.protocol_type,
+                        &self.name.clone(),
+                        peer,
+                        reason,
+                    ))?;
+                    self.flush()?;
+                    Err(error.into())
+                }
+            };
+        }
+    }
+
+    /// Queues a telegram behind those queued through
+    /// [`SCIConnection::sender`] and sends all of them. Like
+    /// [`RastaConnection::send_data`], they are written at the next
+    /// receive or on [`SCIConnection::flush`].
+    pub fn send_telegram(&mut self, telegram: SCITelegram) -> Result<(), RastaError> {
+        self.validate_outgoing(&telegram)?;
+        // Make room in the queue first, so direct sends never overflow it
+        self.send_queued()?;
+        self.sender().send(telegram)?;
+        self.send_queued()?;
+        Ok(())
+    }
+
+    /// Telegrams are never split across RaSTA messages, so telegrams
+    /// exceeding the limits of the peer are rejected before they are queued.
+    fn validate_outgoing(&self, telegram: &SCITelegram) -> Result<(), RastaError> {
+        telegram.validate_payload_length()?;
+        telegram.validate_baseline(self.baseline)?;
+        self.conn.check_data_length(telegram.wire_length())
+    }
+
+    /// Sends `telegram` right away, bypassing the queue. Used for the
+    /// telegrams of the PDI protocol.
+    fn write_telegram(&mut self, telegram: SCITelegram) -> Result<(), RastaError> {
+        self.validate_outgoing(&telegram)?;
+        self.open(&telegram.receiver.clone())?;
+        if let Some(pdi) = &mut self.pdi {
+            pdi.sent(&telegram);
+        }
+        self.record(Direction::Outgoing, &telegram);
+        let data: Vec<u8> = telegram.into();
+        self.conn.send_data(data.as_slice())?;
+        Ok(())
+    }
+
+    /// The tag of the last queued telegram that was sent.
+    pub fn last_sent_tag(&self) -> Option<SendTag> {
+        self.last_sent
+    }
+
+    /// Replaces the underlying RaSTA connection, e.g. after the previous
+    /// one failed. The old connection is closed if it is still up.
+    /// Telegrams that are still queued are sent over the new connection,
+    /// which is opened (and its PDI started in managed mode) on first use.
+    pub fn reconnect(&mut self, conn: RastaConnection) -> Result<(), RastaError> {
+        if !matches!(
+            conn.connection_state_request(),
+            RastaConnectionState::Down | RastaConnectionState::Up
+        ) {
+            return Err(RastaError::StateError);
+        }
+        let _ = self.conn.close_connection();
+        self.conn = conn;
+        if let Some(pdi) = &mut self.pdi {
+            pdi.reset();
+        }
+        Ok(())
+    }
+
+    /// Writes all queued telegrams to the peer.
+    pub fn flush(&mut self) -> Result<(), RastaError> {
+        self.conn.flush()
+    }
+
+    /// Returns a handle that queues telegrams for this connection, e.g.
+    /// from another thread. Queued telegrams are sent while a
+    /// [`TelegramStream`] of this connection is polled.
+    pub fn sender(&self) -> SCITelegramSender {
+        SCITelegramSender {
+            tx: self.outgoing.0.clone(),
+            capacity: self.conn.config().memory.send_queue.max(1),
+            next_tag: self.next_tag.clone(),
+        }
+    }
+
+    /// Opens the connection to `peer` and returns an iterator over all
+    /// telegrams received from it. This is an alternative to
+    /// [`SCIConnection::run`] for channel-based applications.
+    pub fn telegram_stream(&mut self, peer: &str) -> Result<TelegramStream<'_>, RastaError> {
+        self.open(peer)?;
+        Ok(TelegramStream { conn: self })
+    }
+
+    /// Sends all telegrams queued through [`SCIConnection::sender`]
+    /// and returns their number.
+    fn send_queued(&mut self) -> Result<usize, RastaError> {
+        let mut sent = 0;
+        while let Ok((tag, telegram)) = self.outgoing.1.try_recv() {
+            debug_assert!(self.last_sent < Some(tag));
+            self.last_sent.replace(tag);
+            self.write_telegram(telegram)?;
+            sent += 1;
+        }
+        Ok(sent)
+    }
+
+    /// Waits up to `timeout` for the next telegram and returns `None`
+    /// if none arrived. Heartbeats are sent while waiting, so a caller-owned
+    /// loop of `poll_telegram` and [`send_telegram`](Self::send_telegram)
+    /// keeps the connection alive without blocking on the peer:
+    ///
+    /// ```no_run
+    /// # use std::time::Duration;
+    /// # use rasta_rs::RastaConnection;
+    /// # use sci_rs::SCIConnection;
+    /// # fn main() -> Result<(), rasta_rs::RastaError> {
+    /// # let conn = RastaConnection::try_new("127.0.0.1:8888", 42)?;
+    /// # let mapping = std::collections::HashMap::from([("S".to_string(), 1337)]);
+    /// let mut sci = SCIConnection::try_new(conn, "C".to_string(), mapping)?;
+    /// sci.open("S")?;
+    /// loop {
+    ///     if let Some(telegram) = sci.poll_telegram(Duration::from_millis(100))? {
+    ///         // React to the telegram, e.g. with `sci.send_telegram(...)`
+    ///     }
+    /// }
+    /// # }
+    /// ```
+    pub fn poll_telegram(&mut self, timeout: Duration) -> Result<Option<SCITelegram>, RastaError> {
+        if let Some(telegram) = self.pending.pop_front() {
+            return Ok(Some(telegram));
+        }
+        let deadline = Instant::now() + timeout;
+        loop {
+            self.maintain()?;
+            let wait = deadline
+                .saturating_duration_since(Instant::now())
+                .min(self.conn.time_until_heartbeat());
+            let msg = match self.conn.poll_message(wait).transpose() {
+                Some(polled) => self.supervise(polled)?,
+                None if Instant::now() < deadline => continue,
+                None => return Ok(None),
+            };
+            if !matches!(
+                msg.try_message_type(),
+                Ok(MessageType::Data | MessageType::DiscReq)
+            ) {
+                continue;
+            }
+            if let Some(telegram) = self.accept(&msg)? {
+                return Ok(Some(telegram));
+            }
+        }
+    }
+
+    /// Keeps `telegram` until the application asks for it.
+    fn keep_pending(&mut self, telegram: SCITelegram) -> Result<(), RastaError> {
+        let capacity = self.conn.config().memory.pending_messages;
+        if self.pending.len() >= capacity {
+            return Err(RastaError::BufferFull {
+                buffer: "buffer of pending telegrams",
+                capacity,
+            });
+        }
+        self.pending.push_back(telegram);
+        Ok(())
+    }
+
+    /// Sends a heartbeat if it is due, see [`RastaConnection::maintain`].
+    fn maintain(&mut self) -> Result<(), RastaError> {
+        self.watchdog.check();
+        let heartbeat = self.conn.maintain();
+        self.supervise(heartbeat)?;
+        if self.conn.connection_state_request() == RastaConnectionState::Closed {
+            return Err(SciError::PeerDisconnected.into());
+        }
+        Ok(())
+    }
+
+    pub fn receive_telegram(&mut self) -> Result<SCITelegram, RastaError> {
+        if let Some(telegram) = self.pending.pop_front() {
+            return Ok(telegram);
+        }
+        loop {
+            let received = self.conn.receive_message();
+            let msg = self.supervise(received)?;
+            if let Some(telegram) = self.accept(&msg)? {
+                return Ok(telegram);
+            }
+        }
+    }
+
+    /// Decodes the telegram carried by `msg`. A close telegram or a
+    /// disconnection request of the peer is reported as
+    /// [`SciError::PdiClosed`] or [`SciError::PeerDisconnected`].
+    /// Returns `None` if the telegram was consumed in managed mode.
+    pub(crate) fn accept(&mut self, msg: &Message) -> Result<Option<SCITelegram>, RastaError> {
+        if matches!(msg.try_message_type(), Ok(MessageType::DiscReq)) {
+            if let Some(peer) = &self.peer {
+                self.watchdog.mark_lost(peer);
+            }
+            if let Some(pdi) = &mut self.pdi {
+                pdi.reset();
+            }
+            return Err(SciError::PeerDisconnected.into());
+        }
+        if let Some(handler) = &mut self.raw_data_handler {
+            if !is_sci_telegram(msg.data()) {
+                handler(msg.data());
+                return Ok(None);
+            }
+        }
+        let telegram = self.decode(msg.data())?;
+        if telegram.message_type == SCIMessageType::pdi_close() {
+            self.report_close(msg, &telegram);
+            if self.pdi.is_none() {
+                return Err(pdi::close_error(&telegram).into());
+            }
+        }
+        self.advance_pdi(telegram)
+    }
+
+    /// Records a close telegram of the peer in the incidents
+    /// of the RaSTA connection.
+    fn report_close(&self, msg: &Message, close: &SCITelegram) {
+        self.conn.incidents().record(pdi::close_incident(
+            msg,
+            close,
+            self.conn.id(),
+            self.conn.connection_state_request(),
+            self.conn.config().t_max,
+        ));
+    }
+
+    /// Reports the outcome of an exchange with the peer to the watchdog.
+    pub(crate) fn supervise<T>(&self, result: Result<T, RastaError>) -> Result<T, RastaError> {
+        if let Some(peer) = &self.peer {
+            match &result {
+                Ok(_) => self.watchdog.observe(peer),
+                Err(_) => self.watchdog.mark_lost(peer),
+            }
+        }
+        result
+    }
+
+    fn record(&self, direction: Direction, telegram: &SCITelegram) {
+        logging::telegram(direction, telegram);
+        if let Some(journal) = &self.journal {
+            journal.record(direction, telegram);
+        }
+        self.history.record(direction, telegram);
+        self.stats.record(direction, telegram);
+    }
+
+    fn decode(&mut self, data: &[u8]) -> Result<SCITelegram, RastaError> {
+        let telegram = SCITelegram::try_from(data)?;
+        self.record(Direction::Incoming, &telegram);
+        if let Some(sink) = &self.diagnostic_sink {
+            diagnostics::report(&telegram, &**sink);
+        }
+        telegram.validate_baseline(self.baseline)?;
+        if let Some(handler) = &mut self.unknown_sender_handler {
+            let mapping = &self.sci_name_rasta_id_mapping;
+            if !mapping.is_empty() && !mapping.contains_sender(&telegram.sender) {
+                event!(
+                    warn,
+                    sender = telegram.sender.as_str(),
+                    "Telegram from a sender missing in the peer mapping"
+                );
+                handler(&telegram, data);
+            }
+        }
+        if let Some(peer) = self
+            .peer
+            .clone()
+            .filter(|peer| SciName::truncated(peer) != SciName::truncated(&telegram.sender))
+        {
+            if self.strict {
+                self.write_telegram(SCITelegram::close(
+                    telegram.protocol_type,
+                    &self.name.clone(),
+                    &peer,
+                    SCICloseReason::ContentTelegramError,
+                ))?;
+                self.flush()?;
+                return Err(SciError::UnexpectedSender {
+                    expected: peer,
+                    actual: telegram.sender,
+                }
+                .into());
+            }
+        }
+        let context = PeerContext {
+            local_name: &self.name,
+            expected_peer: self.peer.as_deref(),
+            negotiated_version: self.negotiated_version,
+            baseline: self.baseline,
+        };
+        match self.authorizer.decide(&telegram, &context) {
+            Decision::Accept => {}
+            Decision::Reject => {
+                return Err(SciError::Unauthorized(telegram.message_type.into()).into())
+            }
+            Decision::RejectAndReport(report) => {
+                self.write_telegram(report)?;
+                self.flush()?;
+                return Err(SciError::Unauthorized(telegram.message_type.into()).into());
+            }
+        }
+        if let Some(version) = telegram.sci_version() {
+            self.negotiated_version = version;
+        }
+        self.availability.observe(&telegram);
+        for hook in self.receive_hooks.iter_mut() {
+            hook(&telegram);
+        }
+        Ok(telegram)
+    }
+
+    /// Opens the connection to `peer` and calls `telegram_fn` with every
+    /// received telegram until it asks to disconnect. `telegram_fn` runs on
+    /// a separate thread, so heartbeats are sent whenever nothing was sent
+    /// for a [`heartbeat interval`](RastaConnection::heartbeat_interval),
+    /// however long it takes to decide. Telegrams that arrive in the
+    /// meantime are passed to it afterwards.
+    pub fn run<F>(&mut self, peer: &str, telegram_fn: F) -> Result<(), RastaError>
+    where
+        F: FnMut(Option<SCITelegram>) -> SCICommand + Send,
+    {
+        self.open(peer)?;
+        thread::scope(|scope| {
+            let (input_tx, input_rx) = mpsc::channel();
+            let (command_tx, command_rx) = mpsc::channel();
+            let mut telegram_fn = telegram_fn;
+            scope.spawn(move || {
+                for input in input_rx {
+                    if command_tx.send(telegram_fn(input)).is_err() {
+                        break;
+                    }
+                }
+            });
+            // Dropping the sender on return ends the thread
+            self.run_commands(&input_tx, &command_rx)
+        })
+    }
+
+    fn run_commands(
+        &mut self,
+        input: &mpsc::Sender<Option<SCITelegram>>,
+        commands: &Receiver<SCICommand>,
+    ) -> Result<(), RastaError> {
         let mut previous_data = None;
         loop {
-            match telegram_fn(previous_data.take()) {
+            if previous_data.is_none() {
+                if let Some(telegram) = self.pending.pop_front() {
+                    previous_data.replace(telegram);
+                } else if let Some(msg) = self.conn.take_buffered_message() {
+                    previous_data = self.accept(&msg)?;
+                }
+            }
+            if input.send(previous_data.take()).is_err() {
+                return Err(RastaError::Other("telegram_fn panicked".to_string()));
+            }
+            match self.await_command(commands)? {
                 SCICommand::Telegram(telegram) => {
                     self.send_telegram(telegram)?;
                     let telegram = self.receive_telegram()?;
                     previous_data.replace(telegram);
                 }
                 SCICommand::Wait => {
-                    self.conn.send_heartbeat()?;
-                    std::thread::sleep(RASTA_TIMEOUT_DURATION / 2);
+                    self.watchdog.check();
+                    let heartbeat = self.conn.send_heartbeat();
+                    self.supervise(heartbeat)?;
+                    if self.conn.connection_state_request() == RastaConnectionState::Closed {
+                        return Err(SciError::PeerDisconnected.into());
+                    }
+                    std::thread::sleep(self.conn.heartbeat_interval());
                 }
                 SCICommand::Disconnect => {
                     self.conn.close_connection()?;
@@ -749,4 +2940,987 @@ impl SCIConnection {
         }
         Ok(())
     }
+
+    /// Keeps the connection alive until `telegram_fn` has decided.
+    fn await_command(&mut self, commands: &Receiver<SCICommand>) -> Result<SCICommand, RastaError> {
+        loop {
+            match commands.recv_timeout(self.conn.time_until_heartbeat()) {
+                Ok(command) => return Ok(command),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(RastaError::Other("telegram_fn panicked".to_string()))
+                }
+            }
+            // Consume the answer to the previous heartbeat
+            // and keep telegrams for later
+            loop {
+                let polled = self.conn.poll_message(Duration::ZERO);
+                let Some(msg) = self.supervise(polled)? else {
+                    break;
+                };
+                if matches!(
+                    msg.try_message_type(),
+                    Ok(MessageType::Data | MessageType::DiscReq)
+                ) {
+                    if let Some(telegram) = self.accept(&msg)? {
+                        self.keep_pending(telegram)?;
+                    }
+                }
+            }
+            self.maintain()?;
+        }
+    }
+}
+
+#[cfg(all(test, feature = "scip"))]
+mod tests {
+    use crate::{SCITelegram, SciError, SCI_HEADER_LENGTH};
+
+    #[test]
+    fn test_short_telegrams() {
+        let data = vec![0x40, 0x01, 0x00];
+        for length in 0..SCI_HEADER_LENGTH {
+            let mut data = data.clone();
+            data.resize(length, b'_');
+            assert!(matches!(
+                SCITelegram::try_from(data.as_slice()),
+                Err(SciError::InvalidTelegramLength(l)) if l == length
+            ));
+        }
+    }
+
+    #[test]
+    fn test_parse_header() {
+        use std::borrow::Cow;
+
+        use crate::{parse_header, ProtocolType, SCIMessageType, SCI_NAME_LENGTH};
+
+        let name = "A".repeat(SCI_NAME_LENGTH);
+        let telegram = SCITelegram::version_check(ProtocolType::SCIProtocolP, "C", &name, 1);
+        let data = Vec::from(telegram);
+        let header = parse_header(&data).unwrap();
+        assert!(matches!(header.protocol_type, ProtocolType::SCIProtocolP));
+        assert_eq!(header.message_type, SCIMessageType::pdi_version_check());
+        assert_eq!(header.payload, [1]);
+        // Names are borrowed with their padding, a full-length name has none
+        assert!(matches!(header.sender, Cow::Borrowed(_)));
+        assert_eq!(
+            header.sender,
+            format!("C{}", "_".repeat(SCI_NAME_LENGTH - 1))
+        );
+        assert_eq!(header.receiver, name);
+
+        for length in 0..SCI_HEADER_LENGTH {
+            assert!(matches!(
+                parse_header(&data[..length]),
+                Err(SciError::InvalidTelegramLength(l)) if l == length
+            ));
+        }
+        let header = parse_header(&data[..SCI_HEADER_LENGTH]).unwrap();
+        assert!(header.payload.is_empty());
+    }
+
+    #[test]
+    fn test_format_telegram() {
+        let telegram = SCITelegram::version_check(crate::ProtocolType::SCIProtocolP, "C", "S", 1);
+        let telegram = SCITelegram::try_from(Vec::from(telegram).as_slice()).unwrap();
+        assert_eq!(
+            telegram.to_string(),
+            "SCIProtocolP: VersionRequest C -> S [01]"
+        );
+        assert_eq!(
+            format!("{telegram:?}"),
+            "SCITelegram { protocol_type: SCIProtocolP, message_type: VersionRequest (0x0024), \
+             sender: \"C\", receiver: \"S\", payload: [01] }"
+        );
+
+        let mut telegram = telegram;
+        telegram.message_type = crate::SCIMessageType(0x00ff);
+        telegram.payload = crate::SCIPayload::default();
+        assert_eq!(telegram.to_string(), "SCIProtocolP: 0x00ff C -> S []");
+    }
+
+    #[cfg(all(feature = "serde", feature = "std"))]
+    #[test]
+    fn test_serialize_telegrams() {
+        use crate::journal::{Direction, Journal, JournalEntry};
+
+        let telegram = SCITelegram::version_check(crate::ProtocolType::SCIProtocolP, "C", "S", 1);
+        let json = serde_json::to_string(&telegram).unwrap();
+        assert_eq!(
+            json,
+            r#"{"protocol_type":"SCIProtocolP","message_type":36,"sender":"C","receiver":"S","payload":[1]}"#
+        );
+        let parsed: SCITelegram = serde_json::from_str(&json).unwrap();
+        assert_eq!(Vec::from(parsed), Vec::from(telegram.clone()));
+
+        // Recorded journals can be stored and loaded as fixtures
+        let journal = Journal::new();
+        journal.record(Direction::Incoming, &telegram);
+        let json = serde_json::to_string(&journal.entries()).unwrap();
+        let entries: Vec<JournalEntry> = serde_json::from_str(&json).unwrap();
+        let journal = Journal::from_entries(entries);
+        assert_eq!(journal.entries()[0].direction, Direction::Incoming);
+
+        let too_long = json.replace(
+            r#""payload":[1]"#,
+            &format!(r#""payload":{:?}"#, [0u8; 1000]),
+        );
+        assert!(serde_json::from_str::<Vec<JournalEntry>>(&too_long).is_err());
+    }
+
+    #[cfg(feature = "scitds")]
+    #[test]
+    fn test_long_payloads() {
+        use crate::{ProtocolType, SCIPayload, SCI_INLINE_PAYLOAD_LENGTH, SCI_MAX_PAYLOAD_LENGTH};
+
+        let header = |protocol_type: ProtocolType| {
+            Vec::from(SCITelegram::version_check(protocol_type, "C", "S", 1))[..SCI_HEADER_LENGTH]
+                .to_vec()
+        };
+
+        let mut data = header(ProtocolType::SCIProtocolTDS);
+        data.extend([0xAB; 200]);
+        let telegram = SCITelegram::try_from(data.as_slice()).unwrap();
+        assert_eq!(telegram.payload.len(), 200);
+        assert!(!telegram.payload.is_inline());
+        assert_eq!(Vec::from(telegram), data);
+
+        let mut data = header(ProtocolType::SCIProtocolP);
+        data.resize(SCI_HEADER_LENGTH + SCI_INLINE_PAYLOAD_LENGTH + 1, 0);
+        assert!(matches!(
+            SCITelegram::try_from(data.as_slice()),
+            Err(SciError::PayloadTooLong { length, max: SCI_INLINE_PAYLOAD_LENGTH })
+                if length == SCI_INLINE_PAYLOAD_LENGTH + 1
+        ));
+        assert!(SCIPayload::from_slice(&data[..SCI_INLINE_PAYLOAD_LENGTH]).is_inline());
+        assert!(SCIPayload::try_from_slice(&[0; SCI_MAX_PAYLOAD_LENGTH + 1]).is_err());
+
+        #[cfg(feature = "rasta")]
+        assert_eq!(
+            SCI_HEADER_LENGTH + SCI_MAX_PAYLOAD_LENGTH,
+            rasta_rs::message::MAX_DATA_LENGTH
+        );
+    }
+
+    #[test]
+    fn test_version_response_payload() {
+        use crate::{ProtocolType, SCIPayload, SCIVersionCheckResult, VersionResponsePayload};
+
+        let response = SCITelegram::version_response(
+            ProtocolType::SCIProtocolP,
+            "S",
+            "C",
+            1,
+            SCIVersionCheckResult::VersionsAreNotEqual,
+            &[0xAB, 0xCD],
+        )
+        .unwrap();
+        assert_eq!(
+            VersionResponsePayload::try_from(response.payload).unwrap(),
+            VersionResponsePayload {
+                result: SCIVersionCheckResult::VersionsAreNotEqual,
+                version: 1,
+                checksum: vec![0xAB, 0xCD],
+            }
+        );
+
+        let short = SCIPayload::from_slice(&[2, 1]);
+        let payload = VersionResponsePayload::try_from(&short).unwrap();
+        assert_eq!(payload.result, SCIVersionCheckResult::VersionsAreEqual);
+        assert!(payload.checksum.is_empty());
+        assert!(matches!(
+            VersionResponsePayload::try_from(&SCIPayload::from_slice(&[2, 1, 4, 0xAB])),
+            Err(SciError::InvalidTelegramLength(4))
+        ));
+        assert!(matches!(
+            VersionResponsePayload::try_from(&SCIPayload::from_slice(&[3, 1, 0])),
+            Err(SciError::UnknownVersionCheckResult(3))
+        ));
+    }
+
+    #[test]
+    fn test_telegram_equality() {
+        use std::collections::HashSet;
+
+        use crate::{ProtocolType, SCICloseReason};
+
+        let telegram = SCITelegram::version_check(ProtocolType::SCIProtocolP, "C", "S", 1);
+        let received = SCITelegram::try_from(Vec::from(telegram.clone()).as_slice()).unwrap();
+        assert_eq!(received, telegram);
+        assert_ne!(
+            SCITelegram::version_check(ProtocolType::SCIProtocolP, "C", "S", 2),
+            telegram
+        );
+        assert_ne!(
+            SCITelegram::close(
+                ProtocolType::SCIProtocolP,
+                "C",
+                "S",
+                SCICloseReason::Timeout
+            ),
+            telegram
+        );
+
+        let unique: HashSet<_> = [telegram.clone(), received, telegram].into_iter().collect();
+        assert_eq!(unique.len(), 1);
+    }
+
+    #[cfg(feature = "rasta")]
+    #[test]
+    fn test_message_conversion() {
+        use rasta_rs::message::{Message, MessageType};
+
+        use crate::{MessageFromSci, SequenceState};
+
+        let telegram = SCITelegram::version_check(crate::ProtocolType::SCIProtocolP, "C", "S", 1);
+        let seq_state = SequenceState {
+            receiver: 2,
+            sender: 1,
+            sequence_number: 5,
+            ..Default::default()
+        };
+        let msg = Message::from_sci(&telegram, seq_state).unwrap();
+        assert_eq!(msg.try_message_type().unwrap(), MessageType::Data);
+        assert_eq!(msg.sequence_number(), 5);
+        let received = SCITelegram::try_from(&msg).unwrap();
+        assert_eq!(received.message_type, telegram.message_type);
+        assert_eq!(received.receiver.trim_end_matches('_'), "S");
+
+        let heartbeat = Message::heartbeat(2, 1, 6, 5, 0, 0);
+        assert!(matches!(
+            SCITelegram::try_from(&heartbeat),
+            Err(SciError::NoTelegram(6220))
+        ));
+        let long_name = SCITelegram::version_check(
+            crate::ProtocolType::SCIProtocolP,
+            "C",
+            "A_NAME_LONGER_THAN_20_BYTES",
+            1,
+        );
+        assert!(Message::from_sci(&long_name, seq_state).is_err());
+    }
+
+    #[cfg(all(feature = "rasta", feature = "scip"))]
+    #[test]
+    fn test_listener_survives_malformed_telegrams() {
+        use std::{collections::HashMap, sync::mpsc, thread, time::Duration};
+
+        use rasta_rs::{RastaConnection, RastaListener};
+
+        use crate::{
+            scip::{SCIPointLocation, SCIPointTargetLocation},
+            SCIConnection, SCIListener,
+        };
+
+        let listener = RastaListener::try_new("127.0.0.1:0", 2).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut listener = SCIListener::new(listener, "P".to_string());
+        let (tx, errors) = mpsc::channel();
+        listener.set_parse_error_handler(move |error, data| {
+            let _ = tx.send((error.to_string(), data.len()));
+        });
+        thread::spawn(move || {
+            listener.listen(|telegram| {
+                Some(SCITelegram::location_status(
+                    "P",
+                    &telegram.sender,
+                    SCIPointLocation::PointLocationRight,
+                ))
+            })
+        });
+
+        let conn = RastaConnection::try_new(addr, 1).unwrap();
+        let mapping = HashMap::from([("P".to_string(), 2)]);
+        let mut conn = SCIConnection::try_new(conn, "I".to_string(), mapping).unwrap();
+        conn.open("P").unwrap();
+        conn.send_raw_data(&[0x40, 0x01, 0x00]).unwrap();
+        conn.flush().unwrap();
+        let (error, length) = errors.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(error, SciError::InvalidTelegramLength(3).to_string());
+        assert_eq!(length, 3);
+
+        conn.send_telegram(SCITelegram::change_location(
+            "I",
+            "P",
+            SCIPointTargetLocation::PointLocationChangeToRight,
+        ))
+        .unwrap();
+        let status = conn.poll_telegram(Duration::from_secs(5)).unwrap();
+        assert!(status.is_some());
+    }
+
+    #[cfg(all(feature = "rasta", feature = "scip"))]
+    #[test]
+    fn test_status_provider() {
+        use crate::{scip::SCIPointLocation, status_responses, ProtocolType, SCIMessageType};
+
+        let mut provider = |sender: &str, receiver: &str| {
+            vec![SCITelegram::location_status(
+                sender,
+                receiver,
+                SCIPointLocation::PointLocationLeft,
+            )]
+        };
+        let request = SCITelegram::initialisation_request(ProtocolType::SCIProtocolP, "I", "P");
+        let responses = status_responses("P", &mut provider, &request);
+        let message_types: Vec<_> = responses.iter().map(|t| t.message_type).collect();
+        assert_eq!(
+            message_types,
+            [
+                SCIMessageType::pdi_initialisation_response(),
+                SCIMessageType::scip_location_status(),
+                SCIMessageType::pdi_initialisation_completed(),
+            ]
+        );
+        assert!(responses
+            .iter()
+            .all(|t| t.sender == "P" && t.receiver == "I"));
+        assert_eq!(
+            Vec::from(responses[1].clone()),
+            Vec::from(SCITelegram::location_status(
+                "P",
+                "I",
+                SCIPointLocation::PointLocationLeft,
+            ))
+        );
+
+        // Only initialisation requests are answered
+        let version_check = SCITelegram::version_check(ProtocolType::SCIProtocolP, "I", "P", 1);
+        assert!(status_responses("P", &mut provider, &version_check).is_empty());
+    }
+
+    #[cfg(all(feature = "rasta", feature = "scip"))]
+    #[test]
+    fn test_unknown_senders() {
+        use std::{collections::HashMap, sync::mpsc, thread, time::Duration};
+
+        use rasta_rs::{RastaConnection, RastaListener};
+
+        use crate::{
+            peers::Allowlist,
+            scip::{SCIPointLocation, SCIPointTargetLocation},
+            SCIConnection, SCIListener,
+        };
+
+        let listener = RastaListener::try_new("127.0.0.1:0", 2).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut listener = SCIListener::new(listener, "P".to_string());
+        listener.set_allowlist(Allowlist::from_iter(["I"]));
+        let (tx, unknown) = mpsc::channel();
+        listener.set_unknown_sender_handler(move |telegram, data| {
+            let _ = tx.send((telegram.sender().to_string(), data.len()));
+        });
+        thread::spawn(move || {
+            listener.listen(|telegram| {
+                // Answers as an element the connection does not know
+                Some(SCITelegram::location_status(
+                    "Q",
+                    &telegram.sender,
+                    SCIPointLocation::PointLocationRight,
+                ))
+            })
+        });
+
+        let conn = RastaConnection::try_new(addr, 1).unwrap();
+        let mapping = HashMap::from([("P".to_string(), 2)]);
+        let mut conn = SCIConnection::try_new(conn, "X".to_string(), mapping).unwrap();
+        let (tx, senders) = mpsc::channel();
+        conn.set_unknown_sender_handler(move |telegram, _| {
+            let _ = tx.send(telegram.sender().trim_end_matches('_').to_string());
+        });
+        conn.open("P").unwrap();
+        let change = |sender| {
+            SCITelegram::change_location(
+                sender,
+                "P",
+                SCIPointTargetLocation::PointLocationChangeToRight,
+            )
+        };
+        conn.send_telegram(change("X")).unwrap();
+        conn.flush().unwrap();
+        let (sender, length) = unknown.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(sender.trim_end_matches('_'), "X");
+        assert_eq!(length, Vec::from(change("X")).len());
+
+        conn.send_telegram(change("I")).unwrap();
+        assert!(conn
+            .poll_telegram(Duration::from_secs(5))
+            .unwrap()
+            .is_some());
+        assert_eq!(senders.try_recv().unwrap(), "Q");
+        assert!(unknown.try_recv().is_err());
+    }
+
+    #[cfg(all(feature = "rasta", feature = "scip"))]
+    #[test]
+    fn test_strict_listener() {
+        use std::{collections::HashMap, net::TcpListener, sync::mpsc, thread, time::Duration};
+
+        use rasta_rs::{RastaConnection, RastaListener};
+
+        use crate::{
+            scip::SCIPointTargetLocation, ProtocolType, SCICloseReason, SCIConnection, SCIListener,
+        };
+
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let listener = RastaListener::try_new(addr, 2).unwrap();
+        let mut listener = SCIListener::new(listener, "P".to_string());
+        listener.set_strict(true);
+        let (tx, delivered) = mpsc::channel();
+        thread::spawn(move || {
+            listener.listen(|telegram| {
+                let _ = tx.send(telegram.sender.trim_end_matches('_').to_string());
+                None
+            })
+        });
+
+        let conn = RastaConnection::try_new(addr, 1).unwrap();
+        let mapping = HashMap::from([("P".to_string(), 2)]);
+        let mut conn = SCIConnection::try_new(conn, "I".to_string(), mapping).unwrap();
+        conn.open("P").unwrap();
+        conn.send_telegram(SCITelegram::version_check(
+            ProtocolType::SCIProtocolP,
+            "I",
+            "P",
+            1,
+        ))
+        .unwrap();
+        conn.flush().unwrap();
+        assert_eq!(delivered.recv_timeout(Duration::from_secs(5)).unwrap(), "I");
+
+        conn.send_telegram(SCITelegram::change_location(
+            "X",
+            "P",
+            SCIPointTargetLocation::PointLocationChangeToRight,
+        ))
+        .unwrap();
+        let error = conn.poll_telegram(Duration::from_secs(5)).unwrap_err();
+        assert!(matches!(
+            SciError::from_rasta_error(&error),
+            Some(SciError::PdiClosed(SCICloseReason::ContentTelegramError))
+        ));
+        assert!(delivered.try_recv().is_err());
+    }
+
+    #[cfg(all(feature = "rasta", feature = "scip"))]
+    #[test]
+    fn test_strict_listener_keeps_first_peer() {
+        use std::{collections::HashMap, sync::mpsc, thread, time::Duration};
+
+        use rasta_rs::{RastaConnection, RastaListener};
+
+        use crate::{
+            scip::SCIPointTargetLocation, ProtocolType, SCICloseReason, SCIConnection, SCIListener,
+        };
+
+        let listener = RastaListener::try_new("127.0.0.1:0", 2).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut listener = SCIListener::new(listener, "P".to_string());
+        listener.set_strict(true);
+        let (tx, delivered) = mpsc::channel();
+        thread::spawn(move || {
+            listener.listen(|telegram| {
+                let _ = tx.send(telegram.sender.trim_end_matches('_').to_string());
+                None
+            })
+        });
+
+        let conn = RastaConnection::try_new(addr, 1).unwrap();
+        let mapping = HashMap::from([("P".to_string(), 2)]);
+        let mut conn = SCIConnection::try_new(conn, "I".to_string(), mapping).unwrap();
+        conn.open("P").unwrap();
+        let version_check =
+            |sender| SCITelegram::version_check(ProtocolType::SCIProtocolP, sender, "P", 1);
+        conn.send_telegram(version_check("I")).unwrap();
+        conn.flush().unwrap();
+        assert_eq!(delivered.recv_timeout(Duration::from_secs(5)).unwrap(), "I");
+
+        // A later version check does not take over the connection
+        conn.send_telegram(version_check("X")).unwrap();
+        let error = conn.poll_telegram(Duration::from_secs(5)).unwrap_err();
+        assert!(matches!(
+            SciError::from_rasta_error(&error),
+            Some(SciError::PdiClosed(SCICloseReason::ContentTelegramError))
+        ));
+        assert!(delivered.try_recv().is_err());
+
+        conn.send_telegram(SCITelegram::change_location(
+            "I",
+            "P",
+            SCIPointTargetLocation::PointLocationChangeToRight,
+        ))
+        .unwrap();
+        conn.flush().unwrap();
+        assert_eq!(delivered.recv_timeout(Duration::from_secs(5)).unwrap(), "I");
+    }
+
+    #[cfg(all(feature = "rasta", feature = "scip"))]
+    #[test]
+    fn test_strict_listener_per_connection() {
+        use std::{collections::HashMap, sync::mpsc, thread, time::Duration};
+
+        use rasta_rs::{RastaConnection, RastaListener};
+
+        use crate::{ProtocolType, SCIConnection, SCIListener};
+
+        let listener = RastaListener::try_new("127.0.0.1:0", 2).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut listener = SCIListener::new(listener, "P".to_string());
+        listener.set_strict(true);
+        let (tx, delivered) = mpsc::channel();
+        thread::spawn(move || {
+            listener.listen(|telegram| {
+                let _ = tx.send(telegram.sender.trim_end_matches('_').to_string());
+                None
+            })
+        });
+
+        // Each connection is opened by another element
+        for name in ["I", "J"] {
+            let conn = RastaConnection::try_new(addr, 1).unwrap();
+            let mapping = HashMap::from([("P".to_string(), 2)]);
+            let mut conn = SCIConnection::try_new(conn, name.to_string(), mapping).unwrap();
+            conn.open("P").unwrap();
+            conn.send_telegram(SCITelegram::version_check(
+                ProtocolType::SCIProtocolP,
+                name,
+                "P",
+                1,
+            ))
+            .unwrap();
+            conn.flush().unwrap();
+            assert_eq!(
+                delivered.recv_timeout(Duration::from_secs(5)).unwrap(),
+                name
+            );
+        }
+    }
+
+    #[cfg(all(feature = "rasta", feature = "scip"))]
+    #[test]
+    fn test_strict_connection() {
+        use std::{collections::HashMap, net::TcpListener, sync::mpsc, thread, time::Duration};
+
+        use rasta_rs::{RastaConnection, RastaListener};
+
+        use crate::{
+            scip::{SCIPointLocation, SCIPointTargetLocation},
+            SCICloseReason, SCIConnection, SCIListener, SCIMessageType,
+        };
+
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let listener = RastaListener::try_new(addr, 2).unwrap();
+        let mut listener = SCIListener::new(listener, "P".to_string());
+        let (tx, closes) = mpsc::channel();
+        thread::spawn(move || {
+            listener.listen(|telegram| {
+                if telegram.message_type == SCIMessageType::pdi_close() {
+                    let _ = tx.send(telegram.payload[0]);
+                    return None;
+                }
+                // Answers as an element other than the one that was opened
+                Some(SCITelegram::location_status(
+                    "Q",
+                    &telegram.sender,
+                    SCIPointLocation::PointLocationRight,
+                ))
+            })
+        });
+
+        let conn = RastaConnection::try_new(addr, 1).unwrap();
+        let mapping = HashMap::from([("P".to_string(), 2)]);
+        let mut conn = SCIConnection::try_new(conn, "I".to_string(), mapping).unwrap();
+        conn.set_strict(true);
+        conn.open("P").unwrap();
+        conn.send_telegram(SCITelegram::change_location(
+            "I",
+            "P",
+            SCIPointTargetLocation::PointLocationChangeToRight,
+        ))
+        .unwrap();
+        let error = conn.poll_telegram(Duration::from_secs(5)).unwrap_err();
+        assert!(matches!(
+            SciError::from_rasta_error(&error),
+            Some(SciError::UnexpectedSender { expected, actual })
+                if expected == "P" && actual.trim_end_matches('_') == "Q"
+        ));
+        assert_eq!(
+            closes.recv_timeout(Duration::from_secs(5)).unwrap(),
+            SCICloseReason::ContentTelegramError as u8
+        );
+    }
+
+    #[cfg(all(feature = "rasta", feature = "scip"))]
+    #[test]
+    fn test_strict_connection_accepts_peer() {
+        use std::{collections::HashMap, thread, time::Duration};
+
+        use rasta_rs::{RastaConnection, RastaListener};
+
+        use crate::{
+            scip::{SCIPointLocation, SCIPointTargetLocation},
+            SCIConnection, SCIListener, SCIMessageType,
+        };
+
+        let listener = RastaListener::try_new("127.0.0.1:0", 2).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut listener = SCIListener::new(listener, "P".to_string());
+        thread::spawn(move || {
+            listener.listen(|telegram| {
+                Some(SCITelegram::location_status(
+                    "P",
+                    &telegram.sender,
+                    SCIPointLocation::PointLocationRight,
+                ))
+            })
+        });
+
+        let conn = RastaConnection::try_new(addr, 1).unwrap();
+        let mapping = HashMap::from([("P".to_string(), 2)]);
+        let mut conn = SCIConnection::try_new(conn, "I".to_string(), mapping).unwrap();
+        conn.set_strict(true);
+        conn.open("P").unwrap();
+        conn.send_telegram(SCITelegram::change_location(
+            "I",
+            "P",
+            SCIPointTargetLocation::PointLocationChangeToRight,
+        ))
+        .unwrap();
+        // The sender on the wire is padded, the name given to `open` is not
+        let telegram = conn.poll_telegram(Duration::from_secs(5)).unwrap().unwrap();
+        assert_eq!(
+            telegram.message_type,
+            SCIMessageType::scip_location_status()
+        );
+        assert_eq!(telegram.sender.trim_end_matches('_'), "P");
+    }
+
+    #[cfg(all(feature = "rasta", feature = "scip"))]
+    #[test]
+    fn test_availability_announcements() {
+        use std::{collections::HashMap, thread, time::Duration};
+
+        use rasta_rs::{RastaConnection, RastaListener};
+
+        use crate::{
+            managed::ManagedConfig, scip::SCIPointTargetLocation, ProtocolType, SCIAvailability,
+            SCIConnection, SCIListener,
+        };
+
+        let listener = RastaListener::try_new("127.0.0.1:0", 2).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut listener = SCIListener::new(listener, "P".to_string());
+        listener.set_managed(ManagedConfig::new(ProtocolType::SCIProtocolP));
+        listener.set_availability(SCIAvailability::NotAvailable);
+        let availability = listener.availability();
+        thread::spawn(move || listener.listen(|_| None));
+
+        let conn = RastaConnection::try_new(addr, 1).unwrap();
+        let mapping = HashMap::from([("P".to_string(), 2)]);
+        let mut conn = SCIConnection::try_new(conn, "I".to_string(), mapping).unwrap();
+        conn.set_managed(ManagedConfig::new(ProtocolType::SCIProtocolP));
+        let events = conn.availability().subscribe();
+        // Announced once the PDI is up, since peers assume availability
+        conn.open("P").unwrap();
+        let telegram = conn.poll_telegram(Duration::from_secs(5)).unwrap().unwrap();
+        assert_eq!(telegram.availability(), Some(SCIAvailability::NotAvailable));
+        let event = events.try_recv().unwrap();
+        assert_eq!(event.peer.trim_end_matches('_'), "P");
+        assert_eq!(event.availability, SCIAvailability::NotAvailable);
+
+        availability.set(SCIAvailability::Available);
+        conn.send_telegram(SCITelegram::change_location(
+            "I",
+            "P",
+            SCIPointTargetLocation::PointLocationChangeToRight,
+        ))
+        .unwrap();
+        let telegram = conn.poll_telegram(Duration::from_secs(5)).unwrap().unwrap();
+        assert_eq!(telegram.availability(), Some(SCIAvailability::Available));
+        assert_eq!(
+            events.try_recv().unwrap().availability,
+            SCIAvailability::Available
+        );
+        assert_eq!(
+            conn.availability().of(&telegram.sender),
+            Some(SCIAvailability::Available)
+        );
+    }
+
+    #[cfg(all(feature = "rasta", feature = "scip"))]
+    #[test]
+    fn test_peer_close_reason() {
+        use std::{collections::HashMap, thread};
+
+        use rasta_rs::{incident::IncidentKind, RastaConnection, RastaListener};
+
+        use crate::{
+            scip::SCIPointTargetLocation, SCICloseReason, SCICommand, SCIConnection, SCIListener,
+        };
+
+        let listener = RastaListener::try_new("127.0.0.1:0", 2).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut listener = SCIListener::new(listener, "P".to_string());
+        thread::spawn(move || {
+            listener.listen(|telegram| {
+                Some(SCITelegram::close(
+                    telegram.protocol_type,
+                    "P",
+                    &telegram.sender,
+                    SCICloseReason::ChecksumMismatch,
+                ))
+            })
+        });
+
+        let conn = RastaConnection::try_new(addr, 1).unwrap();
+        let mapping = HashMap::from([("P".to_string(), 2)]);
+        let mut conn = SCIConnection::try_new(conn, "I".to_string(), mapping).unwrap();
+        let mut sent = false;
+        let error = conn
+            .run("P", |_| {
+                if std::mem::replace(&mut sent, true) {
+                    SCICommand::Wait
+                } else {
+                    SCICommand::Telegram(SCITelegram::change_location(
+                        "I",
+                        "P",
+                        SCIPointTargetLocation::PointLocationChangeToLeft,
+                    ))
+                }
+            })
+            .unwrap_err();
+        assert!(matches!(
+            SciError::from_rasta_error(&error),
+            Some(SciError::PdiClosed(SCICloseReason::ChecksumMismatch))
+        ));
+        let incident = conn.incidents().latest().unwrap();
+        assert_eq!(incident.kind, IncidentKind::PeerClosed);
+        assert_eq!(incident.reason.as_deref(), Some("ChecksumMismatch"));
+    }
+
+    #[cfg(all(feature = "rasta", feature = "scip"))]
+    #[test]
+    fn test_peer_close_in_all_receive_paths() {
+        use std::{collections::HashMap, net::TcpListener, thread, time::Duration};
+
+        use rasta_rs::{shutdown::ShutdownHandle, RastaConnection, RastaError, RastaListener};
+
+        use crate::{
+            scip::SCIPointTargetLocation, SCICloseReason, SCICommand, SCIConnection, SCIListener,
+        };
+
+        // Closes the PDI on requests to move left and ignores other telegrams
+        let connect = || -> (SCIConnection, ShutdownHandle) {
+            let addr = TcpListener::bind("127.0.0.1:0")
+                .unwrap()
+                .local_addr()
+                .unwrap();
+            let listener = RastaListener::try_new(addr, 2).unwrap();
+            let mut listener = SCIListener::new(listener, "P".to_string());
+            let shutdown = listener.shutdown_handle();
+            thread::spawn(move || {
+                listener.listen(|telegram| {
+                    (telegram.payload[0] == SCIPointTargetLocation::PointLocationChangeToLeft as u8)
+                        .then(|| {
+                            SCITelegram::close(
+                                telegram.protocol_type,
+                                "P",
+                                &telegram.sender,
+                                SCICloseReason::ProtocolError,
+                            )
+                        })
+                })
+            });
+            let conn = RastaConnection::try_new(addr, 1).unwrap();
+            let mapping = HashMap::from([("P".to_string(), 2)]);
+            let mut conn = SCIConnection::try_new(conn, "I".to_string(), mapping).unwrap();
+            conn.open("P").unwrap();
+            (conn, shutdown)
+        };
+        let send = |conn: &mut SCIConnection, location| {
+            conn.send_telegram(SCITelegram::change_location("I", "P", location))
+                .unwrap();
+            conn.flush().unwrap();
+        };
+        let closed = |error: RastaError| {
+            matches!(
+                SciError::from_rasta_error(&error),
+                Some(SciError::PdiClosed(SCICloseReason::ProtocolError))
+            )
+        };
+        let disconnected = |error: RastaError| {
+            matches!(
+                SciError::from_rasta_error(&error),
+                Some(SciError::PeerDisconnected)
+            )
+        };
+
+        let (mut conn, _) = connect();
+        send(&mut conn, SCIPointTargetLocation::PointLocationChangeToLeft);
+        assert!(closed(conn.receive_telegram().unwrap_err()));
+        send(&mut conn, SCIPointTargetLocation::PointLocationChangeToLeft);
+        assert!(closed(
+            conn.poll_telegram(Duration::from_secs(5)).unwrap_err()
+        ));
+        send(&mut conn, SCIPointTargetLocation::PointLocationChangeToLeft);
+        let mut stream = conn.telegram_stream("P").unwrap();
+        assert!(closed(stream.next().unwrap().unwrap_err()));
+
+        // The listener sends a disconnection request after
+        // the next message once it is shut down
+        let (mut conn, shutdown) = connect();
+        shutdown.shutdown();
+        send(
+            &mut conn,
+            SCIPointTargetLocation::PointLocationChangeToRight,
+        );
+        assert!(disconnected(conn.receive_telegram().unwrap_err()));
+
+        let (mut conn, shutdown) = connect();
+        shutdown.shutdown();
+        send(
+            &mut conn,
+            SCIPointTargetLocation::PointLocationChangeToRight,
+        );
+        assert!(disconnected(
+            conn.poll_telegram(Duration::from_secs(5)).unwrap_err()
+        ));
+
+        let (mut conn, shutdown) = connect();
+        shutdown.shutdown();
+        send(
+            &mut conn,
+            SCIPointTargetLocation::PointLocationChangeToRight,
+        );
+        let mut stream = conn.telegram_stream("P").unwrap();
+        assert!(disconnected(stream.next().unwrap().unwrap_err()));
+
+        let (mut conn, shutdown) = connect();
+        shutdown.shutdown();
+        send(
+            &mut conn,
+            SCIPointTargetLocation::PointLocationChangeToRight,
+        );
+        assert!(disconnected(
+            conn.run("P", |_| SCICommand::Wait).unwrap_err()
+        ));
+    }
+
+    #[cfg(all(feature = "rasta", feature = "scip"))]
+    #[test]
+    fn test_receive_hooks_see_early_telegrams() {
+        use std::{
+            collections::HashMap,
+            sync::{Arc, Mutex},
+            thread,
+        };
+
+        use rasta_rs::{RastaConnection, RastaListener};
+
+        use crate::{
+            scip::{SCIPointLocation, SCIPointTargetLocation},
+            SCICommand, SCIConnection, SCIListener, SCIMessageType,
+        };
+
+        let listener = RastaListener::try_new("127.0.0.1:0", 2).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut listener = SCIListener::new(listener, "P".to_string());
+        thread::spawn(move || {
+            listener.listen(|telegram| {
+                Some(SCITelegram::location_status(
+                    "P",
+                    &telegram.sender,
+                    SCIPointLocation::PointLocationRight,
+                ))
+            })
+        });
+
+        let conn = RastaConnection::try_new(addr, 1).unwrap();
+        let mapping = HashMap::from([("P".to_string(), 2)]);
+        let mut conn = SCIConnection::try_new(conn, "I".to_string(), mapping).unwrap();
+        conn.open("P").unwrap();
+        conn.send_telegram(SCITelegram::change_location(
+            "I",
+            "P",
+            SCIPointTargetLocation::PointLocationChangeToRight,
+        ))
+        .unwrap();
+        // The status arrives while waiting for the heartbeat response
+        conn.conn.send_heartbeat().unwrap();
+
+        let observed = Arc::new(Mutex::new(Vec::new()));
+        let hook_observed = observed.clone();
+        conn.add_receive_hook(move |telegram| {
+            hook_observed.lock().unwrap().push(telegram.message_type);
+        });
+        let mut first = None;
+        conn.run("P", |telegram| {
+            first = Some(telegram);
+            SCICommand::Disconnect
+        })
+        .unwrap();
+        let expected = SCIMessageType::scip_location_status();
+        assert_eq!(first.unwrap().unwrap().message_type, expected);
+        assert_eq!(*observed.lock().unwrap(), [expected]);
+    }
+
+    #[cfg(all(feature = "rasta", feature = "scip"))]
+    #[test]
+    fn test_run_keeps_slow_callbacks_alive() {
+        use std::{collections::HashMap, thread, time::Duration};
+
+        use rasta_rs::{RastaConfig, RastaConnection, RastaListener};
+
+        use crate::{
+            scip::{SCIPointLocation, SCIPointTargetLocation},
+            SCICommand, SCIConnection, SCIListener,
+        };
+
+        let config = RastaConfig {
+            t_max: Duration::from_millis(200),
+            ..Default::default()
+        };
+        let listener =
+            RastaListener::try_new_with_config("127.0.0.1:0", 2, config.clone()).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut listener = SCIListener::new(listener, "P".to_string());
+        thread::spawn(move || {
+            listener.listen(|telegram| {
+                Some(SCITelegram::location_status(
+                    "P",
+                    &telegram.sender,
+                    SCIPointLocation::PointLocationRight,
+                ))
+            })
+        });
+
+        let conn = RastaConnection::try_new_with_config(addr, 1, config).unwrap();
+        let mapping = HashMap::from([("P".to_string(), 2)]);
+        let mut conn = SCIConnection::try_new(conn, "I".to_string(), mapping).unwrap();
+        let mut status = None;
+        conn.run("P", |telegram| match telegram {
+            None => {
+                // Takes several times T_max to decide
+                thread::sleep(Duration::from_millis(600));
+                SCICommand::Telegram(SCITelegram::change_location(
+                    "I",
+                    "P",
+                    SCIPointTargetLocation::PointLocationChangeToRight,
+                ))
+            }
+            Some(telegram) => {
+                status.replace(telegram);
+                SCICommand::Disconnect
+            }
+        })
+        .unwrap();
+        assert!(status.is_some());
+    }
 }
@@ -0,0 +1,247 @@
+//! Supervision of the liveness of peers, based on the messages of the
+//! underlying RaSTA connection. A peer that stays silent for longer than
+//! the configured thresholds first becomes [`Liveness::Degraded`] and then
+//! [`Liveness::Lost`], even if its socket is still open. Applications
+//! subscribe to the resulting [`LivenessEvent`]s to drive the elements of
+//! a lost peer to fail-safe states before the connection is torn down.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use rasta_rs::RastaConfig;
+
+/// How usable the elements behind a peer currently are.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Liveness {
+    /// The peer answers within its supervision time.
+    Alive,
+    /// The peer is late. Its elements can still be used, but new commands
+    /// may not be confirmed in time, so commands that cannot tolerate a
+    /// delayed confirmation should be held back.
+    Degraded,
+    /// The peer is not currently usable. Signals depending on its elements
+    /// should be driven to fail-safe aspects until it is alive again.
+    Lost,
+}
+
+impl Liveness {
+    pub fn is_usable(&self) -> bool {
+        *self != Self::Lost
+    }
+}
+
+/// The time a peer may stay silent before its liveness changes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LivenessThresholds {
+    pub degraded_after: Duration,
+    pub lost_after: Duration,
+}
+
+impl LivenessThresholds {
+    /// Follows the SR-layer supervision of `config`: a peer is degraded
+    /// after half of `t_max` and lost after `t_max`.
+    pub fn from_config(config: &RastaConfig) -> Self {
+        Self {
+            degraded_after: config.t_max / 2,
+            lost_after: config.t_max,
+        }
+    }
+
+    fn classify(&self, silent_for: Duration) -> Liveness {
+        if silent_for > self.lost_after {
+            Liveness::Lost
+        } else if silent_for > self.degraded_after {
+            Liveness::Degraded
+        } else {
+            Liveness::Alive
+        }
+    }
+}
+
+impl Default for LivenessThresholds {
+    fn default() -> Self {
+        Self::from_config(&RastaConfig::default())
+    }
+}
+
+/// A change of the liveness of `peer`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LivenessEvent {
+    pub peer: String,
+    pub liveness: Liveness,
+    /// The time since the last message of the peer.
+    pub silent_for: Duration,
+}
+
+#[derive(Debug)]
+struct PeerState {
+    last_seen: Instant,
+    liveness: Liveness,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    thresholds: LivenessThresholds,
+    peers: HashMap<String, PeerState>,
+    subscribers: Vec<Sender<LivenessEvent>>,
+}
+
+impl State {
+    fn update(&mut self, peer: &str, liveness: Liveness, now: Instant) {
+        let Some(state) = self.peers.get_mut(peer) else {
+            return;
+        };
+        if state.liveness == liveness {
+            return;
+        }
+        state.liveness = liveness;
+        let event = LivenessEvent {
+            peer: peer.to_string(),
+            liveness,
+            silent_for: now.saturating_duration_since(state.last_seen),
+        };
+        self.subscribers
+            .retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
+}
+
+/// A shared handle to the liveness of the peers of an endpoint. Clones
+/// refer to the same watchdog, so one can be kept to subscribe to events
+/// or to call [`Watchdog::check`] periodically from another thread.
+///
+/// The endpoint reports every message and every failed exchange with
+/// its peer. Silence is only noticed when [`Watchdog::check`] runs,
+/// which [`SCIConnection::run`](crate::SCIConnection::run) does before
+/// each heartbeat. An application that blocks on receiving telegrams
+/// should call it from a separate thread to be warned before the
+/// supervision of the RaSTA connection expires.
+#[derive(Clone, Debug, Default)]
+pub struct Watchdog {
+    inner: Arc<Mutex<State>>,
+}
+
+impl Watchdog {
+    pub fn new(thresholds: LivenessThresholds) -> Self {
+        let watchdog = Self::default();
+        watchdog.set_thresholds(thresholds);
+        watchdog
+    }
+
+    pub fn thresholds(&self) -> LivenessThresholds {
+        self.inner.lock().unwrap().thresholds
+    }
+
+    pub fn set_thresholds(&self, thresholds: LivenessThresholds) {
+        self.inner.lock().unwrap().thresholds = thresholds;
+    }
+
+    /// Returns a receiver for all liveness changes from now on.
+    pub fn subscribe(&self) -> Receiver<LivenessEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.inner.lock().unwrap().subscribers.push(sender);
+        receiver
+    }
+
+    /// The liveness of `peer`, or `None` if it was never seen.
+    pub fn liveness(&self, peer: &str) -> Option<Liveness> {
+        self.inner
+            .lock()
+            .unwrap()
+            .peers
+            .get(peer)
+            .map(|state| state.liveness)
+    }
+
+    /// Records that a message of `peer` arrived.
+    pub fn observe(&self, peer: &str) {
+        let now = Instant::now();
+        let mut inner = self.inner.lock().unwrap();
+        inner
+            .peers
+            .entry(peer.to_string())
+            .or_insert(PeerState {
+                last_seen: now,
+                liveness: Liveness::Alive,
+            })
+            .last_seen = now;
+        inner.update(peer, Liveness::Alive, now);
+    }
+
+    /// Records that the connection to `peer` failed, e.g. because its
+    /// supervision time expired or it disconnected.
+    pub fn mark_lost(&self, peer: &str) {
+        let now = Instant::now();
+        let mut inner = self.inner.lock().unwrap();
+        inner.peers.entry(peer.to_string()).or_insert(PeerState {
+            last_seen: now,
+            liveness: Liveness::Alive,
+        });
+        inner.update(peer, Liveness::Lost, now);
+    }
+
+    /// Updates the liveness of all peers according to their silence.
+    pub fn check(&self) {
+        self.check_at(Instant::now());
+    }
+
+    fn check_at(&self, now: Instant) {
+        let mut inner = self.inner.lock().unwrap();
+        let thresholds = inner.thresholds;
+        let changes: Vec<(String, Liveness)> = inner
+            .peers
+            .iter()
+            .map(|(peer, state)| {
+                let liveness = match state.liveness {
+                    // Only a new message makes a lost peer alive again
+                    Liveness::Lost => Liveness::Lost,
+                    _ => thresholds.classify(now.saturating_duration_since(state.last_seen)),
+                };
+                (peer.clone(), liveness)
+            })
+            .collect();
+        for (peer, liveness) in changes {
+            inner.update(&peer, liveness, now);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{Liveness, LivenessThresholds, Watchdog};
+
+    #[test]
+    fn test_liveness_events() {
+        let watchdog = Watchdog::new(LivenessThresholds {
+            degraded_after: Duration::from_millis(100),
+            lost_after: Duration::from_millis(200),
+        });
+        let events = watchdog.subscribe();
+        watchdog.observe("S");
+        let start = Instant::now();
+        watchdog.check_at(start + Duration::from_millis(50));
+        assert!(events.try_recv().is_err());
+        watchdog.check_at(start + Duration::from_millis(150));
+        assert_eq!(events.try_recv().unwrap().liveness, Liveness::Degraded);
+        watchdog.check_at(start + Duration::from_millis(250));
+        let lost = events.try_recv().unwrap();
+        assert_eq!(lost.liveness, Liveness::Lost);
+        assert!(lost.silent_for >= Duration::from_millis(200));
+        assert!(!watchdog.liveness("S").unwrap().is_usable());
+
+        watchdog.observe("S");
+        assert_eq!(events.try_recv().unwrap().liveness, Liveness::Alive);
+        watchdog.mark_lost("S");
+        watchdog.check_at(Instant::now());
+        assert_eq!(events.try_recv().unwrap().liveness, Liveness::Lost);
+        assert!(events.try_recv().is_err());
+    }
+}
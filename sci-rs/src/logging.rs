@@ -0,0 +1,29 @@
+//! Events of the protocol implementation, such as connections being
+//! opened and closed or messages being dropped. With the `tracing` feature
+//! they are emitted through `tracing`, otherwise they are discarded.
+
+/// Emits a `tracing` event at `$level` (`error`, `warn`, `info`, `debug`
+/// or `trace`) with the fields and message of the `tracing` macros.
+/// Expands to nothing without the `tracing` feature.
+macro_rules! event {
+    ($level:ident, $($arg:tt)+) => {
+        #[cfg(feature = "tracing")]
+        tracing::$level!($($arg)+)
+    };
+}
+
+pub(crate) use event;
+
+/// Emits a `trace` event for a telegram exchanged by an endpoint.
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub(crate) fn telegram(direction: crate::journal::Direction, telegram: &crate::SCITelegram) {
+    event!(
+        trace,
+        ?direction,
+        protocol = ?telegram.protocol_type,
+        message_type = u16::from(telegram.message_type),
+        sender = telegram.sender.as_str(),
+        receiver = telegram.receiver.as_str(),
+        "Telegram"
+    );
+}
@@ -0,0 +1,194 @@
+//! # Managed Mode
+//!
+//! In managed mode, [`SCIListener`](crate::SCIListener) and
+//! [`SCIConnection`](crate::SCIConnection) handle the PDI protocol on their
+//! own: version check, initialisation, close and timeout telegrams are
+//! answered or consumed by the endpoint, and only application telegrams
+//! (commands and status reports) reach the handlers.
+//!
+//! The version response carries a checksum over the configuration data of
+//! the element. [`ManagedConfig::with_config_data`] registers that data,
+//! a connection closes the PDI with
+//! [`SCICloseReason::ChecksumMismatch`](crate::SCICloseReason::ChecksumMismatch)
+//! if the peer reports a different checksum.
+
+use crate::{
+    ProtocolType, SCIMessageType, SCITelegram, SciError, SCI_MAX_CHECKSUM_LENGTH, SCI_VERSION,
+};
+
+/// The data an endpoint needs to run the PDI protocol by itself.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ManagedConfig {
+    /// The protocol of telegrams initiated by the endpoint,
+    /// e.g. the version check of an [`SCIConnection`](crate::SCIConnection).
+    pub protocol_type: ProtocolType,
+    /// The SCI version announced to and expected from the peer.
+    pub version: u8,
+    /// The checksum of the configuration data. A listener sends it in its
+    /// version response, a connection compares it to the received one
+    /// unless it is empty.
+    pub checksum: Vec<u8>,
+    /// The configuration data the checksum was computed over, if registered.
+    pub config_data: Option<Vec<u8>>,
+}
+
+impl ManagedConfig {
+    pub fn new(protocol_type: ProtocolType) -> Self {
+        Self {
+            protocol_type,
+            version: SCI_VERSION,
+            checksum: Vec::new(),
+            config_data: None,
+        }
+    }
+
+    pub fn with_version(mut self, version: u8) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// Fails if `checksum` does not fit into a version response,
+    /// see [`SCI_MAX_CHECKSUM_LENGTH`].
+    pub fn with_checksum(mut self, checksum: &[u8]) -> Result<Self, SciError> {
+        if checksum.len() > SCI_MAX_CHECKSUM_LENGTH {
+            return Err(SciError::ChecksumTooLong {
+                length: checksum.len(),
+                max: SCI_MAX_CHECKSUM_LENGTH,
+            });
+        }
+        self.checksum = checksum.to_vec();
+        Ok(self)
+    }
+
+    /// Registers the configuration data of the element and uses its
+    /// [`config_checksum`] as the checksum.
+    pub fn with_config_data(mut self, data: &[u8]) -> Self {
+        self.checksum = config_checksum(data).to_vec();
+        self.config_data = Some(data.to_vec());
+        self
+    }
+
+    /// Compares the checksum received in a version response to the
+    /// configured one. Any checksum is accepted if none is configured.
+    pub fn verify_checksum(&self, received: &[u8]) -> Result<(), SciError> {
+        if self.checksum.is_empty() || self.checksum == received {
+            Ok(())
+        } else {
+            Err(SciError::ChecksumMismatch {
+                expected: self.checksum.clone(),
+                received: received.to_vec(),
+            })
+        }
+    }
+
+    /// Checks that `data` matches the configured checksum,
+    /// e.g. before applying configuration data received from elsewhere.
+    pub fn verify_config_data(&self, data: &[u8]) -> Result<(), SciError> {
+        self.verify_checksum(&config_checksum(data))
+    }
+}
+
+/// The checksum of configuration data: its CRC-32 (IEEE 802.3)
+/// in network byte order.
+pub fn config_checksum(data: &[u8]) -> [u8; 4] {
+    let mut crc = u32::MAX;
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB88320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    (!crc).to_be_bytes()
+}
+
+/// Whether `telegram` belongs to the PDI protocol handled by managed endpoints.
+pub fn is_protocol_telegram(telegram: &SCITelegram) -> bool {
+    [
+        SCIMessageType::pdi_version_check(),
+        SCIMessageType::pdi_version_response(),
+        SCIMessageType::pdi_initialisation_request(),
+        SCIMessageType::pdi_initialisation_response(),
+        SCIMessageType::pdi_initialisation_completed(),
+        SCIMessageType::pdi_close(),
+        SCIMessageType::sci_timeout(),
+    ]
+    .contains(&telegram.message_type)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        ProtocolType, SCICloseReason, SCITelegram, SCIVersionCheckResult, SciError,
+        SCI_MAX_CHECKSUM_LENGTH,
+    };
+
+    use super::ManagedConfig;
+
+    #[test]
+    fn test_protocol_telegrams() {
+        let protocol_type = ProtocolType::SCIProtocolP;
+        assert!(super::is_protocol_telegram(&SCITelegram::version_check(
+            protocol_type,
+            "I",
+            "P",
+            1
+        )));
+        assert!(super::is_protocol_telegram(&SCITelegram::close(
+            protocol_type,
+            "I",
+            "P",
+            SCICloseReason::NormalClose
+        )));
+        assert!(!super::is_protocol_telegram(
+            &SCITelegram::release_for_maintenance(protocol_type, "I", "P")
+        ));
+    }
+
+    #[test]
+    fn test_checksum_length() {
+        let protocol_type = ProtocolType::SCIProtocolP;
+        let checksum = [0xAB; SCI_MAX_CHECKSUM_LENGTH + 1];
+        assert!(ManagedConfig::new(protocol_type)
+            .with_checksum(&checksum)
+            .is_err());
+        let response = |checksum| {
+            SCITelegram::version_response(
+                protocol_type,
+                "P",
+                "I",
+                1,
+                SCIVersionCheckResult::VersionsAreEqual,
+                checksum,
+            )
+        };
+        assert!(response(&checksum).is_err());
+        let response = response(&checksum[..SCI_MAX_CHECKSUM_LENGTH]).unwrap();
+        assert_eq!(response.payload.len(), 85);
+        assert_eq!(
+            response.version_response_checksum(),
+            Some(&checksum[..SCI_MAX_CHECKSUM_LENGTH])
+        );
+    }
+
+    #[test]
+    fn test_config_data() {
+        assert_eq!(
+            super::config_checksum(b"123456789"),
+            [0xCB, 0xF4, 0x39, 0x26]
+        );
+        let config = ManagedConfig::new(ProtocolType::SCIProtocolP).with_config_data(b"P1 left");
+        assert_eq!(config.checksum, super::config_checksum(b"P1 left"));
+        assert!(config.verify_config_data(b"P1 left").is_ok());
+        let error = config.verify_config_data(b"P1 right").unwrap_err();
+        assert!(matches!(error, SciError::ChecksumMismatch { .. }));
+        assert_eq!(error.close_reason(), Some(SCICloseReason::ChecksumMismatch));
+        assert!(ManagedConfig::new(ProtocolType::SCIProtocolP)
+            .verify_checksum(&[1, 2, 3])
+            .is_ok());
+    }
+}
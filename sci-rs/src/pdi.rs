@@ -0,0 +1,503 @@
+//! # PDI State Machine
+//!
+//! Enforces the order of the PDI handshake. The initiator (usually an
+//! interlocking using an [`SCIConnection`](crate::SCIConnection)) starts
+//! with a version check, which the responder (usually a field element
+//! using an [`SCIListener`](crate::SCIListener)) answers. After the
+//! initialisation, application telegrams are exchanged until the PDI is
+//! closed or released for maintenance.
+//!
+//! Telegrams that are not allowed in the current state are rejected by
+//! closing the PDI with [`SCICloseReason::ProtocolError`]. Managed
+//! endpoints drive their state machine on their own, see
+//! [`crate::managed`]. The state diagrams of both roles are shown in
+//! [`PDI_INITIATOR_TRANSITIONS`] and [`PDI_RESPONDER_TRANSITIONS`].
+
+use std::time::Duration;
+
+use rasta_rs::{
+    diagram::{self, Transition},
+    incident::{IncidentKind, IncidentReport},
+    message::{Message, RastaId},
+    state_transitions, RastaConnectionState,
+};
+
+use crate::{
+    baseline::BaselineProfile,
+    logging::event,
+    managed::{self, ManagedConfig},
+    SCICloseReason, SCIMessageType, SCITelegram, SCIVersionCheckResult, SciError,
+    VersionResponsePayload,
+};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PdiState {
+    #[default]
+    Closed,
+    /// The version check was sent (initiator)
+    /// or answered successfully (responder).
+    VersionCheck,
+    /// The initialisation was requested and is not completed yet.
+    Initialising,
+    Up,
+    /// The element was released for maintenance. Only a new
+    /// version check or a close telegram are accepted.
+    ReleasedForMaintenance,
+}
+
+state_transitions! {
+    /// The state changes of a [`PdiStateMachine`] of a [`PdiRole::Initiator`].
+    pub const PDI_INITIATOR_TRANSITIONS: [PdiState] = [
+        Closed => VersionCheck on "start",
+        ReleasedForMaintenance => VersionCheck on "start",
+        VersionCheck => Initialising on "VersionResponse",
+        VersionCheck => Closed on "Close / version or checksum mismatch / unexpected telegram",
+        Initialising => Up on "InitialisationCompleted",
+        Initialising => Closed on "Close / unexpected telegram",
+        Up => ReleasedForMaintenance on "ReleaseForMaintenance",
+        Up => Closed on "Close / unexpected telegram",
+        ReleasedForMaintenance => Closed on "Close / unexpected telegram",
+    ];
+}
+
+state_transitions! {
+    /// The state changes of a [`PdiStateMachine`] of a [`PdiRole::Responder`].
+    pub const PDI_RESPONDER_TRANSITIONS: [PdiState] = [
+        Closed => VersionCheck on "VersionCheck",
+        VersionCheck => Up on "InitialisationRequest",
+        VersionCheck => Closed on "Close / version mismatch / unexpected telegram",
+        Up => VersionCheck on "VersionCheck",
+        Up => ReleasedForMaintenance on "ReleaseForMaintenance",
+        Up => Closed on "Close / version mismatch / unexpected telegram",
+        ReleasedForMaintenance => VersionCheck on "VersionCheck",
+        ReleasedForMaintenance => Closed on "Close / version mismatch / unexpected telegram",
+    ];
+}
+
+/// The side of the handshake a state machine runs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PdiRole {
+    /// Starts the handshake, e.g. an interlocking.
+    Initiator,
+    /// Answers the handshake, e.g. a field element.
+    Responder,
+}
+
+/// What to do with a received telegram.
+pub enum PdiOutcome {
+    /// The telegram is an application telegram and should be passed on.
+    Deliver,
+    /// The telegram was consumed. The given telegrams (if any)
+    /// should be sent in response.
+    Respond(Vec<SCITelegram>),
+    /// The PDI was closed because of `error`.
+    /// `telegram` should be sent to tell the peer.
+    Close {
+        telegram: SCITelegram,
+        error: SciError,
+    },
+    /// The peer closed the PDI.
+    PeerClosed(SciError),
+}
+
+/// Tracks the PDI with one peer and produces the telegrams
+/// that advance the handshake.
+#[derive(Clone, Debug)]
+pub struct PdiStateMachine {
+    role: PdiRole,
+    name: String,
+    config: ManagedConfig,
+    baseline: BaselineProfile,
+    peer: Option<String>,
+    state: PdiState,
+}
+
+impl PdiStateMachine {
+    /// Creates a state machine for the endpoint called `name`,
+    /// which announces the version and checksum of `config`.
+    pub fn new(role: PdiRole, name: &str, config: ManagedConfig) -> Self {
+        Self {
+            role,
+            name: name.to_string(),
+            config,
+            baseline: BaselineProfile::default(),
+            peer: None,
+            state: PdiState::Closed,
+        }
+    }
+
+    pub fn with_baseline(mut self, baseline: BaselineProfile) -> Self {
+        self.baseline = baseline;
+        self
+    }
+
+    pub(crate) fn set_baseline(&mut self, baseline: BaselineProfile) {
+        self.baseline = baseline;
+    }
+
+    pub fn state(&self) -> PdiState {
+        self.state
+    }
+
+    pub fn role(&self) -> PdiRole {
+        self.role
+    }
+
+    pub fn config(&self) -> &ManagedConfig {
+        &self.config
+    }
+
+    /// The peer of the current or last handshake.
+    pub fn peer(&self) -> Option<&str> {
+        self.peer.as_deref()
+    }
+
+    /// Returns to [`PdiState::Closed`], e.g. after the underlying
+    /// connection was lost.
+    pub fn reset(&mut self) {
+        self.enter(PdiState::Closed);
+    }
+
+    /// The transitions of this state machine's role.
+    pub fn transitions(&self) -> &'static [Transition<PdiState>] {
+        match self.role {
+            PdiRole::Initiator => PDI_INITIATOR_TRANSITIONS,
+            PdiRole::Responder => PDI_RESPONDER_TRANSITIONS,
+        }
+    }
+
+    /// Changes the state along one of the [`PdiStateMachine::transitions`].
+    fn enter(&mut self, state: PdiState) {
+        debug_assert!(
+            self.state == state || diagram::allows(self.transitions(), self.state, state),
+            "Undocumented {:?} transition from {:?} to {state:?}",
+            self.role,
+            self.state
+        );
+        self.state = state;
+    }
+
+    /// Starts the handshake with `peer` and returns the version check
+    /// to send. Only an initiator can start a handshake, and only
+    /// while the PDI is closed or released for maintenance.
+    pub fn start(&mut self, peer: &str) -> Result<SCITelegram, SciError> {
+        let version_check = SCITelegram::version_check(
+            self.config.protocol_type,
+            &self.name,
+            peer,
+            self.config.version,
+        );
+        if self.role != PdiRole::Initiator
+            || !matches!(
+                self.state,
+                PdiState::Closed | PdiState::ReleasedForMaintenance
+            )
+        {
+            return Err(SciError::PdiStateViolation {
+                state: self.state,
+                message_type: version_check.message_type.into(),
+            });
+        }
+        self.peer.replace(peer.to_string());
+        self.enter(PdiState::VersionCheck);
+        Ok(version_check)
+    }
+
+    /// Advances the state machine with a received telegram.
+    /// `status` provides the status telegrams a responder sends
+    /// during the initialisation.
+    pub fn handle<S>(&mut self, telegram: &SCITelegram, status: S) -> PdiOutcome
+    where
+        S: FnOnce() -> Vec<SCITelegram>,
+    {
+        let message_type = telegram.message_type;
+        if message_type == SCIMessageType::pdi_close() {
+            self.enter(PdiState::Closed);
+            return PdiOutcome::PeerClosed(close_error(telegram));
+        }
+        if message_type == SCIMessageType::sci_timeout() {
+            return PdiOutcome::Respond(Vec::new());
+        }
+        match (self.role, self.state) {
+            (PdiRole::Responder, _) if message_type == SCIMessageType::pdi_version_check() => {
+                self.answer_version_check(telegram)
+            }
+            (PdiRole::Responder, PdiState::VersionCheck)
+                if message_type == SCIMessageType::pdi_initialisation_request() =>
+            {
+                let protocol_type = telegram.protocol_type;
+                let peer = telegram.sender.as_str();
+                let mut responses = vec![SCITelegram::initialisation_response(
+                    protocol_type,
+                    &self.name,
+                    peer,
+                )];
+                responses.append(&mut status());
+                responses.push(SCITelegram::initialisation_completed(
+                    protocol_type,
+                    &self.name,
+                    peer,
+                ));
+                self.enter(PdiState::Up);
+                PdiOutcome::Respond(responses)
+            }
+            (PdiRole::Initiator, PdiState::VersionCheck)
+                if message_type == SCIMessageType::pdi_version_response() =>
+            {
+                match self.verify_version_response(telegram) {
+                    Ok(()) => {
+                        self.enter(PdiState::Initialising);
+                        PdiOutcome::Respond(vec![SCITelegram::initialisation_request(
+                            telegram.protocol_type,
+                            &self.name,
+                            &telegram.sender,
+                        )])
+                    }
+                    Err(error) => {
+                        let reason = error
+                            .close_reason()
+                            .unwrap_or(SCICloseReason::ProtocolError);
+                        self.close(telegram, reason, error)
+                    }
+                }
+            }
+            (PdiRole::Initiator, PdiState::Initialising)
+                if message_type == SCIMessageType::pdi_initialisation_response() =>
+            {
+                PdiOutcome::Respond(Vec::new())
+            }
+            (PdiRole::Initiator, PdiState::Initialising)
+                if message_type == SCIMessageType::pdi_initialisation_completed() =>
+            {
+                self.enter(PdiState::Up);
+                PdiOutcome::Respond(Vec::new())
+            }
+            // Status telegrams sent during the initialisation
+            (PdiRole::Initiator, PdiState::Initialising)
+                if !managed::is_protocol_telegram(telegram) =>
+            {
+                PdiOutcome::Deliver
+            }
+            (_, PdiState::Up) if message_type == SCIMessageType::pdi_release_for_maintenance() => {
+                self.enter(PdiState::ReleasedForMaintenance);
+                PdiOutcome::Deliver
+            }
+            (_, PdiState::Up) if !managed::is_protocol_telegram(telegram) => PdiOutcome::Deliver,
+            (_, state) => {
+                let error = SciError::PdiStateViolation {
+                    state,
+                    message_type: message_type.into(),
+                };
+                self.close(telegram, SCICloseReason::ProtocolError, error)
+            }
+        }
+    }
+
+    /// Observes a telegram sent to the peer, so that closing the PDI or
+    /// releasing the element for maintenance is reflected in the state.
+    pub fn sent(&mut self, telegram: &SCITelegram) {
+        if telegram.message_type == SCIMessageType::pdi_close() {
+            self.enter(PdiState::Closed);
+        } else if telegram.message_type == SCIMessageType::pdi_release_for_maintenance()
+            && self.state == PdiState::Up
+        {
+            self.enter(PdiState::ReleasedForMaintenance);
+        }
+    }
+
+    fn answer_version_check(&mut self, telegram: &SCITelegram) -> PdiOutcome {
+        let result = if telegram.sci_version() == Some(self.config.version) {
+            SCIVersionCheckResult::VersionsAreEqual
+        } else {
+            SCIVersionCheckResult::VersionsAreNotEqual
+        };
+        match SCITelegram::version_response(
+            telegram.protocol_type,
+            &self.name,
+            &telegram.sender,
+            self.config.version,
+            result,
+            self.baseline
+                .version_response_checksum(&self.config.checksum),
+        ) {
+            Ok(response) => {
+                self.peer.replace(telegram.sender.clone());
+                self.enter(if result == SCIVersionCheckResult::VersionsAreEqual {
+                    PdiState::VersionCheck
+                } else {
+                    PdiState::Closed
+                });
+                PdiOutcome::Respond(vec![response])
+            }
+            Err(error) => self.close(telegram, SCICloseReason::ProtocolError, error),
+        }
+    }
+
+    fn verify_version_response(&self, response: &SCITelegram) -> Result<(), SciError> {
+        let payload = VersionResponsePayload::try_from(&response.payload)?;
+        if payload.result != SCIVersionCheckResult::VersionsAreEqual {
+            return Err(SciError::PdiClosed(SCICloseReason::OtherVersionRequired));
+        }
+        if self.baseline.version_response_has_checksum() {
+            self.config.verify_checksum(&payload.checksum)?;
+        }
+        Ok(())
+    }
+
+    fn close(
+        &mut self,
+        received: &SCITelegram,
+        reason: SCICloseReason,
+        error: SciError,
+    ) -> PdiOutcome {
+        self.enter(PdiState::Closed);
+        PdiOutcome::Close {
+            telegram: SCITelegram::close(
+                received.protocol_type,
+                &self.name,
+                &received.sender,
+                reason,
+            ),
+            error,
+        }
+    }
+}
+
+/// The error reported for a close telegram of the peer.
+pub(crate) fn close_error(close: &SCITelegram) -> SciError {
+    match close.payload.first().copied().map(SCICloseReason::try_from) {
+        Some(Ok(reason)) => SciError::PdiClosed(reason),
+        Some(Err(e)) => e,
+        None => SciError::InvalidTelegramLength(close.wire_length()),
+    }
+}
+
+/// The incident report of a close telegram of the peer,
+/// received in `msg` by the endpoint `local_id`.
+pub(crate) fn close_incident(
+    msg: &Message,
+    close: &SCITelegram,
+    local_id: RastaId,
+    state: RastaConnectionState,
+    t_max: Duration,
+) -> IncidentReport {
+    let mut report = IncidentReport::new(IncidentKind::PeerClosed, msg, local_id, state, t_max);
+    report.peer = Some(msg.sender());
+    report.reason = Some(match (close.close_reason(), close.payload.first()) {
+        (Some(reason), _) => format!("{reason:?}"),
+        (None, Some(value)) => format!("Unknown close reason {value:#04x}"),
+        (None, None) => "Missing close reason".to_string(),
+    });
+    event!(info, peer = close.sender.as_str(), reason = ?report.reason, "PDI closed by peer");
+    report
+}
+
+#[cfg(all(test, feature = "scip"))]
+mod tests {
+    use crate::{
+        managed::ManagedConfig,
+        scip::{SCIPointLocation, SCIPointTargetLocation},
+        ProtocolType, SCICloseReason, SCIMessageType, SCITelegram, SciError,
+    };
+
+    use super::{PdiOutcome, PdiRole, PdiState, PdiStateMachine};
+
+    fn respond(outcome: PdiOutcome) -> Vec<SCITelegram> {
+        match outcome {
+            PdiOutcome::Respond(responses) => responses,
+            _ => panic!("Expected responses"),
+        }
+    }
+
+    #[test]
+    fn test_handshake() {
+        let config = ManagedConfig::new(ProtocolType::SCIProtocolP).with_config_data(b"P1");
+        let mut interlocking = PdiStateMachine::new(PdiRole::Initiator, "I", config.clone());
+        let mut point = PdiStateMachine::new(PdiRole::Responder, "P1", config);
+        assert!(point.start("I").is_err());
+
+        let version_check = interlocking.start("P1").unwrap();
+        assert_eq!(interlocking.state(), PdiState::VersionCheck);
+        let version_response = respond(point.handle(&version_check, Vec::new)).remove(0);
+        assert_eq!(point.state(), PdiState::VersionCheck);
+        let init_request = respond(interlocking.handle(&version_response, Vec::new)).remove(0);
+        assert_eq!(interlocking.state(), PdiState::Initialising);
+
+        let status = SCITelegram::location_status("P1", "I", SCIPointLocation::PointLocationLeft);
+        let responses = respond(point.handle(&init_request, || vec![status]));
+        assert_eq!(point.state(), PdiState::Up);
+        assert_eq!(responses.len(), 3);
+        assert!(matches!(
+            interlocking.handle(&responses[0], Vec::new),
+            PdiOutcome::Respond(_)
+        ));
+        assert!(matches!(
+            interlocking.handle(&responses[1], Vec::new),
+            PdiOutcome::Deliver
+        ));
+        assert!(matches!(
+            interlocking.handle(&responses[2], Vec::new),
+            PdiOutcome::Respond(_)
+        ));
+        assert_eq!(interlocking.state(), PdiState::Up);
+
+        let release = SCITelegram::release_for_maintenance(ProtocolType::SCIProtocolP, "I", "P1");
+        interlocking.sent(&release);
+        assert_eq!(interlocking.state(), PdiState::ReleasedForMaintenance);
+        assert!(matches!(
+            point.handle(&release, Vec::new),
+            PdiOutcome::Deliver
+        ));
+        assert_eq!(point.state(), PdiState::ReleasedForMaintenance);
+
+        // Only a new version check is accepted after the release
+        let command = SCITelegram::change_location(
+            "I",
+            "P1",
+            SCIPointTargetLocation::PointLocationChangeToRight,
+        );
+        match point.handle(&command, Vec::new) {
+            PdiOutcome::Close { telegram, error } => {
+                assert_eq!(telegram.message_type, SCIMessageType::pdi_close());
+                assert_eq!(telegram.payload[0], SCICloseReason::ProtocolError as u8);
+                assert!(matches!(
+                    error,
+                    SciError::PdiStateViolation {
+                        state: PdiState::ReleasedForMaintenance,
+                        ..
+                    }
+                ));
+            }
+            _ => panic!("Expected the PDI to be closed"),
+        }
+        assert_eq!(point.state(), PdiState::Closed);
+        respond(point.handle(&version_check, Vec::new));
+        assert_eq!(point.state(), PdiState::VersionCheck);
+    }
+
+    #[test]
+    fn test_checksum_mismatch() {
+        let config = ManagedConfig::new(ProtocolType::SCIProtocolP);
+        let mut interlocking = PdiStateMachine::new(
+            PdiRole::Initiator,
+            "I",
+            config.clone().with_config_data(b"P1 left"),
+        );
+        let mut point = PdiStateMachine::new(
+            PdiRole::Responder,
+            "P1",
+            config.with_config_data(b"P1 right"),
+        );
+        let version_check = interlocking.start("P1").unwrap();
+        let version_response = respond(point.handle(&version_check, Vec::new)).remove(0);
+        match interlocking.handle(&version_response, Vec::new) {
+            PdiOutcome::Close { telegram, error } => {
+                assert_eq!(telegram.payload[0], SCICloseReason::ChecksumMismatch as u8);
+                assert!(matches!(error, SciError::ChecksumMismatch { .. }));
+            }
+            _ => panic!("Expected the PDI to be closed"),
+        }
+        assert_eq!(interlocking.state(), PdiState::Closed);
+    }
+}
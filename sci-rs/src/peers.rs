@@ -0,0 +1,122 @@
+//! # Peers
+//!
+//! Shared tables of known peers. Both types are cheap handles to the
+//! same underlying data, so a clone can be kept by the application to
+//! add or remove peers while an endpoint using another clone is running.
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, RwLock},
+};
+
+use rasta_rs::message::RastaId;
+
+use crate::SciName;
+
+/// Maps SCI names to the RaSTA IDs used to connect to them.
+#[derive(Clone, Debug, Default)]
+pub struct PeerMapping {
+    inner: Arc<RwLock<HashMap<String, RastaId>>>,
+}
+
+impl PeerMapping {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds or replaces the RaSTA ID of `name`, returning the previous one.
+    pub fn insert(&self, name: &str, id: RastaId) -> Option<RastaId> {
+        self.inner.write().unwrap().insert(name.to_string(), id)
+    }
+
+    /// Removes `name`. Established sessions with it are not affected.
+    pub fn remove(&self, name: &str) -> Option<RastaId> {
+        self.inner.write().unwrap().remove(name)
+    }
+
+    pub fn get(&self, name: &str) -> Option<RastaId> {
+        self.inner.read().unwrap().get(name).copied()
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.inner.read().unwrap().contains_key(name)
+    }
+
+    /// Whether `sender`, a name as received in a telegram, belongs to a
+    /// peer. Names are compared as they are padded on the wire.
+    pub fn contains_sender(&self, sender: &str) -> bool {
+        let sender = SciName::truncated(sender);
+        self.inner
+            .read()
+            .unwrap()
+            .keys()
+            .any(|name| SciName::truncated(name) == sender)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.read().unwrap().is_empty()
+    }
+
+    /// Returns a copy of the current entries.
+    pub fn snapshot(&self) -> HashMap<String, RastaId> {
+        self.inner.read().unwrap().clone()
+    }
+}
+
+impl From<HashMap<String, RastaId>> for PeerMapping {
+    fn from(value: HashMap<String, RastaId>) -> Self {
+        Self {
+            inner: Arc::new(RwLock::new(value)),
+        }
+    }
+}
+
+/// The SCI names an endpoint accepts telegrams from.
+#[derive(Clone, Debug, Default)]
+pub struct Allowlist {
+    inner: Arc<RwLock<HashSet<String>>>,
+}
+
+impl Allowlist {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allows `name`. Returns whether it was newly added.
+    pub fn allow(&self, name: &str) -> bool {
+        self.inner.write().unwrap().insert(name.to_string())
+    }
+
+    /// Revokes `name`. Returns whether it was allowed before.
+    pub fn revoke(&self, name: &str) -> bool {
+        self.inner.write().unwrap().remove(name)
+    }
+
+    pub fn is_allowed(&self, name: &str) -> bool {
+        self.inner.read().unwrap().contains(name)
+    }
+
+    /// Whether `sender`, a name as received in a telegram, is allowed.
+    /// Names are compared as they are padded on the wire.
+    pub fn allows_sender(&self, sender: &str) -> bool {
+        let sender = SciName::truncated(sender);
+        self.inner
+            .read()
+            .unwrap()
+            .iter()
+            .any(|name| SciName::truncated(name) == sender)
+    }
+
+    /// Returns a copy of the currently allowed names.
+    pub fn snapshot(&self) -> HashSet<String> {
+        self.inner.read().unwrap().clone()
+    }
+}
+
+impl<S: Into<String>> FromIterator<S> for Allowlist {
+    fn from_iter<T: IntoIterator<Item = S>>(iter: T) -> Self {
+        Self {
+            inner: Arc::new(RwLock::new(iter.into_iter().map(Into::into).collect())),
+        }
+    }
+}
@@ -0,0 +1,456 @@
+//! # Connection Pool
+//!
+//! Serves many [`SCIConnection`]s from a fixed number of worker threads
+//! instead of one thread per connection. Each connection is assigned to
+//! the worker serving the fewest connections. Workers poll their
+//! connections without blocking, send the telegrams submitted for them
+//! and keep them alive with heartbeats. Received telegrams and the state
+//! of the connections are reported as [`PoolEvent`]s.
+//!
+//! ```no_run
+//! # use std::collections::HashMap;
+//! # use rasta_rs::{RastaConnection, RastaError};
+//! # use sci_rs::{pool::{ConnectionPool, PoolEvent}, SCIConnection};
+//! let mut pool = ConnectionPool::new(4);
+//! for (element, id, addr) in [("P1", 2, "10.0.0.2:8888"), ("P2", 3, "10.0.0.3:8888")] {
+//!     let conn = RastaConnection::try_new(addr, 1)?;
+//!     let mapping = HashMap::from([(element.to_string(), id)]);
+//!     pool.add(element, SCIConnection::try_new(conn, "I".to_string(), mapping)?)?;
+//! }
+//! while let Ok(event) = pool.events().recv() {
+//!     if let PoolEvent::Telegram { element, telegram } = event {
+//!         println!("{element}: {:?}", telegram.message_type());
+//!     }
+//! }
+//! # Ok::<(), RastaError>(())
+//! ```
+
+use std::{
+    collections::HashMap,
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    thread,
+    time::Duration,
+};
+
+use rasta_rs::RastaError;
+
+use crate::{
+    stream::{SCITelegramSender, SendTag},
+    SCIConnection, SCITelegram,
+};
+
+/// How long an idle worker waits before polling its connections again.
+pub const POOL_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Something that happened to a connection of a [`ConnectionPool`].
+pub enum PoolEvent {
+    /// The connection to `element` was opened (and its PDI started
+    /// in managed mode).
+    Connected { element: String },
+    Telegram {
+        element: String,
+        telegram: SCITelegram,
+    },
+    /// Data from `element` that is not an SCI telegram, reported for
+    /// connections added with [`ConnectionPool::add_with_raw_data`].
+    RawData { element: String, data: Vec<u8> },
+    /// The connection to `element` failed. Telegrams can no longer be
+    /// submitted for it. Remove it before adding a new connection.
+    Failed { element: String, error: RastaError },
+    /// The connection to `element` was closed by [`ConnectionPool::remove`].
+    Removed { element: String },
+}
+
+enum Command {
+    /// Adds a connection, optionally reporting its raw data.
+    Add(String, Box<SCIConnection>, bool),
+    SendRawData(String, Vec<u8>),
+    Remove(String),
+    Shutdown,
+}
+
+struct Entry {
+    element: String,
+    conn: SCIConnection,
+}
+
+impl Entry {
+    /// Sends queued telegrams and reports received ones. Heartbeats are
+    /// sent while polling. Returns whether any telegram was exchanged.
+    fn service(&mut self, events: &Sender<PoolEvent>) -> Result<bool, RastaError> {
+        let mut active = false;
+        if self.conn.send_queued()? > 0 {
+            self.conn.flush()?;
+            active = true;
+        }
+        while let Some(telegram) = self.conn.poll_telegram(Duration::ZERO)? {
+            let _ = events.send(PoolEvent::Telegram {
+                element: self.element.clone(),
+                telegram,
+            });
+            active = true;
+        }
+        Ok(active)
+    }
+}
+
+struct Worker {
+    commands: Sender<Command>,
+    thread: Option<thread::JoinHandle<()>>,
+    connections: usize,
+}
+
+/// Multiplexes [`SCIConnection`]s over a fixed number of threads.
+/// Dropping the pool closes all connections.
+pub struct ConnectionPool {
+    workers: Vec<Worker>,
+    elements: HashMap<String, (usize, SCITelegramSender)>,
+    events: Receiver<PoolEvent>,
+}
+
+impl ConnectionPool {
+    /// Creates a pool with `threads` (at least one) worker threads.
+    pub fn new(threads: usize) -> Self {
+        let (events_tx, events) = mpsc::channel();
+        let workers = (0..threads.max(1))
+            .map(|_| {
+                let (commands, receiver) = mpsc::channel();
+                let events = events_tx.clone();
+                Worker {
+                    commands,
+                    thread: Some(thread::spawn(move || work(receiver, events))),
+                    connections: 0,
+                }
+            })
+            .collect();
+        Self {
+            workers,
+            elements: HashMap::new(),
+            events,
+        }
+    }
+
+    /// Adds the connection to `element`. It is opened by its worker,
+    /// which reports the outcome as [`PoolEvent::Connected`] or
+    /// [`PoolEvent::Failed`].
+    pub fn add(&mut self, element: &str, conn: SCIConnection) -> Result<(), RastaError> {
+        self.insert(element, conn, false)
+    }
+
+    /// Like [`ConnectionPool::add`], but data from `element` that is not an
+    /// SCI telegram is reported as [`PoolEvent::RawData`] instead of failing
+    /// the connection. This replaces the raw data handler of `conn`.
+    pub fn add_with_raw_data(
+        &mut self,
+        element: &str,
+        conn: SCIConnection,
+    ) -> Result<(), RastaError> {
+        self.insert(element, conn, true)
+    }
+
+    fn insert(&mut self, element: &str, conn: SCIConnection, raw: bool) -> Result<(), RastaError> {
+        if self.elements.contains_key(element) {
+            return Err(RastaError::Other(format!(
+                "{element} is already part of the pool"
+            )));
+        }
+        let (index, worker) = self
+            .workers
+            .iter_mut()
+            .enumerate()
+            .min_by_key(|(_, worker)| worker.connections)
+            .unwrap();
+        let sender = conn.sender();
+        worker
+            .commands
+            .send(Command::Add(element.to_string(), Box::new(conn), raw))
+            .map_err(|_| RastaError::StateError)?;
+        worker.connections += 1;
+        self.elements.insert(element.to_string(), (index, sender));
+        Ok(())
+    }
+
+    /// Closes the connection to `element` and removes it from the pool.
+    pub fn remove(&mut self, element: &str) -> Result<(), RastaError> {
+        let (index, _) = self
+            .elements
+            .remove(element)
+            .ok_or_else(|| RastaError::Other(format!("Unknown element {element}")))?;
+        let worker = &mut self.workers[index];
+        worker.connections -= 1;
+        worker
+            .commands
+            .send(Command::Remove(element.to_string()))
+            .map_err(|_| RastaError::StateError)
+    }
+
+    /// Queues `telegram` for the connection to its receiver. Telegrams
+    /// for the same receiver are sent in the order they were submitted.
+    pub fn submit(&self, telegram: SCITelegram) -> Result<SendTag, RastaError> {
+        let (_, sender) = self
+            .elements
+            .get(&telegram.receiver)
+            .ok_or_else(|| RastaError::Other(format!("Unknown element {}", telegram.receiver)))?;
+        sender.send(telegram)
+    }
+
+    /// Sends `data` to `element` as a plain RaSTA data message,
+    /// see [`SCIConnection::send_raw_data`].
+    pub fn submit_raw_data(&self, element: &str, data: &[u8]) -> Result<(), RastaError> {
+        let (index, _) = self
+            .elements
+            .get(element)
+            .ok_or_else(|| RastaError::Other(format!("Unknown element {element}")))?;
+        self.workers[*index]
+            .commands
+            .send(Command::SendRawData(element.to_string(), data.to_vec()))
+            .map_err(|_| RastaError::StateError)
+    }
+
+    /// The elements whose connections are part of the pool.
+    pub fn elements(&self) -> impl Iterator<Item = &str> {
+        self.elements.keys().map(String::as_str)
+    }
+
+    /// The events of all connections in the pool.
+    pub fn events(&self) -> &Receiver<PoolEvent> {
+        &self.events
+    }
+
+    /// Closes all connections and waits for the workers to finish.
+    pub fn shutdown(mut self) {
+        self.stop();
+    }
+
+    fn stop(&mut self) {
+        for worker in &self.workers {
+            let _ = worker.commands.send(Command::Shutdown);
+        }
+        for worker in &mut self.workers {
+            if let Some(thread) = worker.thread.take() {
+                let _ = thread.join();
+            }
+        }
+        self.elements.clear();
+    }
+}
+
+impl Drop for ConnectionPool {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Serves the connections assigned to one worker until the pool shuts down.
+fn work(commands: Receiver<Command>, events: Sender<PoolEvent>) {
+    let mut entries: Vec<Entry> = Vec::new();
+    let mut idle = false;
+    loop {
+        let first = if idle {
+            match commands.recv_timeout(POOL_POLL_INTERVAL) {
+                Ok(command) => Some(command),
+                Err(RecvTimeoutError::Timeout) => None,
+                Err(RecvTimeoutError::Disconnected) => Some(Command::Shutdown),
+            }
+        } else {
+            None
+        };
+        for command in first.into_iter().chain(commands.try_iter()) {
+            match command {
+                Command::Add(element, mut conn, raw) => {
+                    if raw {
+                        let events = events.clone();
+                        let element = element.clone();
+                        conn.set_raw_data_handler(move |data| {
+                            let _ = events.send(PoolEvent::RawData {
+                                element: element.clone(),
+                                data: data.to_vec(),
+                            });
+                        });
+                    }
+                    match conn.open(&element) {
+                        Ok(()) => {
+                            let _ = events.send(PoolEvent::Connected {
+                                element: element.clone(),
+                            });
+                            entries.push(Entry {
+                                element,
+                                conn: *conn,
+                            });
+                        }
+                        Err(error) => {
+                            let _ = events.send(PoolEvent::Failed { element, error });
+                        }
+                    }
+                }
+                Command::SendRawData(element, data) => {
+                    if let Some(index) = entries.iter().position(|e| e.element == element) {
+                        let entry = &mut entries[index];
+                        let sent = entry.conn.send_raw_data(&data);
+                        if let Err(error) = sent.and_then(|_| entry.conn.flush()) {
+                            entries.swap_remove(index);
+                            let _ = events.send(PoolEvent::Failed { element, error });
+                        }
+                    }
+                }
+                Command::Remove(element) => {
+                    if let Some(index) = entries.iter().position(|e| e.element == element) {
+                        let mut entry = entries.swap_remove(index);
+                        let _ = entry.conn.conn.close_connection();
+                        let _ = events.send(PoolEvent::Removed { element });
+                    }
+                }
+                Command::Shutdown => {
+                    for entry in &mut entries {
+                        let _ = entry.conn.conn.close_connection();
+                    }
+                    return;
+                }
+            }
+        }
+        idle = true;
+        entries.retain_mut(|entry| match entry.service(&events) {
+            Ok(active) => {
+                idle &= !active;
+                true
+            }
+            Err(error) => {
+                let _ = events.send(PoolEvent::Failed {
+                    element: entry.element.clone(),
+                    error,
+                });
+                false
+            }
+        });
+    }
+}
+
+#[cfg(all(test, feature = "test-support", feature = "scip"))]
+mod tests {
+    use std::{collections::HashMap, thread, time::Duration};
+
+    use rasta_rs::{RastaConnection, RastaListener};
+
+    use crate::{
+        managed::ManagedConfig,
+        scip::{SCIPointLocation, SCIPointTargetLocation},
+        testing::FakeElement,
+        ProtocolType, SCIConnection, SCIListener, SCITelegram,
+    };
+
+    use super::{ConnectionPool, PoolEvent};
+
+    #[test]
+    fn test_pool_multiplexes_connections() {
+        let points: Vec<FakeElement> = (0..4)
+            .map(|i| {
+                FakeElement::point(
+                    &format!("P{i}"),
+                    10 + i,
+                    SCIPointLocation::PointLocationLeft,
+                )
+                .unwrap()
+            })
+            .collect();
+        let mut pool = ConnectionPool::new(2);
+        for point in &points {
+            let conn = RastaConnection::try_new(point.addr(), 1).unwrap();
+            let mapping = HashMap::from([(point.name().to_string(), point.rasta_id())]);
+            let mut conn = SCIConnection::try_new(conn, "I".to_string(), mapping).unwrap();
+            conn.set_managed(ManagedConfig::new(ProtocolType::SCIProtocolP));
+            pool.add(point.name(), conn).unwrap();
+        }
+        assert!(pool.add("P0", dummy_connection(&points[0])).is_err());
+
+        let mut connected = 0;
+        let mut statuses = 0;
+        while connected < points.len() || statuses < points.len() {
+            match pool.events().recv_timeout(Duration::from_secs(5)).unwrap() {
+                PoolEvent::Connected { .. } => connected += 1,
+                PoolEvent::Telegram { .. } => statuses += 1,
+                _ => panic!("Unexpected event"),
+            }
+        }
+
+        // Idle connections are kept alive with heartbeats
+        thread::sleep(Duration::from_millis(700));
+        pool.submit(SCITelegram::change_location(
+            "I",
+            "P3",
+            SCIPointTargetLocation::PointLocationChangeToRight,
+        ))
+        .unwrap();
+        match pool.events().recv_timeout(Duration::from_secs(5)).unwrap() {
+            PoolEvent::Telegram { element, telegram } => {
+                assert_eq!(element, "P3");
+                assert_eq!(
+                    telegram.payload[0],
+                    SCIPointLocation::PointLocationRight as u8
+                );
+            }
+            _ => panic!("Unexpected event"),
+        }
+
+        pool.remove("P0").unwrap();
+        assert!(matches!(
+            pool.events().recv_timeout(Duration::from_secs(5)).unwrap(),
+            PoolEvent::Removed { element } if element == "P0"
+        ));
+        assert!(pool
+            .submit(SCITelegram::change_location(
+                "I",
+                "P0",
+                SCIPointTargetLocation::PointLocationChangeToRight,
+            ))
+            .is_err());
+        pool.shutdown();
+    }
+
+    #[test]
+    fn test_pool_raw_data() {
+        // A neighbouring system answers plain data next to SCI telegrams
+        let listener = RastaListener::try_new("127.0.0.1:0", 2).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut listener = SCIListener::new(listener, "N".to_string());
+        listener.set_raw_data_handler(|data| vec![data.iter().rev().copied().collect()]);
+        thread::spawn(move || {
+            listener.listen(|telegram| {
+                Some(SCITelegram::location_status(
+                    "N",
+                    &telegram.sender,
+                    SCIPointLocation::PointLocationRight,
+                ))
+            })
+        });
+
+        let mut pool = ConnectionPool::new(1);
+        let conn = RastaConnection::try_new(addr, 1).unwrap();
+        let mapping = HashMap::from([("N".to_string(), 2)]);
+        let conn = SCIConnection::try_new(conn, "I".to_string(), mapping).unwrap();
+        pool.add_with_raw_data("N", conn).unwrap();
+        let next_event = || pool.events().recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(matches!(next_event(), PoolEvent::Connected { .. }));
+
+        pool.submit_raw_data("N", b"neighbour").unwrap();
+        match next_event() {
+            PoolEvent::RawData { element, data } => {
+                assert_eq!(element, "N");
+                assert_eq!(data, b"ruobhgien");
+            }
+            _ => panic!("Unexpected event"),
+        }
+        pool.submit(SCITelegram::change_location(
+            "I",
+            "N",
+            SCIPointTargetLocation::PointLocationChangeToRight,
+        ))
+        .unwrap();
+        assert!(matches!(next_event(), PoolEvent::Telegram { .. }));
+        pool.shutdown();
+    }
+
+    fn dummy_connection(point: &FakeElement) -> SCIConnection {
+        let conn = RastaConnection::try_new(point.addr(), 1).unwrap();
+        SCIConnection::try_new(conn, "I".to_string(), HashMap::new()).unwrap()
+    }
+}
@@ -0,0 +1,18 @@
+//! The types needed by most users of SCI endpoints. Import them all
+//! with `use sci_rs::prelude::*;`. With the `rasta` feature, this
+//! includes [`rasta_rs::prelude`], so one import suffices to set up
+//! listeners and connections. Protocol-specific types stay in their
+//! modules, e.g. [`scip`](crate::scip).
+
+#[cfg(feature = "rasta")]
+pub use rasta_rs::prelude::*;
+
+#[cfg(feature = "rasta")]
+pub use crate::{
+    managed::ManagedConfig, MessageFromSci, SCICommand, SCIConnection, SCIListener, SequenceState,
+    StatusProvider,
+};
+pub use crate::{
+    ProtocolType, SCIAvailability, SCICloseReason, SCIMessageType, SCIPayload, SCITelegram,
+    SCIVersionCheckResult, SciError, VersionResponsePayload, SCI_VERSION,
+};
@@ -0,0 +1,145 @@
+//! # Replay
+//!
+//! Feeds the incoming telegrams of a [`Journal`] to an application
+//! handler and checks that it responds exactly like the recorded
+//! session did. This allows golden-master regression tests of
+//! element and interlocking logic without a network.
+
+use std::{thread, time::Duration};
+
+use crate::{
+    journal::{Direction, Journal},
+    SCITelegram,
+};
+
+/// How the time between recorded telegrams is reproduced.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReplayTiming {
+    /// Keep the original relative timing between incoming telegrams.
+    Original,
+    /// Feed telegrams as fast as the handler processes them.
+    AsFastAsPossible,
+}
+
+/// The handler responded differently than recorded.
+#[derive(Clone, thiserror::Error)]
+#[error(
+    "Responses to journal entry {entry} differ: expected {} telegram(s), got {}",
+    expected.len(),
+    actual.len()
+)]
+pub struct ReplayMismatch {
+    /// The index of the incoming journal entry whose responses differ.
+    pub entry: usize,
+    pub expected: Vec<SCITelegram>,
+    pub actual: Vec<SCITelegram>,
+}
+
+impl std::fmt::Debug for ReplayMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let names = |telegrams: &[SCITelegram]| {
+            telegrams
+                .iter()
+                .map(|t| t.to_string())
+                .collect::<Vec<_>>()
+        };
+        f.debug_struct("ReplayMismatch")
+            .field("entry", &self.entry)
+            .field("expected", &names(&self.expected))
+            .field("actual", &names(&self.actual))
+            .finish()
+    }
+}
+
+/// Replays `journal` against `handler` as fast as possible.
+/// `handler` has the same signature as the callback of
+/// [`SCIListener::listen`](crate::SCIListener::listen).
+pub fn run<F, I>(journal: &Journal, handler: F) -> Result<(), ReplayMismatch>
+where
+    F: FnMut(SCITelegram) -> I,
+    I: IntoIterator<Item = SCITelegram>,
+{
+    run_with_timing(journal, ReplayTiming::AsFastAsPossible, handler)
+}
+
+/// Replays `journal` against `handler` using the given timing.
+/// The telegrams recorded as outgoing after each incoming telegram
+/// are the expected responses of the handler.
+pub fn run_with_timing<F, I>(
+    journal: &Journal,
+    timing: ReplayTiming,
+    mut handler: F,
+) -> Result<(), ReplayMismatch>
+where
+    F: FnMut(SCITelegram) -> I,
+    I: IntoIterator<Item = SCITelegram>,
+{
+    let entries = journal.entries();
+    let mut previous: Option<Duration> = None;
+    for (idx, entry) in entries.iter().enumerate() {
+        if entry.direction != Direction::Incoming {
+            continue;
+        }
+        if timing == ReplayTiming::Original {
+            if let Some(previous) = previous {
+                thread::sleep(entry.elapsed.saturating_sub(previous));
+            }
+            previous.replace(entry.elapsed);
+        }
+        let expected: Vec<SCITelegram> = entries[idx + 1..]
+            .iter()
+            .take_while(|e| e.direction == Direction::Outgoing)
+            .map(|e| e.telegram.clone())
+            .collect();
+        let actual: Vec<SCITelegram> = handler(entry.telegram.clone()).into_iter().collect();
+        let as_bytes = |telegrams: &[SCITelegram]| {
+            telegrams
+                .iter()
+                .map(|t| Vec::<u8>::from(t.clone()))
+                .collect::<Vec<_>>()
+        };
+        if as_bytes(&expected) != as_bytes(&actual) {
+            return Err(ReplayMismatch {
+                entry: idx,
+                expected,
+                actual,
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(all(test, feature = "scip"))]
+mod tests {
+    use crate::{
+        journal::{Direction, Journal},
+        scip::{SCIPointLocation, SCIPointTargetLocation},
+        SCITelegram,
+    };
+
+    #[test]
+    fn test_replay() {
+        let journal = Journal::new();
+        journal.record(
+            Direction::Incoming,
+            &SCITelegram::change_location(
+                "I",
+                "P",
+                SCIPointTargetLocation::PointLocationChangeToRight,
+            ),
+        );
+        journal.record(
+            Direction::Outgoing,
+            &SCITelegram::location_status("P", "I", SCIPointLocation::PointLocationRight),
+        );
+        assert!(super::run(&journal, |_| Some(SCITelegram::location_status(
+            "P",
+            "I",
+            SCIPointLocation::PointLocationRight
+        )))
+        .is_ok());
+        let mismatch = super::run(&journal, |_| None).unwrap_err();
+        assert_eq!(mismatch.entry, 0);
+        assert_eq!(mismatch.expected.len(), 1);
+    }
+}
@@ -0,0 +1,228 @@
+//! # SCI Generic Input/Output
+//!
+//! The Standard Communication Interface for generic I/O object controllers,
+//! which switch outputs and read inputs whose meaning is only known to the
+//! interlocking, e.g. relays of level crossings or barriers of depots.
+//! Each controller has up to [`ChannelStates::CHANNELS`] inputs and outputs,
+//! whose states are transmitted as a bitfield.
+
+use alloc::string::ToString;
+
+use crate::{
+    impl_sci_message_type, ProtocolType, SCIMessageType, SCIPayload, SCITelegram, SciError,
+};
+
+#[derive(Clone, Copy, Debug, thiserror::Error)]
+pub enum SciGioError {
+    #[error("Unknown Rejection Reason {0:x}")]
+    UnknownRejectionReason(u8),
+    #[error("Bad Payload Length {0}")]
+    BadPayloadLength(usize),
+}
+
+impl_sci_message_type!(
+    (scigio_set_outputs, 0x0001),
+    (scigio_input_status, 0x0002),
+    (scigio_output_status, 0x0003),
+    (scigio_command_rejected, 0x0004)
+);
+
+enumerate! {
+    GioRejectionReason,
+    u8,
+    SciGioError::UnknownRejectionReason,
+    {Operational = 0x01, Technical = 0x02}
+}
+
+/// The states of the inputs or outputs of a controller. Channel 0 is the
+/// least significant bit; a set bit means the channel is active.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ChannelStates(u32);
+
+impl ChannelStates {
+    /// The number of channels of a controller.
+    pub const CHANNELS: u8 = 32;
+
+    /// Whether `channel` is active. Channels beyond
+    /// [`ChannelStates::CHANNELS`] are never active.
+    pub fn get(&self, channel: u8) -> bool {
+        channel < Self::CHANNELS && self.0 & (1 << channel) != 0
+    }
+
+    /// Activates or deactivates `channel`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `channel` is not below [`ChannelStates::CHANNELS`].
+    pub fn set(&mut self, channel: u8, active: bool) {
+        assert!(channel < Self::CHANNELS, "invalid channel {channel}");
+        if active {
+            self.0 |= 1 << channel;
+        } else {
+            self.0 &= !(1 << channel);
+        }
+    }
+
+    /// Like [`ChannelStates::set`], for building states in one expression.
+    pub fn with(mut self, channel: u8, active: bool) -> Self {
+        self.set(channel, active);
+        self
+    }
+
+    /// The active channels in ascending order.
+    pub fn active(&self) -> impl Iterator<Item = u8> + '_ {
+        (0..Self::CHANNELS).filter(|channel| self.get(*channel))
+    }
+
+    pub fn bits(&self) -> u32 {
+        self.0
+    }
+}
+
+impl From<u32> for ChannelStates {
+    fn from(value: u32) -> Self {
+        Self(value)
+    }
+}
+
+impl TryFrom<&[u8]> for ChannelStates {
+    type Error = SciError;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        let bits =
+            <[u8; 4]>::try_from(value).map_err(|_| SciGioError::BadPayloadLength(value.len()))?;
+        Ok(Self(u32::from_be_bytes(bits)))
+    }
+}
+
+impl From<ChannelStates> for SCIPayload {
+    fn from(value: ChannelStates) -> Self {
+        SCIPayload::from_slice(&value.0.to_be_bytes())
+    }
+}
+
+/// The payload of [`SCITelegram::gio_set_outputs`]. Only the outputs
+/// selected by `mask` are switched to their state in `states`,
+/// all others keep their current state.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SetOutputsPayload {
+    pub mask: ChannelStates,
+    pub states: ChannelStates,
+}
+
+impl SetOutputsPayload {
+    /// Applies the command to the `current` states of the outputs.
+    pub fn apply(&self, current: ChannelStates) -> ChannelStates {
+        ChannelStates((current.0 & !self.mask.0) | (self.states.0 & self.mask.0))
+    }
+}
+
+impl TryFrom<&[u8]> for SetOutputsPayload {
+    type Error = SciError;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        if value.len() != 8 {
+            return Err(SciGioError::BadPayloadLength(value.len()).into());
+        }
+        Ok(Self {
+            mask: ChannelStates::try_from(&value[..4])?,
+            states: ChannelStates::try_from(&value[4..])?,
+        })
+    }
+}
+
+impl From<SetOutputsPayload> for SCIPayload {
+    fn from(value: SetOutputsPayload) -> Self {
+        let mut data = [0; 8];
+        data[..4].copy_from_slice(&value.mask.0.to_be_bytes());
+        data[4..].copy_from_slice(&value.states.0.to_be_bytes());
+        SCIPayload::from_slice(&data)
+    }
+}
+
+impl SCITelegram {
+    pub fn gio_set_outputs(sender: &str, receiver: &str, payload: SetOutputsPayload) -> Self {
+        Self {
+            protocol_type: ProtocolType::SCIProtocolGIO,
+            message_type: SCIMessageType::scigio_set_outputs(),
+            sender: sender.to_string(),
+            receiver: receiver.to_string(),
+            payload: payload.into(),
+        }
+    }
+
+    pub fn gio_input_status(sender: &str, receiver: &str, inputs: ChannelStates) -> Self {
+        Self {
+            protocol_type: ProtocolType::SCIProtocolGIO,
+            message_type: SCIMessageType::scigio_input_status(),
+            sender: sender.to_string(),
+            receiver: receiver.to_string(),
+            payload: inputs.into(),
+        }
+    }
+
+    pub fn gio_output_status(sender: &str, receiver: &str, outputs: ChannelStates) -> Self {
+        Self {
+            protocol_type: ProtocolType::SCIProtocolGIO,
+            message_type: SCIMessageType::scigio_output_status(),
+            sender: sender.to_string(),
+            receiver: receiver.to_string(),
+            payload: outputs.into(),
+        }
+    }
+
+    pub fn gio_command_rejected(sender: &str, receiver: &str, reason: GioRejectionReason) -> Self {
+        Self {
+            protocol_type: ProtocolType::SCIProtocolGIO,
+            message_type: SCIMessageType::scigio_command_rejected(),
+            sender: sender.to_string(),
+            receiver: receiver.to_string(),
+            payload: SCIPayload::from_slice(&[reason as u8]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        scigio::{ChannelStates, SetOutputsPayload},
+        ProtocolType, SCIMessageType, SCITelegram, SciError,
+    };
+
+    #[test]
+    fn test_gio_telegrams() {
+        let payload = SetOutputsPayload {
+            mask: ChannelStates::default().with(0, true).with(31, true),
+            states: ChannelStates::default().with(31, true),
+        };
+        let telegram = SCITelegram::gio_set_outputs("EIL", "GIO", payload);
+        let data: Vec<u8> = telegram.into();
+        let telegram = SCITelegram::try_from(data.as_slice()).unwrap();
+        assert_eq!(telegram.protocol_type, ProtocolType::SCIProtocolGIO);
+        assert_eq!(telegram.message_type, SCIMessageType::scigio_set_outputs());
+        assert_eq!(
+            telegram.to_string(),
+            "SCIProtocolGIO: SetOutputs EIL -> GIO [80 00 00 01 80 00 00 00]"
+        );
+        assert_eq!(
+            &telegram.payload[..8],
+            [0x80, 0x00, 0x00, 0x01, 0x80, 0x00, 0x00, 0x00]
+        );
+        let received = SetOutputsPayload::try_from(&*telegram.payload).unwrap();
+        assert_eq!(received, payload);
+
+        let current = ChannelStates::from(0b11);
+        let outputs = received.apply(current);
+        assert_eq!(outputs.active().collect::<Vec<_>>(), [1, 31]);
+        assert!(!outputs.get(32));
+
+        let status = SCITelegram::gio_input_status("GIO", "EIL", outputs);
+        assert_eq!(ChannelStates::try_from(&*status.payload).unwrap(), outputs);
+        assert!(matches!(
+            ChannelStates::try_from(&[0, 1][..]),
+            Err(SciError::Gio(_))
+        ));
+    }
+}
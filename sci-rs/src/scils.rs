@@ -2,29 +2,40 @@
 //!
 //! The Standard Communication Interface for light signals.
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, thiserror::Error)]
 pub enum SciLsError {
+    #[error("Invalid Main Signal Aspect {0:x}")]
     InvalidMainSignalAspect(u8),
+    #[error("Invalid Additional Signal Aspect {0:x}")]
     InvalidAdditionalSignalAspect(u8),
+    #[error("Invalid Zs2 Aspect {0:x}")]
     InvalidZs2Aspect(u8),
+    #[error("Invalid Zs3 Aspect {0:x}")]
     InvalidZs3Aspect(u8),
+    #[error("Invalid Depreciation Information {0:x}")]
     InvalidDepreciationInformation(u8),
+    #[error("Invalid Driveway Information {0:x}")]
     InvalidDrivewayInformation(u8),
+    #[error("Invalid Dark Switching {0:x}")]
     InvalidDarkSwitching(u8),
+    #[error("Invalid Brightness {0:x}")]
     InvalidBrightness(u8),
+    #[error("Invalid Nationally Specified Information {0:x}")]
+    InvalidNationalInformation(u8),
+    #[error("Invalid Rejection Reason {0:x}")]
+    InvalidRejectionReason(u8),
 }
 
-impl Display for SciLsError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:?}", self)
-    }
-}
-
-impl std::error::Error for SciLsError {}
+#[cfg(feature = "std")]
+use alloc::string::String;
+use alloc::{boxed::Box, string::ToString, vec, vec::Vec};
+use core::time::Duration;
+#[cfg(feature = "std")]
+use std::time::Instant;
 
-use std::fmt::Display;
-
-use crate::SciError;
+#[cfg(feature = "std")]
+use crate::SCI_VERSION;
+use crate::{split_extensions, ParseMode, SciError};
 
 use super::{ProtocolType, SCIMessageType, SCIPayload, SCITelegram};
 
@@ -44,10 +55,15 @@ impl SCIMessageType {
     pub const fn scils_brightness_status() -> Self {
         Self(0x0004)
     }
+
+    pub const fn scils_command_rejected() -> Self {
+        Self(0x0005)
+    }
 }
 
 /// The possible aspects of a main signal
 #[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
 pub enum SCILSMain {
     Hp0 = 0x01,
@@ -70,6 +86,16 @@ pub enum SCILSMain {
     Off = 0xFF,
 }
 
+impl SCILSMain {
+    /// Whether the aspect is shown as a flashing light.
+    pub fn is_flashing(&self) -> bool {
+        matches!(
+            self,
+            Self::Ks1Flashing | Self::Ks1FlashingWithAdditionalLight
+        )
+    }
+}
+
 impl TryFrom<u8> for SCILSMain {
     type Error = SciError;
 
@@ -101,6 +127,7 @@ impl TryFrom<u8> for SCILSMain {
 /// (excluding Zs2(v) and Zs3(v) which can show
 /// additional information and are listed separately)
 #[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
 pub enum SCILSAdditional {
     Zs1 = 0x01,
@@ -130,6 +157,7 @@ impl TryFrom<u8> for SCILSAdditional {
 
 /// Possible aspects for Zs3 and Zs3v signals
 #[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
 pub enum SCILSZs3 {
     Index1 = 0x01,
@@ -178,7 +206,8 @@ impl TryFrom<u8> for SCILSZs3 {
 }
 
 /// Possible aspects for Zs2 and Zs2v signals
-#[derive(Default, Clone, Copy)]
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
 pub enum SCILSZs2 {
     LetterA = 0x01,
@@ -248,7 +277,8 @@ impl TryFrom<u8> for SCILSZs2 {
     }
 }
 
-#[derive(Default, Clone, Copy)]
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
 pub enum SCILSDepreciationInformation {
     Type1 = 0x01,
@@ -272,7 +302,8 @@ impl TryFrom<u8> for SCILSDepreciationInformation {
     }
 }
 
-#[derive(Default, Clone, Copy)]
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
 pub enum SCILSDrivewayInformation {
     Way1 = 0x1,
@@ -298,7 +329,8 @@ impl TryFrom<u8> for SCILSDrivewayInformation {
     }
 }
 
-#[derive(Default, Clone, Copy)]
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
 pub enum SCILSDarkSwitching {
     Show = 0x01,
@@ -321,6 +353,7 @@ impl TryFrom<u8> for SCILSDarkSwitching {
 }
 
 #[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
 pub enum SCILSBrightness {
     Day = 0x01,
@@ -341,7 +374,35 @@ impl TryFrom<u8> for SCILSBrightness {
     }
 }
 
-#[derive(Clone)]
+/// Why a signal did not execute a command.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u8)]
+pub enum SCILSRejectionReason {
+    Operational = 0x01,
+    /// The hardware cannot show the commanded aspect.
+    Technical = 0x02,
+}
+
+impl TryFrom<u8> for SCILSRejectionReason {
+    type Error = SciError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x01 => Ok(Self::Operational),
+            0x02 => Ok(Self::Technical),
+            v => Err(SciLsError::InvalidRejectionReason(v).into()),
+        }
+    }
+}
+
+/// The length of a signal aspect including the nationally specified information.
+pub(crate) const SIGNAL_ASPECT_LENGTH: usize = 18;
+/// The length of the mandatory part of a signal aspect.
+pub(crate) const MANDATORY_SIGNAL_ASPECT_LENGTH: usize = 9;
+
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// A complete signal aspect.
 pub struct SCILSSignalAspect {
     main: SCILSMain,
@@ -355,6 +416,7 @@ pub struct SCILSSignalAspect {
     downstream_driveway_information: SCILSDrivewayInformation,
     dark_switching: SCILSDarkSwitching,
     nationally_specified_information: [u8; 9],
+    extensions: Vec<u8>,
 }
 
 impl SCILSSignalAspect {
@@ -384,6 +446,7 @@ impl SCILSSignalAspect {
             downstream_driveway_information,
             dark_switching,
             nationally_specified_information,
+            extensions: Vec::new(),
         }
     }
 
@@ -430,11 +493,46 @@ impl SCILSSignalAspect {
     pub fn nationally_specified_information(&self) -> &[u8] {
         &self.nationally_specified_information
     }
+
+    /// Interprets the nationally specified information using the DB profile.
+    pub fn db_national_information(&self) -> Result<DbNationalInformation, SciError> {
+        DbNationalInformation::try_from(self.nationally_specified_information)
+    }
+
+    /// Trailing bytes appended by newer SCI versions.
+    pub fn extensions(&self) -> &[u8] {
+        &self.extensions
+    }
+
+    /// Parses the aspect as sent by a peer using SCI `version`.
+    /// Unknown trailing bytes of newer versions are kept as extensions.
+    pub fn try_from_versioned(value: &[u8], version: u8) -> Result<Self, SciError> {
+        let (value, extensions) = split_extensions(value, SIGNAL_ASPECT_LENGTH, version)
+            .ok_or(SciError::InvalidTelegramLength(value.len()))?;
+        let mut aspect = Self::try_from(value)?;
+        aspect.extensions = extensions.to_vec();
+        Ok(aspect)
+    }
+
+    /// Parses the aspect according to `mode`. In [`ParseMode::Lenient`],
+    /// aspects of only the 9 mandatory bytes are accepted as well; their
+    /// nationally specified information is filled with zeros.
+    pub fn try_from_with_mode(value: &[u8], mode: ParseMode) -> Result<Self, SciError> {
+        if mode == ParseMode::Lenient && value.len() == MANDATORY_SIGNAL_ASPECT_LENGTH {
+            let mut padded = [0; SIGNAL_ASPECT_LENGTH];
+            padded[..MANDATORY_SIGNAL_ASPECT_LENGTH].copy_from_slice(value);
+            Self::try_from(&padded[..])
+        } else {
+            Self::try_from(value)
+        }
+    }
 }
 
+/// Writes the mandatory part, the nationally specified information
+/// and the extensions kept from a newer peer.
 impl From<SCILSSignalAspect> for SCIPayload {
     fn from(value: SCILSSignalAspect) -> Self {
-        let mut data = vec![0; 9];
+        let mut data = vec![0; SIGNAL_ASPECT_LENGTH];
         data[0] = value.main as u8;
         data[1] = value.additional as u8;
         data[2] = value.zs3 as u8;
@@ -446,6 +544,9 @@ impl From<SCILSSignalAspect> for SCIPayload {
         driveway_info |= value.upstream_driveway_information as u8;
         data[7] = driveway_info;
         data[8] = value.dark_switching as u8;
+        data[MANDATORY_SIGNAL_ASPECT_LENGTH..]
+            .copy_from_slice(&value.nationally_specified_information);
+        data.extend_from_slice(&value.extensions);
 
         Self::from_slice(&data)
     }
@@ -455,6 +556,9 @@ impl TryFrom<&[u8]> for SCILSSignalAspect {
     type Error = SciError;
 
     fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        if value.len() < SIGNAL_ASPECT_LENGTH {
+            return Err(SciError::InvalidTelegramLength(value.len()));
+        }
         let main = SCILSMain::try_from(value[0])?;
         let additional = SCILSAdditional::try_from(value[1])?;
         let zs3 = SCILSZs3::try_from(value[2])?;
@@ -467,7 +571,8 @@ impl TryFrom<&[u8]> for SCILSSignalAspect {
         let upstream_driveway_information = SCILSDrivewayInformation::try_from(value[7] & 0x0F)?;
         let dark_switching = SCILSDarkSwitching::try_from(value[8])?;
         let mut nationally_specified_information = [0; 9];
-        nationally_specified_information[..].copy_from_slice(&value[9..18]);
+        nationally_specified_information[..]
+            .copy_from_slice(&value[MANDATORY_SIGNAL_ASPECT_LENGTH..SIGNAL_ASPECT_LENGTH]);
         Ok(Self {
             main,
             additional,
@@ -480,6 +585,76 @@ impl TryFrom<&[u8]> for SCILSSignalAspect {
             downstream_driveway_information,
             dark_switching,
             nationally_specified_information,
+            extensions: Vec::new(),
+        })
+    }
+}
+
+/// The nationally specified information of a signal aspect
+/// as used by DB. Flags are encoded as `0x01` (shown) and `0xFF`
+/// (not shown), like the other parts of the aspect.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DbNationalInformation {
+    /// Byte 0: the additional light indicating a shortened braking distance.
+    pub shortened_braking_distance: bool,
+    /// Byte 1: the additional light of a distant signal repeater.
+    pub distant_signal_repeater: bool,
+    /// Byte 2: Zs10, end of a speed restriction.
+    pub zs10: bool,
+    /// Byte 3: the index of a light speed indicator (Lf), if shown.
+    pub speed_indicator: Option<SCILSZs3>,
+    /// Bytes 4 to 8, reserved for future use and passed through unchanged.
+    pub reserved: [u8; 5],
+}
+
+impl DbNationalInformation {
+    fn flag_to_byte(flag: bool) -> u8 {
+        if flag {
+            0x01
+        } else {
+            0xFF
+        }
+    }
+
+    fn flag_from_byte(value: u8) -> Result<bool, SciError> {
+        match value {
+            0x01 => Ok(true),
+            0xFF => Ok(false),
+            v => Err(SciLsError::InvalidNationalInformation(v).into()),
+        }
+    }
+}
+
+impl From<DbNationalInformation> for [u8; 9] {
+    fn from(value: DbNationalInformation) -> Self {
+        let mut data = [0; 9];
+        data[0] = DbNationalInformation::flag_to_byte(value.shortened_braking_distance);
+        data[1] = DbNationalInformation::flag_to_byte(value.distant_signal_repeater);
+        data[2] = DbNationalInformation::flag_to_byte(value.zs10);
+        data[3] = value.speed_indicator.unwrap_or(SCILSZs3::Off) as u8;
+        data[4..].copy_from_slice(&value.reserved);
+        data
+    }
+}
+
+impl TryFrom<[u8; 9]> for DbNationalInformation {
+    type Error = SciError;
+
+    fn try_from(value: [u8; 9]) -> Result<Self, Self::Error> {
+        let speed_indicator = match SCILSZs3::try_from(value[3]) {
+            Ok(SCILSZs3::Off) => None,
+            Ok(index) => Some(index),
+            Err(_) => return Err(SciLsError::InvalidNationalInformation(value[3]).into()),
+        };
+        let mut reserved = [0; 5];
+        reserved.copy_from_slice(&value[4..]);
+        Ok(Self {
+            shortened_braking_distance: Self::flag_from_byte(value[0])?,
+            distant_signal_repeater: Self::flag_from_byte(value[1])?,
+            zs10: Self::flag_from_byte(value[2])?,
+            speed_indicator,
+            reserved,
         })
     }
 }
@@ -540,4 +715,426 @@ impl SCITelegram {
             payload: SCIPayload::from_slice(&[brightness as u8]),
         }
     }
+
+    /// Reports that a command was not executed. It is followed by a
+    /// status telegram with the aspect that is actually shown.
+    pub fn scils_command_rejected(
+        sender: &str,
+        receiver: &str,
+        reason: SCILSRejectionReason,
+    ) -> Self {
+        Self {
+            protocol_type: ProtocolType::SCIProtocolLS,
+            message_type: SCIMessageType::scils_command_rejected(),
+            sender: sender.to_string(),
+            receiver: receiver.to_string(),
+            payload: SCIPayload::from_slice(&[reason as u8]),
+        }
+    }
+}
+
+/// The timing of a flashing aspect as supervised by the signal.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BlinkCadence {
+    /// How long the lamp is lit in each period.
+    pub on: Duration,
+    /// How long the lamp is dark in each period.
+    pub off: Duration,
+    /// Lamp observations closer than this to a phase change are not checked,
+    /// since the lamp may still be switching.
+    pub tolerance: Duration,
+}
+
+impl Default for BlinkCadence {
+    fn default() -> Self {
+        Self {
+            on: Duration::from_millis(500),
+            off: Duration::from_millis(500),
+            tolerance: Duration::from_millis(50),
+        }
+    }
+}
+
+/// Whether a flashing lamp is currently supposed to be lit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlinkPhase {
+    On,
+    Off,
+}
+
+impl BlinkCadence {
+    pub fn period(&self) -> Duration {
+        self.on + self.off
+    }
+
+    /// The phase `elapsed` after flashing started, or `None` if
+    /// `elapsed` is within the tolerance of a phase change.
+    pub fn phase_at(&self, elapsed: Duration) -> Option<BlinkPhase> {
+        let period = self.period().as_nanos();
+        if period == 0 {
+            return None;
+        }
+        let offset = elapsed.as_nanos() % period;
+        let on = self.on.as_nanos();
+        let tolerance = self.tolerance.as_nanos();
+        let distance_to_edge = offset.abs_diff(on).min(offset).min(period - offset);
+        if distance_to_edge < tolerance {
+            None
+        } else if offset < on {
+            Some(BlinkPhase::On)
+        } else {
+            Some(BlinkPhase::Off)
+        }
+    }
+}
+
+/// A lamp-proving failure detected while a flashing aspect was shown.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LampFailure {
+    /// The aspect that was shown when the failure occurred.
+    pub main: SCILSMain,
+    /// The phase the lamp was expected to be in.
+    pub expected: BlinkPhase,
+    /// The time since flashing started.
+    pub elapsed: Duration,
+}
+
+/// Decides whether the hardware of a signal can show an aspect.
+pub type AspectDriver = Box<dyn FnMut(&SCILSSignalAspect) -> bool + Send>;
+
+/// The element side of an SCI-LS connection. It keeps track of the
+/// shown aspect and brightness, answers commands with status telegrams
+/// and supervises the blink cadence of flashing aspects.
+///
+/// Commanded aspects the [`AspectDriver`] cannot show are answered with
+/// a Command Rejected telegram followed by the status of the aspect that
+/// remains shown.
+#[cfg(feature = "std")]
+pub struct SignalController {
+    name: String,
+    peer: Option<String>,
+    aspect: SCILSSignalAspect,
+    brightness: SCILSBrightness,
+    cadence: BlinkCadence,
+    fallback: SCILSMain,
+    flashing_since: Option<Instant>,
+    lamp_failures: Vec<LampFailure>,
+    driver: Option<AspectDriver>,
+}
+
+#[cfg(feature = "std")]
+impl SignalController {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            peer: None,
+            aspect: SCILSSignalAspect::default(),
+            brightness: SCILSBrightness::Day,
+            cadence: BlinkCadence::default(),
+            fallback: SCILSMain::Hp0,
+            flashing_since: None,
+            lamp_failures: Vec::new(),
+            driver: None,
+        }
+    }
+
+    /// Sets the blink cadence of flashing aspects.
+    pub fn with_cadence(mut self, cadence: BlinkCadence) -> Self {
+        self.cadence = cadence;
+        self
+    }
+
+    /// Sets the main aspect shown after a lamp-proving failure (`Hp0` by default).
+    pub fn with_fallback(mut self, fallback: SCILSMain) -> Self {
+        self.fallback = fallback;
+        self
+    }
+
+    /// Sets the driver that is asked before a commanded aspect is shown.
+    /// Without a driver, all aspects are shown.
+    pub fn with_driver<F>(mut self, driver: F) -> Self
+    where
+        F: FnMut(&SCILSSignalAspect) -> bool + Send + 'static,
+    {
+        self.driver.replace(Box::new(driver));
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn aspect(&self) -> &SCILSSignalAspect {
+        &self.aspect
+    }
+
+    pub fn brightness(&self) -> SCILSBrightness {
+        self.brightness
+    }
+
+    pub fn cadence(&self) -> BlinkCadence {
+        self.cadence
+    }
+
+    /// All lamp-proving failures detected so far.
+    pub fn lamp_failures(&self) -> &[LampFailure] {
+        &self.lamp_failures
+    }
+
+    /// The status telegrams describing the current state of the signal,
+    /// addressed to the last peer that sent a command.
+    pub fn status(&self) -> Vec<SCITelegram> {
+        match &self.peer {
+            Some(peer) => vec![
+                SCITelegram::scils_signal_aspect_status(&self.name, peer, self.aspect.clone()),
+                SCITelegram::scils_brightness_status(&self.name, peer, self.brightness),
+            ],
+            None => Vec::new(),
+        }
+    }
+
+    /// Executes a command received at `now` and returns the status telegrams to send.
+    pub fn handle(
+        &mut self,
+        telegram: &SCITelegram,
+        now: Instant,
+    ) -> Result<Vec<SCITelegram>, SciError> {
+        if telegram.message_type == SCIMessageType::scils_show_signal_aspect() {
+            let aspect = SCILSSignalAspect::try_from_versioned(&telegram.payload, SCI_VERSION)?;
+            self.peer.replace(telegram.sender.clone());
+            let mut responses = Vec::new();
+            if self
+                .driver
+                .as_mut()
+                .is_none_or(|can_show| can_show(&aspect))
+            {
+                self.show(aspect, now);
+            } else {
+                responses.push(SCITelegram::scils_command_rejected(
+                    &self.name,
+                    &telegram.sender,
+                    SCILSRejectionReason::Technical,
+                ));
+            }
+            responses.push(SCITelegram::scils_signal_aspect_status(
+                &self.name,
+                &telegram.sender,
+                self.aspect.clone(),
+            ));
+            Ok(responses)
+        } else if telegram.message_type == SCIMessageType::scils_change_brightness() {
+            let brightness = SCILSBrightness::try_from(
+                *telegram
+                    .payload
+                    .first()
+                    .ok_or(SciError::InvalidTelegramLength(0))?,
+            )?;
+            self.peer.replace(telegram.sender.clone());
+            self.brightness = brightness;
+            Ok(vec![SCITelegram::scils_brightness_status(
+                &self.name,
+                &telegram.sender,
+                brightness,
+            )])
+        } else {
+            Err(SciError::UnsupportedMessageType(
+                telegram.message_type.into(),
+            ))
+        }
+    }
+
+    /// Checks the lamp state measured at `now` against the blink cadence.
+    /// If a flashing lamp is not in its expected phase, the failure is
+    /// recorded, the fallback aspect is shown and the resulting status
+    /// telegrams are returned. Steady aspects are not supervised here.
+    pub fn prove_lamp(&mut self, lit: bool, now: Instant) -> Vec<SCITelegram> {
+        let Some(since) = self.flashing_since else {
+            return Vec::new();
+        };
+        let elapsed = now.saturating_duration_since(since);
+        let Some(expected) = self.cadence.phase_at(elapsed) else {
+            return Vec::new();
+        };
+        if lit == (expected == BlinkPhase::On) {
+            return Vec::new();
+        }
+        self.lamp_failures.push(LampFailure {
+            main: self.aspect.main,
+            expected,
+            elapsed,
+        });
+        let mut fallback = self.aspect.clone();
+        fallback.main = self.fallback;
+        self.show(fallback, now);
+        match &self.peer {
+            Some(peer) => vec![SCITelegram::scils_signal_aspect_status(
+                &self.name,
+                peer,
+                self.aspect.clone(),
+            )],
+            None => Vec::new(),
+        }
+    }
+
+    fn show(&mut self, aspect: SCILSSignalAspect, now: Instant) {
+        self.flashing_since = aspect.main.is_flashing().then_some(now);
+        self.aspect = aspect;
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{
+        BlinkCadence, BlinkPhase, DbNationalInformation, SCILSMain, SCILSRejectionReason,
+        SCILSSignalAspect, SCILSZs3, SignalController,
+    };
+    use crate::{ParseMode, ProtocolType, SCIMessageType, SCIPayload, SCITelegram, SciError};
+
+    #[test]
+    fn test_db_national_information() {
+        let nsi = DbNationalInformation {
+            zs10: true,
+            speed_indicator: Some(SCILSZs3::Index8),
+            ..Default::default()
+        };
+        let bytes: [u8; 9] = nsi.into();
+        assert_eq!(bytes[..4], [0xFF, 0xFF, 0x01, 0x08]);
+        assert_eq!(DbNationalInformation::try_from(bytes).unwrap(), nsi);
+        assert!(DbNationalInformation::try_from([0; 9]).is_err());
+    }
+
+    #[test]
+    fn test_signal_aspect_round_trip() {
+        use super::{
+            SCILSAdditional, SCILSDarkSwitching, SCILSDepreciationInformation,
+            SCILSDrivewayInformation, SCILSZs2,
+        };
+
+        let nsi = DbNationalInformation {
+            zs10: true,
+            speed_indicator: Some(SCILSZs3::Index8),
+            ..Default::default()
+        };
+        let aspect = SCILSSignalAspect::new(
+            SCILSMain::Ks1,
+            SCILSAdditional::Zs1,
+            SCILSZs3::Index4,
+            SCILSZs3::Index6,
+            SCILSZs2::LetterA,
+            SCILSZs2::default(),
+            SCILSDepreciationInformation::Type1,
+            SCILSDrivewayInformation::default(),
+            SCILSDrivewayInformation::default(),
+            SCILSDarkSwitching::default(),
+            nsi.into(),
+        );
+        let payload = SCIPayload::from(aspect.clone());
+        assert_eq!(payload.len(), 18);
+        assert_eq!(payload[9..], <[u8; 9]>::from(nsi));
+        let parsed = SCILSSignalAspect::try_from(&payload[..]).unwrap();
+        assert_eq!(parsed, aspect);
+        assert_eq!(parsed.db_national_information().unwrap(), nsi);
+        assert_eq!(SCIPayload::from(parsed), payload);
+
+        let mut extended = payload.to_vec();
+        extended.extend([0xAB, 0xCD]);
+        let parsed =
+            SCILSSignalAspect::try_from_versioned(&extended, crate::SCI_VERSION + 1).unwrap();
+        assert_eq!(parsed.extensions(), [0xAB, 0xCD]);
+        assert_eq!(SCIPayload::from(parsed)[..], extended[..]);
+
+        let telegram = SCITelegram::scils_show_signal_aspect("C", "S", aspect.clone());
+        let received = SCITelegram::try_from(Vec::from(telegram).as_slice()).unwrap();
+        assert_eq!(
+            SCILSSignalAspect::try_from(&received.payload()[..]).unwrap(),
+            aspect
+        );
+    }
+
+    #[test]
+    fn test_mandatory_only_aspect() {
+        let mut payload = [0xFF; 18];
+        payload[0] = SCILSMain::Hp0 as u8;
+        payload[9] = 0x01;
+
+        let strict = SCILSSignalAspect::try_from_with_mode(&payload, ParseMode::Strict).unwrap();
+        assert_eq!(strict.nationally_specified_information()[0], 0x01);
+        let lenient = SCILSSignalAspect::try_from_with_mode(&payload, ParseMode::Lenient).unwrap();
+        assert_eq!(lenient.nationally_specified_information()[0], 0x01);
+
+        assert!(matches!(
+            SCILSSignalAspect::try_from_with_mode(&payload[..9], ParseMode::Strict),
+            Err(SciError::InvalidTelegramLength(9))
+        ));
+        let lenient =
+            SCILSSignalAspect::try_from_with_mode(&payload[..9], ParseMode::Lenient).unwrap();
+        assert_eq!(lenient.main(), SCILSMain::Hp0);
+        assert_eq!(lenient.nationally_specified_information(), [0; 9]);
+        assert!(SCILSSignalAspect::try_from_with_mode(&payload[..8], ParseMode::Lenient).is_err());
+    }
+
+    #[test]
+    fn test_flashing_lamp_failure() {
+        let mut payload = [0xFF; 18];
+        payload[0] = SCILSMain::Ks1Flashing as u8;
+        let command = SCITelegram {
+            protocol_type: ProtocolType::SCIProtocolLS,
+            message_type: SCIMessageType::scils_show_signal_aspect(),
+            sender: "I".to_string(),
+            receiver: "S".to_string(),
+            payload: SCIPayload::from_slice(&payload),
+        };
+        let cadence = BlinkCadence::default();
+        let mut controller = SignalController::new("S").with_cadence(cadence);
+        let start = Instant::now();
+        controller.handle(&command, start).unwrap();
+        assert_eq!(controller.aspect().main(), SCILSMain::Ks1Flashing);
+
+        assert_eq!(
+            cadence.phase_at(Duration::from_millis(250)),
+            Some(BlinkPhase::On)
+        );
+        assert_eq!(cadence.phase_at(Duration::from_millis(500)), None);
+        assert!(controller
+            .prove_lamp(true, start + Duration::from_millis(250))
+            .is_empty());
+        assert!(controller
+            .prove_lamp(false, start + Duration::from_millis(750))
+            .is_empty());
+
+        let status = controller.prove_lamp(false, start + Duration::from_millis(1250));
+        assert_eq!(status.len(), 1);
+        assert_eq!(status[0].payload[0], SCILSMain::Hp0 as u8);
+        assert_eq!(controller.lamp_failures().len(), 1);
+        assert_eq!(controller.lamp_failures()[0].expected, BlinkPhase::On);
+    }
+
+    #[test]
+    fn test_rejected_aspect() {
+        let mut payload = [0xFF; 18];
+        payload[0] = SCILSMain::Ks1Flashing as u8;
+        let command = SCITelegram {
+            protocol_type: ProtocolType::SCIProtocolLS,
+            message_type: SCIMessageType::scils_show_signal_aspect(),
+            sender: "I".to_string(),
+            receiver: "S".to_string(),
+            payload: SCIPayload::from_slice(&payload),
+        };
+        let mut controller =
+            SignalController::new("S").with_driver(|aspect| !aspect.main().is_flashing());
+        let responses = controller.handle(&command, Instant::now()).unwrap();
+        assert_eq!(responses.len(), 2);
+        assert_eq!(
+            responses[0].message_type,
+            SCIMessageType::scils_command_rejected()
+        );
+        assert_eq!(
+            SCILSRejectionReason::try_from(responses[0].payload[0]).unwrap(),
+            SCILSRejectionReason::Technical
+        );
+        assert_eq!(controller.aspect().main(), SCILSMain::default());
+    }
 }
@@ -1,30 +1,40 @@
 //! # SCI Point
 //!
 //! The Standard Communication Interface for points.
+//! [`PointSimulator`] models the movement of a point machine.
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, thiserror::Error)]
 pub enum SciPError {
+    #[error("Unknown Target Location {0:x}")]
     UnknownTargetLocation(u8),
+    #[error("Unknown Location {0:x}")]
     UnknownLocation(u8),
+    #[error("Unknown Heating State {0:x}")]
+    UnknownHeatingState(u8),
+    #[error("Unknown Auxiliary Equipment {0:x}")]
+    UnknownAuxiliaryEquipment(u8),
+    #[error("Unknown Auxiliary Equipment State {0:x}")]
+    UnknownAuxiliaryEquipmentState(u8),
+    #[error("Bad Payload Length {0}")]
+    BadPayloadLength(usize),
 }
 
-impl Display for SciPError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:?}", self)
-    }
-}
-
-impl std::error::Error for SciPError {}
-
-use std::fmt::Display;
+#[cfg(feature = "std")]
+use alloc::string::String;
+use alloc::{string::ToString, vec, vec::Vec};
+use core::time::Duration;
+#[cfg(feature = "std")]
+use std::time::Instant;
 
 use crate::impl_sci_message_type;
 
-use super::{ProtocolType, SCIMessageType, SCIPayload, SCITelegram};
+use super::{ProtocolType, SCIMessageType, SCIPayload, SCITelegram, SciError};
 
 impl_sci_message_type!(
     (scip_change_location, 0x0001),
-    (scip_location_status, 0x000B)
+    (scip_location_status, 0x000B),
+    (scip_heating_status, 0x0010),
+    (scip_auxiliary_status, 0x0011)
 );
 
 enumerate! {
@@ -49,6 +59,119 @@ enumerate! {
     }
 }
 
+enumerate! {
+    SCIPointHeatingState,
+    "The state of the heating of a point, see [`SCITelegram::scip_heating_status`].",
+    u8,
+    SciPError::UnknownHeatingState,
+    {HeatingOff = 0x01, HeatingOn = 0x02, HeatingFaulty = 0x03}
+}
+
+enumerate! {
+    SCIPointAuxiliaryEquipment,
+    "Equipment of a point besides the point machine, see [`SCITelegram::scip_auxiliary_status`].",
+    u8,
+    SciPError::UnknownAuxiliaryEquipment,
+    {Heating = 0x01, Lock = 0x02, EndPositionDetector = 0x03}
+}
+
+enumerate! {
+    SCIAuxiliaryEquipmentState,
+    u8,
+    SciPError::UnknownAuxiliaryEquipmentState,
+    {Operational = 0x01, Degraded = 0x02, Failed = 0x03, NotFitted = 0x04}
+}
+
+/// The value of the temperature byte if the point has no rail temperature sensor.
+const TEMPERATURE_UNAVAILABLE: i8 = i8::MIN;
+
+/// The payload of [`SCITelegram::scip_heating_status`].
+/// The rail temperature is given in °C.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HeatingStatusPayload {
+    pub state: SCIPointHeatingState,
+    pub rail_temperature: Option<i8>,
+}
+
+impl TryFrom<&[u8]> for HeatingStatusPayload {
+    type Error = SciError;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        let [state, temperature] =
+            <[u8; 2]>::try_from(value).map_err(|_| SciPError::BadPayloadLength(value.len()))?;
+        let temperature = temperature as i8;
+        Ok(Self {
+            state: SCIPointHeatingState::try_from(state)?,
+            rail_temperature: (temperature != TEMPERATURE_UNAVAILABLE).then_some(temperature),
+        })
+    }
+}
+
+impl From<HeatingStatusPayload> for SCIPayload {
+    fn from(value: HeatingStatusPayload) -> Self {
+        // The lowest value marks a missing sensor
+        let temperature = value
+            .rail_temperature
+            .unwrap_or(TEMPERATURE_UNAVAILABLE)
+            .max(TEMPERATURE_UNAVAILABLE + 1);
+        SCIPayload::from_slice(&[value.state as u8, temperature as u8])
+    }
+}
+
+/// The payload of [`SCITelegram::scip_auxiliary_status`]: the state of
+/// each piece of auxiliary equipment the point reports on.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AuxiliaryStatusPayload {
+    pub equipment: Vec<(SCIPointAuxiliaryEquipment, SCIAuxiliaryEquipmentState)>,
+}
+
+impl AuxiliaryStatusPayload {
+    /// The state of `equipment`, if it is reported.
+    pub fn state_of(
+        &self,
+        equipment: SCIPointAuxiliaryEquipment,
+    ) -> Option<SCIAuxiliaryEquipmentState> {
+        self.equipment
+            .iter()
+            .find(|(e, _)| *e == equipment)
+            .map(|(_, state)| *state)
+    }
+}
+
+impl TryFrom<&[u8]> for AuxiliaryStatusPayload {
+    type Error = SciError;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        let bad_length = || SciPError::BadPayloadLength(value.len());
+        let (count, entries) = value.split_first().ok_or_else(bad_length)?;
+        if entries.len() != usize::from(*count) * 2 {
+            return Err(bad_length().into());
+        }
+        let equipment = entries
+            .chunks_exact(2)
+            .map(|entry| {
+                Ok((
+                    SCIPointAuxiliaryEquipment::try_from(entry[0])?,
+                    SCIAuxiliaryEquipmentState::try_from(entry[1])?,
+                ))
+            })
+            .collect::<Result<_, SciError>>()?;
+        Ok(Self { equipment })
+    }
+}
+
+impl From<&AuxiliaryStatusPayload> for SCIPayload {
+    fn from(value: &AuxiliaryStatusPayload) -> Self {
+        let mut data = vec![value.equipment.len() as u8];
+        for (equipment, state) in &value.equipment {
+            data.extend([*equipment as u8, *state as u8]);
+        }
+        SCIPayload::from_slice(&data)
+    }
+}
+
 impl SCITelegram {
     pub fn change_location(sender: &str, receiver: &str, to: SCIPointTargetLocation) -> Self {
         Self {
@@ -69,4 +192,287 @@ impl SCITelegram {
             payload: SCIPayload::from_slice(&[location as u8]),
         }
     }
+
+    /// Reports the state of the point heating.
+    /// Not supported by [`BaselineProfile::BL3`](crate::baseline::BaselineProfile::BL3).
+    pub fn scip_heating_status(
+        sender: &str,
+        receiver: &str,
+        payload: HeatingStatusPayload,
+    ) -> Self {
+        Self {
+            protocol_type: ProtocolType::SCIProtocolP,
+            message_type: SCIMessageType::scip_heating_status(),
+            sender: sender.to_string(),
+            receiver: receiver.to_string(),
+            payload: payload.into(),
+        }
+    }
+
+    /// Reports the state of the auxiliary equipment of the point.
+    /// Not supported by [`BaselineProfile::BL3`](crate::baseline::BaselineProfile::BL3).
+    pub fn scip_auxiliary_status(
+        sender: &str,
+        receiver: &str,
+        payload: &AuxiliaryStatusPayload,
+    ) -> Self {
+        Self {
+            protocol_type: ProtocolType::SCIProtocolP,
+            message_type: SCIMessageType::scip_auxiliary_status(),
+            sender: sender.to_string(),
+            receiver: receiver.to_string(),
+            payload: payload.into(),
+        }
+    }
+}
+
+/// The default time a [`PointSimulator`] takes to reach its target location.
+pub const DEFAULT_MOVEMENT_DURATION: Duration = Duration::from_secs(3);
+/// The default time after which a movement is reported as timed out.
+pub const DEFAULT_MOVEMENT_TIMEOUT: Duration = Duration::from_secs(8);
+
+#[cfg(feature = "std")]
+#[derive(Clone, Debug)]
+struct Movement {
+    target: SCIPointLocation,
+    requester: String,
+    started: Instant,
+}
+
+/// Simulates the movement of a point machine. On a change of location,
+/// [`SCIPointLocation::PointNoTargetLocation`] is reported while the
+/// point moves. [`PointSimulator::poll`] reports the target location once
+/// the movement duration has passed, or a timeout telegram if the movement
+/// takes longer than the timeout.
+#[cfg(feature = "std")]
+#[derive(Clone, Debug)]
+pub struct PointSimulator {
+    location: SCIPointLocation,
+    movement: Option<Movement>,
+    movement_duration: Duration,
+    timeout: Duration,
+    stuck: bool,
+}
+
+#[cfg(feature = "std")]
+impl PointSimulator {
+    pub fn new(location: SCIPointLocation) -> Self {
+        Self {
+            location,
+            movement: None,
+            movement_duration: DEFAULT_MOVEMENT_DURATION,
+            timeout: DEFAULT_MOVEMENT_TIMEOUT,
+            stuck: false,
+        }
+    }
+
+    /// Sets the time the point takes to move. Points that move
+    /// instantly report their target location right away.
+    pub fn with_movement_duration(mut self, movement_duration: Duration) -> Self {
+        self.movement_duration = movement_duration;
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// A stuck point starts moving but never reaches its target,
+    /// so its movements time out.
+    pub fn set_stuck(&mut self, stuck: bool) {
+        self.stuck = stuck;
+    }
+
+    pub fn location(&self) -> SCIPointLocation {
+        self.location
+    }
+
+    pub fn is_moving(&self) -> bool {
+        self.movement.is_some()
+    }
+
+    /// The location status of the point called `sender`.
+    pub fn status(&self, sender: &str, receiver: &str) -> SCITelegram {
+        SCITelegram::location_status(sender, receiver, self.location)
+    }
+
+    /// Handles a telegram sent to the point called `sender` and returns
+    /// the responses. Only change location commands are handled.
+    pub fn handle(&mut self, telegram: &SCITelegram, sender: &str) -> Vec<SCITelegram> {
+        self.handle_at(telegram, sender, Instant::now())
+    }
+
+    fn handle_at(
+        &mut self,
+        telegram: &SCITelegram,
+        sender: &str,
+        now: Instant,
+    ) -> Vec<SCITelegram> {
+        if telegram.message_type != SCIMessageType::scip_change_location() {
+            return Vec::new();
+        }
+        let Some(target) = telegram
+            .payload
+            .first()
+            .and_then(|t| SCIPointTargetLocation::try_from(*t).ok())
+        else {
+            return Vec::new();
+        };
+        let target = match target {
+            SCIPointTargetLocation::PointLocationChangeToRight => {
+                SCIPointLocation::PointLocationRight
+            }
+            SCIPointTargetLocation::PointLocationChangeToLeft => {
+                SCIPointLocation::PointLocationLeft
+            }
+        };
+        if self.location == target && !self.stuck {
+            self.movement = None;
+            return vec![self.status(sender, &telegram.sender)];
+        }
+        self.location = SCIPointLocation::PointNoTargetLocation;
+        self.movement = Some(Movement {
+            target,
+            requester: telegram.sender.clone(),
+            started: now,
+        });
+        let mut responses = self.poll_at(sender, now);
+        if responses.is_empty() {
+            responses.push(self.status(sender, &telegram.sender));
+        }
+        responses
+    }
+
+    /// Returns the telegrams of the point called `sender` that are due,
+    /// i.e. the final location or a timeout at the end of a movement.
+    pub fn poll(&mut self, sender: &str) -> Vec<SCITelegram> {
+        self.poll_at(sender, Instant::now())
+    }
+
+    fn poll_at(&mut self, sender: &str, now: Instant) -> Vec<SCITelegram> {
+        let Some(movement) = &self.movement else {
+            return Vec::new();
+        };
+        let elapsed = now.saturating_duration_since(movement.started);
+        let arrives = !self.stuck && self.movement_duration <= self.timeout;
+        if arrives && elapsed >= self.movement_duration {
+            let movement = self.movement.take().unwrap();
+            self.location = movement.target;
+            vec![self.status(sender, &movement.requester)]
+        } else if elapsed >= self.timeout {
+            let movement = self.movement.take().unwrap();
+            vec![SCITelegram::timeout(
+                ProtocolType::SCIProtocolP,
+                sender,
+                &movement.requester,
+            )]
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::{baseline::BaselineProfile, SCIMessageType, SCITelegram};
+
+    use super::{
+        AuxiliaryStatusPayload, HeatingStatusPayload, PointSimulator, SCIAuxiliaryEquipmentState,
+        SCIPointAuxiliaryEquipment, SCIPointHeatingState, SCIPointLocation, SCIPointTargetLocation,
+    };
+
+    #[test]
+    fn test_auxiliary_telegrams() {
+        let heating = HeatingStatusPayload {
+            state: SCIPointHeatingState::HeatingOn,
+            rail_temperature: Some(-12),
+        };
+        let telegram = SCITelegram::scip_heating_status("P", "I", heating);
+        let telegram = SCITelegram::try_from(Vec::from(telegram).as_slice()).unwrap();
+        assert_eq!(telegram.message_type_name(), Some("HeatingStatus"));
+        assert_eq!(
+            HeatingStatusPayload::try_from(&telegram.payload[..]).unwrap(),
+            heating
+        );
+        let without_sensor = HeatingStatusPayload::try_from(&[0x03, 0x80][..]).unwrap();
+        assert_eq!(without_sensor.rail_temperature, None);
+        assert!(HeatingStatusPayload::try_from(&[0x04, 0x00][..]).is_err());
+
+        let auxiliary = AuxiliaryStatusPayload {
+            equipment: vec![
+                (
+                    SCIPointAuxiliaryEquipment::Heating,
+                    SCIAuxiliaryEquipmentState::Degraded,
+                ),
+                (
+                    SCIPointAuxiliaryEquipment::Lock,
+                    SCIAuxiliaryEquipmentState::Operational,
+                ),
+            ],
+        };
+        let telegram = SCITelegram::scip_auxiliary_status("P", "I", &auxiliary);
+        assert_eq!(&telegram.payload[..], &[2, 0x01, 0x02, 0x02, 0x01]);
+        let parsed = AuxiliaryStatusPayload::try_from(&telegram.payload[..]).unwrap();
+        assert_eq!(parsed, auxiliary);
+        assert_eq!(
+            parsed.state_of(SCIPointAuxiliaryEquipment::Heating),
+            Some(SCIAuxiliaryEquipmentState::Degraded)
+        );
+        assert_eq!(
+            parsed.state_of(SCIPointAuxiliaryEquipment::EndPositionDetector),
+            None
+        );
+        assert!(AuxiliaryStatusPayload::try_from(&[2, 0x01, 0x02][..]).is_err());
+
+        assert!(telegram.validate_baseline(BaselineProfile::BL4).is_ok());
+        assert!(telegram.validate_baseline(BaselineProfile::BL3).is_err());
+    }
+
+    #[test]
+    fn test_point_movement() {
+        let mut point = PointSimulator::new(SCIPointLocation::PointLocationLeft)
+            .with_movement_duration(Duration::from_secs(2))
+            .with_timeout(Duration::from_secs(5));
+        let to_right = SCITelegram::change_location(
+            "I",
+            "P",
+            SCIPointTargetLocation::PointLocationChangeToRight,
+        );
+        let start = Instant::now();
+        let responses = point.handle_at(&to_right, "P", start);
+        assert_eq!(responses.len(), 1);
+        assert_eq!(
+            responses[0].payload[0],
+            SCIPointLocation::PointNoTargetLocation as u8
+        );
+        assert!(point.is_moving());
+        assert!(point
+            .poll_at("P", start + Duration::from_secs(1))
+            .is_empty());
+        let responses = point.poll_at("P", start + Duration::from_secs(2));
+        assert_eq!(responses[0].receiver, "I");
+        assert_eq!(point.location(), SCIPointLocation::PointLocationRight);
+        assert!(!point.is_moving());
+
+        // A point that does not arrive in time reports a timeout
+        point.set_stuck(true);
+        let to_left = SCITelegram::change_location(
+            "I",
+            "P",
+            SCIPointTargetLocation::PointLocationChangeToLeft,
+        );
+        point.handle_at(&to_left, "P", start);
+        assert!(point
+            .poll_at("P", start + Duration::from_secs(4))
+            .is_empty());
+        let responses = point.poll_at("P", start + Duration::from_secs(5));
+        assert_eq!(responses[0].message_type, SCIMessageType::sci_timeout());
+        assert_eq!(point.location(), SCIPointLocation::PointNoTargetLocation);
+        assert!(point
+            .poll_at("P", start + Duration::from_secs(6))
+            .is_empty());
+    }
 }
@@ -0,0 +1,262 @@
+//! # SCI Radio Block Centre
+//!
+//! The Standard Communication Interface between an interlocking and a
+//! radio block centre (RBC). The interlocking reports the routes and
+//! signals the RBC derives movement authorities from, and the RBC asks
+//! for emergency stops in areas that have to be cleared of trains.
+//! Routes, signals and stop areas are identified by numbers
+//! agreed on in the configuration of both sides.
+
+use alloc::string::ToString;
+
+use crate::{
+    impl_sci_message_type, ProtocolType, SCIMessageType, SCIPayload, SCITelegram, SciError,
+};
+
+#[derive(Clone, Copy, Debug, thiserror::Error)]
+pub enum SciRbcError {
+    #[error("Unknown Route Status {0:x}")]
+    UnknownRouteStatus(u8),
+    #[error("Unknown Signal Status {0:x}")]
+    UnknownSignalStatus(u8),
+    #[error("Unknown Emergency Stop Kind {0:x}")]
+    UnknownEmergencyStopKind(u8),
+    #[error("Unknown Rejection Reason {0:x}")]
+    UnknownRejectionReason(u8),
+    #[error("Bad Payload Length {0}")]
+    BadPayloadLength(usize),
+}
+
+impl_sci_message_type!(
+    (scirbc_route_status, 0x0001),
+    (scirbc_signal_status, 0x0002),
+    (scirbc_emergency_stop, 0x0003),
+    (scirbc_revoke_emergency_stop, 0x0004),
+    (scirbc_command_rejected, 0x0005)
+);
+
+enumerate! {
+    RouteStatus,
+    "The state of a route as reported to the RBC.",
+    u8,
+    SciRbcError::UnknownRouteStatus,
+    {NotSet = 0x01, Set = 0x02, Locked = 0x03, Released = 0x04}
+}
+
+enumerate! {
+    SignalStatus,
+    "Whether a signal allows a train to pass.",
+    u8,
+    SciRbcError::UnknownSignalStatus,
+    {Stop = 0x01, Proceed = 0x02, Disturbed = 0x03}
+}
+
+enumerate! {
+    EmergencyStopKind,
+    "Conditional emergency stops only affect trains that can still stop before the area.",
+    u8,
+    SciRbcError::UnknownEmergencyStopKind,
+    {Conditional = 0x01, Unconditional = 0x02}
+}
+
+enumerate! {
+    RbcRejectionReason,
+    u8,
+    SciRbcError::UnknownRejectionReason,
+    {Operational = 0x01, Technical = 0x02}
+}
+
+/// The payload of [`SCITelegram::rbc_route_status`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RouteStatusPayload {
+    pub route: u16,
+    pub status: RouteStatus,
+}
+
+impl TryFrom<&[u8]> for RouteStatusPayload {
+    type Error = SciError;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        let [route @ .., status] =
+            <[u8; 3]>::try_from(value).map_err(|_| SciRbcError::BadPayloadLength(value.len()))?;
+        Ok(Self {
+            route: u16::from_be_bytes(route),
+            status: RouteStatus::try_from(status)?,
+        })
+    }
+}
+
+impl From<RouteStatusPayload> for SCIPayload {
+    fn from(value: RouteStatusPayload) -> Self {
+        let route = value.route.to_be_bytes();
+        SCIPayload::from_slice(&[route[0], route[1], value.status as u8])
+    }
+}
+
+/// The payload of [`SCITelegram::rbc_signal_status`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SignalStatusPayload {
+    pub signal: u16,
+    pub status: SignalStatus,
+}
+
+impl TryFrom<&[u8]> for SignalStatusPayload {
+    type Error = SciError;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        let [signal @ .., status] =
+            <[u8; 3]>::try_from(value).map_err(|_| SciRbcError::BadPayloadLength(value.len()))?;
+        Ok(Self {
+            signal: u16::from_be_bytes(signal),
+            status: SignalStatus::try_from(status)?,
+        })
+    }
+}
+
+impl From<SignalStatusPayload> for SCIPayload {
+    fn from(value: SignalStatusPayload) -> Self {
+        let signal = value.signal.to_be_bytes();
+        SCIPayload::from_slice(&[signal[0], signal[1], value.status as u8])
+    }
+}
+
+/// The payload of [`SCITelegram::rbc_emergency_stop`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EmergencyStopPayload {
+    pub area: u16,
+    pub kind: EmergencyStopKind,
+}
+
+impl TryFrom<&[u8]> for EmergencyStopPayload {
+    type Error = SciError;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        let [area @ .., kind] =
+            <[u8; 3]>::try_from(value).map_err(|_| SciRbcError::BadPayloadLength(value.len()))?;
+        Ok(Self {
+            area: u16::from_be_bytes(area),
+            kind: EmergencyStopKind::try_from(kind)?,
+        })
+    }
+}
+
+impl From<EmergencyStopPayload> for SCIPayload {
+    fn from(value: EmergencyStopPayload) -> Self {
+        let area = value.area.to_be_bytes();
+        SCIPayload::from_slice(&[area[0], area[1], value.kind as u8])
+    }
+}
+
+/// The area of an emergency stop that is revoked.
+pub fn parse_revoked_area(payload: &[u8]) -> Result<u16, SciError> {
+    let area =
+        <[u8; 2]>::try_from(payload).map_err(|_| SciRbcError::BadPayloadLength(payload.len()))?;
+    Ok(u16::from_be_bytes(area))
+}
+
+impl SCITelegram {
+    pub fn rbc_route_status(sender: &str, receiver: &str, payload: RouteStatusPayload) -> Self {
+        Self {
+            protocol_type: ProtocolType::SCIProtocolRBC,
+            message_type: SCIMessageType::scirbc_route_status(),
+            sender: sender.to_string(),
+            receiver: receiver.to_string(),
+            payload: payload.into(),
+        }
+    }
+
+    pub fn rbc_signal_status(sender: &str, receiver: &str, payload: SignalStatusPayload) -> Self {
+        Self {
+            protocol_type: ProtocolType::SCIProtocolRBC,
+            message_type: SCIMessageType::scirbc_signal_status(),
+            sender: sender.to_string(),
+            receiver: receiver.to_string(),
+            payload: payload.into(),
+        }
+    }
+
+    pub fn rbc_emergency_stop(sender: &str, receiver: &str, payload: EmergencyStopPayload) -> Self {
+        Self {
+            protocol_type: ProtocolType::SCIProtocolRBC,
+            message_type: SCIMessageType::scirbc_emergency_stop(),
+            sender: sender.to_string(),
+            receiver: receiver.to_string(),
+            payload: payload.into(),
+        }
+    }
+
+    pub fn rbc_revoke_emergency_stop(sender: &str, receiver: &str, area: u16) -> Self {
+        Self {
+            protocol_type: ProtocolType::SCIProtocolRBC,
+            message_type: SCIMessageType::scirbc_revoke_emergency_stop(),
+            sender: sender.to_string(),
+            receiver: receiver.to_string(),
+            payload: SCIPayload::from_slice(&area.to_be_bytes()),
+        }
+    }
+
+    pub fn rbc_command_rejected(sender: &str, receiver: &str, reason: RbcRejectionReason) -> Self {
+        Self {
+            protocol_type: ProtocolType::SCIProtocolRBC,
+            message_type: SCIMessageType::scirbc_command_rejected(),
+            sender: sender.to_string(),
+            receiver: receiver.to_string(),
+            payload: SCIPayload::from_slice(&[reason as u8]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        scirbc::{
+            parse_revoked_area, EmergencyStopKind, EmergencyStopPayload, RouteStatus,
+            RouteStatusPayload,
+        },
+        ProtocolType, SCIMessageType, SCITelegram, SciError,
+    };
+
+    #[test]
+    fn test_rbc_telegrams() {
+        let payload = RouteStatusPayload {
+            route: 0x0102,
+            status: RouteStatus::Locked,
+        };
+        let telegram = SCITelegram::rbc_route_status("EIL", "RBC", payload);
+        let data: Vec<u8> = telegram.into();
+        let telegram = SCITelegram::try_from(data.as_slice()).unwrap();
+        assert_eq!(telegram.protocol_type, ProtocolType::SCIProtocolRBC);
+        assert_eq!(telegram.message_type, SCIMessageType::scirbc_route_status());
+        assert_eq!(
+            telegram.to_string(),
+            "SCIProtocolRBC: RouteStatus EIL -> RBC [01 02 03]"
+        );
+        assert_eq!(
+            RouteStatusPayload::try_from(&*telegram.payload).unwrap(),
+            payload
+        );
+
+        let stop = SCITelegram::rbc_emergency_stop(
+            "RBC",
+            "EIL",
+            EmergencyStopPayload {
+                area: 7,
+                kind: EmergencyStopKind::Conditional,
+            },
+        );
+        assert_eq!(
+            EmergencyStopPayload::try_from(&*stop.payload).unwrap().area,
+            7
+        );
+        assert!(matches!(
+            EmergencyStopPayload::try_from(&[0, 7, 3][..]),
+            Err(SciError::Rbc(_))
+        ));
+        let revoke = SCITelegram::rbc_revoke_emergency_stop("RBC", "EIL", 7);
+        assert_eq!(parse_revoked_area(&revoke.payload).unwrap(), 7);
+        assert!(parse_revoked_area(&[7]).is_err());
+    }
+}
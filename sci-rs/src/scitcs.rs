@@ -0,0 +1,271 @@
+//! # SCI Train Control System
+//!
+//! The Standard Communication Interface between an interlocking and a
+//! train control system (TCS, also called SCI-CC for command and control),
+//! such as a train protection system with continuous supervision.
+//! The TCS reports the positions of the trains it supervises and the
+//! interlocking reports the state of its sections and imposes temporary
+//! speed restrictions. Trains, sections and restriction areas are identified
+//! by numbers agreed on in the configuration of both sides.
+
+use alloc::string::ToString;
+
+use crate::{
+    impl_sci_message_type, ProtocolType, SCIMessageType, SCIPayload, SCITelegram, SciError,
+};
+
+#[derive(Clone, Copy, Debug, thiserror::Error)]
+pub enum SciTcsError {
+    #[error("Unknown Travel Direction {0:x}")]
+    UnknownTravelDirection(u8),
+    #[error("Unknown Section Status {0:x}")]
+    UnknownSectionStatus(u8),
+    #[error("Unknown Rejection Reason {0:x}")]
+    UnknownRejectionReason(u8),
+    #[error("Bad Payload Length {0}")]
+    BadPayloadLength(usize),
+}
+
+impl_sci_message_type!(
+    (scitcs_train_position, 0x0001),
+    (scitcs_section_status, 0x0002),
+    (scitcs_speed_restriction, 0x0003),
+    (scitcs_revoke_speed_restriction, 0x0004),
+    (scitcs_command_rejected, 0x0005)
+);
+
+enumerate! {
+    TravelDirection,
+    "The direction of travel relative to the nominal direction of the line.",
+    u8,
+    SciTcsError::UnknownTravelDirection,
+    {Nominal = 0x01, Reverse = 0x02, Unknown = 0x03}
+}
+
+enumerate! {
+    SectionStatus,
+    "Whether trains may be guided into a section.",
+    u8,
+    SciTcsError::UnknownSectionStatus,
+    {Free = 0x01, Occupied = 0x02, Blocked = 0x03}
+}
+
+enumerate! {
+    TcsRejectionReason,
+    u8,
+    SciTcsError::UnknownRejectionReason,
+    {Operational = 0x01, Technical = 0x02, UnknownArea = 0x03}
+}
+
+/// The payload of [`SCITelegram::tcs_train_position`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TrainPositionPayload {
+    pub train: u32,
+    pub section: u16,
+    pub direction: TravelDirection,
+}
+
+impl TryFrom<&[u8]> for TrainPositionPayload {
+    type Error = SciError;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        let [t0, t1, t2, t3, s0, s1, direction] =
+            <[u8; 7]>::try_from(value).map_err(|_| SciTcsError::BadPayloadLength(value.len()))?;
+        Ok(Self {
+            train: u32::from_be_bytes([t0, t1, t2, t3]),
+            section: u16::from_be_bytes([s0, s1]),
+            direction: TravelDirection::try_from(direction)?,
+        })
+    }
+}
+
+impl From<TrainPositionPayload> for SCIPayload {
+    fn from(value: TrainPositionPayload) -> Self {
+        let mut data = [0; 7];
+        data[..4].copy_from_slice(&value.train.to_be_bytes());
+        data[4..6].copy_from_slice(&value.section.to_be_bytes());
+        data[6] = value.direction as u8;
+        SCIPayload::from_slice(&data)
+    }
+}
+
+/// The payload of [`SCITelegram::tcs_section_status`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SectionStatusPayload {
+    pub section: u16,
+    pub status: SectionStatus,
+}
+
+impl TryFrom<&[u8]> for SectionStatusPayload {
+    type Error = SciError;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        let [section @ .., status] =
+            <[u8; 3]>::try_from(value).map_err(|_| SciTcsError::BadPayloadLength(value.len()))?;
+        Ok(Self {
+            section: u16::from_be_bytes(section),
+            status: SectionStatus::try_from(status)?,
+        })
+    }
+}
+
+impl From<SectionStatusPayload> for SCIPayload {
+    fn from(value: SectionStatusPayload) -> Self {
+        let section = value.section.to_be_bytes();
+        SCIPayload::from_slice(&[section[0], section[1], value.status as u8])
+    }
+}
+
+/// The payload of [`SCITelegram::tcs_speed_restriction`].
+/// The speed limit is given in km/h.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SpeedRestrictionPayload {
+    pub area: u16,
+    pub speed_limit: u16,
+}
+
+impl TryFrom<&[u8]> for SpeedRestrictionPayload {
+    type Error = SciError;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        let [a0, a1, s0, s1] =
+            <[u8; 4]>::try_from(value).map_err(|_| SciTcsError::BadPayloadLength(value.len()))?;
+        Ok(Self {
+            area: u16::from_be_bytes([a0, a1]),
+            speed_limit: u16::from_be_bytes([s0, s1]),
+        })
+    }
+}
+
+impl From<SpeedRestrictionPayload> for SCIPayload {
+    fn from(value: SpeedRestrictionPayload) -> Self {
+        let area = value.area.to_be_bytes();
+        let speed_limit = value.speed_limit.to_be_bytes();
+        SCIPayload::from_slice(&[area[0], area[1], speed_limit[0], speed_limit[1]])
+    }
+}
+
+/// The area of a speed restriction that is revoked.
+pub fn parse_revoked_restriction(payload: &[u8]) -> Result<u16, SciError> {
+    let area =
+        <[u8; 2]>::try_from(payload).map_err(|_| SciTcsError::BadPayloadLength(payload.len()))?;
+    Ok(u16::from_be_bytes(area))
+}
+
+impl SCITelegram {
+    pub fn tcs_train_position(sender: &str, receiver: &str, payload: TrainPositionPayload) -> Self {
+        Self {
+            protocol_type: ProtocolType::SCIProtocolTCS,
+            message_type: SCIMessageType::scitcs_train_position(),
+            sender: sender.to_string(),
+            receiver: receiver.to_string(),
+            payload: payload.into(),
+        }
+    }
+
+    pub fn tcs_section_status(sender: &str, receiver: &str, payload: SectionStatusPayload) -> Self {
+        Self {
+            protocol_type: ProtocolType::SCIProtocolTCS,
+            message_type: SCIMessageType::scitcs_section_status(),
+            sender: sender.to_string(),
+            receiver: receiver.to_string(),
+            payload: payload.into(),
+        }
+    }
+
+    pub fn tcs_speed_restriction(
+        sender: &str,
+        receiver: &str,
+        payload: SpeedRestrictionPayload,
+    ) -> Self {
+        Self {
+            protocol_type: ProtocolType::SCIProtocolTCS,
+            message_type: SCIMessageType::scitcs_speed_restriction(),
+            sender: sender.to_string(),
+            receiver: receiver.to_string(),
+            payload: payload.into(),
+        }
+    }
+
+    pub fn tcs_revoke_speed_restriction(sender: &str, receiver: &str, area: u16) -> Self {
+        Self {
+            protocol_type: ProtocolType::SCIProtocolTCS,
+            message_type: SCIMessageType::scitcs_revoke_speed_restriction(),
+            sender: sender.to_string(),
+            receiver: receiver.to_string(),
+            payload: SCIPayload::from_slice(&area.to_be_bytes()),
+        }
+    }
+
+    pub fn tcs_command_rejected(sender: &str, receiver: &str, reason: TcsRejectionReason) -> Self {
+        Self {
+            protocol_type: ProtocolType::SCIProtocolTCS,
+            message_type: SCIMessageType::scitcs_command_rejected(),
+            sender: sender.to_string(),
+            receiver: receiver.to_string(),
+            payload: SCIPayload::from_slice(&[reason as u8]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        scitcs::{
+            parse_revoked_restriction, SpeedRestrictionPayload, TrainPositionPayload,
+            TravelDirection,
+        },
+        ProtocolType, SCIMessageType, SCITelegram, SciError,
+    };
+
+    #[test]
+    fn test_tcs_telegrams() {
+        let payload = TrainPositionPayload {
+            train: 0x01020304,
+            section: 0x0506,
+            direction: TravelDirection::Reverse,
+        };
+        let telegram = SCITelegram::tcs_train_position("TCS", "EIL", payload);
+        let data: Vec<u8> = telegram.into();
+        let telegram = SCITelegram::try_from(data.as_slice()).unwrap();
+        assert_eq!(telegram.protocol_type, ProtocolType::SCIProtocolTCS);
+        assert_eq!(
+            telegram.message_type,
+            SCIMessageType::scitcs_train_position()
+        );
+        assert_eq!(
+            telegram.to_string(),
+            "SCIProtocolTCS: TrainPosition TCS -> EIL [01 02 03 04 05 06 02]"
+        );
+        assert_eq!(&telegram.payload[..7], [1, 2, 3, 4, 5, 6, 2]);
+        assert_eq!(
+            TrainPositionPayload::try_from(&*telegram.payload).unwrap(),
+            payload
+        );
+
+        let restriction = SCITelegram::tcs_speed_restriction(
+            "EIL",
+            "TCS",
+            SpeedRestrictionPayload {
+                area: 3,
+                speed_limit: 40,
+            },
+        );
+        assert_eq!(
+            SpeedRestrictionPayload::try_from(&*restriction.payload)
+                .unwrap()
+                .speed_limit,
+            40
+        );
+        assert!(matches!(
+            TrainPositionPayload::try_from(&[0, 0, 0, 1, 0, 2, 4][..]),
+            Err(SciError::Tcs(_))
+        ));
+        let revoke = SCITelegram::tcs_revoke_speed_restriction("EIL", "TCS", 3);
+        assert_eq!(parse_revoked_restriction(&revoke.payload).unwrap(), 3);
+        assert!(parse_revoked_restriction(&[3]).is_err());
+    }
+}
@@ -1,29 +1,117 @@
 //! SCI Train Detection System
+//!
+//! [`TvpsStateMachine`] models the force clear procedures of a track
+//! vacancy proving section (TVPS) as seen by an axle counter.
 
-use std::fmt::Display;
+#[cfg(feature = "std")]
+use alloc::string::String;
+use alloc::{string::ToString, vec, vec::Vec};
+use core::time::Duration;
+#[cfg(feature = "std")]
+use std::time::Instant;
 
 use crate::{
-    impl_sci_message_type, impl_sci_messages_without_payload, ProtocolType, SCIMessageType,
-    SCIPayload, SCITelegram, SciError,
+    impl_sci_message_type, impl_sci_messages_without_payload, split_extensions, ProtocolType,
+    SCIMessageType, SCIPayload, SCITelegram, SciError, SCI_VERSION,
 };
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, thiserror::Error)]
 pub enum SciTdsError {
+    #[error("Unknown FC Mode {0:x}")]
     UnknownFcMode(u8),
+    #[error("Unknown Occupancy Status {0:x}")]
     UnknownOccupancyStatus(u8),
+    #[error("Unknown POM Status {0:x}")]
     UnknownPOMStatus(u8),
+    #[error("Unknown Disturbance Status {0:x}")]
     UnknownDisturbanceStatus(u8),
+    #[error("Unknown Change Trigger {0:x}")]
     UnknownChangeTrigger(u8),
+    #[error("Unknown Rejection Reason {0:x}")]
     UnknownRejectionReason(u8),
+    #[error("Unknown FC-P Failure Reason {0:x}")]
     UnknownFCPFailureReason(u8),
+    #[error("Unknown State Of Passing {0:x}")]
     UnknownStateOfPassing(u8),
+    #[error("Unknown Direction Of Passing {0:x}")]
     UnknownDirectionOfPassing(u8),
+    #[error("Unknown Ability To Be Forced To Clear {0:x}")]
+    UnknownAbilityToBeForcedToClear(u8),
+    #[error("Bad Payload Length {0}")]
     BadPayloadLength(usize),
+    #[error("Invalid Filling Level {0}")]
+    InvalidFillingLevel(i16),
+    #[error("Invalid BCD Digit {0}")]
+    InvalidBcdDigit(u8),
+    #[error("Too Many BCD Digits ({0}, at most {MAX_BCD_DIGITS})")]
+    TooManyBcdDigits(usize),
+}
+
+/// The number of decimal digits of the speed and the wheel diameter
+/// in additional information telegrams.
+pub const MAX_BCD_DIGITS: usize = 4;
+
+/// The number of axles in a TVPS as reported in occupancy status
+/// telegrams (see Eu.Doc.44). It is transmitted as a signed 16 bit
+/// value, where [`FillingLevel::NOT_APPLICABLE`] (`-1`) indicates that
+/// the section does not count axles and [`FillingLevel::SATURATED`] is
+/// reported for all counts that do not fit. Other negative values are invalid.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "i16", into = "i16")
+)]
+pub struct FillingLevel(i16);
+
+impl FillingLevel {
+    pub const NOT_APPLICABLE: Self = Self(-1);
+    pub const EMPTY: Self = Self(0);
+    pub const SATURATED: Self = Self(i16::MAX);
+
+    /// The filling level for `axles` axles, saturating at [`FillingLevel::SATURATED`].
+    pub fn from_axle_count(axles: u32) -> Self {
+        Self(axles.min(i16::MAX as u32) as i16)
+    }
+
+    /// The number of axles, or `None` if the filling level is not applicable.
+    pub fn axle_count(&self) -> Option<u16> {
+        u16::try_from(self.0).ok()
+    }
+
+    pub fn is_not_applicable(&self) -> bool {
+        *self == Self::NOT_APPLICABLE
+    }
+
+    /// Whether the real number of axles may be higher than reported.
+    pub fn is_saturated(&self) -> bool {
+        *self == Self::SATURATED
+    }
+
+    pub fn to_be_bytes(self) -> [u8; 2] {
+        self.0.to_be_bytes()
+    }
+
+    pub fn from_be_bytes(bytes: [u8; 2]) -> Result<Self, SciTdsError> {
+        Self::try_from(i16::from_be_bytes(bytes))
+    }
+}
+
+impl TryFrom<i16> for FillingLevel {
+    type Error = SciTdsError;
+
+    fn try_from(value: i16) -> Result<Self, Self::Error> {
+        if value < -1 {
+            Err(SciTdsError::InvalidFillingLevel(value))
+        } else {
+            Ok(Self(value))
+        }
+    }
 }
 
-impl Display for SciTdsError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:?}", self)
+impl From<FillingLevel> for i16 {
+    fn from(value: FillingLevel) -> Self {
+        value.0
     }
 }
 
@@ -158,7 +246,7 @@ impl SCITelegram {
         receiver: &str,
         occupancy_status: OccupancyStatus,
         can_be_forced_to_clear: bool,
-        filling_level: i16,
+        filling_level: FillingLevel,
         pom_status: POMStatus,
         disturbance_status: DisturbanceStatus,
         change_trigger: ChangeTrigger,
@@ -172,8 +260,8 @@ impl SCITelegram {
             payload: SCIPayload::from_slice(&[
                 occupancy_status as u8,
                 match can_be_forced_to_clear {
-                    true => 0x01,
-                    false => 0x02,
+                    true => 0x02,
+                    false => 0x01,
                 },
                 filling_level_bytes[0],
                 filling_level_bytes[1],
@@ -214,17 +302,18 @@ impl SCITelegram {
         }
     }
 
-    /// Speed and wheel diameter are encoded as BCD.
-    /// Pass them as an array of u8 digits.
+    /// Speed and wheel diameter are encoded as BCD. Pass them as
+    /// up to [`MAX_BCD_DIGITS`] decimal digits, most significant first.
+    /// Shorter values are padded with leading zeros.
     pub fn additional_information(
         sender: &str,
         receiver: &str,
-        speed: [u8; 4],
-        wheel_diameter: [u8; 4],
-    ) -> Self {
-        let speed_bcd = to_bcd(speed).to_be_bytes();
-        let wheel_diameter_bcd = to_bcd(wheel_diameter).to_be_bytes();
-        Self {
+        speed: impl AsRef<[u8]>,
+        wheel_diameter: impl AsRef<[u8]>,
+    ) -> Result<Self, SciTdsError> {
+        let speed_bcd = to_bcd(speed.as_ref())?.to_be_bytes();
+        let wheel_diameter_bcd = to_bcd(wheel_diameter.as_ref())?.to_be_bytes();
+        Ok(Self {
             protocol_type: ProtocolType::SCIProtocolTDS,
             message_type: SCIMessageType::scitds_additional_information(),
             sender: sender.to_string(),
@@ -235,7 +324,7 @@ impl SCITelegram {
                 wheel_diameter_bcd[0],
                 wheel_diameter_bcd[1],
             ]),
-        }
+        })
     }
 
     pub fn tdp_status(
@@ -254,41 +343,56 @@ impl SCITelegram {
     }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct OccupancyStatusPayload {
     pub occupancy_status: OccupancyStatus,
     pub can_be_forced_to_clear: bool,
-    pub filling_level: u16,
+    pub filling_level: FillingLevel,
     pub pom_status: POMStatus,
     pub disturbance_status: DisturbanceStatus,
     pub change_trigger: ChangeTrigger,
+    /// Trailing bytes appended by newer SCI versions.
+    pub extensions: Vec<u8>,
 }
 
-impl TryFrom<SCIPayload> for OccupancyStatusPayload {
-    type Error = SciError;
-
-    fn try_from(value: SCIPayload) -> Result<Self, Self::Error> {
-        if value.len() != 7 {
-            return Err(SciError::Tds(SciTdsError::BadPayloadLength(value.len())));
-        }
+impl OccupancyStatusPayload {
+    /// Parses the payload as sent by a peer using SCI `version`.
+    /// Unknown trailing bytes of newer versions are kept in `extensions`.
+    pub fn try_from_versioned(value: &[u8], version: u8) -> Result<Self, SciError> {
+        let (value, extensions) = split_extensions(value, 7, version)
+            .ok_or(SciError::Tds(SciTdsError::BadPayloadLength(value.len())))?;
         Ok(OccupancyStatusPayload {
             occupancy_status: OccupancyStatus::try_from(value[0])?,
             can_be_forced_to_clear: match value[1] {
                 1 => false,
                 2 => true,
-                _ => unreachable!(),
+                other => {
+                    return Err(SciError::Tds(SciTdsError::UnknownAbilityToBeForcedToClear(
+                        other,
+                    )))
+                }
             },
-            filling_level: u16::from_be_bytes([value[2], value[3]]),
+            filling_level: FillingLevel::from_be_bytes([value[2], value[3]])?,
             pom_status: POMStatus::try_from(value[4])?,
             disturbance_status: DisturbanceStatus::try_from(value[5])?,
             change_trigger: ChangeTrigger::try_from(value[6])?,
+            extensions: extensions.to_vec(),
         })
     }
 }
 
+impl TryFrom<SCIPayload> for OccupancyStatusPayload {
+    type Error = SciError;
+
+    fn try_from(value: SCIPayload) -> Result<Self, Self::Error> {
+        Self::try_from_versioned(&value, SCI_VERSION)
+    }
+}
+
 impl From<OccupancyStatusPayload> for SCIPayload {
     fn from(value: OccupancyStatusPayload) -> Self {
-        SCIPayload::from_slice(&[
+        let mut data = vec![
             value.occupancy_status as u8,
             if value.can_be_forced_to_clear { 2 } else { 1 },
             value.filling_level.to_be_bytes()[0],
@@ -296,16 +400,43 @@ impl From<OccupancyStatusPayload> for SCIPayload {
             value.pom_status as u8,
             value.disturbance_status as u8,
             value.change_trigger as u8,
-        ])
+        ];
+        data.extend_from_slice(&value.extensions);
+        SCIPayload::from_slice(&data)
+    }
+}
+
+/// The payload of [`SCITelegram::additional_information`]: speed and
+/// wheel diameter as [`MAX_BCD_DIGITS`] decimal digits each, most
+/// significant first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AdditionalInformationPayload {
+    pub speed: [u8; MAX_BCD_DIGITS],
+    pub wheel_diameter: [u8; MAX_BCD_DIGITS],
+}
+
+impl TryFrom<SCIPayload> for AdditionalInformationPayload {
+    type Error = SciError;
+
+    fn try_from(value: SCIPayload) -> Result<Self, Self::Error> {
+        let [speed_high, speed_low, diameter_high, diameter_low] = value[..] else {
+            return Err(SciError::Tds(SciTdsError::BadPayloadLength(value.len())));
+        };
+        Ok(Self {
+            speed: from_bcd(u16::from_be_bytes([speed_high, speed_low]))?,
+            wheel_diameter: from_bcd(u16::from_be_bytes([diameter_high, diameter_low]))?,
+        })
     }
 }
 
 #[cfg(feature = "neupro")]
 #[derive(Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct NeuProOccupancyStatusPayload {
     pub occupancy_status: OccupancyStatus,
     pub can_be_forced_to_clear: bool,
-    pub filling_level: u16,
+    pub filling_level: FillingLevel,
 }
 
 #[cfg(feature = "neupro")]
@@ -321,9 +452,13 @@ impl TryFrom<SCIPayload> for NeuProOccupancyStatusPayload {
             can_be_forced_to_clear: match value[1] {
                 0 => false,
                 1 => true,
-                _ => unreachable!(),
+                other => {
+                    return Err(SciError::Tds(SciTdsError::UnknownAbilityToBeForcedToClear(
+                        other,
+                    )))
+                }
             },
-            filling_level: u16::from_be_bytes([value[2], value[3]]),
+            filling_level: FillingLevel::from_be_bytes([value[2], value[3]])?,
         })
     }
 }
@@ -338,6 +473,7 @@ impl From<NeuProOccupancyStatusPayload> for OccupancyStatusPayload {
             pom_status: POMStatus::NotApplicable,
             disturbance_status: DisturbanceStatus::NotApplicable,
             change_trigger: ChangeTrigger::NotApplicable,
+            extensions: Vec::new(),
         }
     }
 }
@@ -353,25 +489,593 @@ impl From<OccupancyStatusPayload> for NeuProOccupancyStatusPayload {
     }
 }
 
-fn to_bcd(digits: [u8; 4]) -> u16 {
-    assert!(
-        digits.iter().all(|&d| d <= 9),
-        "BCD Digits must be between 0 and 9"
-    );
-    let digit_0 = (digits[0] << 4) + digits[1];
-    let digit_1 = (digits[2] << 4) + digits[3];
-    u16::from_be_bytes([digit_0, digit_1])
+/// The default time a force clear with sweeping train waits
+/// for the sweeping train to pass the section.
+pub const DEFAULT_SWEEPING_TRAIN_TIMEOUT: Duration = Duration::from_secs(600);
+
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Debug)]
+struct SweepingTrain {
+    /// FC-P-A has to be acknowledged after the sweeping train passed.
+    acknowledge: bool,
+    started: Instant,
+    /// When the first axle of the sweeping train entered the section.
+    entered: Option<Instant>,
+}
+
+/// Tracks the occupancy of a TVPS and its force clear (FC) procedures
+/// as described in Eu.Doc.44, so that an axle counter can be simulated:
+///
+/// - FC-U clears the section unconditionally.
+/// - FC-C clears the section only if as many axles left as entered.
+/// - FC-P waits for a sweeping train to pass the section. FC-P-A
+///   additionally waits for an acknowledgement (FC mode `Ack`).
+/// - DRFC lifts the restriction that prevents forcing the section clear.
+/// - Cancel aborts a running FC-P or FC-P-A.
+///
+/// Commands that are not allowed in the current state are rejected.
+/// A sweeping train that is counted incorrectly, leaves the section
+/// before the minimum occupancy time or does not pass in time makes
+/// the procedure fail, leaving the section disturbed.
+#[cfg(feature = "std")]
+#[derive(Clone, Debug)]
+pub struct TvpsStateMachine {
+    name: String,
+    interlocking: String,
+    occupancy_status: OccupancyStatus,
+    axles: u32,
+    can_be_forced_to_clear: bool,
+    technical_failure: bool,
+    sweeping_train: Option<SweepingTrain>,
+    timeout: Duration,
+    min_occupancy: Duration,
+}
+
+#[cfg(feature = "std")]
+impl TvpsStateMachine {
+    /// Creates the section called `name` reporting to `interlocking`.
+    /// It starts out disturbed, as the axles in it are not known yet.
+    pub fn new(name: &str, interlocking: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            interlocking: interlocking.to_string(),
+            occupancy_status: OccupancyStatus::Disturbed,
+            axles: 0,
+            can_be_forced_to_clear: true,
+            technical_failure: false,
+            sweeping_train: None,
+            timeout: DEFAULT_SWEEPING_TRAIN_TIMEOUT,
+            min_occupancy: Duration::ZERO,
+        }
+    }
+
+    pub fn with_sweeping_train_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets the time the sweeping train has to occupy the section
+    /// before its first axle may leave.
+    pub fn with_min_occupancy(mut self, min_occupancy: Duration) -> Self {
+        self.min_occupancy = min_occupancy;
+        self
+    }
+
+    pub fn occupancy_status(&self) -> OccupancyStatus {
+        self.occupancy_status
+    }
+
+    pub fn filling_level(&self) -> FillingLevel {
+        FillingLevel::from_axle_count(self.axles)
+    }
+
+    pub fn can_be_forced_to_clear(&self) -> bool {
+        self.can_be_forced_to_clear
+    }
+
+    /// Restricts forcing the section clear until a DRFC is received,
+    /// or lifts the restriction. Returns the status to report.
+    pub fn set_can_be_forced_to_clear(&mut self, can_be_forced_to_clear: bool) -> Vec<SCITelegram> {
+        if self.can_be_forced_to_clear == can_be_forced_to_clear {
+            return Vec::new();
+        }
+        self.can_be_forced_to_clear = can_be_forced_to_clear;
+        vec![self.status(ChangeTrigger::InternalTrigger)]
+    }
+
+    /// A technical failure disturbs the section and aborts a running
+    /// sweeping train procedure. Until it is resolved, all commands are
+    /// rejected for technical reasons. Returns the status to report.
+    pub fn set_technical_failure(&mut self, technical_failure: bool) -> Vec<SCITelegram> {
+        if self.technical_failure == technical_failure {
+            return Vec::new();
+        }
+        self.technical_failure = technical_failure;
+        if technical_failure {
+            self.sweeping_train = None;
+            self.occupancy_status = OccupancyStatus::Disturbed;
+        }
+        vec![self.status(ChangeTrigger::TechnicalFailure)]
+    }
+
+    /// The occupancy status of the section, reported because of `change_trigger`.
+    pub fn status(&self, change_trigger: ChangeTrigger) -> SCITelegram {
+        SCITelegram::tvps_occupancy_status(
+            &self.name,
+            &self.interlocking,
+            self.occupancy_status,
+            self.can_be_forced_to_clear,
+            self.filling_level(),
+            POMStatus::NotApplicable,
+            if self.technical_failure {
+                DisturbanceStatus::Technical
+            } else {
+                DisturbanceStatus::Operational
+            },
+            change_trigger,
+        )
+    }
+
+    /// Handles a telegram sent to the section and returns the responses.
+    /// FC, DRFC, Cancel and Update Filling Level commands are handled.
+    pub fn handle(&mut self, telegram: &SCITelegram) -> Vec<SCITelegram> {
+        self.handle_at(telegram, Instant::now())
+    }
+
+    fn handle_at(&mut self, telegram: &SCITelegram, now: Instant) -> Vec<SCITelegram> {
+        let message_type = telegram.message_type;
+        if telegram.protocol_type != ProtocolType::SCIProtocolTDS {
+            Vec::new()
+        } else if message_type == SCIMessageType::scitds_update_filling_level() {
+            vec![self.status(ChangeTrigger::CommandFromEILAccepted)]
+        } else if message_type != SCIMessageType::scitds_fc()
+            && message_type != SCIMessageType::scitds_drfc()
+            && message_type != SCIMessageType::scitds_cancel()
+        {
+            Vec::new()
+        } else if self.technical_failure {
+            self.reject(RejectionReason::Technical)
+        } else if message_type == SCIMessageType::scitds_drfc() {
+            self.can_be_forced_to_clear = true;
+            vec![self.status(ChangeTrigger::CommandFromEILAccepted)]
+        } else if message_type == SCIMessageType::scitds_cancel() {
+            if self.sweeping_train.is_some() {
+                self.fail(
+                    FCPFailureReason::ProcessCancelled,
+                    ChangeTrigger::CommandFromEILAccepted,
+                )
+            } else {
+                self.reject(RejectionReason::Operational)
+            }
+        } else {
+            match telegram
+                .payload
+                .first()
+                .and_then(|mode| FCMode::try_from(*mode).ok())
+            {
+                Some(mode) => self.force_clear(mode, now),
+                None => self.reject(RejectionReason::Operational),
+            }
+        }
+    }
+
+    fn force_clear(&mut self, mode: FCMode, now: Instant) -> Vec<SCITelegram> {
+        match mode {
+            FCMode::Ack if self.occupancy_status == OccupancyStatus::WaitingForAck => {
+                self.sweeping_train = None;
+                self.occupancy_status = OccupancyStatus::Vacant;
+            }
+            FCMode::Ack => return self.reject(RejectionReason::Operational),
+            _ if self.sweeping_train.is_some() || !self.can_be_forced_to_clear => {
+                return self.reject(RejectionReason::Operational)
+            }
+            FCMode::C if self.axles != 0 => return self.reject(RejectionReason::Operational),
+            FCMode::U | FCMode::C => {
+                self.axles = 0;
+                self.occupancy_status = OccupancyStatus::Vacant;
+            }
+            FCMode::P | FCMode::PA => {
+                // The sweeping train is counted from an empty section
+                self.axles = 0;
+                self.occupancy_status = OccupancyStatus::WaitingForSweepingTrain;
+                self.sweeping_train = Some(SweepingTrain {
+                    acknowledge: mode == FCMode::PA,
+                    started: now,
+                    entered: None,
+                });
+            }
+        }
+        vec![self.status(ChangeTrigger::CommandFromEILAccepted)]
+    }
+
+    /// Counts `axles` entering the section and returns the status to report.
+    pub fn count_in(&mut self, axles: u32) -> Vec<SCITelegram> {
+        self.count_in_at(axles, Instant::now())
+    }
+
+    fn count_in_at(&mut self, axles: u32, now: Instant) -> Vec<SCITelegram> {
+        if axles == 0 {
+            return Vec::new();
+        }
+        self.axles = self.axles.saturating_add(axles);
+        match (self.occupancy_status, &mut self.sweeping_train) {
+            (OccupancyStatus::WaitingForSweepingTrain, Some(train)) => {
+                train.entered = Some(now);
+                self.occupancy_status = OccupancyStatus::SweepingTrainDetected;
+            }
+            // Another train entered before the acknowledgement
+            (OccupancyStatus::WaitingForAck, _) => {
+                self.sweeping_train = None;
+                self.occupancy_status = OccupancyStatus::Occupied;
+            }
+            (OccupancyStatus::Vacant, _) => self.occupancy_status = OccupancyStatus::Occupied,
+            _ => {}
+        }
+        vec![self.status(ChangeTrigger::PassingDetected)]
+    }
+
+    /// Counts `axles` leaving the section and returns the telegrams to report.
+    pub fn count_out(&mut self, axles: u32) -> Vec<SCITelegram> {
+        self.count_out_at(axles, Instant::now())
+    }
+
+    fn count_out_at(&mut self, axles: u32, now: Instant) -> Vec<SCITelegram> {
+        if axles == 0 {
+            return Vec::new();
+        }
+        let Some(remaining) = self.axles.checked_sub(axles) else {
+            // More axles left than entered
+            self.axles = 0;
+            if self.sweeping_train.is_some() {
+                return self.fail(
+                    FCPFailureReason::IncorrectCountOfSweepingTrain,
+                    ChangeTrigger::PassingDetected,
+                );
+            }
+            self.occupancy_status = OccupancyStatus::Disturbed;
+            return vec![self.status(ChangeTrigger::PassingDetected)];
+        };
+        self.axles = remaining;
+        match (self.occupancy_status, self.sweeping_train) {
+            (OccupancyStatus::SweepingTrainDetected, Some(train)) => {
+                if train.entered.is_some_and(|entered| {
+                    now.saturating_duration_since(entered) < self.min_occupancy
+                }) {
+                    return self.fail(
+                        FCPFailureReason::OutgoingAxleBeforeMinTimerExpiry,
+                        ChangeTrigger::PassingDetected,
+                    );
+                }
+                if remaining == 0 && train.acknowledge {
+                    self.occupancy_status = OccupancyStatus::WaitingForAck;
+                } else if remaining == 0 {
+                    self.sweeping_train = None;
+                    self.occupancy_status = OccupancyStatus::Vacant;
+                }
+            }
+            (OccupancyStatus::Occupied, _) if remaining == 0 => {
+                self.occupancy_status = OccupancyStatus::Vacant;
+            }
+            _ => {}
+        }
+        vec![self.status(ChangeTrigger::PassingDetected)]
+    }
+
+    /// Returns the telegrams that are due, i.e. the failure of a
+    /// sweeping train procedure whose sweeping train did not pass in time.
+    pub fn poll(&mut self) -> Vec<SCITelegram> {
+        self.poll_at(Instant::now())
+    }
+
+    fn poll_at(&mut self, now: Instant) -> Vec<SCITelegram> {
+        match self.sweeping_train {
+            Some(train)
+                if self.occupancy_status != OccupancyStatus::WaitingForAck
+                    && now.saturating_duration_since(train.started) >= self.timeout =>
+            {
+                self.fail(FCPFailureReason::Timeout, ChangeTrigger::InternalTrigger)
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn reject(&self, reason: RejectionReason) -> Vec<SCITelegram> {
+        vec![SCITelegram::command_rejected(
+            &self.name,
+            &self.interlocking,
+            reason,
+        )]
+    }
+
+    /// Ends the running sweeping train procedure because of `reason`.
+    fn fail(
+        &mut self,
+        reason: FCPFailureReason,
+        change_trigger: ChangeTrigger,
+    ) -> Vec<SCITelegram> {
+        let Some(train) = self.sweeping_train.take() else {
+            return Vec::new();
+        };
+        self.occupancy_status = OccupancyStatus::Disturbed;
+        let failed = if train.acknowledge {
+            SCITelegram::tvps_fc_p_a_failed(&self.name, &self.interlocking, reason)
+        } else {
+            SCITelegram::tvps_fc_p_failed(&self.name, &self.interlocking, reason)
+        };
+        vec![failed, self.status(change_trigger)]
+    }
+}
+
+fn to_bcd(digits: &[u8]) -> Result<u16, SciTdsError> {
+    if digits.len() > MAX_BCD_DIGITS {
+        return Err(SciTdsError::TooManyBcdDigits(digits.len()));
+    }
+    digits.iter().try_fold(0, |bcd, &digit| {
+        if digit > 9 {
+            Err(SciTdsError::InvalidBcdDigit(digit))
+        } else {
+            Ok((bcd << 4) | u16::from(digit))
+        }
+    })
+}
+
+fn from_bcd(bcd: u16) -> Result<[u8; MAX_BCD_DIGITS], SciTdsError> {
+    let mut digits = [0; MAX_BCD_DIGITS];
+    for (n, digit) in digits.iter_mut().rev().enumerate() {
+        *digit = ((bcd >> (4 * n)) & 0xf) as u8;
+        if *digit > 9 {
+            return Err(SciTdsError::InvalidBcdDigit(*digit));
+        }
+    }
+    Ok(digits)
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::scitds::to_bcd;
+    use crate::scitds::{
+        from_bcd, to_bcd, AdditionalInformationPayload, FillingLevel, OccupancyStatusPayload,
+        SciTdsError, MAX_BCD_DIGITS,
+    };
+    #[cfg(feature = "std")]
+    use crate::{
+        scitds::{FCMode, FCPFailureReason, OccupancyStatus, RejectionReason, TvpsStateMachine},
+        SCIMessageType, SCIPayload, SCITelegram,
+    };
 
     #[test]
     fn test_bcd() {
-        assert_eq!(to_bcd([0, 0, 0, 1]), 1);
-        assert_eq!(to_bcd([0, 0, 1, 1]), 17);
-        assert_eq!(to_bcd([0, 1, 1, 1]), 273);
-        assert_eq!(to_bcd([1, 1, 1, 1]), 4369);
+        assert_eq!(to_bcd(&[0, 0, 0, 1]).unwrap(), 1);
+        assert_eq!(to_bcd(&[0, 0, 1, 1]).unwrap(), 17);
+        assert_eq!(to_bcd(&[0, 1, 1, 1]).unwrap(), 273);
+        assert_eq!(to_bcd(&[1, 1, 1, 1]).unwrap(), 4369);
+        assert_eq!(to_bcd(&[9, 9, 9, 9]).unwrap(), 0x9999);
+        assert_eq!(to_bcd(&[1, 2, 3]).unwrap(), 0x0123);
+        assert_eq!(to_bcd(&[]).unwrap(), 0);
+        assert!(matches!(
+            to_bcd(&[1, 10]),
+            Err(SciTdsError::InvalidBcdDigit(10))
+        ));
+        assert!(matches!(
+            to_bcd(&[1, 2, 3, 4, 5]),
+            Err(SciTdsError::TooManyBcdDigits(5))
+        ));
+    }
+
+    #[test]
+    fn test_bcd_round_trip() {
+        for value in 0..=9999u16 {
+            let digits = value
+                .to_string()
+                .bytes()
+                .map(|digit| digit - b'0')
+                .collect::<Vec<_>>();
+            let bcd = to_bcd(&digits).unwrap();
+            let mut padded = [0; MAX_BCD_DIGITS];
+            padded[MAX_BCD_DIGITS - digits.len()..].copy_from_slice(&digits);
+            assert_eq!(from_bcd(bcd).unwrap(), padded);
+        }
+        // Boundaries
+        assert_eq!(from_bcd(0).unwrap(), [0; MAX_BCD_DIGITS]);
+        assert_eq!(from_bcd(0x9999).unwrap(), [9; MAX_BCD_DIGITS]);
+        assert!(matches!(
+            from_bcd(0x000a),
+            Err(SciTdsError::InvalidBcdDigit(10))
+        ));
+        assert!(matches!(
+            from_bcd(0xf999),
+            Err(SciTdsError::InvalidBcdDigit(15))
+        ));
+        assert!(matches!(
+            to_bcd(&[9, 9, 9, 9, 9]),
+            Err(SciTdsError::TooManyBcdDigits(5))
+        ));
+        assert!(matches!(
+            to_bcd(&[u8::MAX]),
+            Err(SciTdsError::InvalidBcdDigit(u8::MAX))
+        ));
+    }
+
+    #[test]
+    fn test_occupancy_status_extensions() {
+        let payload = [0x01, 0x01, 0x00, 0x02, 0x01, 0x01, 0x01, 0xAA, 0xBB];
+        assert!(OccupancyStatusPayload::try_from_versioned(&payload, 0x01).is_err());
+        let status = OccupancyStatusPayload::try_from_versioned(&payload, 0x02).unwrap();
+        assert_eq!(status.filling_level.axle_count(), Some(2));
+        assert_eq!(status.extensions, vec![0xAA, 0xBB]);
+    }
+
+    #[test]
+    fn test_unknown_ability_to_be_forced_to_clear() {
+        let payload = [0x01, 0x03, 0x00, 0x02, 0x01, 0x01, 0x01];
+        assert!(matches!(
+            OccupancyStatusPayload::try_from_versioned(&payload, 0x01),
+            Err(crate::SciError::Tds(
+                SciTdsError::UnknownAbilityToBeForcedToClear(0x03)
+            ))
+        ));
+        #[cfg(feature = "neupro")]
+        assert!(matches!(
+            crate::scitds::NeuProOccupancyStatusPayload::try_from(crate::SCIPayload::from_slice(
+                &[0x01, 0x02, 0x00, 0x02]
+            )),
+            Err(crate::SciError::Tds(
+                SciTdsError::UnknownAbilityToBeForcedToClear(0x02)
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_filling_level() {
+        assert_eq!(
+            FillingLevel::from_axle_count(100_000),
+            FillingLevel::SATURATED
+        );
+        assert_eq!(
+            FillingLevel::from_be_bytes([0xFF, 0xFF]).unwrap(),
+            FillingLevel::NOT_APPLICABLE
+        );
+        assert_eq!(FillingLevel::NOT_APPLICABLE.axle_count(), None);
+        assert!(FillingLevel::from_be_bytes([0xFF, 0xFE]).is_err());
+        let payload = [0x01, 0x01, 0x80, 0x00, 0x01, 0x01, 0x01];
+        assert!(OccupancyStatusPayload::try_from_versioned(&payload, 0x01).is_err());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_tvps_force_clear() {
+        use std::time::{Duration, Instant};
+
+        let status = |telegrams: &[SCITelegram]| {
+            let status = telegrams.last().unwrap();
+            assert_eq!(
+                status.message_type,
+                SCIMessageType::scitds_tvps_occupancy_status()
+            );
+            OccupancyStatusPayload::try_from(status.payload.clone()).unwrap()
+        };
+        let fc = |mode| SCITelegram::fc("I", "T", mode);
+        let start = Instant::now();
+        let mut tvps = TvpsStateMachine::new("T", "I")
+            .with_sweeping_train_timeout(Duration::from_secs(60))
+            .with_min_occupancy(Duration::from_secs(2));
+
+        // FC-P-A: the sweeping train passes, then the EIL acknowledges
+        let responses = tvps.handle_at(&fc(FCMode::PA), start);
+        assert_eq!(
+            status(&responses).occupancy_status,
+            OccupancyStatus::WaitingForSweepingTrain
+        );
+        let ack = tvps.handle_at(&fc(FCMode::Ack), start);
+        assert_eq!(
+            ack[0].message_type,
+            SCIMessageType::scitds_command_rejected()
+        );
+        tvps.count_in_at(4, start + Duration::from_secs(1));
+        assert_eq!(
+            tvps.occupancy_status(),
+            OccupancyStatus::SweepingTrainDetected
+        );
+        tvps.count_out_at(4, start + Duration::from_secs(3));
+        assert_eq!(tvps.occupancy_status(), OccupancyStatus::WaitingForAck);
+        let responses = tvps.handle_at(&fc(FCMode::Ack), start);
+        let payload = status(&responses);
+        assert_eq!(payload.occupancy_status, OccupancyStatus::Vacant);
+        assert!(payload.can_be_forced_to_clear);
+        assert_eq!(payload.filling_level.axle_count(), Some(0));
+
+        // FC-C needs a balanced count, the restriction is lifted by DRFC
+        tvps.count_in_at(2, start);
+        assert_eq!(tvps.occupancy_status(), OccupancyStatus::Occupied);
+        let rejected = tvps.handle_at(&fc(FCMode::C), start);
+        assert_eq!(
+            rejected[0].message_type,
+            SCIMessageType::scitds_command_rejected()
+        );
+        tvps.set_can_be_forced_to_clear(false);
+        assert_eq!(tvps.handle_at(&fc(FCMode::U), start).len(), 1);
+        assert_eq!(tvps.occupancy_status(), OccupancyStatus::Occupied);
+        tvps.handle_at(&SCITelegram::drfc("I", "T"), start);
+        tvps.handle_at(&fc(FCMode::U), start);
+        assert_eq!(tvps.occupancy_status(), OccupancyStatus::Vacant);
+
+        // A running FC-P is cancelled, times out or fails on a wrong count
+        tvps.handle_at(&fc(FCMode::P), start);
+        let cancelled = tvps.handle_at(&SCITelegram::cancel("I", "T"), start);
+        assert_eq!(
+            cancelled[0],
+            SCITelegram::tvps_fc_p_failed("T", "I", FCPFailureReason::ProcessCancelled)
+        );
+        assert_eq!(
+            status(&cancelled).occupancy_status,
+            OccupancyStatus::Disturbed
+        );
+        tvps.handle_at(&fc(FCMode::P), start);
+        assert!(tvps.poll_at(start + Duration::from_secs(59)).is_empty());
+        let timeout = tvps.poll_at(start + Duration::from_secs(60));
+        assert_eq!(
+            timeout[0],
+            SCITelegram::tvps_fc_p_failed("T", "I", FCPFailureReason::Timeout)
+        );
+        tvps.handle_at(&fc(FCMode::PA), start);
+        tvps.count_in_at(2, start);
+        let early = tvps.count_out_at(1, start + Duration::from_secs(1));
+        assert_eq!(
+            early[0],
+            SCITelegram::tvps_fc_p_a_failed(
+                "T",
+                "I",
+                FCPFailureReason::OutgoingAxleBeforeMinTimerExpiry
+            )
+        );
+        tvps.handle_at(&fc(FCMode::P), start);
+        let miscounted = tvps.count_out_at(1, start);
+        assert_eq!(
+            miscounted[0],
+            SCITelegram::tvps_fc_p_failed(
+                "T",
+                "I",
+                FCPFailureReason::IncorrectCountOfSweepingTrain
+            )
+        );
+        assert_eq!(tvps.occupancy_status(), OccupancyStatus::Disturbed);
+        assert!(tvps.poll_at(start + Duration::from_secs(120)).is_empty());
+
+        // An FC without a known mode is rejected and changes nothing
+        let mut unknown_mode = fc(FCMode::U);
+        for payload in [&[][..], &[0xff]] {
+            unknown_mode.payload = SCIPayload::from_slice(payload);
+            assert_eq!(
+                tvps.handle_at(&unknown_mode, start),
+                [SCITelegram::command_rejected(
+                    "T",
+                    "I",
+                    RejectionReason::Operational
+                )]
+            );
+        }
+        assert_eq!(tvps.occupancy_status(), OccupancyStatus::Disturbed);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_additional_information_payload() {
+        let telegram =
+            SCITelegram::additional_information("a", "b", [1, 2, 0], [9, 9, 9, 9]).unwrap();
+        let payload = AdditionalInformationPayload::try_from(telegram.payload).unwrap();
+        assert_eq!(payload.speed, [0, 1, 2, 0]);
+        assert_eq!(payload.wheel_diameter, [9, 9, 9, 9]);
+
+        let telegram = SCITelegram::additional_information("a", "b", [], [0]).unwrap();
+        let payload = AdditionalInformationPayload::try_from(telegram.payload).unwrap();
+        assert_eq!(payload.speed, [0; MAX_BCD_DIGITS]);
+        assert_eq!(payload.wheel_diameter, [0; MAX_BCD_DIGITS]);
+
+        assert!(
+            AdditionalInformationPayload::try_from(SCIPayload::from_slice(&[0x12, 0xa0, 0, 0]))
+                .is_err()
+        );
+        assert!(
+            AdditionalInformationPayload::try_from(SCIPayload::from_slice(&[0x12, 0x34])).is_err()
+        );
     }
 }
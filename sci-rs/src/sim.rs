@@ -0,0 +1,409 @@
+//! # Simulated Elements
+//!
+//! Minimal simulations of field elements that answer SCI telegrams
+//! the way a real element would. Failures can be injected to test
+//! how the interlocking side copes with degraded elements.
+
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+#[cfg(feature = "scils")]
+use crate::scils::SCILSSignalAspect;
+#[cfg(feature = "scip")]
+use crate::scip::{PointSimulator, SCIPointLocation};
+#[cfg(feature = "scitds")]
+use crate::scitds::{ChangeTrigger, DisturbanceStatus, FillingLevel, OccupancyStatus, POMStatus};
+use crate::{
+    ProtocolType, SCICloseReason, SCIMessageType, SCITelegram, SCIVersionCheckResult, SCI_VERSION,
+};
+
+/// A failure that can be injected into a [`SimulatedElement`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Failure {
+    /// The point starts moving but never reaches its end position.
+    #[cfg(feature = "scip")]
+    PointStuck,
+    /// The signal cannot light any lamp and stays dark.
+    #[cfg(feature = "scils")]
+    LampFailure,
+    /// The track vacancy proving section reports a disturbance.
+    #[cfg(feature = "scitds")]
+    TvpsDisturbance,
+    /// All responses are sent only after the given delay.
+    DelayedResponses(Duration),
+    /// The element answers version checks with the given version.
+    WrongVersion(u8),
+    /// The element closes the PDI with the given reason on the next telegram.
+    PrematureClose(SCICloseReason),
+}
+
+/// The protocol-specific behaviour of a simulated element.
+pub trait ElementBehaviour {
+    fn protocol_type(&self) -> ProtocolType;
+
+    /// The status telegrams sent during PDI initialisation.
+    fn status(&self, sender: &str, receiver: &str, failures: &[Failure]) -> Vec<SCITelegram>;
+
+    /// Handles a protocol-specific command and returns the responses.
+    fn handle_command(
+        &mut self,
+        telegram: &SCITelegram,
+        sender: &str,
+        failures: &[Failure],
+    ) -> Vec<SCITelegram>;
+
+    /// Returns telegrams the element sends on its own, e.g. at the end
+    /// of a movement. Called by [`SimulatedElement::poll`].
+    fn poll(&mut self, _sender: &str, _failures: &[Failure]) -> Vec<SCITelegram> {
+        Vec::new()
+    }
+}
+
+/// A simulated element consisting of generic PDI handling and an [`ElementBehaviour`].
+/// Responses are returned by [`SimulatedElement::handle`] or, if delayed,
+/// by [`SimulatedElement::poll`].
+pub struct SimulatedElement<B: ElementBehaviour> {
+    name: String,
+    behaviour: B,
+    failures: Vec<Failure>,
+    delayed: VecDeque<(Instant, SCITelegram)>,
+}
+
+impl<B: ElementBehaviour> SimulatedElement<B> {
+    pub fn new(name: &str, behaviour: B) -> Self {
+        Self {
+            name: name.to_string(),
+            behaviour,
+            failures: Vec::new(),
+            delayed: VecDeque::new(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn behaviour(&self) -> &B {
+        &self.behaviour
+    }
+
+    /// Injects a failure. It stays active until [`SimulatedElement::clear_failures`] is called.
+    pub fn inject(&mut self, failure: Failure) {
+        if !self.failures.contains(&failure) {
+            self.failures.push(failure);
+        }
+    }
+
+    pub fn clear_failures(&mut self) {
+        self.failures.clear();
+    }
+
+    pub fn failures(&self) -> &[Failure] {
+        &self.failures
+    }
+
+    fn delay(&self) -> Option<Duration> {
+        self.failures.iter().find_map(|f| match f {
+            Failure::DelayedResponses(d) => Some(*d),
+            _ => None,
+        })
+    }
+
+    /// Handles a received telegram and returns the responses that are due immediately.
+    pub fn handle(&mut self, telegram: &SCITelegram) -> Vec<SCITelegram> {
+        let responses = self.respond(telegram);
+        match self.delay() {
+            Some(delay) => {
+                let due = Instant::now() + delay;
+                self.delayed
+                    .extend(responses.into_iter().map(|response| (due, response)));
+                Vec::new()
+            }
+            None => responses,
+        }
+    }
+
+    /// Returns delayed responses whose delay has expired
+    /// and telegrams the element sends on its own.
+    pub fn poll(&mut self) -> Vec<SCITelegram> {
+        let now = Instant::now();
+        let mut due = Vec::new();
+        while self.delayed.front().is_some_and(|(at, _)| *at <= now) {
+            due.push(self.delayed.pop_front().unwrap().1);
+        }
+        due.append(&mut self.behaviour.poll(&self.name, &self.failures));
+        due
+    }
+
+    fn respond(&mut self, telegram: &SCITelegram) -> Vec<SCITelegram> {
+        let protocol_type = self.behaviour.protocol_type();
+        let peer = telegram.sender.as_str();
+        if let Some(reason) = self.failures.iter().find_map(|f| match f {
+            Failure::PrematureClose(reason) => Some(*reason),
+            _ => None,
+        }) {
+            return vec![SCITelegram::close(protocol_type, &self.name, peer, reason)];
+        }
+        if telegram.message_type == SCIMessageType::pdi_version_check() {
+            let version = self
+                .failures
+                .iter()
+                .find_map(|f| match f {
+                    Failure::WrongVersion(v) => Some(*v),
+                    _ => None,
+                })
+                .unwrap_or(SCI_VERSION);
+            let result = if telegram.payload.first() == Some(&version) {
+                SCIVersionCheckResult::VersionsAreEqual
+            } else {
+                SCIVersionCheckResult::VersionsAreNotEqual
+            };
+            SCITelegram::version_response(protocol_type, &self.name, peer, version, result, &[])
+                .into_iter()
+                .collect()
+        } else if telegram.message_type == SCIMessageType::pdi_initialisation_request() {
+            let mut responses = vec![SCITelegram::initialisation_response(
+                protocol_type,
+                &self.name,
+                peer,
+            )];
+            responses.append(&mut self.behaviour.status(&self.name, peer, &self.failures));
+            responses.push(SCITelegram::initialisation_completed(
+                protocol_type,
+                &self.name,
+                peer,
+            ));
+            responses
+        } else if telegram.message_type == SCIMessageType::pdi_close()
+            || telegram.message_type == SCIMessageType::pdi_release_for_maintenance()
+        {
+            Vec::new()
+        } else {
+            self.behaviour
+                .handle_command(telegram, &self.name, &self.failures)
+        }
+    }
+}
+
+/// A simulated point driven by a [`PointSimulator`]. It moves instantly
+/// unless a movement duration is set with [`SimulatedPoint::with_movement`].
+/// If [`Failure::PointStuck`] is injected, it starts moving but never
+/// reaches its target.
+#[cfg(feature = "scip")]
+pub struct SimulatedPoint {
+    simulator: PointSimulator,
+}
+
+#[cfg(feature = "scip")]
+impl SimulatedPoint {
+    pub fn new(location: SCIPointLocation) -> Self {
+        Self {
+            simulator: PointSimulator::new(location).with_movement_duration(Duration::ZERO),
+        }
+    }
+
+    /// Lets movements take `duration` and report a timeout
+    /// if they take longer than `timeout`.
+    pub fn with_movement(mut self, duration: Duration, timeout: Duration) -> Self {
+        self.simulator = self
+            .simulator
+            .with_movement_duration(duration)
+            .with_timeout(timeout);
+        self
+    }
+
+    pub fn location(&self) -> SCIPointLocation {
+        self.simulator.location()
+    }
+}
+
+#[cfg(feature = "scip")]
+impl ElementBehaviour for SimulatedPoint {
+    fn protocol_type(&self) -> ProtocolType {
+        ProtocolType::SCIProtocolP
+    }
+
+    fn status(&self, sender: &str, receiver: &str, _failures: &[Failure]) -> Vec<SCITelegram> {
+        vec![self.simulator.status(sender, receiver)]
+    }
+
+    fn handle_command(
+        &mut self,
+        telegram: &SCITelegram,
+        sender: &str,
+        failures: &[Failure],
+    ) -> Vec<SCITelegram> {
+        self.simulator
+            .set_stuck(failures.contains(&Failure::PointStuck));
+        self.simulator.handle(telegram, sender)
+    }
+
+    fn poll(&mut self, sender: &str, failures: &[Failure]) -> Vec<SCITelegram> {
+        self.simulator
+            .set_stuck(failures.contains(&Failure::PointStuck));
+        self.simulator.poll(sender)
+    }
+}
+
+/// A simulated light signal that shows every commanded aspect
+/// unless [`Failure::LampFailure`] is injected.
+#[cfg(feature = "scils")]
+#[derive(Default)]
+pub struct SimulatedSignal {
+    aspect: SCILSSignalAspect,
+}
+
+#[cfg(feature = "scils")]
+impl SimulatedSignal {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn aspect(&self) -> &SCILSSignalAspect {
+        &self.aspect
+    }
+}
+
+#[cfg(feature = "scils")]
+impl ElementBehaviour for SimulatedSignal {
+    fn protocol_type(&self) -> ProtocolType {
+        ProtocolType::SCIProtocolLS
+    }
+
+    fn status(&self, sender: &str, receiver: &str, failures: &[Failure]) -> Vec<SCITelegram> {
+        let aspect = if failures.contains(&Failure::LampFailure) {
+            SCILSSignalAspect::default()
+        } else {
+            self.aspect.clone()
+        };
+        vec![SCITelegram::scils_signal_aspect_status(
+            sender, receiver, aspect,
+        )]
+    }
+
+    fn handle_command(
+        &mut self,
+        telegram: &SCITelegram,
+        sender: &str,
+        failures: &[Failure],
+    ) -> Vec<SCITelegram> {
+        if telegram.message_type != SCIMessageType::scils_show_signal_aspect() {
+            return Vec::new();
+        }
+        match SCILSSignalAspect::try_from(&*telegram.payload) {
+            Ok(aspect) => {
+                self.aspect = aspect;
+                self.status(sender, &telegram.sender, failures)
+            }
+            Err(_) => Vec::new(),
+        }
+    }
+}
+
+/// A simulated track vacancy proving section that can be forced clear.
+#[cfg(feature = "scitds")]
+pub struct SimulatedTvps {
+    occupancy_status: OccupancyStatus,
+    filling_level: FillingLevel,
+}
+
+#[cfg(feature = "scitds")]
+impl SimulatedTvps {
+    pub fn new(occupancy_status: OccupancyStatus) -> Self {
+        Self {
+            occupancy_status,
+            filling_level: FillingLevel::EMPTY,
+        }
+    }
+
+    pub fn occupancy_status(&self) -> OccupancyStatus {
+        self.occupancy_status
+    }
+}
+
+#[cfg(feature = "scitds")]
+impl ElementBehaviour for SimulatedTvps {
+    fn protocol_type(&self) -> ProtocolType {
+        ProtocolType::SCIProtocolTDS
+    }
+
+    fn status(&self, sender: &str, receiver: &str, failures: &[Failure]) -> Vec<SCITelegram> {
+        let (occupancy_status, disturbance_status) = if failures.contains(&Failure::TvpsDisturbance)
+        {
+            (OccupancyStatus::Disturbed, DisturbanceStatus::Technical)
+        } else {
+            (self.occupancy_status, DisturbanceStatus::Operational)
+        };
+        vec![SCITelegram::tvps_occupancy_status(
+            sender,
+            receiver,
+            occupancy_status,
+            true,
+            self.filling_level,
+            POMStatus::NotApplicable,
+            disturbance_status,
+            ChangeTrigger::NotApplicable,
+        )]
+    }
+
+    fn handle_command(
+        &mut self,
+        telegram: &SCITelegram,
+        sender: &str,
+        failures: &[Failure],
+    ) -> Vec<SCITelegram> {
+        if telegram.message_type == SCIMessageType::scitds_fc()
+            && !failures.contains(&Failure::TvpsDisturbance)
+        {
+            self.occupancy_status = OccupancyStatus::Vacant;
+            self.filling_level = FillingLevel::EMPTY;
+        } else if telegram.message_type != SCIMessageType::scitds_update_filling_level()
+            && telegram.message_type != SCIMessageType::scitds_drfc()
+            && telegram.message_type != SCIMessageType::scitds_fc()
+        {
+            return Vec::new();
+        }
+        self.status(sender, &telegram.sender, failures)
+    }
+}
+
+#[cfg(all(test, feature = "scip"))]
+mod tests {
+    use super::*;
+    use crate::scip::SCIPointTargetLocation;
+
+    #[test]
+    fn test_stuck_point() {
+        let mut point = SimulatedElement::new(
+            "P",
+            SimulatedPoint::new(SCIPointLocation::PointLocationLeft),
+        );
+        point.inject(Failure::PointStuck);
+        let responses = point.handle(&SCITelegram::change_location(
+            "I",
+            "P",
+            SCIPointTargetLocation::PointLocationChangeToRight,
+        ));
+        assert_eq!(responses.len(), 1);
+        assert_eq!(
+            point.behaviour().location(),
+            SCIPointLocation::PointNoTargetLocation
+        );
+    }
+
+    #[test]
+    fn test_delayed_responses() {
+        let mut point = SimulatedElement::new(
+            "P",
+            SimulatedPoint::new(SCIPointLocation::PointLocationLeft),
+        );
+        point.inject(Failure::DelayedResponses(Duration::from_millis(10)));
+        let telegram = SCITelegram::version_check(ProtocolType::SCIProtocolP, "I", "P", 1);
+        assert!(point.handle(&telegram).is_empty());
+        assert!(point.poll().is_empty());
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(point.poll().len(), 1);
+    }
+}
@@ -0,0 +1,406 @@
+//! # Statistics
+//!
+//! Counts the telegrams exchanged by an endpoint per protocol, message
+//! type and direction, together with the number of payload bytes they
+//! carried. The counters cover the whole lifetime of the endpoint and
+//! can be used to plan the bandwidth of new installations from real
+//! traffic.
+//!
+//! The time between a command and the status that concludes it, e.g.
+//! from a change location command to the final location of the point,
+//! is collected per peer and command type in a [`RoundTripHistogram`].
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{journal::Direction, ProtocolType, SCIMessageType, SCITelegram};
+
+/// The traffic of one message type in one direction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TelegramCounter {
+    pub protocol_type: ProtocolType,
+    pub message_type: SCIMessageType,
+    pub direction: Direction,
+    pub telegrams: u64,
+    pub payload_bytes: u64,
+}
+
+/// The upper bounds of the buckets of a [`RoundTripHistogram`].
+/// Longer round trips are counted in an additional bucket.
+pub const ROUND_TRIP_BUCKETS: [Duration; 12] = [
+    Duration::from_millis(10),
+    Duration::from_millis(25),
+    Duration::from_millis(50),
+    Duration::from_millis(100),
+    Duration::from_millis(250),
+    Duration::from_millis(500),
+    Duration::from_secs(1),
+    Duration::from_millis(2500),
+    Duration::from_secs(5),
+    Duration::from_secs(10),
+    Duration::from_secs(25),
+    Duration::from_secs(60),
+];
+
+/// The round trips of one command type sent to one peer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RoundTripHistogram {
+    /// The name of the peer without padding.
+    pub peer: String,
+    pub protocol_type: ProtocolType,
+    pub command: SCIMessageType,
+    /// The number of round trips per bucket of [`ROUND_TRIP_BUCKETS`],
+    /// followed by the number of longer ones.
+    pub buckets: [u64; ROUND_TRIP_BUCKETS.len() + 1],
+    pub count: u64,
+    pub sum: Duration,
+    pub max: Duration,
+}
+
+impl RoundTripHistogram {
+    fn new(peer: String, protocol_type: ProtocolType, command: SCIMessageType) -> Self {
+        Self {
+            peer,
+            protocol_type,
+            command,
+            buckets: [0; ROUND_TRIP_BUCKETS.len() + 1],
+            count: 0,
+            sum: Duration::ZERO,
+            max: Duration::ZERO,
+        }
+    }
+
+    fn add(&mut self, round_trip: Duration) {
+        let bucket = ROUND_TRIP_BUCKETS.partition_point(|bound| *bound < round_trip);
+        self.buckets[bucket] += 1;
+        self.count += 1;
+        self.sum += round_trip;
+        self.max = self.max.max(round_trip);
+    }
+
+    pub fn mean(&self) -> Option<Duration> {
+        (self.count > 0).then(|| self.sum / self.count as u32)
+    }
+
+    /// An upper bound of the round trip below which `percentile` percent
+    /// of the round trips lie: the upper bound of the bucket containing
+    /// that round trip, but at most the longest round trip.
+    /// `None` if no round trip was completed.
+    pub fn percentile(&self, percentile: f64) -> Option<Duration> {
+        let rank = (percentile.clamp(0.0, 100.0) / 100.0 * self.count as f64).ceil() as u64;
+        let mut seen = 0;
+        for (bucket, count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= rank.max(1) {
+                let bound = ROUND_TRIP_BUCKETS.get(bucket).copied().unwrap_or(self.max);
+                return Some(bound.min(self.max));
+            }
+        }
+        None
+    }
+}
+
+/// Whether a telegram of the peer concludes a command of type `command`,
+/// or `None` if no round trips are measured for such commands.
+fn final_status(
+    protocol_type: ProtocolType,
+    command: SCIMessageType,
+) -> Option<fn(&SCITelegram) -> bool> {
+    if command == SCIMessageType::pdi_version_check() {
+        return Some(|status| status.message_type == SCIMessageType::pdi_version_response());
+    }
+    match protocol_type {
+        #[cfg(feature = "scip")]
+        ProtocolType::SCIProtocolP if command == SCIMessageType::scip_change_location() => {
+            // The point reports that it left its end position first
+            Some(|status| {
+                use crate::scip::SCIPointLocation;
+
+                status.message_type == SCIMessageType::scip_location_status()
+                    && status
+                        .payload
+                        .first()
+                        .and_then(|location| SCIPointLocation::try_from(*location).ok())
+                        .is_some_and(|location| location != SCIPointLocation::PointNoTargetLocation)
+            })
+        }
+        #[cfg(feature = "scils")]
+        ProtocolType::SCIProtocolLS if command == SCIMessageType::scils_show_signal_aspect() => {
+            Some(|status| status.message_type == SCIMessageType::scils_signal_aspect_status())
+        }
+        #[cfg(feature = "scils")]
+        ProtocolType::SCIProtocolLS if command == SCIMessageType::scils_change_brightness() => {
+            Some(|status| status.message_type == SCIMessageType::scils_brightness_status())
+        }
+        _ => None,
+    }
+}
+
+type Key = (ProtocolType, SCIMessageType, Direction);
+
+type RoundTripKey = (String, ProtocolType, SCIMessageType);
+
+#[derive(Debug, Default)]
+struct RoundTrips {
+    /// The latest unanswered command per peer and command type.
+    pending: HashMap<RoundTripKey, Instant>,
+    histograms: HashMap<RoundTripKey, RoundTripHistogram>,
+}
+
+impl RoundTrips {
+    fn record(&mut self, direction: Direction, telegram: &SCITelegram, now: Instant) {
+        match direction {
+            Direction::Outgoing => {
+                if final_status(telegram.protocol_type, telegram.message_type).is_some() {
+                    let peer = telegram.receiver.trim_end_matches('_').to_string();
+                    // A repeated command restarts the measurement
+                    self.pending
+                        .insert((peer, telegram.protocol_type, telegram.message_type), now);
+                }
+            }
+            Direction::Incoming => {
+                let peer = telegram.sender.trim_end_matches('_');
+                let concluded: Vec<RoundTripKey> = self
+                    .pending
+                    .keys()
+                    .filter(|(p, protocol_type, command)| {
+                        p == peer
+                            && *protocol_type == telegram.protocol_type
+                            && final_status(*protocol_type, *command)
+                                .is_some_and(|concludes| concludes(telegram))
+                    })
+                    .cloned()
+                    .collect();
+                for key in concluded {
+                    let sent = self.pending.remove(&key).unwrap();
+                    let (peer, protocol_type, command) = key.clone();
+                    self.histograms
+                        .entry(key)
+                        .or_insert_with(|| RoundTripHistogram::new(peer, protocol_type, command))
+                        .add(now.saturating_duration_since(sent));
+                }
+            }
+        }
+    }
+}
+
+/// A shared handle to the telegram counters and round trips of an
+/// endpoint. Clones share the same counters, so a handle can be kept to
+/// read them while the endpoint is running on another thread.
+#[derive(Clone, Debug, Default)]
+pub struct TelegramStats {
+    counters: Arc<Mutex<HashMap<Key, (u64, u64)>>>,
+    round_trips: Arc<Mutex<RoundTrips>>,
+}
+
+impl TelegramStats {
+    pub fn record(&self, direction: Direction, telegram: &SCITelegram) {
+        self.record_at(direction, telegram, Instant::now());
+    }
+
+    fn record_at(&self, direction: Direction, telegram: &SCITelegram, now: Instant) {
+        self.round_trips
+            .lock()
+            .unwrap()
+            .record(direction, telegram, now);
+        let mut counters = self.counters.lock().unwrap();
+        let (telegrams, payload_bytes) = counters
+            .entry((telegram.protocol_type, telegram.message_type, direction))
+            .or_default();
+        *telegrams += 1;
+        *payload_bytes += telegram.payload.len() as u64;
+    }
+
+    /// The counter of one message type, or `None` if no such
+    /// telegram was exchanged in `direction`.
+    pub fn get(
+        &self,
+        protocol_type: ProtocolType,
+        message_type: SCIMessageType,
+        direction: Direction,
+    ) -> Option<TelegramCounter> {
+        let counters = self.counters.lock().unwrap();
+        counters.get(&(protocol_type, message_type, direction)).map(
+            |&(telegrams, payload_bytes)| TelegramCounter {
+                protocol_type,
+                message_type,
+                direction,
+                telegrams,
+                payload_bytes,
+            },
+        )
+    }
+
+    /// Returns a snapshot of all counters, ordered by protocol,
+    /// message type and direction.
+    pub fn counters(&self) -> Vec<TelegramCounter> {
+        let mut counters: Vec<TelegramCounter> = self
+            .counters
+            .lock()
+            .unwrap()
+            .iter()
+            .map(
+                |(&(protocol_type, message_type, direction), &(telegrams, payload_bytes))| {
+                    TelegramCounter {
+                        protocol_type,
+                        message_type,
+                        direction,
+                        telegrams,
+                        payload_bytes,
+                    }
+                },
+            )
+            .collect();
+        counters.sort_by_key(|c| (u8::from(c.protocol_type), c.message_type, c.direction));
+        counters
+    }
+
+    /// The number of telegrams and payload bytes in `direction`
+    /// over all message types.
+    pub fn totals(&self, direction: Direction) -> (u64, u64) {
+        self.counters
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|((_, _, d), _)| *d == direction)
+            .fold((0, 0), |(telegrams, bytes), (_, &(t, b))| {
+                (telegrams + t, bytes + b)
+            })
+    }
+
+    /// The round trips of commands of type `command` sent to `peer`,
+    /// or `None` if none of them was concluded yet.
+    pub fn round_trip(
+        &self,
+        peer: &str,
+        protocol_type: ProtocolType,
+        command: SCIMessageType,
+    ) -> Option<RoundTripHistogram> {
+        let peer = peer.trim_end_matches('_').to_string();
+        self.round_trips
+            .lock()
+            .unwrap()
+            .histograms
+            .get(&(peer, protocol_type, command))
+            .cloned()
+    }
+
+    /// Returns a snapshot of all round trip histograms,
+    /// ordered by peer, protocol and command type.
+    pub fn round_trips(&self) -> Vec<RoundTripHistogram> {
+        let mut histograms: Vec<RoundTripHistogram> = self
+            .round_trips
+            .lock()
+            .unwrap()
+            .histograms
+            .values()
+            .cloned()
+            .collect();
+        histograms.sort_by(|a, b| {
+            (&a.peer, u8::from(a.protocol_type), a.command).cmp(&(
+                &b.peer,
+                u8::from(b.protocol_type),
+                b.command,
+            ))
+        });
+        histograms
+    }
+
+    pub fn reset(&self) {
+        self.counters.lock().unwrap().clear();
+        *self.round_trips.lock().unwrap() = RoundTrips::default();
+    }
+}
+
+#[cfg(all(test, feature = "scip"))]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::{
+        journal::Direction,
+        scip::{SCIPointLocation, SCIPointTargetLocation},
+        ProtocolType, SCIMessageType, SCITelegram,
+    };
+
+    use super::TelegramStats;
+
+    #[test]
+    fn test_telegram_counters() {
+        let stats = TelegramStats::default();
+        let handle = stats.clone();
+        let command = SCITelegram::change_location(
+            "C",
+            "S",
+            SCIPointTargetLocation::PointLocationChangeToLeft,
+        );
+        stats.record(Direction::Outgoing, &command);
+        stats.record(Direction::Outgoing, &command);
+        stats.record(
+            Direction::Incoming,
+            &SCITelegram::location_status("S", "C", SCIPointLocation::PointLocationLeft),
+        );
+
+        let sent = handle
+            .get(
+                ProtocolType::SCIProtocolP,
+                SCIMessageType::scip_change_location(),
+                Direction::Outgoing,
+            )
+            .unwrap();
+        assert_eq!(sent.telegrams, 2);
+        assert_eq!(sent.payload_bytes, 2 * command.payload.len() as u64);
+        assert_eq!(handle.counters().len(), 2);
+        assert_eq!(handle.totals(Direction::Incoming).0, 1);
+        handle.reset();
+        assert!(stats.counters().is_empty());
+    }
+
+    #[test]
+    fn test_round_trips() {
+        let stats = TelegramStats::default();
+        let command = SCITelegram::change_location(
+            "C",
+            "S",
+            SCIPointTargetLocation::PointLocationChangeToLeft,
+        );
+        let start = Instant::now();
+        for (offset, direction, telegram) in [
+            (0, Direction::Outgoing, command.clone()),
+            (
+                500,
+                Direction::Incoming,
+                SCITelegram::location_status("S", "C", SCIPointLocation::PointNoTargetLocation),
+            ),
+            (
+                3000,
+                Direction::Incoming,
+                SCITelegram::location_status("S", "C", SCIPointLocation::PointLocationLeft),
+            ),
+            (4000, Direction::Outgoing, command),
+            (
+                4040,
+                Direction::Incoming,
+                SCITelegram::location_status("S", "C", SCIPointLocation::PointLocationLeft),
+            ),
+        ] {
+            stats.record_at(direction, &telegram, start + Duration::from_millis(offset));
+        }
+
+        let histogram = stats
+            .round_trip(
+                "S",
+                ProtocolType::SCIProtocolP,
+                SCIMessageType::scip_change_location(),
+            )
+            .unwrap();
+        assert_eq!(histogram.count, 2);
+        assert_eq!(histogram.max, Duration::from_secs(3));
+        assert_eq!(histogram.mean(), Some(Duration::from_millis(1520)));
+        assert_eq!(histogram.percentile(50.0), Some(Duration::from_millis(50)));
+        assert_eq!(histogram.percentile(99.0), Some(Duration::from_secs(3)));
+        assert_eq!(stats.round_trips(), [histogram]);
+    }
+}
@@ -0,0 +1,342 @@
+//! # Streams
+//!
+//! A pull-based alternative to the callback of [`SCIConnection::run`].
+//! [`SCIConnection::telegram_stream`] yields every decoded incoming
+//! telegram, while [`SCITelegramSender`] handles obtained from
+//! [`SCIConnection::sender`] can queue outgoing telegrams from
+//! any thread.
+//!
+//! ## Ordering
+//!
+//! All telegrams of a connection are sent in the order they were
+//! queued, whether through a sender handle or with
+//! [`SCIConnection::send_telegram`], which queues the telegram behind
+//! those already waiting. Each queued telegram is tagged with a
+//! [`SendTag`] reflecting this order. Telegrams queued concurrently from
+//! several threads are ordered by the time they were queued.
+//!
+//! Queued telegrams survive [`SCIConnection::reconnect`] and are sent
+//! over the new connection. Telegrams that were already written to the
+//! old connection are not repeated, so delivery is at most once.
+
+use std::sync::{
+    mpsc::{SyncSender, TrySendError},
+    Arc, Mutex,
+};
+
+use rasta_rs::{message::MessageType, RastaConnectionState, RastaError};
+
+use crate::{SCIConnection, SCITelegram};
+
+/// The position of a telegram in the send order of its connection.
+/// Tags of later telegrams compare greater.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SendTag(u64);
+
+impl SendTag {
+    pub fn value(&self) -> u64 {
+        self.0
+    }
+}
+
+pub(crate) type QueuedTelegram = (SendTag, SCITelegram);
+
+/// Queues telegrams for an [`SCIConnection`]. They are sent the next
+/// time its [`TelegramStream`] is polled or a telegram is sent directly.
+#[derive(Clone)]
+pub struct SCITelegramSender {
+    pub(crate) tx: SyncSender<QueuedTelegram>,
+    pub(crate) capacity: usize,
+    pub(crate) next_tag: Arc<Mutex<u64>>,
+}
+
+impl SCITelegramSender {
+    /// Queues `telegram` and returns its position in the send order.
+    /// Fails if the connection has been dropped. The queue holds up to
+    /// [`MemoryBudget::send_queue`](rasta_rs::MemoryBudget::send_queue)
+    /// telegrams; further telegrams are rejected with
+    /// [`RastaError::BufferFull`] until the connection sends the queue.
+    pub fn send(&self, telegram: SCITelegram) -> Result<SendTag, RastaError> {
+        // Tags are handed out and queued under the same lock,
+        // so the queue is always in tag order
+        let mut next_tag = self.next_tag.lock().unwrap();
+        let tag = SendTag(*next_tag);
+        self.tx.try_send((tag, telegram)).map_err(|e| match e {
+            TrySendError::Full(_) => RastaError::BufferFull {
+                buffer: "send queue",
+                capacity: self.capacity,
+            },
+            TrySendError::Disconnected(_) => RastaError::StateError,
+        })?;
+        *next_tag += 1;
+        Ok(tag)
+    }
+}
+
+/// An iterator over the telegrams received by an [`SCIConnection`].
+/// While waiting, queued telegrams are sent and the connection is kept
+/// alive with heartbeats. The stream ends once the connection is closed.
+/// A close of the peer is yielded as an error before.
+pub struct TelegramStream<'a> {
+    pub(crate) conn: &'a mut SCIConnection,
+}
+
+impl Iterator for TelegramStream<'_> {
+    type Item = Result<SCITelegram, RastaError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Err(e) = self.conn.send_queued() {
+                return Some(Err(e));
+            }
+            if let Some(telegram) = self.conn.pending.pop_front() {
+                return Some(Ok(telegram));
+            }
+            if let Some(msg) = self.conn.conn.take_buffered_message() {
+                match self.conn.accept(&msg) {
+                    Ok(Some(telegram)) => return Some(Ok(telegram)),
+                    Ok(None) => continue,
+                    Err(e) => return Some(Err(e)),
+                }
+            }
+            if self.conn.conn.connection_state_request() != RastaConnectionState::Up {
+                return None;
+            }
+            if let Err(e) = self.conn.maintain() {
+                return Some(Err(e));
+            }
+            // Waiting no longer than until the next heartbeat is due,
+            // telegrams queued in the meantime are sent on the next pass
+            let wait = self.conn.conn.time_until_heartbeat();
+            let msg = match self.conn.conn.poll_message(wait).transpose() {
+                Some(polled) => match self.conn.supervise(polled) {
+                    Ok(msg) => msg,
+                    Err(e) => return Some(Err(e)),
+                },
+                None => continue,
+            };
+            if matches!(
+                msg.try_message_type(),
+                Ok(MessageType::Data | MessageType::DiscReq)
+            ) {
+                match self.conn.accept(&msg) {
+                    Ok(Some(telegram)) => return Some(Ok(telegram)),
+                    Ok(None) => {}
+                    Err(e) => return Some(Err(e)),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "scip"))]
+mod tests {
+    use std::{collections::HashMap, net::TcpListener, sync::mpsc, thread, time::Duration};
+
+    use rasta_rs::{RastaConfig, RastaConnection, RastaError, RastaListener};
+
+    use crate::{scip::SCIPointTargetLocation, SCIConnection, SCIPayload, SCITelegram};
+
+    fn numbered(number: u8) -> SCITelegram {
+        let mut telegram = SCITelegram::change_location(
+            "I",
+            "P",
+            SCIPointTargetLocation::PointLocationChangeToLeft,
+        );
+        telegram.payload = SCIPayload::from_slice(&[number]);
+        telegram
+    }
+
+    #[test]
+    fn test_bounded_send_queue() {
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut config = RastaConfig::default();
+        config.memory.send_queue = 2;
+        let conn =
+            RastaConnection::try_new_with_config(server.local_addr().unwrap(), 1, config).unwrap();
+        let conn = SCIConnection::try_new(conn, "I".to_string(), HashMap::new()).unwrap();
+
+        let sender = conn.sender();
+        sender.send(numbered(0)).unwrap();
+        sender.send(numbered(1)).unwrap();
+        assert!(matches!(
+            sender.send(numbered(2)),
+            Err(RastaError::BufferFull { capacity: 2, .. })
+        ));
+        drop(conn);
+        assert!(matches!(
+            sender.send(numbered(2)),
+            Err(RastaError::StateError)
+        ));
+    }
+
+    #[test]
+    fn test_per_peer_fifo() {
+        let mut listener = RastaListener::try_new("127.0.0.1:0", 2).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (tx, received) = mpsc::channel();
+        thread::spawn(move || {
+            listener.listen(|msg| {
+                let _ = tx.send(*msg.data().last().unwrap());
+                None::<Vec<u8>>
+            })
+        });
+        let receive = |count: usize| -> Vec<u8> {
+            (0..count)
+                .map(|_| received.recv_timeout(Duration::from_secs(5)).unwrap())
+                .collect()
+        };
+
+        let mapping = HashMap::from([("P".to_string(), 2)]);
+        let conn = RastaConnection::try_new(addr, 1).unwrap();
+        let mut conn = SCIConnection::try_new(conn, "I".to_string(), mapping).unwrap();
+
+        // Batches queued from several threads keep their order,
+        // and direct sends go out behind them
+        let batches: Vec<_> = [0u8, 100]
+            .into_iter()
+            .map(|start| {
+                let sender = conn.sender();
+                thread::spawn(move || {
+                    (start..start + 50)
+                        .map(|number| sender.send(numbered(number)).unwrap())
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        for batch in batches {
+            let tags = batch.join().unwrap();
+            assert!(tags.windows(2).all(|pair| pair[0] < pair[1]));
+        }
+        conn.send_telegram(numbered(200)).unwrap();
+        conn.flush().unwrap();
+        assert_eq!(conn.last_sent_tag().unwrap().value(), 100);
+        let numbers = receive(101);
+        assert_eq!(numbers.last(), Some(&200));
+        for start in [0, 100] {
+            let batch: Vec<u8> = numbers
+                .iter()
+                .copied()
+                .filter(|number| (start..start + 50).contains(number))
+                .collect();
+            assert_eq!(batch, (start..start + 50).collect::<Vec<_>>());
+        }
+
+        // Telegrams queued before a reconnect are sent over the new connection.
+        // The responses of the peer are read first, so that the old
+        // connection can be closed without resetting it.
+        while conn
+            .poll_telegram(Duration::from_millis(50))
+            .unwrap()
+            .is_some()
+        {}
+        let sender = conn.sender();
+        for number in 1..=10 {
+            sender.send(numbered(number)).unwrap();
+        }
+        conn.reconnect(RastaConnection::try_new(addr, 1).unwrap())
+            .unwrap();
+        conn.send_telegram(numbered(11)).unwrap();
+        conn.flush().unwrap();
+        assert_eq!(receive(11), (1..=11).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_telegram_stream() {
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let mut listener = RastaListener::try_new(addr, 2).unwrap();
+        thread::spawn(move || listener.listen(|msg| Some(msg.data().to_vec())));
+
+        let conn = RastaConnection::try_new(addr, 1).unwrap();
+        let mapping = HashMap::from([("P".to_string(), 2)]);
+        let mut conn = SCIConnection::try_new(conn, "I".to_string(), mapping).unwrap();
+        let sender = conn.sender();
+        let queue = sender.clone();
+        thread::spawn(move || {
+            for number in 0..3 {
+                queue.send(numbered(number)).unwrap();
+            }
+        })
+        .join()
+        .unwrap();
+
+        // Queued telegrams are sent once the stream is polled
+        let echoes: Vec<u8> = conn
+            .telegram_stream("P")
+            .unwrap()
+            .take(3)
+            .map(|telegram| telegram.unwrap().payload[0])
+            .collect();
+        assert_eq!(echoes, [0, 1, 2]);
+
+        drop(conn);
+        assert!(matches!(
+            sender.send(numbered(3)),
+            Err(RastaError::StateError)
+        ));
+    }
+
+    #[test]
+    fn test_stream_sends_while_waiting() {
+        // The listener disconnects peers that are silent for longer than T_max
+        let config = RastaConfig {
+            t_max: Duration::from_millis(300),
+            ..RastaConfig::default()
+        };
+        let mut listener =
+            RastaListener::try_new_with_config("127.0.0.1:0", 2, config.clone()).unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || listener.listen(|msg| Some(msg.data().to_vec())));
+
+        let conn = RastaConnection::try_new_with_config(addr, 1, config).unwrap();
+        let mapping = HashMap::from([("P".to_string(), 2)]);
+        let mut conn = SCIConnection::try_new(conn, "I".to_string(), mapping).unwrap();
+        let sender = conn.sender();
+        let mut stream = conn.telegram_stream("P").unwrap();
+        // Sent while the stream is waiting for the peer, the second one
+        // after the idle connection was kept alive for longer than T_max
+        thread::spawn(move || {
+            for (delay, number) in [(50, 7), (500, 8)] {
+                thread::sleep(Duration::from_millis(delay));
+                sender.send(numbered(number)).unwrap();
+            }
+        });
+        for number in [7, 8] {
+            let echo = stream.next().unwrap().unwrap();
+            assert_eq!(echo.payload[0], number);
+        }
+    }
+
+    #[test]
+    fn test_poll_sends_heartbeats() {
+        // The listener disconnects peers that are silent for longer than T_max
+        let config = RastaConfig {
+            t_max: Duration::from_millis(100),
+            ..RastaConfig::default()
+        };
+        let mut listener = RastaListener::try_new_with_config("127.0.0.1:0", 2, config).unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || listener.listen(|msg| Some(msg.data().to_vec())));
+
+        let config = RastaConfig {
+            max_heartbeat_interval: Duration::from_millis(20),
+            ..RastaConfig::default()
+        };
+        let conn = RastaConnection::try_new_with_config(addr, 1, config).unwrap();
+        let mapping = HashMap::from([("P".to_string(), 2)]);
+        let mut conn = SCIConnection::try_new(conn, "I".to_string(), mapping).unwrap();
+        conn.open("P").unwrap();
+        for number in 0..3 {
+            assert!(conn
+                .poll_telegram(Duration::from_millis(300))
+                .unwrap()
+                .is_none());
+            conn.send_telegram(numbered(number)).unwrap();
+            let echo = conn.poll_telegram(Duration::from_secs(1)).unwrap().unwrap();
+            assert_eq!(echo.payload[0], number);
+        }
+    }
+}
@@ -0,0 +1,230 @@
+//! # Test Support
+//!
+//! Minimal counterparts for integration tests: a [`FakeElement`] that
+//! serves a [`SimulatedElement`] over RaSTA on a background thread, and a
+//! [`FakeInterlocking`] that connects to it, runs the PDI handshake and
+//! sends commands. Both only behave correctly enough for a handshake and
+//! a few commands; use [`Failure`](crate::sim::Failure)s on the simulated
+//! element to test degraded behaviour.
+//!
+//! ```no_run
+//! # use sci_rs::{scip::{SCIPointLocation, SCIPointTargetLocation}, SCITelegram};
+//! # use sci_rs::testing::{FakeElement, FakeInterlocking};
+//! let point = FakeElement::point("P", 2, SCIPointLocation::PointLocationLeft)?;
+//! let mut interlocking = FakeInterlocking::connect("I", 1, &point)?;
+//! let status = interlocking.start_pdi()?;
+//! let response = interlocking.command(SCITelegram::change_location(
+//!     "I",
+//!     "P",
+//!     SCIPointTargetLocation::PointLocationChangeToRight,
+//! ))?;
+//! # Ok::<(), rasta_rs::RastaError>(())
+//! ```
+
+use std::{cell::RefCell, collections::HashMap, net::SocketAddr, thread, time::Duration};
+
+use rasta_rs::{
+    message::RastaId, shutdown::ShutdownHandle, RastaConnection, RastaError, RastaListener,
+};
+
+#[cfg(feature = "scils")]
+use crate::sim::SimulatedSignal;
+use crate::{
+    managed::ManagedConfig,
+    sim::{ElementBehaviour, SimulatedElement},
+    ProtocolType, SCIConnection, SCIListener, SCITelegram,
+};
+#[cfg(feature = "scip")]
+use crate::{scip::SCIPointLocation, sim::SimulatedPoint};
+#[cfg(feature = "scitds")]
+use crate::{scitds::OccupancyStatus, sim::SimulatedTvps};
+
+/// How often a [`FakeElement`] checks for delayed responses.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// A simulated element listening on a local port. It is stopped
+/// when dropped.
+pub struct FakeElement {
+    name: String,
+    rasta_id: RastaId,
+    protocol_type: ProtocolType,
+    addr: SocketAddr,
+    shutdown: ShutdownHandle,
+    thread: Option<thread::JoinHandle<Result<(), RastaError>>>,
+}
+
+impl FakeElement {
+    /// Serves `element` with the RaSTA ID `rasta_id` on a free local port.
+    pub fn spawn<B>(rasta_id: RastaId, element: SimulatedElement<B>) -> Result<Self, RastaError>
+    where
+        B: ElementBehaviour + Send + 'static,
+    {
+        let listener = RastaListener::try_new("127.0.0.1:0", rasta_id)?;
+        let addr = listener.local_addr()?;
+        let name = element.name().to_string();
+        let protocol_type = element.behaviour().protocol_type();
+        let mut listener = SCIListener::new(listener, name.clone());
+        let shutdown = listener.shutdown_handle();
+        let thread = thread::spawn(move || {
+            let element = RefCell::new(element);
+            listener.listen_with_tick(
+                POLL_INTERVAL,
+                |telegram| element.borrow_mut().handle(&telegram),
+                || element.borrow_mut().poll(),
+            )
+        });
+        Ok(Self {
+            name,
+            rasta_id,
+            protocol_type,
+            addr,
+            shutdown,
+            thread: Some(thread),
+        })
+    }
+
+    #[cfg(feature = "scip")]
+    pub fn point(
+        name: &str,
+        rasta_id: RastaId,
+        location: SCIPointLocation,
+    ) -> Result<Self, RastaError> {
+        Self::spawn(
+            rasta_id,
+            SimulatedElement::new(name, SimulatedPoint::new(location)),
+        )
+    }
+
+    #[cfg(feature = "scils")]
+    pub fn signal(name: &str, rasta_id: RastaId) -> Result<Self, RastaError> {
+        Self::spawn(
+            rasta_id,
+            SimulatedElement::new(name, SimulatedSignal::new()),
+        )
+    }
+
+    #[cfg(feature = "scitds")]
+    pub fn tvps(
+        name: &str,
+        rasta_id: RastaId,
+        occupancy_status: OccupancyStatus,
+    ) -> Result<Self, RastaError> {
+        Self::spawn(
+            rasta_id,
+            SimulatedElement::new(name, SimulatedTvps::new(occupancy_status)),
+        )
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn rasta_id(&self) -> RastaId {
+        self.rasta_id
+    }
+
+    pub fn protocol_type(&self) -> ProtocolType {
+        self.protocol_type
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Stops the element and returns the result of its listener.
+    pub fn stop(mut self) -> Result<(), RastaError> {
+        self.shutdown.shutdown();
+        self.thread.take().unwrap().join().unwrap()
+    }
+}
+
+impl Drop for FakeElement {
+    fn drop(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            self.shutdown.shutdown();
+            let _ = thread.join();
+        }
+    }
+}
+
+/// The interlocking side of a PDI with a [`FakeElement`].
+pub struct FakeInterlocking {
+    conn: SCIConnection,
+    peer: String,
+}
+
+impl FakeInterlocking {
+    /// Connects to `element` as `name` with the RaSTA ID `rasta_id`.
+    /// The PDI is started by [`FakeInterlocking::start_pdi`].
+    pub fn connect(
+        name: &str,
+        rasta_id: RastaId,
+        element: &FakeElement,
+    ) -> Result<Self, RastaError> {
+        let conn = RastaConnection::try_new(element.addr(), rasta_id)?;
+        let mapping = HashMap::from([(element.name().to_string(), element.rasta_id())]);
+        let mut conn = SCIConnection::try_new(conn, name.to_string(), mapping)?;
+        conn.set_managed(ManagedConfig::new(element.protocol_type()));
+        Ok(Self {
+            conn,
+            peer: element.name().to_string(),
+        })
+    }
+
+    /// Opens the connection and runs the version check and the
+    /// initialisation. Returns the status telegrams sent by the element.
+    pub fn start_pdi(&mut self) -> Result<Vec<SCITelegram>, RastaError> {
+        self.conn.open(&self.peer)?;
+        Ok(self.conn.pending.drain(..).collect())
+    }
+
+    /// Sends `telegram` and returns the first telegram received afterwards.
+    pub fn command(&mut self, telegram: SCITelegram) -> Result<SCITelegram, RastaError> {
+        self.conn.send_telegram(telegram)?;
+        self.conn.receive_telegram()
+    }
+
+    pub fn receive(&mut self) -> Result<SCITelegram, RastaError> {
+        self.conn.receive_telegram()
+    }
+
+    /// The underlying connection, e.g. to inspect its history.
+    pub fn connection(&mut self) -> &mut SCIConnection {
+        &mut self.conn
+    }
+}
+
+#[cfg(all(test, feature = "scip"))]
+mod tests {
+    use crate::{
+        scip::{SCIPointLocation, SCIPointTargetLocation},
+        SCIMessageType, SCITelegram,
+    };
+
+    use super::{FakeElement, FakeInterlocking};
+
+    #[test]
+    fn test_fake_point() {
+        let point = FakeElement::point("P", 2, SCIPointLocation::PointLocationLeft).unwrap();
+        let mut interlocking = FakeInterlocking::connect("I", 1, &point).unwrap();
+        let status = interlocking.start_pdi().unwrap();
+        assert_eq!(status.len(), 1);
+        assert_eq!(
+            status[0].message_type,
+            SCIMessageType::scip_location_status()
+        );
+        let response = interlocking
+            .command(SCITelegram::change_location(
+                "I",
+                "P",
+                SCIPointTargetLocation::PointLocationChangeToRight,
+            ))
+            .unwrap();
+        assert_eq!(
+            response.payload[0],
+            SCIPointLocation::PointLocationRight as u8
+        );
+        drop(interlocking);
+        point.stop().unwrap();
+    }
+}
@@ -0,0 +1,273 @@
+//! # Version Check
+//!
+//! The version check is the first exchange of the PDI: the initiator
+//! announces its SCI version, the responder answers whether the versions
+//! are equal together with a checksum of its configuration data.
+//! [`VersionCheck`] performs this exchange without the rest of managed
+//! mode, see [`SCIConnection::version_check`](crate::SCIConnection::version_check)
+//! and [`SCIListener::set_version_check`](crate::SCIListener::set_version_check).
+//! The checksum is taken from a [`ChecksumProvider`], so that it can
+//! follow configuration data that changes at runtime.
+
+use std::{sync::Arc, time::Duration};
+
+use crate::{
+    baseline::BaselineProfile, managed::config_checksum, ProtocolType, SCICloseReason,
+    SCIMessageType, SCITelegram, SCIVersionCheckResult, SciError, VersionResponsePayload,
+    SCI_VERSION,
+};
+
+/// How long [`SCIConnection::version_check`](crate::SCIConnection::version_check)
+/// waits for the response by default.
+pub const DEFAULT_VERSION_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Provides the checksum of the configuration data, which a responder
+/// sends in its version response and an initiator expects from its peer.
+/// An empty checksum is sent as is and accepts any checksum of the peer.
+pub trait ChecksumProvider: Send + Sync {
+    fn checksum(&self) -> Vec<u8>;
+}
+
+impl<F> ChecksumProvider for F
+where
+    F: Fn() -> Vec<u8> + Send + Sync,
+{
+    fn checksum(&self) -> Vec<u8> {
+        self()
+    }
+}
+
+/// A fixed checksum.
+impl ChecksumProvider for Vec<u8> {
+    fn checksum(&self) -> Vec<u8> {
+        self.clone()
+    }
+}
+
+/// The [`config_checksum`] of configuration data.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ConfigDataChecksum(pub Vec<u8>);
+
+impl ChecksumProvider for ConfigDataChecksum {
+    fn checksum(&self) -> Vec<u8> {
+        config_checksum(&self.0).to_vec()
+    }
+}
+
+/// The settings of a version check and the logic of both sides.
+#[derive(Clone)]
+pub struct VersionCheck {
+    protocol_type: ProtocolType,
+    version: u8,
+    checksum: Arc<dyn ChecksumProvider>,
+    timeout: Duration,
+}
+
+impl VersionCheck {
+    /// A version check of [`SCI_VERSION`] without a checksum.
+    pub fn new(protocol_type: ProtocolType) -> Self {
+        Self {
+            protocol_type,
+            version: SCI_VERSION,
+            checksum: Arc::new(Vec::new()),
+            timeout: DEFAULT_VERSION_CHECK_TIMEOUT,
+        }
+    }
+
+    pub fn with_version(mut self, version: u8) -> Self {
+        self.version = version;
+        self
+    }
+
+    pub fn with_checksum_provider(mut self, provider: impl ChecksumProvider + 'static) -> Self {
+        self.checksum = Arc::new(provider);
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn protocol_type(&self) -> ProtocolType {
+        self.protocol_type
+    }
+
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    /// The current checksum of the provider.
+    pub fn checksum(&self) -> Vec<u8> {
+        self.checksum.checksum()
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// The version check `sender` sends to `receiver`.
+    pub fn request(&self, sender: &str, receiver: &str) -> SCITelegram {
+        SCITelegram::version_check(self.protocol_type, sender, receiver, self.version)
+    }
+
+    /// The response of `sender` to the version check `request`. The
+    /// checksum is left out if `baseline` does not support it. Fails if
+    /// `request` is no version check or the checksum is too long.
+    pub fn respond(
+        &self,
+        sender: &str,
+        request: &SCITelegram,
+        baseline: BaselineProfile,
+    ) -> Result<SCITelegram, SciError> {
+        if request.message_type != SCIMessageType::pdi_version_check() {
+            return Err(SciError::UnknownMessageType(request.message_type.into()));
+        }
+        let result = if request.sci_version() == Some(self.version) {
+            SCIVersionCheckResult::VersionsAreEqual
+        } else {
+            SCIVersionCheckResult::VersionsAreNotEqual
+        };
+        let checksum = self.checksum();
+        SCITelegram::version_response(
+            request.protocol_type,
+            sender,
+            &request.sender,
+            self.version,
+            result,
+            baseline.version_response_checksum(&checksum),
+        )
+    }
+
+    /// Checks a version response and returns the version of the peer.
+    /// Fails with [`SciError::PdiClosed`] if the peer reports different
+    /// versions and with [`SciError::ChecksumMismatch`] if the checksum
+    /// differs from the one of the provider. The checksum is not checked
+    /// if the provider's is empty or `baseline` does not support it.
+    pub fn verify(
+        &self,
+        response: &SCITelegram,
+        baseline: BaselineProfile,
+    ) -> Result<u8, SciError> {
+        if response.message_type != SCIMessageType::pdi_version_response() {
+            return Err(SciError::UnknownMessageType(response.message_type.into()));
+        }
+        let payload = VersionResponsePayload::try_from(&response.payload)?;
+        if payload.result != SCIVersionCheckResult::VersionsAreEqual {
+            return Err(SciError::PdiClosed(SCICloseReason::OtherVersionRequired));
+        }
+        let expected = self.checksum();
+        if baseline.version_response_has_checksum()
+            && !expected.is_empty()
+            && payload.checksum != expected
+        {
+            return Err(SciError::ChecksumMismatch {
+                expected,
+                received: payload.checksum,
+            });
+        }
+        Ok(payload.version)
+    }
+}
+
+impl std::fmt::Debug for VersionCheck {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("VersionCheck")
+            .field("protocol_type", &self.protocol_type)
+            .field("version", &self.version)
+            .field("timeout", &self.timeout)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicU8, Ordering},
+        Arc,
+    };
+
+    use crate::{baseline::BaselineProfile, ProtocolType, SCICloseReason, SciError};
+
+    use super::{ConfigDataChecksum, VersionCheck};
+
+    #[test]
+    fn test_version_check_exchange() {
+        let protocol_type = ProtocolType::SCIProtocolP;
+        let initiator = VersionCheck::new(protocol_type)
+            .with_checksum_provider(ConfigDataChecksum(b"P1 left".to_vec()));
+        let revision = Arc::new(AtomicU8::new(0));
+        let provider = Arc::clone(&revision);
+        let responder = VersionCheck::new(protocol_type).with_checksum_provider(move || {
+            let revision = provider.load(Ordering::Relaxed);
+            crate::managed::config_checksum(if revision == 0 {
+                b"P1 left"
+            } else {
+                b"P1 right"
+            })
+            .to_vec()
+        });
+
+        let request = initiator.request("I", "P");
+        let response = responder
+            .respond("P", &request, BaselineProfile::BL4)
+            .unwrap();
+        assert_eq!(response.receiver, "I");
+        assert_eq!(
+            initiator.verify(&response, BaselineProfile::BL4).unwrap(),
+            1
+        );
+
+        revision.store(1, Ordering::Relaxed);
+        let response = responder
+            .respond("P", &request, BaselineProfile::BL4)
+            .unwrap();
+        assert!(matches!(
+            initiator.verify(&response, BaselineProfile::BL4),
+            Err(SciError::ChecksumMismatch { .. })
+        ));
+        // BL3 responses carry no checksum
+        let response = responder
+            .respond("P", &request, BaselineProfile::BL3)
+            .unwrap();
+        assert!(initiator.verify(&response, BaselineProfile::BL3).is_ok());
+
+        let request = initiator.clone().with_version(2).request("I", "P");
+        let response = responder
+            .respond("P", &request, BaselineProfile::BL4)
+            .unwrap();
+        assert!(matches!(
+            initiator.verify(&response, BaselineProfile::BL4),
+            Err(SciError::PdiClosed(SCICloseReason::OtherVersionRequired))
+        ));
+    }
+
+    #[cfg(feature = "scip")]
+    #[test]
+    fn test_version_check_endpoints() {
+        use std::{collections::HashMap, thread};
+
+        use rasta_rs::{RastaConnection, RastaListener};
+
+        use crate::{SCIConnection, SCIListener, SCITelegram};
+
+        let protocol_type = ProtocolType::SCIProtocolP;
+        let listener = RastaListener::try_new("127.0.0.1:0", 2).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut listener = SCIListener::new(listener, "P".to_string());
+        listener.set_version_check(
+            VersionCheck::new(protocol_type)
+                .with_checksum_provider(ConfigDataChecksum(b"P1 left".to_vec())),
+        );
+        thread::spawn(move || listener.listen(|_| None::<SCITelegram>));
+
+        let conn = RastaConnection::try_new(addr, 1).unwrap();
+        let mapping = HashMap::from([("P".to_string(), 2)]);
+        let mut conn = SCIConnection::try_new(conn, "I".to_string(), mapping).unwrap();
+        let check = VersionCheck::new(protocol_type)
+            .with_checksum_provider(ConfigDataChecksum(b"P1 left".to_vec()));
+        assert_eq!(conn.version_check("P", &check).unwrap(), 1);
+        let check = check.with_checksum_provider(ConfigDataChecksum(b"P1 right".to_vec()));
+        assert!(conn.version_check("P", &check).is_err());
+    }
+}
@@ -0,0 +1,73 @@
+#!/usr/bin/env bash
+# Checks that every combination of crate features compiles,
+# including tests and examples.
+#
+# Usage: scripts/feature-matrix.sh
+set -euo pipefail
+
+cd "$(dirname "$0")/.."
+
+check_powerset() {
+    local package=$1
+    shift
+    local features=("$@")
+    local count=${#features[@]}
+    local failed=0
+    for ((mask = 0; mask < (1 << count); mask++)); do
+        local selected=()
+        for ((i = 0; i < count; i++)); do
+            if (((mask >> i) & 1)); then
+                selected+=("${features[$i]}")
+            fi
+        done
+        local list
+        list=$(IFS=,; echo "${selected[*]:-}")
+        echo "Checking $package [$list]"
+        if ! cargo check --quiet --package "$package" --all-targets \
+            --no-default-features --features "std,$list"; then
+            echo "FAILED: $package [$list]"
+            failed=1
+        fi
+    done
+    return $failed
+}
+
+# Checks that the library builds without `std` for every combination of
+# the given features. The cdylib crate type needs a panic handler on the
+# host, so only the rlib is built.
+check_no_std_powerset() {
+    local package=$1
+    shift
+    local features=("$@")
+    local count=${#features[@]}
+    local failed=0
+    for ((mask = 0; mask < (1 << count); mask++)); do
+        local selected=()
+        for ((i = 0; i < count; i++)); do
+            if (((mask >> i) & 1)); then
+                selected+=("${features[$i]}")
+            fi
+        done
+        local list
+        list=$(IFS=,; echo "${selected[*]:-}")
+        echo "Checking $package without std [$list]"
+        if ! cargo rustc --quiet --package "$package" --lib --crate-type lib \
+            --profile check --no-default-features --features "$list"; then
+            echo "FAILED: $package without std [$list]"
+            failed=1
+        fi
+    done
+    return $failed
+}
+
+status=0
+# wasi_sockets only builds for WASI targets.
+check_powerset rasta-rs rand serde tls tracing || status=1
+check_powerset sci-rs scip scils scirbc scitcs scitds scigio neupro generic rasta sim test-support || status=1
+# serde and tracing only interact with the RaSTA transport, checking them
+# against every protocol combination would multiply the run time.
+check_powerset sci-rs rasta serde tracing || status=1
+check_no_std_powerset rasta-rs serde || status=1
+check_no_std_powerset sci-rs scip scils scitds serde || status=1
+check_no_std_powerset sci-rs scirbc scitcs scigio neupro generic || status=1
+exit $status
@@ -0,0 +1,103 @@
+//! Maintenance tasks, run with `cargo xtask <task>`.
+//!
+//! - `semver-checks [--baseline-rev <rev>]` compares the public API of the
+//!   published crates with `<rev>` (the latest tag by default) using
+//!   [cargo-semver-checks](https://github.com/obi1kenobi/cargo-semver-checks).
+//! - `release [--baseline-rev <rev>]` runs the checks required before a
+//!   release: the feature matrix, the tests and `semver-checks`.
+
+use std::{
+    env,
+    path::PathBuf,
+    process::{self, Command},
+};
+
+/// The crates whose public API is checked, with the features it is checked with.
+const PUBLISHED: [(&str, &str); 2] = [("rasta-rs", "full"), ("sci-rs", "full")];
+
+type Result<T> = std::result::Result<T, String>;
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        Some("semver-checks") => baseline_rev(&args[1..]).and_then(|rev| semver_checks(&rev)),
+        Some("release") => baseline_rev(&args[1..]).and_then(|rev| release(&rev)),
+        _ => Err("usage: cargo xtask <semver-checks|release> [--baseline-rev <rev>]".to_string()),
+    };
+    if let Err(error) = result {
+        eprintln!("xtask: {error}");
+        process::exit(1);
+    }
+}
+
+/// The revision given with `--baseline-rev`, or the latest tag.
+fn baseline_rev(args: &[String]) -> Result<String> {
+    match args {
+        [] => {
+            let output = Command::new("git")
+                .args(["describe", "--tags", "--abbrev=0"])
+                .current_dir(workspace_root())
+                .output()
+                .map_err(|error| format!("failed to run git: {error}"))?;
+            if !output.status.success() {
+                return Err("no release tag found, pass --baseline-rev".to_string());
+            }
+            Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+        }
+        [flag, rev] if flag == "--baseline-rev" => Ok(rev.clone()),
+        _ => Err(format!("unexpected arguments: {}", args.join(" "))),
+    }
+}
+
+fn semver_checks(baseline_rev: &str) -> Result<()> {
+    for (package, features) in PUBLISHED {
+        println!("Checking the public API of {package} against {baseline_rev}");
+        run(Command::new(cargo()).args([
+            "semver-checks",
+            "check-release",
+            "--package",
+            package,
+            "--baseline-rev",
+            baseline_rev,
+            "--only-explicit-features",
+            "--features",
+            features,
+        ]))
+        .map_err(|error| {
+            format!("{error} (install the checker with `cargo install cargo-semver-checks`)")
+        })?;
+    }
+    Ok(())
+}
+
+fn release(baseline_rev: &str) -> Result<()> {
+    run(&mut Command::new(
+        workspace_root().join("scripts/feature-matrix.sh"),
+    ))?;
+    run(Command::new(cargo()).args(["test", "--workspace"]))?;
+    semver_checks(baseline_rev)
+}
+
+/// Runs `command` in the workspace root and fails if it does not succeed.
+fn run(command: &mut Command) -> Result<()> {
+    let status = command
+        .current_dir(workspace_root())
+        .status()
+        .map_err(|error| format!("failed to run {command:?}: {error}"))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("{command:?} failed with {status}"))
+    }
+}
+
+fn cargo() -> String {
+    env::var("CARGO").unwrap_or_else(|_| "cargo".to_string())
+}
+
+fn workspace_root() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .parent()
+        .expect("xtask is located in the workspace")
+        .to_path_buf()
+}